@@ -850,4 +850,9 @@ CREATE INDEX IF NOT EXISTS idx_user_favorite_stickers_user ON user_favorite_stic
 -- Theme published status
 ALTER TABLE themes ADD COLUMN IF NOT EXISTS published BOOLEAN NOT NULL DEFAULT true;
 
+-- Per-user conversation pins. Existing owner pins on conversations.pinned_at are
+-- copied over once by the server at startup; that column is kept for now.
+ALTER TABLE conversation_reads ADD COLUMN IF NOT EXISTS pinned_at TIMESTAMP;
+CREATE INDEX IF NOT EXISTS idx_conversation_reads_pinned ON conversation_reads(user_id) WHERE pinned_at IS NOT NULL;
+
 COMMIT;
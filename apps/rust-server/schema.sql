@@ -108,6 +108,7 @@ CREATE TABLE conversations (
     title VARCHAR(200),
     user_id TEXT NOT NULL,
     agent_id UUID,
+    -- Legacy owner-wide pin, no longer written; per-user pins are conversation_reads.pinned_at
     pinned_at TIMESTAMP,
     created_at TIMESTAMP NOT NULL DEFAULT NOW(),
     updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
@@ -183,6 +184,7 @@ CREATE TABLE conversation_reads (
     last_read_seq INTEGER NOT NULL DEFAULT 0,
     updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
     muted BOOLEAN NOT NULL DEFAULT FALSE,
+    pinned_at TIMESTAMP,
     UNIQUE(user_id, conversation_id)
 );
 
@@ -673,6 +675,7 @@ CREATE INDEX idx_messages_conversation_created ON messages(conversation_id, crea
 CREATE INDEX idx_messages_thread ON messages(thread_id) WHERE thread_id IS NOT NULL;
 CREATE INDEX idx_thread_summaries_last ON thread_summaries(last_reply_at DESC);
 CREATE INDEX idx_conversation_reads_user_conv ON conversation_reads(user_id, conversation_id);
+CREATE INDEX idx_conversation_reads_pinned ON conversation_reads(user_id) WHERE pinned_at IS NOT NULL;
 CREATE INDEX idx_conversations_user ON conversations(user_id);
 CREATE INDEX idx_conversation_user_members_conv ON conversation_user_members(conversation_id);
 CREATE INDEX idx_conversation_user_members_user ON conversation_user_members(user_id);
@@ -1,5 +1,92 @@
 use std::env;
 
+/// Upper bound for per-conversation agent history windows.
+pub const MAX_HISTORY_LIMIT: i32 = 50;
+
+/// One entry of `CORS_ORIGIN`: an exact origin, or a wildcard subdomain
+/// pattern such as `https://*.arinova.ai` (scheme optional).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OriginPattern {
+    Exact(String),
+    Subdomain {
+        scheme: Option<String>,
+        /// Host (and optional port) after `*.`, lowercase.
+        suffix: String,
+    },
+}
+
+/// Resource caps for one sandbox runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SandboxLimits {
+    /// Wall-clock seconds before a run is killed.
+    pub timeout_secs: u64,
+    /// Memory ceiling in MiB.
+    pub memory_mb: u64,
+}
+
+impl SandboxLimits {
+    /// Read `SANDBOX_<LANG>_TIMEOUT_SECS` and `SANDBOX_<LANG>_MEMORY_MB`, falling back to `default`.
+    fn from_env(lang: &str, default: Self) -> Self {
+        let var = |name: &str| {
+            env::var(format!("SANDBOX_{}_{}", lang, name))
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|&n| n > 0)
+        };
+        Self {
+            timeout_secs: var("TIMEOUT_SECS").unwrap_or(default.timeout_secs),
+            memory_mb: var("MEMORY_MB").unwrap_or(default.memory_mb),
+        }
+    }
+}
+
+impl OriginPattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim().trim_end_matches('/');
+        if pattern.is_empty() {
+            return Err("empty origin".into());
+        }
+        let (scheme, host) = match pattern.split_once("://") {
+            Some((s, h)) => (Some(s.to_ascii_lowercase()), h),
+            None => (None, pattern),
+        };
+        let Some(suffix) = host.strip_prefix("*.") else {
+            if pattern.contains('*') {
+                return Err(format!("'{}': '*' is only allowed as the leading label", pattern));
+            }
+            return Ok(Self::Exact(pattern.to_string()));
+        };
+        let host_part = suffix.split(':').next().unwrap_or("");
+        if suffix.contains('*') || suffix.contains('/') || !host_part.contains('.') {
+            return Err(format!("'{}': wildcard needs a domain like *.example.com", pattern));
+        }
+        Ok(Self::Subdomain { scheme, suffix: suffix.to_ascii_lowercase() })
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Exact(o) => o == origin,
+            Self::Subdomain { scheme, suffix } => {
+                let Some((origin_scheme, host)) = origin.split_once("://") else {
+                    return false;
+                };
+                if scheme.as_deref().is_some_and(|s| !s.eq_ignore_ascii_case(origin_scheme)) {
+                    return false;
+                }
+                let host = host.to_ascii_lowercase();
+                let Some(sub) = host.strip_suffix(suffix.as_str()).and_then(|h| h.strip_suffix('.')) else {
+                    return false;
+                };
+                !sub.is_empty()
+                    && sub.split('.').all(|label| {
+                        !label.is_empty()
+                            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    })
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub port: u16,
@@ -34,16 +121,95 @@ pub struct Config {
     pub gemini_api_key: Option<String>,
     /// AES-256 key for encrypting user settings (hex-encoded 32-byte key).
     pub settings_encryption_key: Option<String>,
+    /// Version tag written into new ciphertexts (default: 1).
+    pub settings_encryption_key_version: u32,
+    /// Key for version `settings_encryption_key_version - 1`, kept readable during rotation.
+    pub settings_encryption_key_previous: Option<String>,
     /// TURN server shared secret for time-limited credentials.
     pub turn_secret: Option<String>,
     /// TURN server host (default: turn.arinova.ai).
     pub turn_host: String,
     /// Frontend URL for OAuth redirects (avoids relying on CORS_ORIGIN).
     pub frontend_url: Option<String>,
+    /// Default number of history messages sent to agents when a conversation has no override.
+    pub default_history_limit: i32,
+    /// Additional `provider/model` IDs accepted for marketplace listings on top of
+    /// the built-in allowlist in `services::llm`.
+    pub extra_llm_models: Vec<String>,
+    /// ISO currency code coins are displayed in (default: USD).
+    pub coin_currency: String,
+    /// Value of one coin in `coin_currency` (default: 0.01).
+    pub coin_to_currency_rate: f64,
+    /// Smallest non-zero fee, in coins, a creator may set for a paid action.
+    pub min_paid_action_coins: i32,
+    /// Extra words/phrases blocked in marketplace listings, on top of the built-in patterns.
+    pub content_blocklist: Vec<String>,
+    /// Reviews with at least this many flags are hidden from listing review lists.
+    pub review_flag_hide_threshold: i32,
+    /// Smallest amount, in coins, a user may withdraw in one payout request.
+    pub min_payout_coins: i32,
+    /// Shared secret used to verify payment provider top-up webhooks (HMAC-SHA256).
+    pub topup_webhook_secret: Option<String>,
+    /// Retries for transient OpenRouter failures before a stream starts (default: 3).
+    pub openrouter_max_retries: u32,
+    /// Base backoff delay in milliseconds, doubled per retry (default: 500).
+    pub openrouter_retry_base_ms: u64,
+    /// Most agents (listings plus directly added agents) a community may have (default: 20).
+    pub max_community_agents: i64,
+    /// Highest per-group user limit an admin may configure (default: 500).
+    pub group_max_users_ceiling: i32,
+    /// Highest per-group agent limit an admin may configure (default: 50).
+    pub group_max_agents_ceiling: i32,
+    /// Agent streams a single user may have running at once (default: 5).
+    pub max_concurrent_streams_per_user: usize,
+    /// Most files attached to a single message (default: 10).
+    pub max_attachments_per_message: usize,
+    /// Combined size in bytes of all files on one message (default: MAX_FILE_SIZE).
+    pub max_message_attachments_size: usize,
+    /// Remove EXIF metadata from uploaded JPEG/PNG images (default: on; STRIP_IMAGE_METADATA=false disables).
+    pub strip_image_metadata: bool,
+    /// Largest file accepted through chunked uploads, in bytes (default: 2 GiB).
+    pub max_chunked_upload_size: u64,
+    /// Outgoing friend requests a user may send per UTC day; 0 disables the cap (default: 50).
+    pub friend_request_daily_limit: u32,
+    /// Hours a sender must wait to re-request someone who rejected them (default: 72).
+    pub friend_request_reject_cooldown_hours: u64,
+    /// Longest chain of agents triggering each other via @mention (default: 3).
+    pub agent_mention_max_depth: u32,
+    /// Seconds before the same agent may @mention-trigger the same agent again (default: 30).
+    pub agent_mention_cooldown_secs: u64,
+    /// Postgres pool size (default: 50).
+    pub db_max_connections: u32,
+    /// Seconds an idle pooled connection is kept before closing (default: 60).
+    pub db_idle_timeout_secs: u64,
+    /// Seconds to wait for a free pooled connection before erroring (default: 30).
+    pub db_acquire_timeout_secs: u64,
+    /// Non-archived agent hub listings one creator may hold (default: 25).
+    pub max_active_listings_per_user: i64,
+    /// Agent hub listings one creator may create per rolling 24 hours (default: 10).
+    pub max_listings_created_per_day: i64,
+    /// Title untitled direct conversations from their first exchange (default: true).
+    pub auto_title_conversations: bool,
+    /// Give every buyer their first message to each paid agent hub listing for free,
+    /// before the listing's own trial (default: true).
+    pub marketplace_free_preview: bool,
+    /// Bearer token required to scrape `/metrics`; the endpoint is off when unset.
+    pub metrics_token: Option<String>,
+    /// Sandbox limits for Python runs (default: 30s, 512 MiB).
+    pub sandbox_python_limits: SandboxLimits,
+    /// Sandbox limits for Node runs (default: 30s, 512 MiB).
+    pub sandbox_node_limits: SandboxLimits,
+    /// Sandbox limits for Bash runs (default: 10s, 256 MiB).
+    pub sandbox_bash_limits: SandboxLimits,
 }
 
 impl Config {
     pub fn from_env() -> Self {
+        let max_file_size = env::var("MAX_FILE_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10 * 1024 * 1024);
+
         Self {
             port: env::var("PORT")
                 .ok()
@@ -61,10 +227,7 @@ impl Config {
             github_client_id: env::var("GITHUB_CLIENT_ID").unwrap_or_default(),
             github_client_secret: env::var("GITHUB_CLIENT_SECRET").unwrap_or_default(),
             upload_dir: env::var("UPLOAD_DIR").unwrap_or_else(|_| "./uploads".into()),
-            max_file_size: env::var("MAX_FILE_SIZE")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(10 * 1024 * 1024),
+            max_file_size,
             r2_endpoint: env::var("R2_ENDPOINT").unwrap_or_default(),
             r2_access_key_id: env::var("R2_ACCESS_KEY_ID").unwrap_or_default(),
             r2_secret_access_key: env::var("R2_SECRET_ACCESS_KEY").unwrap_or_default(),
@@ -86,9 +249,165 @@ impl Config {
             anthropic_api_key: env::var("ANTHROPIC_API_KEY").ok().filter(|s| !s.is_empty()),
             gemini_api_key: env::var("GEMINI_API_KEY").ok().filter(|s| !s.is_empty()),
             settings_encryption_key: env::var("SETTINGS_ENCRYPTION_KEY").ok().filter(|s| !s.is_empty()),
+            settings_encryption_key_version: env::var("SETTINGS_ENCRYPTION_KEY_VERSION")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .filter(|&v| v > 0)
+                .unwrap_or(1),
+            settings_encryption_key_previous: env::var("SETTINGS_ENCRYPTION_KEY_PREVIOUS").ok().filter(|s| !s.is_empty()),
             turn_secret: env::var("TURN_SECRET").ok().filter(|s| !s.is_empty()),
             turn_host: env::var("TURN_HOST").unwrap_or_else(|_| "turn.arinova.ai".into()),
             frontend_url: env::var("FRONTEND_URL").ok().filter(|s| !s.is_empty()),
+            default_history_limit: env::var("DEFAULT_HISTORY_LIMIT")
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .map(|v| v.clamp(0, MAX_HISTORY_LIMIT))
+                .unwrap_or(5),
+            extra_llm_models: env::var("EXTRA_LLM_MODELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            coin_currency: env::var("COIN_CURRENCY").unwrap_or_else(|_| "USD".into()),
+            coin_to_currency_rate: env::var("COIN_TO_CURRENCY_RATE")
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(0.01),
+            min_paid_action_coins: env::var("MIN_PAID_ACTION_COINS")
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .map(|v| v.max(1))
+                .unwrap_or(1),
+            content_blocklist: env::var("CONTENT_BLOCKLIST")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            review_flag_hide_threshold: env::var("REVIEW_FLAG_HIDE_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .map(|v| v.max(1))
+                .unwrap_or(5),
+            min_payout_coins: env::var("MIN_PAYOUT_COINS")
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .map(|v| v.max(1))
+                .unwrap_or(100),
+            topup_webhook_secret: env::var("TOPUP_WEBHOOK_SECRET").ok().filter(|s| !s.is_empty()),
+            openrouter_max_retries: env::var("OPENROUTER_MAX_RETRIES")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .map(|v| v.min(10))
+                .unwrap_or(3),
+            openrouter_retry_base_ms: env::var("OPENROUTER_RETRY_BASE_MS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(500),
+            max_community_agents: env::var("MAX_COMMUNITY_AGENTS")
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .map(|v| v.max(1))
+                .unwrap_or(20),
+            group_max_users_ceiling: env::var("GROUP_MAX_USERS_CEILING")
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .map(|v| v.max(1))
+                .unwrap_or(500),
+            group_max_agents_ceiling: env::var("GROUP_MAX_AGENTS_CEILING")
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .map(|v| v.max(1))
+                .unwrap_or(50),
+            max_concurrent_streams_per_user: env::var("MAX_CONCURRENT_STREAMS_PER_USER")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .map(|v| v.max(1))
+                .unwrap_or(5),
+            max_attachments_per_message: env::var("MAX_ATTACHMENTS_PER_MESSAGE")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .map(|v| v.max(1))
+                .unwrap_or(10),
+            max_message_attachments_size: env::var("MAX_MESSAGE_ATTACHMENTS_SIZE")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(max_file_size),
+            strip_image_metadata: env::var("STRIP_IMAGE_METADATA")
+                .map(|v| !matches!(v.trim().to_ascii_lowercase().as_str(), "false" | "0" | "off"))
+                .unwrap_or(true),
+            max_chunked_upload_size: env::var("MAX_CHUNKED_UPLOAD_SIZE")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(2 * 1024 * 1024 * 1024),
+            friend_request_daily_limit: env::var("FRIEND_REQUEST_DAILY_LIMIT")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .unwrap_or(50),
+            friend_request_reject_cooldown_hours: env::var("FRIEND_REQUEST_REJECT_COOLDOWN_HOURS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(72),
+            agent_mention_max_depth: env::var("AGENT_MENTION_MAX_DEPTH")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .unwrap_or(3),
+            agent_mention_cooldown_secs: env::var("AGENT_MENTION_COOLDOWN_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(30),
+            db_max_connections: env::var("DB_MAX_CONNECTIONS")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(50),
+            db_idle_timeout_secs: env::var("DB_IDLE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(60),
+            db_acquire_timeout_secs: env::var("DB_ACQUIRE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(30),
+            max_active_listings_per_user: env::var("MAX_ACTIVE_LISTINGS_PER_USER")
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .unwrap_or(25),
+            max_listings_created_per_day: env::var("MAX_LISTINGS_CREATED_PER_DAY")
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .unwrap_or(10),
+            auto_title_conversations: env::var("AUTO_TITLE_CONVERSATIONS")
+                .map(|v| !matches!(v.trim().to_ascii_lowercase().as_str(), "false" | "0" | "off"))
+                .unwrap_or(true),
+            marketplace_free_preview: env::var("MARKETPLACE_FREE_PREVIEW")
+                .map(|v| !matches!(v.trim().to_ascii_lowercase().as_str(), "false" | "0" | "off"))
+                .unwrap_or(true),
+            metrics_token: env::var("METRICS_TOKEN").ok().filter(|s| !s.is_empty()),
+            sandbox_python_limits: SandboxLimits::from_env(
+                "PYTHON",
+                SandboxLimits { timeout_secs: 30, memory_mb: 512 },
+            ),
+            sandbox_node_limits: SandboxLimits::from_env(
+                "NODE",
+                SandboxLimits { timeout_secs: 30, memory_mb: 512 },
+            ),
+            sandbox_bash_limits: SandboxLimits::from_env(
+                "BASH",
+                SandboxLimits { timeout_secs: 10, memory_mb: 256 },
+            ),
+        }
+    }
+
+    /// Limits for a runtime name as returned by `routes::sandbox::resolve_language`.
+    pub fn sandbox_limits(&self, language: &str) -> Option<SandboxLimits> {
+        match language {
+            "python" => Some(self.sandbox_python_limits),
+            "node" => Some(self.sandbox_node_limits),
+            "bash" => Some(self.sandbox_bash_limits),
+            _ => None,
         }
     }
 
@@ -102,10 +421,45 @@ impl Config {
         !self.vapid_public_key.is_empty() && !self.vapid_private_key.is_empty()
     }
 
+    /// Display value of `coins` in `coin_currency`.
+    pub fn coins_to_currency(&self, coins: i32) -> f64 {
+        coins as f64 * self.coin_to_currency_rate
+    }
+
+    /// Validate a creator-configured fee: free (0) or at least `min_paid_action_coins`.
+    /// `label` names the fee in the error, e.g. "Join fee".
+    pub fn validate_fee(&self, label: &str, fee: i32) -> Result<(), String> {
+        if fee < 0 {
+            return Err(format!("{} cannot be negative", label));
+        }
+        if fee > 0 && fee < self.min_paid_action_coins {
+            return Err(format!(
+                "{} must be 0 (free) or at least {} coins",
+                label, self.min_paid_action_coins
+            ));
+        }
+        Ok(())
+    }
+
     pub fn cors_origins(&self) -> Vec<String> {
         self.cors_origin
             .split(',')
             .map(|s| s.trim().to_string())
             .collect()
     }
+
+    /// `cors_origins()` compiled into patterns. Invalid entries are logged and skipped.
+    pub fn cors_origin_patterns(&self) -> Vec<OriginPattern> {
+        self.cors_origins()
+            .iter()
+            .filter(|o| !o.is_empty())
+            .filter_map(|o| match OriginPattern::parse(o) {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    tracing::warn!("Ignoring invalid CORS origin {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
 }
@@ -4,11 +4,14 @@ pub mod redis;
 use sqlx::postgres::{PgPool, PgPoolOptions};
 use std::time::Duration;
 
-pub async fn create_pool(database_url: &str) -> PgPool {
+use crate::config::Config;
+
+pub async fn create_pool(config: &Config) -> PgPool {
+    let database_url = &config.database_url;
     let options = PgPoolOptions::new()
-        .max_connections(50)
-        .idle_timeout(Duration::from_secs(60))
-        .acquire_timeout(Duration::from_secs(30));
+        .max_connections(config.db_max_connections)
+        .idle_timeout(Duration::from_secs(config.db_idle_timeout_secs))
+        .acquire_timeout(Duration::from_secs(config.db_acquire_timeout_secs));
 
     let mut last_err = None;
     for attempt in 1..=3 {
@@ -29,3 +32,37 @@ pub async fn create_pool(database_url: &str) -> PgPool {
         last_err.unwrap()
     );
 }
+
+/// `history_limit` used to be `NOT NULL DEFAULT 5`, so untouched conversations
+/// still hold 5. Clearing it lets them fall back to the configured default
+/// (30 for community agent chat) like conversations created since.
+pub const CLEAR_LEGACY_HISTORY_LIMIT: &str = "UPDATE conversations SET history_limit = NULL WHERE history_limit = 5";
+
+/// Pins used to be a single owner-wide `conversations.pinned_at`; they now live
+/// per user on `conversation_reads`. Copies each owner's legacy pin across. The
+/// old column is kept (and no longer written) so instances still reading it keep
+/// working during a rolling deploy; it can be dropped in a later release.
+pub const COPY_LEGACY_CONVERSATION_PINS: &str = r#"INSERT INTO conversation_reads (id, user_id, conversation_id, last_read_seq, pinned_at, updated_at)
+    SELECT gen_random_uuid(), c.user_id, c.id, 0, c.pinned_at, NOW()
+    FROM conversations c
+    WHERE c.pinned_at IS NOT NULL
+    ON CONFLICT (user_id, conversation_id) DO UPDATE SET pinned_at = EXCLUDED.pinned_at"#;
+
+/// Run a one-off data migration at most once per database. Applied names are
+/// recorded in `startup_migrations`; unlike the idempotent schema statements
+/// run at startup, seeds like these must not re-run and overwrite later edits.
+/// Returns whether the migration ran now.
+pub async fn run_migration_once(db: &PgPool, name: &str, sql: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let first = sqlx::query("INSERT INTO startup_migrations (name) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(name)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+        == 1;
+    if first {
+        sqlx::query(sql).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(first)
+}
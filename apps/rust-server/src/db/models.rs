@@ -176,6 +176,12 @@ pub struct Agent {
     pub token_refreshed_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// When the agent's WS connection last closed; `None` if it never connected.
+    pub last_seen_at: Option<NaiveDateTime>,
+    /// Client version declared in the most recent `agent_auth` handshake.
+    pub client_version: Option<String>,
+    /// Agent-hub listing whose knowledge base is searched for chat context.
+    pub kb_listing_id: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -189,6 +195,10 @@ pub struct Conversation {
     pub agent_id: Option<Uuid>,
     #[serde(rename = "mentionOnly")]
     pub mention_only: bool,
+    /// The requesting user's pin (`conversation_reads.pinned_at`), selected as
+    /// `user_pinned_at`. The legacy owner-wide `conversations.pinned_at` that
+    /// `SELECT *` returns is deliberately not read.
+    #[sqlx(rename = "user_pinned_at", default)]
     pub pinned_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
@@ -302,6 +312,7 @@ pub struct Attachment {
     pub duration_seconds: Option<i32>,
     pub width: Option<i32>,
     pub height: Option<i32>,
+    pub thumbnail_path: Option<String>,
     pub created_at: NaiveDateTime,
 }
 
@@ -316,6 +327,8 @@ pub struct PushSubscription {
     pub auth: String,
     pub device_info: Option<String>,
     pub created_at: NaiveDateTime,
+    /// Last successful delivery or re-subscribe; stale rows are pruned.
+    pub last_used_at: NaiveDateTime,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -330,6 +343,12 @@ pub struct NotificationPreference {
     pub quiet_hours_start: Option<String>,
     pub quiet_hours_end: Option<String>,
     pub always_push_mobile: bool,
+    pub mention_enabled: bool,
+    pub community_enabled: bool,
+    pub friend_request_enabled: bool,
+    pub voice_call_enabled: bool,
+    /// Minutes east of UTC that quiet hours are expressed in; `None` uses server local time.
+    pub quiet_hours_utc_offset: Option<i32>,
 }
 
 // ===== Community tables (Lounge + Hub) =====
@@ -22,3 +22,21 @@ pub fn create_redis_pool(redis_url: &str) -> Pool {
         last_err.unwrap()
     );
 }
+
+/// Delete the given keys, `UNLINK`ing them in batches so a large sweep never
+/// blocks Redis. Returns the number of keys that existed and were removed.
+pub async fn delete_keys(pool: &Pool, keys: &[String]) -> Result<usize, anyhow::Error> {
+    if keys.is_empty() {
+        return Ok(0);
+    }
+    let mut conn = pool.get().await?;
+    let mut removed = 0;
+    for batch in keys.chunks(500) {
+        let n: usize = deadpool_redis::redis::cmd("UNLINK")
+            .arg(batch)
+            .query_async(&mut conn)
+            .await?;
+        removed += n;
+    }
+    Ok(removed)
+}
@@ -1,10 +1,11 @@
 use axum::extract::DefaultBodyLimit;
 use std::net::SocketAddr;
 use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::EnvFilter;
 
-use arinova_server::{config, db, services, routes, ws, AppState};
+use arinova_server::{config, db, services, routes, utils, ws, AppState};
 
 #[tokio::main(worker_threads = 4)]
 async fn main() {
@@ -18,12 +19,14 @@ async fn main() {
         )
         .init();
 
+    services::metrics::install();
+
     // Load config
     let config = config::Config::from_env();
     let port = config.port;
 
     // Initialize database pool
-    let db = db::create_pool(&config.database_url).await;
+    let db = db::create_pool(&config).await;
     tracing::info!("PostgreSQL connected");
 
     // Run startup migrations (each statement individually so one failure doesn't block the rest)
@@ -800,6 +803,300 @@ async fn main() {
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_support_tickets_user ON support_tickets(user_id)").execute(&db).await.ok();
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_support_tickets_status ON support_tickets(status)").execute(&db).await.ok();
 
+    // Agent reasoning traces (capped, never sent back as history)
+    sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS reasoning TEXT").execute(&db).await.ok();
+
+    // history_limit NULL = fall back to Config::default_history_limit
+    sqlx::query("ALTER TABLE conversations ALTER COLUMN history_limit DROP NOT NULL").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE conversations ALTER COLUMN history_limit DROP DEFAULT").execute(&db).await.ok();
+
+    // Per-user opt-out from live agent stream events in a conversation
+    sqlx::query("ALTER TABLE conversation_reads ADD COLUMN IF NOT EXISTS agent_streams_muted BOOLEAN NOT NULL DEFAULT FALSE").execute(&db).await.ok();
+
+    // Conversation types a marketplace listing may be added to
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS allowed_contexts TEXT[] NOT NULL DEFAULT ARRAY['direct', 'group', 'community']").execute(&db).await.ok();
+    // Connected agent a listing sells; its group/community use follows the listing's contexts
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS agent_id UUID REFERENCES agents(id) ON DELETE SET NULL").execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_agent_listings_agent ON agent_listings(agent_id) WHERE agent_id IS NOT NULL").execute(&db).await.ok();
+
+    // Prior contents of edited messages, kept for auditability
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS message_edits (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+        message_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        previous_content TEXT NOT NULL,
+        edited_by TEXT NOT NULL,
+        edited_at TIMESTAMP NOT NULL DEFAULT NOW()
+    )"#).execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_message_edits_message ON message_edits(message_id, edited_at)").execute(&db).await.ok();
+
+    // Opt-in KB overview injected on the first turn of a marketplace conversation (cached)
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS kb_overview_enabled BOOLEAN NOT NULL DEFAULT FALSE").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS kb_overview TEXT").execute(&db).await.ok();
+
+    // Marketplace review replies + abuse flags
+    sqlx::query("ALTER TABLE agent_reviews ADD COLUMN IF NOT EXISTS creator_reply TEXT").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE agent_reviews ADD COLUMN IF NOT EXISTS creator_replied_at TIMESTAMP").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE agent_reviews ADD COLUMN IF NOT EXISTS flag_count INTEGER NOT NULL DEFAULT 0").execute(&db).await.ok();
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS agent_review_flags (
+        review_id UUID NOT NULL REFERENCES agent_reviews(id) ON DELETE CASCADE,
+        user_id TEXT NOT NULL,
+        reason TEXT,
+        created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (review_id, user_id)
+    )"#).execute(&db).await.ok();
+
+    // One review per user per listing; repeat submissions edit the existing review
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_agent_reviews_listing_user ON agent_reviews(listing_id, user_id)").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE agent_reviews ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP").execute(&db).await.ok();
+
+    // Wallet payout requests
+    sqlx::query(r#"DO $$ BEGIN
+        ALTER TYPE coin_transaction_type ADD VALUE IF NOT EXISTS 'payout_requested';
+    EXCEPTION WHEN duplicate_object THEN NULL;
+    END $$"#).execute(&db).await.ok();
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS payout_requests (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+        user_id TEXT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+        amount INTEGER NOT NULL CHECK (amount > 0),
+        status TEXT NOT NULL DEFAULT 'pending',
+        transaction_id UUID REFERENCES coin_transactions(id) ON DELETE SET NULL,
+        created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+        updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+    )"#).execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_payout_requests_user ON payout_requests(user_id, created_at DESC)").execute(&db).await.ok();
+
+    // Provider transaction IDs already credited by the top-up webhook
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS topup_webhook_events (
+        provider_tx_id TEXT PRIMARY KEY,
+        user_id TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        created_at TIMESTAMP NOT NULL DEFAULT NOW()
+    )"#).execute(&db).await.ok();
+
+    // Two-phase coin holds for in-flight agent calls
+    sqlx::query("ALTER TABLE coin_balances ADD COLUMN IF NOT EXISTS held INTEGER NOT NULL DEFAULT 0").execute(&db).await.ok();
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS coin_holds (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+        user_id TEXT NOT NULL,
+        amount INTEGER NOT NULL CHECK (amount > 0),
+        status TEXT NOT NULL DEFAULT 'held',
+        created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+        settled_at TIMESTAMP
+    )"#).execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_coin_holds_pending ON coin_holds(created_at) WHERE status = 'held'").execute(&db).await.ok();
+
+    // Ordered fallback models tried when a listing's primary model is unavailable
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS fallback_models TEXT[] NOT NULL DEFAULT '{}'").execute(&db).await.ok();
+
+    // Upstream serving each listing: OpenRouter, or Anthropic directly
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS model_provider TEXT NOT NULL DEFAULT 'openrouter'").execute(&db).await.ok();
+
+    // Creator-curated ordering of community agents
+    sqlx::query("ALTER TABLE community_agents ADD COLUMN IF NOT EXISTS sort_order INTEGER NOT NULL DEFAULT 0").execute(&db).await.ok();
+
+    // Optional expiry and usage limit for group invite links
+    sqlx::query("ALTER TABLE group_settings ADD COLUMN IF NOT EXISTS invite_expires_at TIMESTAMP").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE group_settings ADD COLUMN IF NOT EXISTS invite_max_uses INTEGER").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE group_settings ADD COLUMN IF NOT EXISTS invite_use_count INTEGER NOT NULL DEFAULT 0").execute(&db).await.ok();
+
+    // Admin approval gate for joining groups via invite
+    sqlx::query("ALTER TABLE group_settings ADD COLUMN IF NOT EXISTS approval_required BOOLEAN NOT NULL DEFAULT FALSE").execute(&db).await.ok();
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS group_join_requests (
+        conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+        user_id TEXT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+        status VARCHAR(20) NOT NULL DEFAULT 'pending',
+        created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+        decided_at TIMESTAMP,
+        decided_by TEXT,
+        PRIMARY KEY (conversation_id, user_id)
+    )"#).execute(&db).await.ok();
+
+    // Requests from group members to bring in an agent they don't own
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS group_agent_requests (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+        conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+        agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+        requested_by TEXT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+        owner_id TEXT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+        status VARCHAR(20) NOT NULL DEFAULT 'pending',
+        created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+        decided_at TIMESTAMP
+    )"#).execute(&db).await.ok();
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_group_agent_requests_pending ON group_agent_requests(conversation_id, agent_id) WHERE status = 'pending'").execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_group_agent_requests_owner ON group_agent_requests(owner_id) WHERE status = 'pending'").execute(&db).await.ok();
+
+    // Downscaled preview stored next to image attachments
+    sqlx::query("ALTER TABLE attachments ADD COLUMN IF NOT EXISTS thumbnail_path TEXT").execute(&db).await.ok();
+
+    // Granular notification types and quiet-hours timezone
+    sqlx::query("ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS mention_enabled BOOLEAN NOT NULL DEFAULT true").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS community_enabled BOOLEAN NOT NULL DEFAULT true").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS friend_request_enabled BOOLEAN NOT NULL DEFAULT true").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS voice_call_enabled BOOLEAN NOT NULL DEFAULT true").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS quiet_hours_utc_offset INTEGER").execute(&db).await.ok();
+
+    // Track push subscription use so dead ones can be pruned
+    sqlx::query("ALTER TABLE push_subscriptions ADD COLUMN IF NOT EXISTS last_used_at TIMESTAMP NOT NULL DEFAULT NOW()").execute(&db).await.ok();
+
+    // Per-conversation push mode: 'all' or 'mentions'
+    sqlx::query("ALTER TABLE conversation_reads ADD COLUMN IF NOT EXISTS push_mode TEXT NOT NULL DEFAULT 'all'").execute(&db).await.ok();
+
+    // Per-user app color theme: a built-in id or a custom token palette
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS user_themes (
+        user_id TEXT PRIMARY KEY REFERENCES "user"(id) ON DELETE CASCADE,
+        theme_id TEXT,
+        custom_name TEXT,
+        custom_tokens JSONB,
+        updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+        CHECK (theme_id IS NOT NULL OR custom_tokens IS NOT NULL)
+    )"#).execute(&db).await.ok();
+
+    // Agent WS connect/disconnect history for uptime reporting
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS agent_health_events (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+        agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+        event TEXT NOT NULL,
+        reason TEXT,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+    )"#).execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_agent_health_events_agent ON agent_health_events(agent_id, created_at)").execute(&db).await.ok();
+    // Connections did not survive the restart; close any agent still marked connected
+    sqlx::query(r#"INSERT INTO agent_health_events (agent_id, event, reason)
+        SELECT agent_id, 'disconnected', 'server restart' FROM (
+            SELECT DISTINCT ON (agent_id) agent_id, event
+            FROM agent_health_events
+            ORDER BY agent_id, created_at DESC
+        ) latest
+        WHERE latest.event = 'connected'"#).execute(&db).await.ok();
+
+    // Agent presence details shown when an agent is offline
+    sqlx::query("ALTER TABLE agents ADD COLUMN IF NOT EXISTS last_seen_at TIMESTAMP").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE agents ADD COLUMN IF NOT EXISTS client_version TEXT").execute(&db).await.ok();
+
+    // User privacy: username discoverability and who may start a DM
+    sqlx::query(r#"ALTER TABLE "user" ADD COLUMN IF NOT EXISTS discoverable_by_username BOOLEAN NOT NULL DEFAULT TRUE"#).execute(&db).await.ok();
+    sqlx::query(r#"ALTER TABLE "user" ADD COLUMN IF NOT EXISTS who_can_message TEXT NOT NULL DEFAULT 'friends'"#).execute(&db).await.ok();
+
+    // Source offsets of knowledge base chunks (character positions in the file)
+    sqlx::query("ALTER TABLE knowledge_base_chunks ADD COLUMN IF NOT EXISTS start_offset INTEGER").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE knowledge_base_chunks ADD COLUMN IF NOT EXISTS end_offset INTEGER").execute(&db).await.ok();
+
+    // Knowledge base (listing) an agent retrieves context from at chat time
+    sqlx::query("ALTER TABLE agents ADD COLUMN IF NOT EXISTS kb_listing_id UUID REFERENCES agent_listings(id) ON DELETE SET NULL").execute(&db).await.ok();
+
+    // Archived conversations are hidden from the default list and sync.
+    // Archive state is per user, so it lives on conversation_reads; move any
+    // owner-set value off the shared conversations row (no-op once dropped).
+    sqlx::query("ALTER TABLE conversation_reads ADD COLUMN IF NOT EXISTS archived_at TIMESTAMP").execute(&db).await.ok();
+    sqlx::query(r#"INSERT INTO conversation_reads (id, user_id, conversation_id, last_read_seq, archived_at, updated_at)
+        SELECT gen_random_uuid(), c.user_id, c.id, 0, c.archived_at, NOW()
+        FROM conversations c
+        WHERE c.archived_at IS NOT NULL
+        ON CONFLICT (user_id, conversation_id) DO UPDATE SET archived_at = EXCLUDED.archived_at"#).execute(&db).await.ok();
+    sqlx::query("ALTER TABLE conversations DROP COLUMN IF EXISTS archived_at").execute(&db).await.ok();
+
+    // Pins are per user too (legacy owner pins are copied over once, below)
+    sqlx::query("ALTER TABLE conversation_reads ADD COLUMN IF NOT EXISTS pinned_at TIMESTAMP").execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_conversation_reads_pinned ON conversation_reads(user_id) WHERE pinned_at IS NOT NULL").execute(&db).await.ok();
+
+    // Users and agents mentioned by each message (Mentions inbox)
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS message_mentions (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+        message_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        conversation_id UUID NOT NULL,
+        mentioned_user_id TEXT,
+        mentioned_agent_id UUID,
+        created_at TIMESTAMP NOT NULL DEFAULT NOW()
+    )"#).execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_message_mentions_user ON message_mentions(mentioned_user_id, created_at DESC)").execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_message_mentions_agent ON message_mentions(mentioned_agent_id, created_at DESC)").execute(&db).await.ok();
+
+    // Creator-supplied provider key for a listing (encrypted at rest)
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS api_key_encrypted TEXT").execute(&db).await.ok();
+
+    // Marketplace surfaces: editorial `featured` flag and 7-day sales counter for trending
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS featured BOOLEAN NOT NULL DEFAULT FALSE").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE agent_listings ADD COLUMN IF NOT EXISTS recent_sales INTEGER NOT NULL DEFAULT 0").execute(&db).await.ok();
+
+    // Messages queued by users for later delivery
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS scheduled_messages (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+        conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+        user_id TEXT NOT NULL,
+        content TEXT NOT NULL,
+        thread_id UUID,
+        send_at TIMESTAMP NOT NULL,
+        status VARCHAR(20) NOT NULL DEFAULT 'pending',
+        sent_at TIMESTAMP,
+        created_at TIMESTAMP NOT NULL DEFAULT NOW()
+    )"#).execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_scheduled_messages_due ON scheduled_messages(send_at) WHERE status = 'pending'").execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_scheduled_messages_conv ON scheduled_messages(conversation_id, user_id) WHERE status = 'pending'").execute(&db).await.ok();
+
+    // Agent reply versions: replies to the same user message by the same agent
+    sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS parent_message_id UUID").execute(&db).await.ok();
+    sqlx::query("ALTER TABLE messages ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1").execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_parent ON messages(parent_message_id, sender_agent_id) WHERE parent_message_id IS NOT NULL").execute(&db).await.ok();
+
+    // One-off data migrations already applied (see db::run_migration_once)
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS startup_migrations (
+        name TEXT PRIMARY KEY,
+        applied_at TIMESTAMP NOT NULL DEFAULT NOW()
+    )"#).execute(&db).await.ok();
+
+    // Per-buyer free-trial usage on agent hub listings. Seeded once from existing
+    // conversations so buyers who already used their trial don't get a new one.
+    sqlx::query(r#"CREATE TABLE IF NOT EXISTS marketplace_trial_usage (
+        listing_id UUID NOT NULL REFERENCES agent_listings(id) ON DELETE CASCADE,
+        user_id TEXT NOT NULL,
+        messages_used INTEGER NOT NULL DEFAULT 0,
+        created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+        updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (listing_id, user_id)
+    )"#).execute(&db).await.ok();
+    if let Err(e) = db::run_migration_once(&db, "seed_marketplace_trial_usage", r#"INSERT INTO marketplace_trial_usage (listing_id, user_id, messages_used)
+        SELECT mc.listing_id, mc.user_id, LEAST(SUM(mc.message_count), MAX(al.free_trial_messages))::int
+        FROM marketplace_conversations mc
+        JOIN agent_listings al ON al.id = mc.listing_id
+        WHERE al.free_trial_messages > 0 AND mc.message_count > 0
+        GROUP BY mc.listing_id, mc.user_id
+        ON CONFLICT (listing_id, user_id) DO NOTHING"#).await {
+        tracing::warn!("Seeding marketplace_trial_usage failed: {}", e);
+    }
+
+    // Free first-message preview per buyer and listing. Buyers who had already
+    // messaged a listing before the preview existed have had their first message
+    // (seeded once, so an admin reset sticks).
+    sqlx::query("ALTER TABLE marketplace_trial_usage ADD COLUMN IF NOT EXISTS preview_used BOOLEAN NOT NULL DEFAULT FALSE").execute(&db).await.ok();
+    if let Err(e) = db::run_migration_once(&db, "seed_marketplace_preview_used", r#"INSERT INTO marketplace_trial_usage (listing_id, user_id, preview_used)
+        SELECT DISTINCT listing_id, user_id FROM marketplace_conversations WHERE message_count > 0
+        ON CONFLICT (listing_id, user_id) DO UPDATE SET preview_used = TRUE
+        WHERE NOT marketplace_trial_usage.preview_used"#).await {
+        tracing::warn!("Seeding marketplace preview usage failed: {}", e);
+    }
+
+    // history_limit 5 was the old column default, not a user choice (see db::CLEAR_LEGACY_HISTORY_LIMIT)
+    if let Err(e) = db::run_migration_once(&db, "clear_legacy_history_limit", db::CLEAR_LEGACY_HISTORY_LIMIT).await {
+        tracing::warn!("Clearing legacy history_limit failed: {}", e);
+    }
+
+    // Owner pins on conversations.pinned_at move to conversation_reads once; the
+    // column stays for now (see db::COPY_LEGACY_CONVERSATION_PINS)
+    if let Err(e) = db::run_migration_once(&db, "copy_legacy_conversation_pins", db::COPY_LEGACY_CONVERSATION_PINS).await {
+        tracing::warn!("Copying legacy conversation pins failed: {}", e);
+    }
+
+    // Per-conversation message seq counter, allocated atomically by message_seq::get_next_seq
+    sqlx::query("ALTER TABLE conversations ADD COLUMN IF NOT EXISTS next_seq INTEGER NOT NULL DEFAULT 0").execute(&db).await.ok();
+    sqlx::query(r#"UPDATE conversations c SET next_seq = m.max_seq
+        FROM (SELECT conversation_id, MAX(seq) AS max_seq FROM messages GROUP BY conversation_id) m
+        WHERE m.conversation_id = c.id AND c.next_seq < m.max_seq"#).execute(&db).await.ok();
+
+    // Sync hot path: per-conversation last-message / missed-message lookups by seq,
+    // and the stream_resume lookup of a conversation's in-flight reply
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_conversation_seq ON messages(conversation_id, seq)").execute(&db).await.ok();
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_streaming ON messages(conversation_id, created_at) WHERE status = 'streaming'").execute(&db).await.ok();
+
     tracing::info!("Startup migrations completed");
 
     // Backfill Backlog + Review columns for existing kanban boards
@@ -837,20 +1134,25 @@ async fn main() {
     }
 
     // Clean up stuck streaming messages from previous run
-    match sqlx::query(
+    let interrupted_streams = match sqlx::query_scalar::<_, String>(
         r#"UPDATE messages SET status = 'error', content = CASE WHEN content = '' THEN 'Stream interrupted by server restart' ELSE content END, updated_at = NOW()
-           WHERE status = 'streaming'"#,
+           WHERE status = 'streaming'
+           RETURNING id::text"#,
     )
-    .execute(&db)
+    .fetch_all(&db)
     .await
     {
-        Ok(result) => {
-            if result.rows_affected() > 0 {
-                tracing::info!("Cleaned up {} stuck streaming messages", result.rows_affected());
+        Ok(ids) => {
+            if !ids.is_empty() {
+                tracing::info!("Cleaned up {} stuck streaming messages", ids.len());
             }
+            ids
         }
-        Err(e) => tracing::warn!("Failed to clean up stuck streaming messages: {}", e),
-    }
+        Err(e) => {
+            tracing::warn!("Failed to clean up stuck streaming messages: {}", e);
+            Vec::new()
+        }
+    };
 
     // Reset stuck extracting capsules from previous run
     match sqlx::query(
@@ -871,6 +1173,15 @@ async fn main() {
     let redis = db::redis::create_redis_pool(&config.redis_url);
     tracing::info!("Redis pool created");
 
+    // Drop the stream caches of just the messages marked failed above; other
+    // instances sharing this Redis may have live streams of their own
+    let stream_keys: Vec<String> = interrupted_streams.iter().map(|id| format!("stream:{}", id)).collect();
+    match db::redis::delete_keys(&redis, &stream_keys).await {
+        Ok(n) if n > 0 => tracing::info!("Removed {} orphaned stream cache keys", n),
+        Ok(_) => {}
+        Err(e) => tracing::warn!("Failed to clear orphaned stream cache keys: {}", e),
+    }
+
     // Initialize S3 client for R2
     let s3 = services::r2::create_s3_client(&config);
     if s3.is_some() {
@@ -879,6 +1190,19 @@ async fn main() {
 
     // Create WebSocket state
     let ws_state = ws::state::WsState::new();
+    match sqlx::query_as::<_, (String, String)>(
+        "SELECT conversation_id::text, user_id FROM conversation_reads WHERE agent_streams_muted = TRUE",
+    )
+    .fetch_all(&db)
+    .await
+    {
+        Ok(rows) => {
+            for (conv_id, user_id) in rows {
+                ws_state.set_agent_stream_optout(&conv_id, &user_id, true);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load agent stream opt-outs: {}", e),
+    }
 
     // Create Office state + start periodic tick loop
     let office_state = services::office::OfficeState::new();
@@ -894,6 +1218,108 @@ async fn main() {
     }
     tracing::info!("Office state initialized");
 
+    // Release coin holds whose request died before settling them
+    {
+        let db = db.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                match services::wallet::release_stale_holds(&db, services::wallet::HOLD_TIMEOUT_SECS).await {
+                    Ok(0) => {}
+                    Ok(n) => tracing::info!("Released {} stale coin holds", n),
+                    Err(e) => tracing::warn!("Release stale coin holds failed: {}", e),
+                }
+            }
+        });
+    }
+
+    // Prune push subscriptions that have not been used in months
+    {
+        let db = db.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(86400));
+            loop {
+                interval.tick().await;
+                match services::push::prune_stale_subscriptions(&db, services::push::STALE_SUBSCRIPTION_DAYS).await {
+                    Ok(0) => {}
+                    Ok(n) => tracing::info!("Pruned {} stale push subscriptions", n),
+                    Err(e) => tracing::warn!("Prune stale push subscriptions failed: {}", e),
+                }
+            }
+        });
+    }
+
+    // Embed knowledge base chunks stored without vectors
+    if config.openai_api_key.is_some() {
+        let db = db.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(86400));
+            loop {
+                interval.tick().await;
+                match services::embedding::backfill_missing_embeddings(&db, &config).await {
+                    Ok(0) => {}
+                    Ok(n) => tracing::info!("Backfilled embeddings for {} KB chunks", n),
+                    Err(e) => tracing::warn!("KB embedding backfill failed: {:?}", e),
+                }
+            }
+        });
+    }
+
+    // Send scheduled messages once they are due
+    {
+        let ws = ws_state.clone();
+        let db = db.clone();
+        let redis = redis.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(10));
+            loop {
+                interval.tick().await;
+                if ws.is_shutting_down() {
+                    continue;
+                }
+                match routes::scheduled_messages::dispatch_due(&ws, &db, &redis, &config).await {
+                    Ok(0) => {}
+                    Ok(n) => tracing::info!("Sent {} scheduled messages", n),
+                    Err(e) => tracing::warn!("Scheduled message dispatch failed: {}", e),
+                }
+            }
+        });
+    }
+
+    // Refresh the recent sales counter behind trending listings
+    {
+        let db = db.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(900));
+            loop {
+                interval.tick().await;
+                if let Err(e) = routes::agent_hub::refresh_recent_sales(&db).await {
+                    tracing::warn!("Refresh listing recent sales failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // Remove temp files left behind by abandoned chunked uploads
+    {
+        let upload_dir = config.upload_dir.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                let n = services::chunked_upload::sweep_stale_temp_files(&upload_dir).await;
+                if n > 0 {
+                    tracing::info!("Removed {} stale chunked upload files", n);
+                }
+            }
+        });
+    }
+
+    let shutdown_ws = ws_state.clone();
+
     // Build application state
     let state = AppState {
         db,
@@ -915,16 +1341,24 @@ async fn main() {
             .allow_origin(AllowOrigin::mirror_request())
             .allow_methods(AllowMethods::mirror_request())
             .allow_headers(AllowHeaders::mirror_request())
+            .expose_headers([axum::http::HeaderName::from_static(utils::api_error::REQUEST_ID_HEADER)])
             .allow_credentials(true)
     } else {
-        let origins: Vec<axum::http::HeaderValue> = cors_origins
-            .iter()
-            .filter_map(|o| o.parse().ok())
-            .collect();
+        // Exact origins and `*.domain` patterns, matched per request
+        let patterns = config.cors_origin_patterns();
         CorsLayer::new()
-            .allow_origin(AllowOrigin::list(origins))
+            .allow_origin(AllowOrigin::predicate(move |origin, _| {
+                let allowed = origin
+                    .to_str()
+                    .is_ok_and(|o| patterns.iter().any(|p| p.matches(o)));
+                if !allowed {
+                    tracing::debug!("CORS origin rejected: {:?}", origin);
+                }
+                allowed
+            }))
             .allow_methods(AllowMethods::mirror_request())
             .allow_headers(AllowHeaders::mirror_request())
+            .expose_headers([axum::http::HeaderName::from_static(utils::api_error::REQUEST_ID_HEADER)])
             .allow_credentials(true)
     };
 
@@ -934,9 +1368,30 @@ async fn main() {
         .merge(ws::agent_handler::router())
         .merge(ws::voice_handler::router())
         .with_state(state)
-        .layer(DefaultBodyLimit::max(config.max_file_size))
+        .layer(DefaultBodyLimit::max(
+            config
+                .max_file_size
+                .max(config.max_message_attachments_size)
+                .max(services::chunked_upload::MAX_CHUNK_SIZE as usize),
+        ))
         .layer(cors)
-        .layer(TraceLayer::new_for_http());
+        .layer(axum::middleware::from_fn(utils::api_error::enrich_error_body))
+        .layer(axum::middleware::from_fn(services::metrics::track_http))
+        .layer(TraceLayer::new_for_http().make_span_with(|req: &axum::http::Request<_>| {
+            let request_id = req
+                .headers()
+                .get(utils::api_error::REQUEST_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("");
+            tracing::info_span!(
+                "request",
+                method = %req.method(),
+                uri = %req.uri(),
+                request_id = %request_id,
+            )
+        }))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
     // Start server
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
@@ -946,6 +1401,49 @@ async fn main() {
         .await
         .unwrap_or_else(|e| panic!("Failed to bind TCP listener on {addr}: {e}"));
     axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(shutdown_ws))
         .await
         .unwrap_or_else(|e| panic!("Server error: {e}"));
+    tracing::info!("Server stopped");
+}
+
+/// How long shutdown waits for active agent streams to finalize.
+const SHUTDOWN_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Resolve on SIGTERM/SIGINT after asking active streams to persist what they
+/// have and waiting (up to `SHUTDOWN_DRAIN_TIMEOUT`) for them to finish.
+async fn shutdown_signal(ws_state: ws::state::WsState) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.ok();
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    let signalled = ws_state.drain_streams();
+    tracing::info!("Shutdown requested, draining {} active streams", signalled);
+
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_DRAIN_TIMEOUT;
+    // Wait for the stream tasks themselves, not their cancellers: a task drops
+    // its canceller before it has written the partial content to the DB
+    while ws_state.active_stream_tasks() > 0 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let remaining = ws_state.active_stream_tasks();
+    if remaining > 0 {
+        tracing::warn!("Shutdown drain timed out with {} streams still active", remaining);
+    }
 }
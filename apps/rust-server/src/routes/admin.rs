@@ -18,13 +18,17 @@ pub fn router() -> Router<AppState> {
         .route("/api/admin/stats", get(stats))
         .route("/api/admin/users", get(list_users))
         .route("/api/admin/users/{id}/verify", patch(set_verify))
+        .route("/api/admin/agent-listings/{id}/featured", patch(set_listing_featured))
+        .route("/api/admin/agent-listings/{id}/trial-usage/{userId}", delete(reset_trial_usage))
         .route("/api/admin/users/{id}/ban", post(ban_user))
         .route("/api/admin/users/{id}/unban", post(unban_user))
         .route("/api/admin/backfill-embeddings", post(backfill_embeddings))
+        .route("/api/admin/reencrypt-keys", post(reencrypt_keys))
         .route("/api/admin/users/{id}", get(get_user_detail))
         .route("/api/admin/stats/trends", get(stats_trends))
         .route("/api/admin/messages", get(search_messages))
         .route("/api/admin/messages/{id}", delete(delete_message_admin))
+        .route("/api/admin/conversations/{id}/reset-streams", post(reset_conversation_streams))
         .route("/api/admin/audit-logs", get(list_audit_logs))
         .route("/api/admin/maintenance", get(get_maintenance).post(toggle_maintenance))
         .route("/api/admin/agents", get(list_agents))
@@ -34,6 +38,7 @@ pub fn router() -> Router<AppState> {
         .route("/api/admin/content-filters/{id}", delete(delete_content_filter))
         .route("/api/admin/feature-flags", get(list_feature_flags).post(upsert_feature_flag))
         .route("/api/admin/health", get(server_health))
+        .route("/api/metrics/db", get(db_metrics))
         .route("/api/admin/stats/revenue", get(stats_revenue))
         .route("/api/admin/support-tickets", get(list_support_tickets))
         .route("/api/admin/support-tickets/{id}/reply", post(reply_support_ticket))
@@ -415,6 +420,80 @@ async fn set_verify(
     }
 }
 
+// ── Agent hub listings ─────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct SetFeaturedBody {
+    featured: bool,
+}
+
+/// PATCH /api/admin/agent-listings/:id/featured — Toggle marketplace featured flag
+async fn set_listing_featured(
+    State(state): State<AppState>,
+    admin: AuthAdmin,
+    Path(listing_id): Path<uuid::Uuid>,
+    Json(body): Json<SetFeaturedBody>,
+) -> Response {
+    let result = sqlx::query(
+        "UPDATE agent_listings SET featured = $1, updated_at = NOW() WHERE id = $2",
+    )
+    .bind(body.featured)
+    .bind(listing_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            let target = listing_id.to_string();
+            audit(&state.db, &admin.email, if body.featured { "feature_listing" } else { "unfeature_listing" }, Some(&target), None).await;
+            Json(json!({"success": true, "featured": body.featured})).into_response()
+        }
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Listing not found"})),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
+/// DELETE /api/admin/agent-listings/:id/trial-usage/:userId — Give a buyer their free trial back
+async fn reset_trial_usage(
+    State(state): State<AppState>,
+    admin: AuthAdmin,
+    Path((listing_id, user_id)): Path<(uuid::Uuid, String)>,
+) -> Response {
+    let result = sqlx::query(
+        "DELETE FROM marketplace_trial_usage WHERE listing_id = $1 AND user_id = $2",
+    )
+    .bind(listing_id)
+    .bind(&user_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            let target = listing_id.to_string();
+            audit(&state.db, &admin.email, "reset_trial_usage", Some(&target), Some(json!({"userId": user_id}))).await;
+            Json(json!({"success": true})).into_response()
+        }
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "No trial usage for this buyer"})),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
 // ── Ban / Unban ───────────────────────────────────────────────────────
 
 /// POST /api/admin/users/:id/ban — Ban a user
@@ -473,6 +552,111 @@ async fn set_banned(state: &AppState, user_id: &str, banned: bool) -> Response {
     }
 }
 
+// ── Re-encrypt stored API keys ────────────────────────────────────────
+
+/// POST /api/admin/reencrypt-keys
+/// Rewrite stored user-settings and listing API keys with the current
+/// encryption key version. Run after rotating SETTINGS_ENCRYPTION_KEY, before
+/// dropping SETTINGS_ENCRYPTION_KEY_PREVIOUS.
+async fn reencrypt_keys(
+    State(state): State<AppState>,
+    admin: AuthAdmin,
+) -> Response {
+    use crate::routes::user_settings::{needs_reencrypt, reencrypt_api_key};
+
+    if state.config.settings_encryption_key.is_none() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"error": "SETTINGS_ENCRYPTION_KEY not configured"})),
+        )
+            .into_response();
+    }
+
+    let user_keys = sqlx::query_as::<_, (String, String)>(
+        "SELECT user_id, gemini_api_key FROM user_settings WHERE gemini_api_key IS NOT NULL AND gemini_api_key <> ''",
+    )
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+    let listing_keys = sqlx::query_as::<_, (uuid::Uuid, String)>(
+        "SELECT id, api_key_encrypted FROM agent_listings WHERE api_key_encrypted IS NOT NULL",
+    )
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    let (mut updated, mut failed) = (0u32, 0u32);
+    for (user_id, stored) in user_keys.iter().filter(|(_, k)| needs_reencrypt(&state.config, k)) {
+        match reencrypt_api_key(&state.config, stored) {
+            Ok(value) => {
+                // Only swap the value we read, so a key changed meanwhile isn't overwritten
+                let result = sqlx::query(
+                    "UPDATE user_settings SET gemini_api_key = $2 WHERE user_id = $1 AND gemini_api_key = $3",
+                )
+                    .bind(user_id)
+                    .bind(&value)
+                    .bind(stored)
+                    .execute(&state.db)
+                    .await;
+                match result {
+                    Ok(r) if r.rows_affected() > 0 => updated += 1,
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::warn!("Re-encrypt user settings key for {}: update failed: {}", user_id, e);
+                        failed += 1;
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Re-encrypt user settings key for {} failed: {}", user_id, e);
+                failed += 1;
+            }
+        }
+    }
+    for (listing_id, stored) in listing_keys.iter().filter(|(_, k)| needs_reencrypt(&state.config, k)) {
+        match reencrypt_api_key(&state.config, stored) {
+            Ok(value) => {
+                let result = sqlx::query(
+                    "UPDATE agent_listings SET api_key_encrypted = $2 WHERE id = $1 AND api_key_encrypted = $3",
+                )
+                    .bind(listing_id)
+                    .bind(&value)
+                    .bind(stored)
+                    .execute(&state.db)
+                    .await;
+                match result {
+                    Ok(r) if r.rows_affected() > 0 => updated += 1,
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::warn!("Re-encrypt listing key for {}: update failed: {}", listing_id, e);
+                        failed += 1;
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Re-encrypt listing key for {} failed: {}", listing_id, e);
+                failed += 1;
+            }
+        }
+    }
+
+    audit(
+        &state.db,
+        &admin.email,
+        "reencrypt_keys",
+        None,
+        Some(json!({"updated": updated, "failed": failed})),
+    )
+    .await;
+
+    Json(json!({
+        "updated": updated,
+        "failed": failed,
+        "keyVersion": state.config.settings_encryption_key_version,
+    }))
+    .into_response()
+}
+
 // ── Backfill agent memory embeddings ──────────────────────────────────
 
 /// POST /api/admin/backfill-embeddings
@@ -718,6 +902,98 @@ async fn delete_message_admin(
     }
 }
 
+/// POST /api/admin/conversations/:id/reset-streams — finalize messages stuck
+/// in `streaming` (keeping any Redis-cached content) and clear stream state.
+async fn reset_conversation_streams(
+    State(state): State<AppState>,
+    admin: AuthAdmin,
+    Path(id): Path<uuid::Uuid>,
+) -> Response {
+    use deadpool_redis::redis::AsyncCommands;
+
+    let conversation_id = id.to_string();
+    let stuck = match sqlx::query_as::<_, (uuid::Uuid, i32, Option<uuid::Uuid>, String)>(
+        "SELECT id, seq, sender_agent_id, content FROM messages WHERE conversation_id = $1 AND status = 'streaming'",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))).into_response(),
+    };
+
+    let member_ids = crate::ws::handler::get_conv_member_ids(&state.ws, &state.db, &conversation_id, "").await;
+    let mut reset = Vec::new();
+    // Stream keys whose task is still alive; it finalizes and drains its own queue
+    let mut signalled = std::collections::HashSet::new();
+    for (msg_id, seq, agent_id, content) in &stuck {
+        let key = msg_id.to_string();
+        let mut cached: Option<String> = None;
+        if let Ok(mut conn) = state.redis.get().await {
+            cached = conn.get(format!("stream:{}", key)).await.ok().flatten();
+            let _: Result<(), _> = conn.del(format!("stream:{}", key)).await;
+        }
+        let final_content = cached.unwrap_or_else(|| content.clone());
+        let status = if final_content.is_empty() { "error" } else { "completed" };
+        if let Err(e) = sqlx::query(
+            r#"UPDATE messages SET content = CASE WHEN $1 = '' THEN 'Stream reset by admin' ELSE $1 END,
+                   status = $2::message_status, updated_at = NOW()
+               WHERE id = $3 AND status = 'streaming'"#,
+        )
+        .bind(&final_content)
+        .bind(status)
+        .bind(msg_id)
+        .execute(&state.db)
+        .await
+        {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))).into_response();
+        }
+
+        if state.ws.cancel_stream(&key) {
+            if let Some(agent_id) = agent_id {
+                signalled.insert(format!("{}:{}", conversation_id, agent_id));
+            }
+        }
+
+        state.ws.broadcast_to_members(&member_ids, &json!({
+            "type": "stream_end",
+            "conversationId": &conversation_id,
+            "messageId": &key,
+            "seq": seq,
+            "senderAgentId": agent_id,
+            "reason": "reset",
+        }), &state.redis);
+        reset.push(key);
+    }
+
+    // Active stream keys are "{conversation_id}:{agent_id}"; start whatever
+    // was queued behind the streams nobody is left to finish
+    let prefix = format!("{}:", conversation_id);
+    state.ws.active_streams.retain(|k, _| !k.starts_with(&prefix) || signalled.contains(k));
+    let queued: Vec<String> = state
+        .ws
+        .agent_response_queues
+        .iter()
+        .map(|e| e.key().clone())
+        .filter(|k| k.starts_with(&prefix) && !signalled.contains(k))
+        .collect();
+    for key in &queued {
+        crate::ws::handler::process_next_in_queue(key, &state.ws, &state.db, &state.redis, &state.config);
+    }
+
+    audit(
+        &state.db,
+        &admin.email,
+        "reset_conversation_streams",
+        Some(&conversation_id),
+        Some(json!({"messageIds": &reset})),
+    )
+    .await;
+
+    Json(json!({"reset": reset.len(), "messageIds": reset})).into_response()
+}
+
 // ── Audit logs ────────────────────────────────────────────────────────
 
 #[derive(Deserialize)]
@@ -951,6 +1227,24 @@ async fn server_health(
     })).into_response()
 }
 
+/// GET /api/metrics/db — Postgres connection pool usage
+async fn db_metrics(
+    State(state): State<AppState>,
+    _admin: AuthAdmin,
+) -> Response {
+    let size = state.db.size();
+    let idle = state.db.num_idle() as u32;
+
+    Json(json!({
+        "maxConnections": state.config.db_max_connections,
+        "size": size,
+        "idle": idle,
+        "inUse": size.saturating_sub(idle),
+        "idleTimeoutSecs": state.config.db_idle_timeout_secs,
+        "acquireTimeoutSecs": state.config.db_acquire_timeout_secs,
+    })).into_response()
+}
+
 // ── Revenue analytics ─────────────────────────────────────────────────
 
 /// GET /api/admin/stats/revenue — Revenue analytics
@@ -1,12 +1,15 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Json, Response},
     routing::get,
     Router,
 };
+use chrono::{DateTime, Duration, Utc};
 use deadpool_redis::redis::AsyncCommands;
+use serde::Deserialize;
 use serde_json::json;
+use sqlx::PgPool;
 
 use crate::auth::middleware::AuthUser;
 use crate::AppState;
@@ -14,6 +17,7 @@ use crate::AppState;
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/agents/{id}/health", get(check_agent_health))
+        .route("/api/agents/{id}/health/history", get(agent_health_history))
         .route("/api/agents/health", get(check_all_agents_health))
 }
 
@@ -172,3 +176,187 @@ async fn check_a2a_health_cached(
 
     (Some(reachable), Some(elapsed))
 }
+
+// ---------------------------------------------------------------------------
+// Connection history
+// ---------------------------------------------------------------------------
+
+/// Longest window the history endpoint reports on.
+const MAX_HISTORY_RANGE_DAYS: i64 = 30;
+/// Most timeline events returned; uptime always covers the whole window.
+const MAX_HISTORY_EVENTS: i64 = 500;
+
+/// Record an agent WS connect (`online = true`) or disconnect transition.
+pub async fn record_health_event(db: &PgPool, agent_id: &str, online: bool, reason: Option<&str>) {
+    let result = sqlx::query(
+        "INSERT INTO agent_health_events (agent_id, event, reason) VALUES ($1::uuid, $2, $3)",
+    )
+    .bind(agent_id)
+    .bind(if online { "connected" } else { "disconnected" })
+    .bind(reason)
+    .execute(db)
+    .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to record health event for agent {}: {}", agent_id, e);
+    }
+}
+
+/// Parse a history range such as "24h" or "7d", capped at 30 days.
+pub fn parse_history_range(range: &str) -> Option<Duration> {
+    let range = range.trim();
+    let unit = range.chars().last()?;
+    let n: i64 = range[..range.len() - unit.len_utf8()].parse().ok().filter(|n| *n > 0)?;
+    let duration = match unit {
+        'h' => Duration::hours(n),
+        'd' => Duration::days(n),
+        _ => return None,
+    };
+    (duration <= Duration::days(MAX_HISTORY_RANGE_DAYS)).then_some(duration)
+}
+
+/// Percentage of `from..to` the agent spent online, aggregated over every
+/// transition in the window. Each event opens a span that lasts until the next
+/// one (or `to`); the span starting at `from` carries the state left by the
+/// last earlier transition.
+pub async fn window_uptime_percent(
+    db: &PgPool,
+    agent_id: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<f64, sqlx::Error> {
+    let total = (to - from).num_milliseconds() as f64 / 1000.0;
+    let online_secs = sqlx::query_scalar::<_, f64>(
+        r#"WITH transitions AS (
+               SELECT $2::timestamptz AS at, 0 AS ord, COALESCE((
+                   SELECT event = 'connected' FROM agent_health_events
+                   WHERE agent_id = $1::uuid AND created_at < $2
+                   ORDER BY created_at DESC LIMIT 1
+               ), false) AS online
+               UNION ALL
+               SELECT created_at, 1, event = 'connected' FROM agent_health_events
+               WHERE agent_id = $1::uuid AND created_at >= $2 AND created_at < $3
+           ),
+           spans AS (
+               SELECT online, at, LEAD(at, 1, $3::timestamptz) OVER (ORDER BY at, ord) AS until
+               FROM transitions
+           )
+           SELECT COALESCE(EXTRACT(EPOCH FROM SUM(until - at) FILTER (WHERE online)), 0)::float8
+           FROM spans"#,
+    )
+    .bind(agent_id)
+    .bind(from)
+    .bind(to)
+    .fetch_one(db)
+    .await?;
+
+    if total <= 0.0 {
+        return Ok(0.0);
+    }
+    Ok((online_secs / total * 100.0).clamp(0.0, 100.0))
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    range: Option<String>,
+}
+
+/// GET /api/agents/{id}/health/history?range=24h — Uptime and connect/disconnect
+/// timeline for an owned agent.
+async fn agent_health_history(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(q): Query<HistoryQuery>,
+) -> Response {
+    let range = q.range.unwrap_or_else(|| "24h".into());
+    let Some(window) = parse_history_range(&range) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "range must look like 24h or 7d (max 30d)"})),
+        )
+            .into_response();
+    };
+
+    let owned = sqlx::query_scalar::<_, String>(
+        "SELECT id::text FROM agents WHERE id = $1::uuid AND owner_id = $2",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await;
+    let agent_id = match owned {
+        Ok(Some(a)) => a,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Agent not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let to = Utc::now();
+    let from = to - window;
+
+    let events = match sqlx::query_as::<_, (String, Option<String>, DateTime<Utc>)>(
+        r#"SELECT event, reason, created_at FROM agent_health_events
+           WHERE agent_id = $1::uuid AND created_at >= $2
+           ORDER BY created_at ASC
+           LIMIT $3"#,
+    )
+    .bind(&agent_id)
+    .bind(from)
+    .bind(MAX_HISTORY_EVENTS)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let uptime = match window_uptime_percent(&state.db, &agent_id, from, to).await {
+        Ok(u) => u,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let timeline: Vec<serde_json::Value> = events
+        .iter()
+        .map(|(event, reason, at)| {
+            json!({
+                "event": event,
+                "reason": reason,
+                "at": at.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    Json(json!({
+        "agentId": agent_id,
+        "range": range,
+        "from": from.to_rfc3339(),
+        "to": to.to_rfc3339(),
+        "uptimePercent": (uptime * 100.0).round() / 100.0,
+        "online": state.ws.is_agent_connected(&agent_id),
+        "events": timeline,
+    }))
+    .into_response()
+}
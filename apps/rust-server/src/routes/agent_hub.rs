@@ -6,11 +6,15 @@ use axum::{
     Router,
 };
 use chrono::NaiveDateTime;
-use serde::Deserialize;
+use regex_lite::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::sync::{LazyLock, Mutex};
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
+use crate::services::{billing, llm, openrouter};
+use crate::utils::pagination::PageWindow;
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
@@ -21,11 +25,22 @@ pub fn router() -> Router<AppState> {
             get(get_detail).put(update_listing).delete(archive_listing),
         )
         .route("/api/agent-hub/agents/{id}/manage", get(manage_detail))
+        .route("/api/agent-hub/agents/{id}/reactivate", post(reactivate_listing))
+        .route("/api/agent-hub/agents/{id}/rotate-key", post(rotate_key))
         .route(
             "/api/agent-hub/agents/{id}/reviews",
             post(create_review).get(list_reviews),
         )
+        .route(
+            "/api/agent-hub/agents/{id}/reviews/{review_id}/reply",
+            post(reply_review),
+        )
+        .route(
+            "/api/agent-hub/agents/{id}/reviews/{review_id}/flag",
+            post(flag_review),
+        )
         .route("/api/agent-hub/manage", get(my_listings))
+        .route("/api/agent-hub/categories", get(list_categories))
 }
 
 // ---------------------------------------------------------------------------
@@ -51,6 +66,8 @@ struct ListingRow {
     total_messages: i32,
     total_revenue: i32,
     example_conversations: Value,
+    allowed_contexts: Vec<String>,
+    agent_id: Option<Uuid>,
     created_at: NaiveDateTime,
     updated_at: NaiveDateTime,
 }
@@ -75,11 +92,14 @@ struct ListingDetailRow {
     total_messages: i32,
     total_revenue: i32,
     example_conversations: Value,
+    allowed_contexts: Vec<String>,
     created_at: NaiveDateTime,
     updated_at: NaiveDateTime,
     creator_name: Option<String>,
     creator_username: Option<String>,
     creator_is_verified: bool,
+    featured: bool,
+    recent_sales: i32,
 }
 
 /// Creator manage view — includes system_prompt, no creator join.
@@ -103,6 +123,10 @@ struct ManageListingRow {
     total_messages: i32,
     total_revenue: i32,
     example_conversations: Value,
+    allowed_contexts: Vec<String>,
+    kb_overview_enabled: bool,
+    fallback_models: Vec<String>,
+    model_provider: String,
     created_at: NaiveDateTime,
     updated_at: NaiveDateTime,
 }
@@ -128,7 +152,9 @@ fn listing_row_to_json(r: &ListingRow) -> Value {
         "reviewCount": r.review_count,
         "totalMessages": r.total_messages,
         "totalRevenue": r.total_revenue,
-        "exampleConversations": r.example_conversations,
+        "exampleConversations": stored_example_conversations(&r.example_conversations),
+        "allowedContexts": r.allowed_contexts,
+        "agentId": r.agent_id,
         "createdAt": r.created_at.and_utc().to_rfc3339(),
         "updatedAt": r.updated_at.and_utc().to_rfc3339(),
     })
@@ -152,12 +178,15 @@ fn detail_row_to_json(r: &ListingDetailRow) -> Value {
         "reviewCount": r.review_count,
         "totalMessages": r.total_messages,
         "totalRevenue": r.total_revenue,
-        "exampleConversations": r.example_conversations,
+        "exampleConversations": stored_example_conversations(&r.example_conversations),
+        "allowedContexts": r.allowed_contexts,
         "createdAt": r.created_at.and_utc().to_rfc3339(),
         "updatedAt": r.updated_at.and_utc().to_rfc3339(),
         "creatorName": r.creator_name,
         "creatorUsername": r.creator_username,
         "creatorIsVerified": r.creator_is_verified,
+        "featured": r.featured,
+        "trending": is_trending(r.recent_sales),
     })
 }
 
@@ -180,7 +209,11 @@ fn manage_row_to_json(r: &ManageListingRow) -> Value {
         "reviewCount": r.review_count,
         "totalMessages": r.total_messages,
         "totalRevenue": r.total_revenue,
-        "exampleConversations": r.example_conversations,
+        "exampleConversations": stored_example_conversations(&r.example_conversations),
+        "allowedContexts": r.allowed_contexts,
+        "kbOverviewEnabled": r.kb_overview_enabled,
+        "fallbackModels": r.fallback_models,
+        "modelProvider": r.model_provider,
         "createdAt": r.created_at.and_utc().to_rfc3339(),
         "updatedAt": r.updated_at.and_utc().to_rfc3339(),
     })
@@ -190,23 +223,300 @@ fn manage_row_to_json(r: &ManageListingRow) -> Value {
 // Content moderation
 // ---------------------------------------------------------------------------
 
-const BLOCKED_WORDS: &[&str] = &[
-    "hack",
-    "exploit",
-    "jailbreak",
-    "ignore previous",
-    "DAN",
-    "bypass",
+/// Built-in blocked patterns, matched case-insensitively on word boundaries so
+/// e.g. "lifehack" or "bypass surgery" pass. Extend with `CONTENT_BLOCKLIST`.
+const BLOCKED_PATTERNS: &[&str] = &[
+    r"hack(?:s|ed|ing|er|ers)?",
+    r"exploit(?:s|ed|ing)?",
+    r"jailbreak(?:s|ed|ing)?",
+    r"ignore\s+(?:all\s+)?previous(?:\s+instructions)?",
+    r"bypass(?:es|ed|ing)?\s+(?:the\s+|your\s+|all\s+)?(?:safety|filters?|moderation|restrictions?|guardrails?|rules)",
+    r"do\s+anything\s+now",
 ];
 
-fn check_content(texts: &[&str]) -> Option<String> {
-    let combined = texts.join(" ").to_lowercase();
-    for word in BLOCKED_WORDS {
-        if combined.contains(&word.to_lowercase()) {
-            return Some(format!("Content contains blocked term: {}", word));
+/// A blocked term found in a moderated field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentMatch {
+    /// Field the match was found in, e.g. "description".
+    pub field: String,
+    /// The offending text exactly as submitted.
+    pub text: String,
+    /// Byte range of `text` within the field.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Why `check_content` rejected a submission.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentRejection {
+    /// A blocked term was found.
+    Blocked(ContentMatch),
+    /// The blocklist couldn't be compiled; content is rejected rather than let through.
+    FilterUnavailable,
+}
+
+impl ContentRejection {
+    fn into_response(self) -> (StatusCode, Json<Value>) {
+        match self {
+            ContentRejection::Blocked(m) => (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": format!("Content contains blocked term: {}", m.text),
+                    "match": {
+                        "field": m.field,
+                        "text": m.text,
+                        "start": m.start,
+                        "end": m.end,
+                    },
+                })),
+            ),
+            ContentRejection::FilterUnavailable => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Content moderation is unavailable" })),
+            ),
+        }
+    }
+}
+
+/// Configured terms and the blocklist regex compiled from them.
+type CompiledBlocklist = (Vec<String>, Regex);
+
+/// Config doesn't change at runtime, so the blocklist is compiled once per process.
+static BLOCKLIST: LazyLock<Mutex<Option<CompiledBlocklist>>> = LazyLock::new(|| Mutex::new(None));
+
+/// The blocklist regex for `extra_terms`, compiling it only if the terms changed.
+fn blocklist_regex(extra_terms: &[String]) -> Result<Regex, regex_lite::Error> {
+    let mut cached = BLOCKLIST.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((terms, re)) = cached.as_ref() {
+        if terms.as_slice() == extra_terms {
+            return Ok(re.clone());
         }
     }
-    None
+
+    let mut alternatives: Vec<String> = BLOCKED_PATTERNS.iter().map(|p| p.to_string()).collect();
+    alternatives.extend(
+        extra_terms
+            .iter()
+            .filter(|t| !t.trim().is_empty())
+            .map(|t| regex_lite::escape(t.trim()).replace(' ', r"\s+")),
+    );
+    let re = Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|")))?;
+    *cached = Some((extra_terms.to_vec(), re.clone()));
+    Ok(re)
+}
+
+/// Undo common character substitutions ("h4ck", "j@ilbreak") one char at a
+/// time, so offsets in the result map back to the original text.
+fn deobfuscate(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => c,
+    }
+}
+
+/// Check `(field, text)` pairs against the built-in patterns plus `extra_terms`
+/// (plain words/phrases from config). Rejects on the first match, or if the
+/// blocklist can't be compiled.
+pub fn check_content(fields: &[(&str, &str)], extra_terms: &[String]) -> Result<(), ContentRejection> {
+    let re = blocklist_regex(extra_terms).map_err(|e| {
+        tracing::error!("Content blocklist failed to compile: {}", e);
+        ContentRejection::FilterUnavailable
+    })?;
+
+    for (field, text) in fields {
+        // Normalized copy with one char per original char; remember original offsets
+        let offsets: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        let normalized: String = text.chars().map(deobfuscate).collect();
+        let norm_offsets: Vec<usize> = normalized.char_indices().map(|(i, _)| i).collect();
+
+        if let Some(m) = re.find(&normalized) {
+            let start_char = norm_offsets.partition_point(|&o| o < m.start());
+            let end_char = norm_offsets.partition_point(|&o| o < m.end());
+            let start = offsets[start_char];
+            let end = offsets.get(end_char).copied().unwrap_or(text.len());
+            return Err(ContentRejection::Blocked(ContentMatch {
+                field: field.to_string(),
+                text: text[start..end].to_string(),
+                start,
+                end,
+            }));
+        }
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Allowed conversation contexts
+// ---------------------------------------------------------------------------
+
+/// Conversation contexts a listing can be licensed for.
+pub const LISTING_CONTEXTS: &[&str] = &["direct", "group", "community"];
+
+/// Validate a listing's allowedContexts: non-empty, known values only.
+pub fn validate_allowed_contexts(contexts: &[String]) -> Result<(), String> {
+    if contexts.is_empty() {
+        return Err("allowedContexts must include at least one context".into());
+    }
+    for c in contexts {
+        if !LISTING_CONTEXTS.contains(&c.as_str()) {
+            return Err(format!(
+                "Invalid context '{}'; expected one of: {}",
+                c,
+                LISTING_CONTEXTS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Reject adding a listing to a conversation context it isn't licensed for.
+/// Returns `Ok(())` when `id` is not a listing.
+pub async fn check_listing_context(
+    db: &sqlx::PgPool,
+    id: Uuid,
+    context: &str,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let allowed = sqlx::query_scalar::<_, Vec<String>>(
+        "SELECT allowed_contexts FROM agent_listings WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_all(db)
+    .await;
+    listing_context_result(allowed, context)
+}
+
+/// Like [`check_listing_context`], but for an `agents.id`: every non-archived
+/// listing selling the agent (`agent_listings.agent_id`) must allow `context`.
+/// Agents not sold through the marketplace are unrestricted.
+pub async fn check_agent_listing_context(
+    db: &sqlx::PgPool,
+    agent_id: Uuid,
+    context: &str,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let allowed = sqlx::query_scalar::<_, Vec<String>>(
+        "SELECT allowed_contexts FROM agent_listings WHERE agent_id = $1 AND status <> 'archived'",
+    )
+    .bind(agent_id)
+    .fetch_all(db)
+    .await;
+    listing_context_result(allowed, context)
+}
+
+fn listing_context_result(
+    allowed: Result<Vec<Vec<String>>, sqlx::Error>,
+    context: &str,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let allowed = allowed.map_err(|e| {
+        tracing::error!("Fetch listing allowed_contexts failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Database error" })),
+        )
+    })?;
+
+    match allowed.into_iter().find(|contexts| !contexts.iter().any(|c| c == context)) {
+        Some(contexts) => Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "error": format!("This agent's listing does not allow use in {} conversations", context),
+                "allowedContexts": contexts,
+            })),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// A listing can only sell an agent its creator owns.
+async fn check_agent_owner(
+    db: &sqlx::PgPool,
+    agent_id: Uuid,
+    user_id: &str,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let owned = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM agents WHERE id = $1 AND owner_id = $2)",
+    )
+    .bind(agent_id)
+    .bind(user_id)
+    .fetch_one(db)
+    .await;
+    match owned {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "agentId must be one of your agents" })),
+        )),
+        Err(e) => {
+            tracing::error!("Check listing agent owner failed: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            ))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Example conversations
+// ---------------------------------------------------------------------------
+
+pub const MAX_EXAMPLE_CONVERSATIONS: usize = 5;
+pub const MAX_EXAMPLE_CHARS: usize = 1000;
+
+/// One user/assistant exchange shown on a listing's detail page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExampleConversation {
+    pub user: String,
+    pub assistant: String,
+}
+
+/// Parse and normalize incoming exampleConversations: an array of at most
+/// `MAX_EXAMPLE_CONVERSATIONS` `{user, assistant}` pairs, each side non-empty
+/// and at most `MAX_EXAMPLE_CHARS` characters after trimming.
+pub fn parse_example_conversations(value: &Value) -> Result<Vec<ExampleConversation>, String> {
+    let pairs: Vec<ExampleConversation> = serde_json::from_value(value.clone()).map_err(|_| {
+        "exampleConversations must be an array of { user, assistant } pairs".to_string()
+    })?;
+    if pairs.len() > MAX_EXAMPLE_CONVERSATIONS {
+        return Err(format!(
+            "exampleConversations supports at most {} pairs",
+            MAX_EXAMPLE_CONVERSATIONS
+        ));
+    }
+    pairs
+        .into_iter()
+        .map(|p| {
+            let user = p.user.trim().to_string();
+            let assistant = p.assistant.trim().to_string();
+            if user.is_empty() || assistant.is_empty() {
+                return Err("exampleConversations entries need both user and assistant text".to_string());
+            }
+            if user.chars().count() > MAX_EXAMPLE_CHARS || assistant.chars().count() > MAX_EXAMPLE_CHARS {
+                return Err(format!(
+                    "exampleConversations entries are limited to {} characters each",
+                    MAX_EXAMPLE_CHARS
+                ));
+            }
+            Ok(ExampleConversation { user, assistant })
+        })
+        .collect()
+}
+
+/// Read stored example conversations, dropping any malformed legacy entries.
+pub(crate) fn stored_example_conversations(value: &Value) -> Vec<ExampleConversation> {
+    value
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| serde_json::from_value(item.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 // ---------------------------------------------------------------------------
@@ -224,9 +534,15 @@ struct CreateListingBody {
     system_prompt: String,
     #[serde(rename = "exampleConversations")]
     example_conversations: Option<Value>,
-    /// OpenRouter model ID, e.g. "openai/gpt-4o", "anthropic/claude-3-sonnet".
-    /// Must be non-empty. Defaults to "openai/gpt-4o-mini".
+    /// OpenRouter model ID, e.g. "openai/gpt-4o", "anthropic/claude-3.5-sonnet".
+    /// Must be in `llm::supported_models`. Defaults to "openai/gpt-4o-mini".
     model: Option<String>,
+    /// Models tried in order when `model` is unavailable. Must be in `llm::supported_models`.
+    #[serde(rename = "fallbackModels")]
+    fallback_models: Option<Vec<String>>,
+    /// "openrouter" (default) or "anthropic" to stream `anthropic/*` models from Anthropic directly.
+    #[serde(rename = "modelProvider")]
+    model_provider: Option<String>,
     /// Max characters per user message. Must be 1..=20000. Defaults to 2000.
     #[serde(rename = "inputCharLimit")]
     input_char_limit: Option<i32>,
@@ -234,19 +550,73 @@ struct CreateListingBody {
     price_per_message: Option<i32>,
     #[serde(rename = "freeTrialMessages")]
     free_trial_messages: Option<i32>,
+    /// Conversation types the agent may be used in ("direct", "group", "community").
+    /// Defaults to all contexts.
+    #[serde(rename = "allowedContexts")]
+    allowed_contexts: Option<Vec<String>>,
+    /// Connected agent (one of the creator's `agents`) sold through this listing;
+    /// adding it to a group or community is then bound by `allowedContexts`.
+    #[serde(rename = "agentId")]
+    agent_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+struct CreateListingQuery {
+    /// Run every validation step but skip the insert; responds `{ valid: true }`.
+    #[serde(rename = "dryRun")]
+    dry_run: Option<bool>,
+}
+
+/// 400 for a creator who already holds `max_active` non-archived listings.
+pub fn active_listing_cap_error(max_active: i64) -> (StatusCode, String) {
+    (
+        StatusCode::BAD_REQUEST,
+        format!(
+            "You can have at most {} active listings. Archive one before creating another.",
+            max_active
+        ),
+    )
+}
+
+/// Reject a new listing when the creator is at either quota: too many
+/// non-archived listings (400) or too many created in the last day (429).
+pub fn listing_quota_error(
+    active: i64,
+    created_today: i64,
+    max_active: i64,
+    max_per_day: i64,
+) -> Option<(StatusCode, String)> {
+    if active >= max_active {
+        return Some(active_listing_cap_error(max_active));
+    }
+    if created_today >= max_per_day {
+        return Some((
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "You can create at most {} listings per day. Try again later.",
+                max_per_day
+            ),
+        ));
+    }
+    None
 }
 
 async fn create_listing(
     State(state): State<AppState>,
     user: AuthUser,
+    Query(query): Query<CreateListingQuery>,
     Json(body): Json<CreateListingBody>,
 ) -> (StatusCode, Json<Value>) {
     // 1. Content moderation
-    if let Some(reason) = check_content(&[&body.name, &body.description, &body.system_prompt]) {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({ "error": reason })),
-        );
+    if let Err(e) = check_content(
+        &[
+            ("name", &body.name),
+            ("description", &body.description),
+            ("systemPrompt", &body.system_prompt),
+        ],
+        &state.config.content_blocklist,
+    ) {
+        return e.into_response();
     }
 
     // 2. Validate model + input_char_limit
@@ -257,6 +627,40 @@ async fn create_listing(
             Json(json!({ "error": "model is required" })),
         );
     }
+    if let Err(reason) = llm::validate_model(model, &state.config.extra_llm_models) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": reason,
+                "supportedModels": llm::supported_models(&state.config.extra_llm_models),
+            })),
+        );
+    }
+
+    let model_provider = body.model_provider.as_deref().unwrap_or("openrouter");
+    if let Err(reason) = llm::validate_model_provider(model_provider, model) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": reason })),
+        );
+    }
+
+    let fallback_models = match llm::validate_fallback_models(
+        model,
+        body.fallback_models.as_deref().unwrap_or_default(),
+        &state.config.extra_llm_models,
+    ) {
+        Ok(chain) => chain,
+        Err(reason) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": reason,
+                    "supportedModels": llm::supported_models(&state.config.extra_llm_models),
+                })),
+            );
+        }
+    };
 
     let input_char_limit = body.input_char_limit.unwrap_or(2000);
     if !(1..=20_000).contains(&input_char_limit) {
@@ -266,23 +670,98 @@ async fn create_listing(
         );
     }
 
+    let allowed_contexts: Vec<String> = body
+        .allowed_contexts
+        .unwrap_or_else(|| LISTING_CONTEXTS.iter().map(|c| c.to_string()).collect());
+    if let Err(reason) = validate_allowed_contexts(&allowed_contexts) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": reason })),
+        );
+    }
+
+    if let Some(agent_id) = body.agent_id {
+        if let Err(resp) = check_agent_owner(&state.db, agent_id, &user.id).await {
+            return resp;
+        }
+    }
+
     let category = body.category.as_deref().unwrap_or("general");
-    let example_conversations = body.example_conversations.unwrap_or(json!([]));
+    let example_conversations = match body.example_conversations {
+        Some(ref v) => match parse_example_conversations(v) {
+            Ok(pairs) => json!(pairs),
+            Err(reason) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": reason })),
+                );
+            }
+        },
+        None => json!([]),
+    };
     let price_per_message = body.price_per_message.unwrap_or(1);
+    if let Err(reason) = state.config.validate_fee("pricePerMessage", price_per_message) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": reason })),
+        );
+    }
     let free_trial_messages = body.free_trial_messages.unwrap_or(3);
 
+    // Per-creator quota: non-archived listings and creations in the last day
+    let counts = sqlx::query_as::<_, (i64, i64)>(
+        r#"SELECT COUNT(*) FILTER (WHERE status <> 'archived'),
+                  COUNT(*) FILTER (WHERE created_at > NOW() - INTERVAL '1 day')
+           FROM agent_listings WHERE creator_id = $1"#,
+    )
+    .bind(&user.id)
+    .fetch_one(&state.db)
+    .await;
+    match counts {
+        Ok((active, today)) => {
+            if let Some((status, reason)) = listing_quota_error(
+                active,
+                today,
+                state.config.max_active_listings_per_user,
+                state.config.max_listings_created_per_day,
+            ) {
+                return (status, Json(json!({ "error": reason })));
+            }
+        }
+        Err(e) => {
+            tracing::error!("Listing quota check failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    }
+
+    if query.dry_run.unwrap_or(false) {
+        return (
+            StatusCode::OK,
+            Json(json!({
+                "valid": true,
+                "model": model,
+                "modelProvider": model_provider,
+                "fallbackModels": fallback_models,
+            })),
+        );
+    }
+
     // 3. INSERT
     let row = sqlx::query_as::<_, ListingRow>(
         r#"INSERT INTO agent_listings
            (creator_id, agent_name, description, category, avatar_url,
             model, input_char_limit, price, price_per_message, free_trial_messages,
-            system_prompt, status, example_conversations)
-           VALUES ($1, $2, $3, $4, $5, $6, $7, 0, $8, $9, $10, 'active', $11)
+            system_prompt, status, example_conversations, allowed_contexts, fallback_models,
+            model_provider, agent_id)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, 0, $8, $9, $10, 'active', $11, $12, $13, $14, $15)
            RETURNING id, agent_name, description, category, avatar_url,
                      model, input_char_limit, price_per_message, free_trial_messages,
                      sales_count, status::text AS status, avg_rating::float8 AS avg_rating,
                      review_count, total_messages, total_revenue,
-                     example_conversations, created_at, updated_at"#,
+                     example_conversations, allowed_contexts, agent_id, created_at, updated_at"#,
     )
     .bind(&user.id)
     .bind(&body.name)
@@ -295,6 +774,10 @@ async fn create_listing(
     .bind(free_trial_messages)
     .bind(&body.system_prompt)
     .bind(&example_conversations)
+    .bind(&allowed_contexts)
+    .bind(&fallback_models)
+    .bind(model_provider)
+    .bind(body.agent_id)
     .fetch_one(&state.db)
     .await;
 
@@ -314,6 +797,101 @@ async fn create_listing(
     }
 }
 
+// ---------------------------------------------------------------------------
+// POST /api/agent-hub/agents/{id}/rotate-key — Replace the listing's API key
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+struct RotateKeyBody {
+    #[serde(rename = "apiKey")]
+    api_key: String,
+}
+
+/// Validate a key for the listing's provider and store it encrypted. Only the
+/// key changes, so no moderation re-run; the key is never echoed back.
+async fn rotate_key(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<RotateKeyBody>,
+) -> (StatusCode, Json<Value>) {
+    let api_key = body.api_key.trim();
+    if api_key.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "apiKey is required" })),
+        );
+    }
+
+    let owner = sqlx::query_as::<_, (String, String)>(
+        "SELECT creator_id, model_provider FROM agent_listings WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await;
+
+    let provider = match owner {
+        Ok(Some((cid, provider))) if cid == user.id => provider,
+        Ok(Some(_)) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "Not your listing" })),
+            );
+        }
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Listing not found" })),
+            );
+        }
+        Err(e) => {
+            tracing::error!("Fetch listing owner failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    };
+
+    let validation = if provider == "anthropic" {
+        llm::validate_api_key(&llm::LlmProvider::Anthropic, api_key).await
+    } else {
+        openrouter::validate_api_key(api_key).await
+    };
+    if let Err(reason) = validation {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": reason })));
+    }
+
+    let stored = match crate::routes::user_settings::encrypt_api_key(&state.config, api_key) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::error!("Encrypt listing key failed: {}", e);
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "error": "API key storage is not available" })),
+            );
+        }
+    };
+    let result = sqlx::query(
+        "UPDATE agent_listings SET api_key_encrypted = $2, updated_at = NOW() WHERE id = $1",
+    )
+    .bind(id)
+    .bind(&stored)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (StatusCode::OK, Json(json!({ "success": true }))),
+        Err(e) => {
+            tracing::error!("Rotate listing key failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to update API key" })),
+            )
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // PUT /api/agent-hub/agents/{id} — Update
 // ---------------------------------------------------------------------------
@@ -329,8 +907,14 @@ struct UpdateListingBody {
     system_prompt: Option<String>,
     #[serde(rename = "exampleConversations")]
     example_conversations: Option<Value>,
-    /// OpenRouter model ID, e.g. "openai/gpt-4o". Must be non-empty if provided.
+    /// OpenRouter model ID, e.g. "openai/gpt-4o". Must be in `llm::supported_models` if provided.
     model: Option<String>,
+    /// Models tried in order when `model` is unavailable. Replaces the existing chain if provided.
+    #[serde(rename = "fallbackModels")]
+    fallback_models: Option<Vec<String>>,
+    /// "openrouter" (default) or "anthropic" to stream `anthropic/*` models from Anthropic directly.
+    #[serde(rename = "modelProvider")]
+    model_provider: Option<String>,
     /// Max characters per user message. Must be 1..=20000 if provided.
     #[serde(rename = "inputCharLimit")]
     input_char_limit: Option<i32>,
@@ -338,6 +922,17 @@ struct UpdateListingBody {
     price_per_message: Option<i32>,
     #[serde(rename = "freeTrialMessages")]
     free_trial_messages: Option<i32>,
+    /// Conversation types the agent may be used in ("direct", "group", "community").
+    /// Defaults to all contexts.
+    #[serde(rename = "allowedContexts")]
+    allowed_contexts: Option<Vec<String>>,
+    /// Connected agent (one of the creator's `agents`) sold through this listing;
+    /// adding it to a group or community is then bound by `allowedContexts`.
+    #[serde(rename = "agentId")]
+    agent_id: Option<Uuid>,
+    /// Inject a cached knowledge-base overview on the first turn of each conversation.
+    #[serde(rename = "kbOverviewEnabled")]
+    kb_overview_enabled: Option<bool>,
 }
 
 async fn update_listing(
@@ -347,15 +942,15 @@ async fn update_listing(
     Json(body): Json<UpdateListingBody>,
 ) -> (StatusCode, Json<Value>) {
     // Verify ownership
-    let owner = sqlx::query_scalar::<_, String>(
-        "SELECT creator_id FROM agent_listings WHERE id = $1",
+    let owner = sqlx::query_as::<_, (String, String, String)>(
+        "SELECT creator_id, model, model_provider FROM agent_listings WHERE id = $1",
     )
     .bind(id)
     .fetch_optional(&state.db)
     .await;
 
-    match owner {
-        Ok(Some(cid)) if cid == user.id => {}
+    let (current_model, current_provider) = match owner {
+        Ok(Some((cid, model, provider))) if cid == user.id => (model, provider),
         Ok(Some(_)) => {
             return (
                 StatusCode::FORBIDDEN,
@@ -375,26 +970,21 @@ async fn update_listing(
                 Json(json!({ "error": "Database error" })),
             );
         }
-    }
+    };
 
     // Content moderation on provided fields
-    let mut texts: Vec<&str> = Vec::new();
+    let mut fields: Vec<(&str, &str)> = Vec::new();
     if let Some(ref n) = body.name {
-        texts.push(n);
+        fields.push(("name", n));
     }
     if let Some(ref d) = body.description {
-        texts.push(d);
+        fields.push(("description", d));
     }
     if let Some(ref sp) = body.system_prompt {
-        texts.push(sp);
+        fields.push(("systemPrompt", sp));
     }
-    if !texts.is_empty() {
-        if let Some(reason) = check_content(&texts) {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "error": reason })),
-            );
-        }
+    if let Err(e) = check_content(&fields, &state.config.content_blocklist) {
+        return e.into_response();
     }
 
     // Validate model if provided
@@ -405,8 +995,49 @@ async fn update_listing(
                 Json(json!({ "error": "model cannot be empty" })),
             );
         }
+        if let Err(reason) = llm::validate_model(m, &state.config.extra_llm_models) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": reason,
+                    "supportedModels": llm::supported_models(&state.config.extra_llm_models),
+                })),
+            );
+        }
     }
 
+    // Validate provider against the (possibly updated) model
+    if body.model_provider.is_some() || body.model.is_some() {
+        let provider = body.model_provider.as_deref().unwrap_or(&current_provider);
+        let model = body.model.as_deref().unwrap_or(&current_model);
+        if let Err(reason) = llm::validate_model_provider(provider, model) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": reason })),
+            );
+        }
+    }
+
+    // Validate fallback chain against the (possibly updated) primary model
+    let fallback_models = match body.fallback_models {
+        Some(ref chain) => {
+            let primary = body.model.as_deref().unwrap_or(&current_model);
+            match llm::validate_fallback_models(primary, chain, &state.config.extra_llm_models) {
+                Ok(chain) => Some(chain),
+                Err(reason) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({
+                            "error": reason,
+                            "supportedModels": llm::supported_models(&state.config.extra_llm_models),
+                        })),
+                    );
+                }
+            }
+        }
+        None => None,
+    };
+
     // Validate input_char_limit if provided (1..=20000)
     if let Some(limit) = body.input_char_limit {
         if !(1..=20_000).contains(&limit) {
@@ -417,6 +1048,45 @@ async fn update_listing(
         }
     }
 
+    // Validate allowed_contexts if provided
+    if let Some(ref contexts) = body.allowed_contexts {
+        if let Err(reason) = validate_allowed_contexts(contexts) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": reason })),
+            );
+        }
+    }
+
+    if let Some(agent_id) = body.agent_id {
+        if let Err(resp) = check_agent_owner(&state.db, agent_id, &user.id).await {
+            return resp;
+        }
+    }
+
+    if let Some(price) = body.price_per_message {
+        if let Err(reason) = state.config.validate_fee("pricePerMessage", price) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": reason })),
+            );
+        }
+    }
+
+    // Validate + normalize example_conversations if provided
+    let example_conversations = match body.example_conversations {
+        Some(ref v) => match parse_example_conversations(v) {
+            Ok(pairs) => Some(json!(pairs)),
+            Err(reason) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": reason })),
+                );
+            }
+        },
+        None => None,
+    };
+
     // Build dynamic UPDATE
     let result = sqlx::query_as::<_, ListingRow>(
         r#"UPDATE agent_listings SET
@@ -430,13 +1100,21 @@ async fn update_listing(
                example_conversations = COALESCE($9, example_conversations),
                price_per_message = COALESCE($10, price_per_message),
                free_trial_messages = COALESCE($11, free_trial_messages),
+               allowed_contexts = COALESCE($12, allowed_contexts),
+               kb_overview_enabled = COALESCE($13, kb_overview_enabled),
+               fallback_models = COALESCE($14, fallback_models),
+               model_provider = COALESCE($15, model_provider),
+               agent_id = COALESCE($16, agent_id),
+               -- A stored key belongs to the old provider
+               api_key_encrypted = CASE WHEN $15 IS NOT NULL AND $15 <> model_provider
+                                        THEN NULL ELSE api_key_encrypted END,
                updated_at = NOW()
            WHERE id = $1
            RETURNING id, agent_name, description, category, avatar_url,
                      model, input_char_limit, price_per_message, free_trial_messages,
                      sales_count, status::text AS status, avg_rating::float8 AS avg_rating,
                      review_count, total_messages, total_revenue,
-                     example_conversations, created_at, updated_at"#,
+                     example_conversations, allowed_contexts, agent_id, created_at, updated_at"#,
     )
     .bind(id)
     .bind(&body.name)
@@ -446,9 +1124,14 @@ async fn update_listing(
     .bind(&body.system_prompt)
     .bind(&body.model)
     .bind(&body.input_char_limit)
-    .bind(&body.example_conversations)
+    .bind(&example_conversations)
     .bind(&body.price_per_message)
     .bind(&body.free_trial_messages)
+    .bind(&body.allowed_contexts)
+    .bind(body.kb_overview_enabled)
+    .bind(&fallback_models)
+    .bind(&body.model_provider)
+    .bind(body.agent_id)
     .fetch_one(&state.db)
     .await;
 
@@ -481,14 +1164,103 @@ async fn archive_listing(
     .execute(&state.db)
     .await;
 
-    match result {
-        Ok(r) if r.rows_affected() == 0 => (
-            StatusCode::NOT_FOUND,
-            Json(json!({ "error": "Listing not found or not owned by you" })),
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Listing not found or not owned by you" })),
+        ),
+        Ok(_) => (StatusCode::OK, Json(json!({ "archived": true }))),
+        Err(e) => {
+            tracing::error!("Archive listing failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            )
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// POST /api/agent-hub/agents/{id}/reactivate — Restore an archived listing
+// ---------------------------------------------------------------------------
+
+/// Moderation is re-run because the blocklist may have changed since the
+/// listing was archived. Listings suspended by an administrator stay suspended.
+async fn reactivate_listing(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<Value>) {
+    let row = sqlx::query_as::<_, (String, String, String, String, String)>(
+        r#"SELECT creator_id, status::text, agent_name, description, system_prompt
+           FROM agent_listings WHERE id = $1"#,
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await;
+
+    let (creator_id, status, name, description, system_prompt) = match row {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Listing not found" })),
+            );
+        }
+        Err(e) => {
+            tracing::error!("Reactivate listing: fetch failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    };
+
+    if creator_id != user.id {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "error": "Not your listing" })),
+        );
+    }
+    match status.as_str() {
+        "archived" => {}
+        "suspended" => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "This listing was suspended by an administrator and cannot be reactivated" })),
+            );
+        }
+        _ => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({ "error": "Listing is not archived" })),
+            );
+        }
+    }
+
+    if let Err(e) = check_content(
+        &[
+            ("name", &name),
+            ("description", &description),
+            ("systemPrompt", &system_prompt),
+        ],
+        &state.config.content_blocklist,
+    ) {
+        return e.into_response();
+    }
+
+    match reactivate_archived_listing(&state.db, id, &user.id, state.config.max_active_listings_per_user).await {
+        Ok(ReactivateOutcome::Reactivated) => (StatusCode::OK, Json(json!({ "reactivated": true }))),
+        Ok(ReactivateOutcome::NotArchived) => (
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "Listing is not archived" })),
         ),
-        Ok(_) => (StatusCode::OK, Json(json!({ "archived": true }))),
+        Ok(ReactivateOutcome::CapReached) => {
+            let (status, reason) = active_listing_cap_error(state.config.max_active_listings_per_user);
+            (status, Json(json!({ "error": reason })))
+        }
         Err(e) => {
-            tracing::error!("Archive listing failed: {}", e);
+            tracing::error!("Reactivate listing failed: {}", e);
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({ "error": "Database error" })),
@@ -497,6 +1269,58 @@ async fn archive_listing(
     }
 }
 
+/// Result of trying to bring an archived listing back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReactivateOutcome {
+    Reactivated,
+    /// The listing isn't the creator's or isn't archived (anymore).
+    NotArchived,
+    /// The creator already holds `max_active` non-archived listings.
+    CapReached,
+}
+
+/// Set an archived listing back to active, subject to the same active-listing
+/// cap as `create_listing`. The creator's row is locked first so concurrent
+/// reactivations can't both slip under the cap.
+pub async fn reactivate_archived_listing(
+    db: &sqlx::PgPool,
+    listing_id: Uuid,
+    creator_id: &str,
+    max_active: i64,
+) -> Result<ReactivateOutcome, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query(r#"SELECT 1 FROM "user" WHERE id = $1 FOR UPDATE"#)
+        .bind(creator_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let active = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM agent_listings WHERE creator_id = $1 AND status <> 'archived'",
+    )
+    .bind(creator_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if active >= max_active {
+        return Ok(ReactivateOutcome::CapReached);
+    }
+
+    let result = sqlx::query(
+        r#"UPDATE agent_listings SET status = 'active', updated_at = NOW()
+           WHERE id = $1 AND creator_id = $2 AND status = 'archived'"#,
+    )
+    .bind(listing_id)
+    .bind(creator_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(if result.rows_affected() == 0 {
+        ReactivateOutcome::NotArchived
+    } else {
+        ReactivateOutcome::Reactivated
+    })
+}
+
 // ---------------------------------------------------------------------------
 // GET /api/agent-hub/agents — Browse / Search (public)
 // ---------------------------------------------------------------------------
@@ -506,26 +1330,138 @@ struct BrowseQuery {
     category: Option<String>,
     search: Option<String>,
     sort: Option<String>,
+    /// `featured` or `trending`
+    filter: Option<String>,
+    /// 1-based; takes precedence over `offset`
+    page: Option<i64>,
     limit: Option<i64>,
     offset: Option<i64>,
 }
 
+/// Sales counted towards `recent_sales` look back this many days.
+pub const TRENDING_WINDOW_DAYS: i32 = 7;
+/// Minimum sales inside the window for a listing to count as trending.
+pub const TRENDING_MIN_RECENT_SALES: i32 = 5;
+
+pub fn is_trending(recent_sales: i32) -> bool {
+    recent_sales >= TRENDING_MIN_RECENT_SALES
+}
+
+/// Recompute `agent_listings.recent_sales` from the same events as
+/// `sales_count` — new conversations and one-off listing purchases — inside the
+/// trending window, counting each buyer once per listing. Per-message chat
+/// payments are also `purchase` transactions but are usage, not sales.
+/// Returns the number of rows changed.
+pub async fn refresh_recent_sales(db: &sqlx::PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"UPDATE agent_listings al
+           SET recent_sales = COALESCE(s.n, 0)
+           FROM agent_listings l
+           LEFT JOIN (
+               SELECT listing_id, COUNT(DISTINCT user_id)::int AS n FROM (
+                   SELECT listing_id, user_id FROM marketplace_conversations
+                   WHERE created_at > NOW() - make_interval(days => $1)
+                   UNION
+                   SELECT related_app_id, user_id FROM coin_transactions
+                   WHERE type = 'purchase' AND related_app_id IS NOT NULL
+                     AND description = 'Purchased agent listing'
+                     AND created_at > NOW() - make_interval(days => $1)
+               ) sales GROUP BY listing_id
+           ) s ON s.listing_id = l.id
+           WHERE al.id = l.id AND al.recent_sales IS DISTINCT FROM COALESCE(s.n, 0)"#,
+    )
+    .bind(TRENDING_WINDOW_DAYS)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+// ---------------------------------------------------------------------------
+// GET /api/agent-hub/categories — Active listing counts per category
+// ---------------------------------------------------------------------------
+
+const CATEGORIES_CACHE_KEY: &str = "agent_hub:categories";
+const CATEGORIES_CACHE_TTL_SECS: u64 = 60;
+
+/// Active listings per category, most populated first (ties by name).
+pub async fn active_category_counts(db: &sqlx::PgPool) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, i64)>(
+        r#"SELECT category, COUNT(*) AS count FROM agent_listings
+           WHERE status = 'active'
+           GROUP BY category ORDER BY count DESC, category"#,
+    )
+    .fetch_all(db)
+    .await
+}
+
+async fn list_categories(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    use deadpool_redis::redis::AsyncCommands;
+
+    if let Ok(mut conn) = state.redis.get().await {
+        let cached: Option<String> = conn.get(CATEGORIES_CACHE_KEY).await.ok().flatten();
+        if let Some(body) = cached.and_then(|c| serde_json::from_str::<Value>(&c).ok()) {
+            return (StatusCode::OK, Json(body));
+        }
+    }
+
+    match active_category_counts(&state.db).await {
+        Ok(cats) => {
+            let items: Vec<Value> = cats
+                .iter()
+                .map(|(cat, count)| json!({ "category": cat, "count": count }))
+                .collect();
+            let body = json!({ "categories": items });
+            if let Ok(mut conn) = state.redis.get().await {
+                let _: Result<(), _> = conn
+                    .set_ex(CATEGORIES_CACHE_KEY, body.to_string(), CATEGORIES_CACHE_TTL_SECS)
+                    .await;
+            }
+            (StatusCode::OK, Json(body))
+        }
+        Err(e) => {
+            tracing::error!("List categories failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            )
+        }
+    }
+}
+
 async fn browse(
     State(state): State<AppState>,
     Query(q): Query<BrowseQuery>,
 ) -> (StatusCode, Json<Value>) {
-    let limit = q.limit.unwrap_or(20).min(50);
-    let offset = q.offset.unwrap_or(0).max(0);
+    let window = PageWindow::resolve(q.page, q.offset, q.limit, 20, 50);
 
-    let order_clause = match q.sort.as_deref() {
-        Some("newest") => "ORDER BY al.created_at DESC",
-        Some("rating") => "ORDER BY al.avg_rating DESC NULLS LAST",
-        Some("price") => "ORDER BY al.price_per_message ASC",
-        _ => "ORDER BY al.sales_count DESC", // popular (default)
+    let sort_clause = match q.sort.as_deref() {
+        Some("newest") => "al.created_at DESC",
+        Some("rating") => "al.avg_rating DESC NULLS LAST",
+        Some("price") => "al.price_per_message ASC",
+        _ => "al.sales_count DESC", // popular (default)
     };
 
     // Build WHERE conditions — bind_idx tracks the next $N placeholder
     let mut conditions = vec!["al.status = 'active'".to_string()];
+
+    // Editorial and velocity-based surfaces
+    let order_clause = match q.filter.as_deref() {
+        None => format!("ORDER BY {}", sort_clause),
+        Some("featured") => {
+            conditions.push("al.featured".to_string());
+            format!("ORDER BY {}", sort_clause)
+        }
+        Some("trending") => {
+            conditions.push(format!("al.recent_sales >= {}", TRENDING_MIN_RECENT_SALES));
+            format!("ORDER BY al.recent_sales DESC, {}", sort_clause)
+        }
+        Some(other) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("Unknown filter '{}'", other) })),
+            );
+        }
+    };
     let mut bind_idx = 0u32;
 
     let category_val = q.category.clone();
@@ -580,9 +1516,10 @@ async fn browse(
                   al.sales_count, al.status::text AS status,
                   al.avg_rating::float8 AS avg_rating, al.review_count,
                   al.total_messages, al.total_revenue,
-                  al.example_conversations, al.created_at, al.updated_at,
+                  al.example_conversations, al.allowed_contexts, al.created_at, al.updated_at,
                   u.name AS creator_name, u.username AS creator_username,
-                  COALESCE(u.is_verified, false) AS creator_is_verified
+                  COALESCE(u.is_verified, false) AS creator_is_verified,
+                  al.featured, al.recent_sales
            FROM agent_listings al
            LEFT JOIN "user" u ON u.id = al.creator_id
            WHERE {}
@@ -598,7 +1535,7 @@ async fn browse(
     if let Some(ref sv) = search_val {
         data_query = data_query.bind(sv);
     }
-    data_query = data_query.bind(limit).bind(offset);
+    data_query = data_query.bind(window.limit).bind(window.offset);
 
     let rows = match data_query.fetch_all(&state.db).await {
         Ok(rows) => rows,
@@ -613,21 +1550,57 @@ async fn browse(
 
     let listings: Vec<Value> = rows.iter().map(detail_row_to_json).collect();
 
-    (
-        StatusCode::OK,
-        Json(json!({
-            "listings": listings,
-            "total": total,
-        })),
-    )
+    let mut body = window.meta(total);
+    body["listings"] = json!(listings);
+    body["total"] = json!(total);
+    (StatusCode::OK, Json(body))
 }
 
 // ---------------------------------------------------------------------------
 // GET /api/agent-hub/agents/{id} — Public Detail
 // ---------------------------------------------------------------------------
 
+/// Most related listings returned with a listing's detail.
+const RELATED_LISTINGS_LIMIT: i64 = 5;
+
+/// Best sellers in `category` for a listing's detail page, excluding the listing
+/// itself and, for a signed-in viewer, their own listings.
+pub async fn related_listings(
+    db: &sqlx::PgPool,
+    listing_id: Uuid,
+    category: &str,
+    viewer_id: Option<&str>,
+) -> Result<Vec<Value>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, ListingDetailRow>(
+        r#"SELECT al.id, al.creator_id, al.agent_name, al.description, al.category,
+                  al.avatar_url, al.model, al.input_char_limit,
+                  al.price_per_message, al.free_trial_messages,
+                  al.sales_count, al.status::text AS status,
+                  al.avg_rating::float8 AS avg_rating, al.review_count,
+                  al.total_messages, al.total_revenue,
+                  al.example_conversations, al.allowed_contexts, al.created_at, al.updated_at,
+                  u.name AS creator_name, u.username AS creator_username,
+                  COALESCE(u.is_verified, false) AS creator_is_verified,
+                  al.featured, al.recent_sales
+           FROM agent_listings al
+           LEFT JOIN "user" u ON u.id = al.creator_id
+           WHERE al.status = 'active' AND al.category = $1 AND al.id <> $2
+             AND ($3::text IS NULL OR al.creator_id <> $3)
+           ORDER BY al.sales_count DESC
+           LIMIT $4"#,
+    )
+    .bind(category)
+    .bind(listing_id)
+    .bind(viewer_id)
+    .bind(RELATED_LISTINGS_LIMIT)
+    .fetch_all(db)
+    .await?;
+    Ok(rows.iter().map(detail_row_to_json).collect())
+}
+
 async fn get_detail(
     State(state): State<AppState>,
+    user: Result<AuthUser, (StatusCode, Json<Value>)>,
     Path(id): Path<Uuid>,
 ) -> (StatusCode, Json<Value>) {
     let row = sqlx::query_as::<_, ListingDetailRow>(
@@ -637,9 +1610,10 @@ async fn get_detail(
                   al.sales_count, al.status::text AS status,
                   al.avg_rating::float8 AS avg_rating, al.review_count,
                   al.total_messages, al.total_revenue,
-                  al.example_conversations, al.created_at, al.updated_at,
+                  al.example_conversations, al.allowed_contexts, al.created_at, al.updated_at,
                   u.name AS creator_name, u.username AS creator_username,
-                  COALESCE(u.is_verified, false) AS creator_is_verified
+                  COALESCE(u.is_verified, false) AS creator_is_verified,
+                  al.featured, al.recent_sales
            FROM agent_listings al
            LEFT JOIN "user" u ON u.id = al.creator_id
            WHERE al.id = $1 AND al.status = 'active'"#,
@@ -649,7 +1623,29 @@ async fn get_detail(
     .await;
 
     match row {
-        Ok(Some(r)) => (StatusCode::OK, Json(detail_row_to_json(&r))),
+        Ok(Some(r)) => {
+            let mut j = detail_row_to_json(&r);
+            j["currency"] = crate::routes::wallet::currency_json(&state.config);
+
+            let viewer_id = user.ok().map(|u| u.id);
+
+            // Signed-in viewers learn whether their first message would be free
+            if let Some(uid) = viewer_id.as_deref() {
+                j["previewAvailable"] = json!(billing::preview_available(
+                    &state.db,
+                    id,
+                    uid,
+                    state.config.marketplace_free_preview,
+                )
+                .await
+                .unwrap_or(false));
+            }
+            j["related"] = json!(related_listings(&state.db, id, &r.category, viewer_id.as_deref())
+                .await
+                .unwrap_or_default());
+
+            (StatusCode::OK, Json(j))
+        }
         Ok(None) => (
             StatusCode::NOT_FOUND,
             Json(json!({ "error": "Listing not found" })),
@@ -680,7 +1676,8 @@ async fn manage_detail(
                   sales_count, status::text AS status,
                   avg_rating::float8 AS avg_rating, review_count,
                   total_messages, total_revenue,
-                  example_conversations, created_at, updated_at
+                  example_conversations, allowed_contexts, kb_overview_enabled, fallback_models,
+                  model_provider, created_at, updated_at
            FROM agent_listings
            WHERE id = $1 AND creator_id = $2"#,
     )
@@ -720,7 +1717,7 @@ async fn my_listings(
                   sales_count, status::text AS status,
                   avg_rating::float8 AS avg_rating, review_count,
                   total_messages, total_revenue,
-                  example_conversations, created_at, updated_at
+                  example_conversations, allowed_contexts, agent_id, created_at, updated_at
            FROM agent_listings
            WHERE creator_id = $1
            ORDER BY created_at DESC"#,
@@ -748,7 +1745,7 @@ async fn my_listings(
 }
 
 // ---------------------------------------------------------------------------
-// POST /api/agent-hub/agents/{id}/reviews — Create a review
+// POST /api/agent-hub/agents/{id}/reviews — Create or edit a review
 // ---------------------------------------------------------------------------
 
 #[derive(Deserialize)]
@@ -818,6 +1815,32 @@ async fn create_review(
         }
     }
 
+    // Only users who have actually chatted with the agent may review it
+    let has_chatted = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM marketplace_conversations WHERE listing_id = $1 AND user_id = $2)",
+    )
+    .bind(listing_id)
+    .bind(&user.id)
+    .fetch_one(&state.db)
+    .await;
+
+    match has_chatted {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "Chat with this agent before reviewing it" })),
+            );
+        }
+        Err(e) => {
+            tracing::error!("Create review: chat history check failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    }
+
     // Atomic transaction: insert review + recalculate aggregates
     let mut tx = match state.db.begin().await {
         Ok(tx) => tx,
@@ -830,10 +1853,14 @@ async fn create_review(
         }
     };
 
-    // Insert review (UNIQUE constraint catches duplicates)
-    let result = sqlx::query_scalar::<_, Uuid>(
+    // Insert review, or edit the caller's existing one. `xmax = 0` only holds
+    // for freshly inserted rows, which tells the two cases apart.
+    let result = sqlx::query_as::<_, (Uuid, bool)>(
         r#"INSERT INTO agent_reviews (listing_id, user_id, rating, comment)
-           VALUES ($1, $2, $3, $4) RETURNING id"#,
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (listing_id, user_id) DO UPDATE
+           SET rating = EXCLUDED.rating, comment = EXCLUDED.comment, updated_at = NOW()
+           RETURNING id, (xmax = 0) AS inserted"#,
     )
     .bind(listing_id)
     .bind(&user.id)
@@ -842,22 +1869,13 @@ async fn create_review(
     .fetch_one(&mut *tx)
     .await;
 
-    let review_id = match result {
-        Ok(id) => id,
+    let (review_id, created) = match result {
+        Ok(row) => row,
         Err(e) => {
-            if e.to_string().contains("unique")
-                || e.to_string().contains("duplicate")
-                || e.to_string().contains("23505")
-            {
-                return (
-                    StatusCode::CONFLICT,
-                    Json(json!({ "error": "You have already reviewed this listing" })),
-                );
-            }
-            tracing::error!("Create review: insert failed: {}", e);
+            tracing::error!("Create review: upsert failed: {}", e);
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Failed to create review" })),
+                Json(json!({ "error": "Failed to save review" })),
             );
         }
     };
@@ -890,8 +1908,12 @@ async fn create_review(
     }
 
     (
-        StatusCode::CREATED,
-        Json(json!({ "id": review_id, "success": true })),
+        if created { StatusCode::CREATED } else { StatusCode::OK },
+        Json(json!({
+            "id": review_id,
+            "success": true,
+            "action": if created { "created" } else { "updated" },
+        })),
     )
 }
 
@@ -909,6 +1931,7 @@ struct ReviewListingCheck {
 struct ReviewsQuery {
     limit: Option<i64>,
     offset: Option<i64>,
+    sort: Option<String>,
 }
 
 #[derive(sqlx::FromRow)]
@@ -919,6 +1942,9 @@ struct ReviewRow {
     created_at: NaiveDateTime,
     user_name: String,
     user_image: Option<String>,
+    creator_reply: Option<String>,
+    creator_replied_at: Option<NaiveDateTime>,
+    verified_purchase: bool,
 }
 
 async fn list_reviews(
@@ -930,10 +1956,13 @@ async fn list_reviews(
     let offset = q.offset.unwrap_or(0).max(0);
 
     // Get total count
+    // Reviews flagged past the threshold are hidden
+    let hide_threshold = state.config.review_flag_hide_threshold;
     let total = match sqlx::query_scalar::<_, i64>(
-        "SELECT count(*) FROM agent_reviews WHERE listing_id = $1",
+        "SELECT count(*) FROM agent_reviews WHERE listing_id = $1 AND flag_count < $2",
     )
     .bind(listing_id)
+    .bind(hide_threshold)
     .fetch_one(&state.db)
     .await
     {
@@ -947,18 +1976,34 @@ async fn list_reviews(
         }
     };
 
-    let rows = sqlx::query_as::<_, ReviewRow>(
+    let order_clause = match q.sort.as_deref() {
+        Some("rating") => "ORDER BY r.rating DESC, r.created_at DESC",
+        Some("recent") => "ORDER BY r.created_at DESC",
+        _ => "ORDER BY verified_purchase DESC, r.created_at DESC", // verified (default)
+    };
+
+    // A review is a verified purchase when its author has paid for at least
+    // one message to this listing. Buyers are resolved once for the whole page.
+    let sql = format!(
         r#"SELECT r.id, r.rating, r.comment, r.created_at,
-                  u.name AS user_name, u.image AS user_image
+                  u.name AS user_name, u.image AS user_image,
+                  r.creator_reply, r.creator_replied_at,
+                  (buyers.user_id IS NOT NULL) AS verified_purchase
            FROM agent_reviews r
            JOIN "user" u ON r.user_id = u.id
-           WHERE r.listing_id = $1
-           ORDER BY r.created_at DESC
-           LIMIT $2 OFFSET $3"#,
-    )
+           LEFT JOIN (
+               SELECT DISTINCT user_id FROM coin_transactions
+               WHERE type = 'purchase' AND related_app_id = $1
+           ) buyers ON buyers.user_id = r.user_id
+           WHERE r.listing_id = $1 AND r.flag_count < $4
+           {order_clause}
+           LIMIT $2 OFFSET $3"#
+    );
+    let rows = sqlx::query_as::<_, ReviewRow>(&sql)
     .bind(listing_id)
     .bind(limit)
     .bind(offset)
+    .bind(hide_threshold)
     .fetch_all(&state.db)
     .await;
 
@@ -974,6 +2019,11 @@ async fn list_reviews(
                         "createdAt": r.created_at.and_utc().to_rfc3339(),
                         "userName": r.user_name,
                         "userImage": r.user_image,
+                        "verifiedPurchase": r.verified_purchase,
+                        "creatorReply": r.creator_reply.as_ref().map(|reply| json!({
+                            "content": reply,
+                            "createdAt": r.creator_replied_at.map(|t| t.and_utc().to_rfc3339()),
+                        })),
                     })
                 })
                 .collect();
@@ -991,3 +2041,214 @@ async fn list_reviews(
         }
     }
 }
+
+// ---------------------------------------------------------------------------
+// POST /api/agent-hub/agents/{id}/reviews/{review_id}/reply — Creator reply
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+struct ReplyReviewBody {
+    content: String,
+}
+
+async fn reply_review(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((listing_id, review_id)): Path<(Uuid, Uuid)>,
+    Json(body): Json<ReplyReviewBody>,
+) -> (StatusCode, Json<Value>) {
+    let content = body.content.trim();
+    if content.is_empty() || content.chars().count() > 2000 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Reply must be 1-2000 characters" })),
+        );
+    }
+    if let Err(e) = check_content(&[("content", content)], &state.config.content_blocklist) {
+        return e.into_response();
+    }
+
+    // Only the listing creator may reply
+    let owner = sqlx::query_scalar::<_, String>(
+        "SELECT creator_id FROM agent_listings WHERE id = $1",
+    )
+    .bind(listing_id)
+    .fetch_optional(&state.db)
+    .await;
+
+    match owner {
+        Ok(Some(cid)) if cid == user.id => {}
+        Ok(Some(_)) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "Only the listing creator can reply to reviews" })),
+            );
+        }
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Listing not found" })),
+            );
+        }
+        Err(e) => {
+            tracing::error!("Reply review: fetch listing owner failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    }
+
+    // One reply per review; replying again replaces it
+    let result = sqlx::query_scalar::<_, NaiveDateTime>(
+        r#"UPDATE agent_reviews SET creator_reply = $3, creator_replied_at = NOW()
+           WHERE id = $1 AND listing_id = $2
+           RETURNING creator_replied_at"#,
+    )
+    .bind(review_id)
+    .bind(listing_id)
+    .bind(content)
+    .fetch_optional(&state.db)
+    .await;
+
+    match result {
+        Ok(Some(replied_at)) => (
+            StatusCode::OK,
+            Json(json!({
+                "id": review_id,
+                "creatorReply": {
+                    "content": content,
+                    "createdAt": replied_at.and_utc().to_rfc3339(),
+                },
+            })),
+        ),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Review not found" })),
+        ),
+        Err(e) => {
+            tracing::error!("Reply review failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to save reply" })),
+            )
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// POST /api/agent-hub/agents/{id}/reviews/{review_id}/flag — Flag a review
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+struct FlagReviewBody {
+    reason: Option<String>,
+}
+
+async fn flag_review(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((listing_id, review_id)): Path<(Uuid, Uuid)>,
+    body: Option<Json<FlagReviewBody>>,
+) -> (StatusCode, Json<Value>) {
+    let reason = body
+        .and_then(|b| b.0.reason)
+        .map(|r| r.chars().take(500).collect::<String>());
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Flag review: begin transaction failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    };
+
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM agent_reviews WHERE id = $1 AND listing_id = $2)",
+    )
+    .bind(review_id)
+    .bind(listing_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    match exists {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Review not found" })),
+            );
+        }
+        Err(e) => {
+            tracing::error!("Flag review: fetch review failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    }
+
+    // Each user counts once towards flag_count
+    let inserted = sqlx::query(
+        r#"INSERT INTO agent_review_flags (review_id, user_id, reason)
+           VALUES ($1, $2, $3)
+           ON CONFLICT (review_id, user_id) DO NOTHING"#,
+    )
+    .bind(review_id)
+    .bind(&user.id)
+    .bind(&reason)
+    .execute(&mut *tx)
+    .await;
+
+    let newly_flagged = match inserted {
+        Ok(r) => r.rows_affected() > 0,
+        Err(e) => {
+            tracing::error!("Flag review: insert flag failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to flag review" })),
+            );
+        }
+    };
+
+    let flag_count = sqlx::query_scalar::<_, i32>(
+        r#"UPDATE agent_reviews SET flag_count = flag_count + $2
+           WHERE id = $1
+           RETURNING flag_count"#,
+    )
+    .bind(review_id)
+    .bind(if newly_flagged { 1 } else { 0 })
+    .fetch_one(&mut *tx)
+    .await;
+
+    let flag_count = match flag_count {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Flag review: update flag_count failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to flag review" })),
+            );
+        }
+    };
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Flag review: commit failed: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Database error" })),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "id": review_id,
+            "flagged": true,
+            "hidden": flag_count >= state.config.review_flag_hide_threshold,
+        })),
+    )
+}
@@ -17,7 +17,7 @@ use tokio_stream::wrappers::ReceiverStream;
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
-use crate::services::{billing, llm, openrouter, tts};
+use crate::services::{billing, llm, openrouter, tts, wallet};
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
@@ -36,12 +36,14 @@ pub fn router() -> Router<AppState> {
 
 #[derive(sqlx::FromRow)]
 struct ChatListingInfo {
+    creator_id: String,
     agent_name: String,
     system_prompt: String,
     model: String,
     input_char_limit: i32,
     status: String,
     tts_voice: Option<String>,
+    fallback_models: Vec<String>,
 }
 
 #[derive(sqlx::FromRow)]
@@ -84,8 +86,8 @@ async fn chat(
 {
     // 1. Load listing (must be active)
     let listing = sqlx::query_as::<_, ChatListingInfo>(
-        r#"SELECT agent_name, system_prompt, model, input_char_limit,
-                  status::text AS status, tts_voice
+        r#"SELECT creator_id, agent_name, system_prompt, model, input_char_limit,
+                  status::text AS status, tts_voice, fallback_models
            FROM agent_listings WHERE id = $1"#,
     )
     .bind(listing_id)
@@ -134,8 +136,14 @@ async fn chat(
 
     // 4. Check billing
     let billing_result =
-        billing::check_billing(&state.db, &user.id, listing_id, body.conversation_id)
-            .await
+        billing::check_billing(
+            &state.db,
+            &user.id,
+            listing_id,
+            body.conversation_id,
+            state.config.marketplace_free_preview,
+        )
+        .await
             .map_err(|e| {
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -152,83 +160,64 @@ async fn chat(
         ));
     }
 
-    // 5. Get or create conversation
-    let conversation_id = match body.conversation_id {
-        Some(cid) => cid,
-        None => {
-            let new_id = sqlx::query_scalar::<_, Uuid>(
-                r#"INSERT INTO marketplace_conversations (listing_id, user_id, title)
-                   VALUES ($1, $2, $3) RETURNING id"#,
-            )
-            .bind(listing_id)
-            .bind(&user.id)
-            .bind(&listing.agent_name)
-            .fetch_one(&state.db)
-            .await
-            .map_err(|e| {
-                tracing::error!("Chat: create conversation failed: {}", e);
-                (
+    // Reserve the price now; it is settled once the stream finishes and
+    // returned to the user if the LLM call fails.
+    let hold = match billing::hold_amount(&billing_result) {
+        Some(amount) => match wallet::hold_coins(&state.db, &user.id, amount).await {
+            Ok(h) => Some(h),
+            Err(wallet::HoldError::InsufficientBalance) => {
+                return Err((
+                    StatusCode::PAYMENT_REQUIRED,
+                    Json(json!({ "error": "Insufficient balance" })),
+                ));
+            }
+            Err(wallet::HoldError::Database(e)) => {
+                tracing::error!("Chat: hold price failed: {}", e);
+                return Err((
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({ "error": "Failed to create conversation" })),
-                )
-            })?;
-
-            // Increment sales_count for new conversation
-            if let Err(e) = sqlx::query(
-                "UPDATE agent_listings SET sales_count = sales_count + 1, updated_at = NOW() WHERE id = $1",
-            )
-            .bind(listing_id)
-            .execute(&state.db)
-            .await
-            {
-                tracing::error!("Chat: increment sales_count failed: {}", e);
+                    Json(json!({ "error": "Payment failed" })),
+                ));
             }
-
-            new_id
-        }
+        },
+        None => None,
     };
 
-    // 6. Store user message
-    sqlx::query(
-        r#"INSERT INTO marketplace_messages (conversation_id, role, content)
-           VALUES ($1, 'user', $2)"#,
+    // 5–7 can fail after the price is held; give it back if they do
+    let prepared = prepare_chat_context(
+        &state.db,
+        &user.id,
+        listing_id,
+        &listing.agent_name,
+        body.conversation_id,
+        &body.message,
     )
-    .bind(conversation_id)
-    .bind(&body.message)
-    .execute(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Chat: store user message failed: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": "Failed to store message" })),
-        )
-    })?;
+    .await;
+    let (conversation_id, history) = match prepared {
+        Ok(v) => v,
+        Err(err) => {
+            if let Some(h) = &hold {
+                release_chat_hold(&state.db, h).await;
+            }
+            release_free_message(&state.db, &billing_result, listing_id, &user.id).await;
+            return Err(err);
+        }
+    };
 
-    // 7. Load last 50 messages for LLM context
-    let history = sqlx::query_as::<_, ChatMessageRow>(
-        r#"SELECT * FROM (
-               SELECT id, role::text AS role, content, tts_audio_url, created_at
-               FROM marketplace_messages
-               WHERE conversation_id = $1
-               ORDER BY created_at DESC
-               LIMIT 50
-           ) sub ORDER BY created_at ASC"#,
-    )
-    .bind(conversation_id)
-    .fetch_all(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Chat: load history failed: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": "Failed to load history" })),
-        )
-    })?;
+    // 8. RAG: augment system prompt with knowledge base context. On the first
+    //    turn, listings that opt in also get a cached overview of the whole KB.
+    let overview = if history.len() <= 1 {
+        crate::services::embedding::kb_overview(&state.db, listing_id)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("KB overview failed for listing {}: {:?}", listing_id, e);
+                None
+            })
+    } else {
+        None
+    };
 
-    // 8. RAG: augment system prompt with knowledge base context
-    let system_prompt = if let Some(ref openai_key) = state.config.openai_api_key {
-        match crate::services::embedding::rag_search(
+    let rag_chunks = if let Some(ref openai_key) = state.config.openai_api_key {
+        crate::services::embedding::rag_search(
             &state.db,
             listing_id,
             &body.message,
@@ -236,24 +225,20 @@ async fn chat(
             5,
         )
         .await
-        {
-            Ok(chunks) if !chunks.is_empty() => {
-                let context = chunks.join("\n\n");
-                format!(
-                    "{}\n\n---\nBelow is relevant context from the knowledge base:\n\n{}",
-                    listing.system_prompt, context
-                )
-            }
-            Ok(_) => listing.system_prompt.clone(),
-            Err(e) => {
-                tracing::warn!("RAG search failed for listing {}: {:?}", listing_id, e);
-                listing.system_prompt.clone()
-            }
-        }
+        .unwrap_or_else(|e| {
+            tracing::warn!("RAG search failed for listing {}: {:?}", listing_id, e);
+            Vec::new()
+        })
     } else {
-        listing.system_prompt.clone()
+        Vec::new()
     };
 
+    let system_prompt = crate::services::embedding::compose_system_prompt(
+        &listing.system_prompt,
+        overview.as_deref(),
+        &rag_chunks,
+    );
+
     // 9. Build LLM messages
     let mut llm_messages = vec![llm::ChatMessage {
         role: "system".into(),
@@ -282,10 +267,14 @@ async fn chat(
     // 10. Setup SSE stream via channel
     let (tx, rx) = tokio::sync::mpsc::channel::<Result<Event, Infallible>>(32);
     let db = state.db.clone();
+    let creator_id = listing.creator_id.clone();
+    let is_free_trial = billing_result.is_free_trial;
+    let is_preview = billing_result.is_preview;
+    let trial_remaining = billing_result.trial_remaining;
     let user_id = user.id.clone();
-    let cost = billing_result.cost;
-    let is_free = billing_result.is_free_trial || cost == 0;
     let api_key = openrouter_key.to_string();
+    let retry_policy = openrouter::RetryPolicy::from_config(&state.config);
+    let fallback_models = listing.fallback_models.clone();
     let s3_clone = state.s3.clone();
     let config_clone = state.config.clone();
     let tts_voice = listing.tts_voice.clone().unwrap_or_else(|| "alloy".into());
@@ -294,20 +283,58 @@ async fn chat(
         // Send meta event
         let _ = tx
             .send(Ok(Event::default().data(
-                json!({"type": "meta", "conversationId": conversation_id}).to_string(),
+                json!({
+                    "type": "meta",
+                    "conversationId": conversation_id,
+                    "freeTrial": is_free_trial,
+                    "preview": is_preview,
+                    "trialRemaining": trial_remaining,
+                })
+                .to_string(),
             )))
             .await;
 
         // Call OpenRouter stream
-        let mut stream = match openrouter::call_stream(&api_key, &or_opts).await {
-            Ok(s) => s,
+        let mut stream = match openrouter::call_stream_with_fallback(
+            &api_key,
+            &or_opts,
+            &fallback_models,
+            &retry_policy,
+        )
+        .await
+        {
+            Ok((s, model)) => {
+                if model != or_opts.model {
+                    let _ = tx
+                        .send(Ok(Event::default().data(
+                            json!({
+                                "type": "model_fallback",
+                                "requestedModel": or_opts.model,
+                                "model": model,
+                            })
+                            .to_string(),
+                        )))
+                        .await;
+                }
+                s
+            }
             Err(e) => {
                 tracing::error!("Chat: OpenRouter stream failed: {}", e);
+                settle_chat_hold(&db, hold, false, listing_id, creator_id).await;
+                release_free_message(&db, &billing_result, listing_id, &user_id).await;
                 let _ = tx
                     .send(Ok(Event::default().data(
-                        json!({"type": "error", "message": "LLM request failed"}).to_string(),
+                        json!({
+                            "type": "error",
+                            "message": "LLM request failed",
+                            "retryAfter": e.retry_after,
+                        })
+                        .to_string(),
                     )))
                     .await;
+                let _ = tx
+                    .send(Ok(Event::default().data(billing::usage_event(0, false).to_string())))
+                    .await;
                 let _ = tx
                     .send(Ok(Event::default().data(
                         json!({"type": "done", "charged": false}).to_string(),
@@ -351,12 +378,10 @@ async fn chat(
             }
         }
 
-        // Post-processing
-        let mut charged = false;
+        // Store assistant message (with RETURNING id for TTS update)
         let mut msg_id: Option<Uuid> = None;
 
         if !full_content.is_empty() {
-            // Store assistant message (with RETURNING id for TTS update)
             msg_id = match sqlx::query_scalar::<_, Uuid>(
                 r#"INSERT INTO marketplace_messages (conversation_id, role, content)
                    VALUES ($1, 'assistant', $2)
@@ -373,25 +398,31 @@ async fn chat(
                     None
                 }
             };
+        }
 
-            // Deduct coins if not free
-            if !is_free && cost > 0 {
-                match billing::deduct_coins(&db, &user_id, listing_id, cost).await {
-                    Ok(_) => charged = true,
-                    Err(e) => tracing::error!("Chat: deduct_coins failed: {}", e),
-                }
-            }
+        // Only a delivered reply costs the user or uses up a free-trial message
+        let replied = msg_id.is_some();
+        let charged_amount = hold.as_ref().map_or(0, |h| h.amount);
+        let charged = settle_chat_hold(&db, hold, replied, listing_id, creator_id).await;
+        let charged_amount = if charged { charged_amount } else { 0 };
 
+        if replied {
             // Record message stats — only record revenue if actually charged
-            let recorded_cost = if charged { cost } else { 0 };
             if let Err(e) =
-                billing::record_message(&db, conversation_id, listing_id, recorded_cost).await
+                billing::record_message(&db, conversation_id, listing_id, charged_amount).await
             {
                 tracing::error!("Chat: record_message failed: {}", e);
             }
-
+        } else {
+            release_free_message(&db, &billing_result, listing_id, &user_id).await;
         }
 
+        let _ = tx
+            .send(Ok(Event::default().data(
+                billing::usage_event(charged_amount, replied && is_free_trial).to_string(),
+            )))
+            .await;
+
         // Send done event BEFORE TTS (so the user sees the reply immediately)
         let _ = tx
             .send(Ok(
@@ -500,6 +531,145 @@ async fn chat(
     Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
 }
 
+/// Steps 5–7 of `chat`: resolve the conversation, store the user message and
+/// load the history sent to the LLM.
+async fn prepare_chat_context(
+    db: &sqlx::PgPool,
+    user_id: &str,
+    listing_id: Uuid,
+    agent_name: &str,
+    conversation_id: Option<Uuid>,
+    message: &str,
+) -> Result<(Uuid, Vec<ChatMessageRow>), (StatusCode, Json<Value>)> {
+    // 5. Get or create conversation
+    let conversation_id = match conversation_id {
+        Some(cid) => cid,
+        None => {
+            let new_id = sqlx::query_scalar::<_, Uuid>(
+                r#"INSERT INTO marketplace_conversations (listing_id, user_id, title)
+                   VALUES ($1, $2, $3) RETURNING id"#,
+            )
+            .bind(listing_id)
+            .bind(user_id)
+            .bind(agent_name)
+            .fetch_one(db)
+            .await
+            .map_err(|e| {
+                tracing::error!("Chat: create conversation failed: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to create conversation" })),
+                )
+            })?;
+
+            // Increment sales_count for new conversation
+            if let Err(e) = sqlx::query(
+                "UPDATE agent_listings SET sales_count = sales_count + 1, updated_at = NOW() WHERE id = $1",
+            )
+            .bind(listing_id)
+            .execute(db)
+            .await
+            {
+                tracing::error!("Chat: increment sales_count failed: {}", e);
+            }
+
+            new_id
+        }
+    };
+
+    // 6. Store user message
+    sqlx::query(
+        r#"INSERT INTO marketplace_messages (conversation_id, role, content)
+           VALUES ($1, 'user', $2)"#,
+    )
+    .bind(conversation_id)
+    .bind(message)
+    .execute(db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Chat: store user message failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to store message" })),
+        )
+    })?;
+
+    // 7. Load last 50 messages for LLM context
+    let history = sqlx::query_as::<_, ChatMessageRow>(
+        r#"SELECT * FROM (
+               SELECT id, role::text AS role, content, tts_audio_url, created_at
+               FROM marketplace_messages
+               WHERE conversation_id = $1
+               ORDER BY created_at DESC
+               LIMIT 50
+           ) sub ORDER BY created_at ASC"#,
+    )
+    .bind(conversation_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Chat: load history failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load history" })),
+        )
+    })?;
+
+    Ok((conversation_id, history))
+}
+
+/// Settle a held message price once the stream ends: charge it (crediting the
+/// creator's share) if the reply was delivered, otherwise give it back.
+/// Returns whether the user was charged.
+async fn settle_chat_hold(
+    db: &sqlx::PgPool,
+    hold: Option<wallet::Hold>,
+    replied: bool,
+    listing_id: Uuid,
+    creator_id: String,
+) -> bool {
+    let Some(hold) = hold else {
+        return false;
+    };
+    if !replied {
+        release_chat_hold(db, &hold).await;
+        return false;
+    }
+
+    let settlement = wallet::Settlement {
+        tx_type: "purchase",
+        description: "Agent Hub message payment".to_string(),
+        related_app_id: Some(listing_id),
+        creator: Some((creator_id, "Agent Hub message earning".to_string())),
+    };
+    match wallet::commit_hold(db, &hold, &settlement).await {
+        Ok(committed) => committed,
+        Err(e) => {
+            tracing::error!("Chat: commit hold {} failed: {}", hold.id, e);
+            false
+        }
+    }
+}
+
+async fn release_chat_hold(db: &sqlx::PgPool, hold: &wallet::Hold) {
+    if let Err(e) = wallet::release_hold(db, hold).await {
+        tracing::error!("Chat: release hold {} failed: {}", hold.id, e);
+    }
+}
+
+/// Give back the free message `check_billing` claimed when no reply is delivered.
+async fn release_free_message(db: &sqlx::PgPool, billing_result: &billing::BillingResult, listing_id: Uuid, user_id: &str) {
+    if billing_result.is_preview {
+        if let Err(e) = billing::release_preview(db, listing_id, user_id).await {
+            tracing::error!("Chat: release_preview failed: {}", e);
+        }
+    } else if billing_result.is_free_trial {
+        if let Err(e) = billing::release_trial_message(db, listing_id, user_id).await {
+            tracing::error!("Chat: release_trial_message failed: {}", e);
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // GET /api/agent-hub/conversations — List user's conversations
 // ---------------------------------------------------------------------------
@@ -12,8 +12,8 @@ use uuid::Uuid;
 
 use crate::auth::middleware::AuthAgent;
 use crate::services::message_seq::get_next_seq;
-use crate::services::push::send_push_to_user;
-use crate::services::push_trigger::{is_conversation_muted, should_send_push};
+use crate::services::push::queue_message_push;
+use crate::services::push_trigger::{is_conversation_muted, is_mentions_only, message_push_type, push_allowed};
 use crate::ws::handler::{filter_agents_for_dispatch, get_conv_member_ids, do_trigger_agent_response, AgentFilterConfig};
 use crate::ws::state::QueuedResponse;
 use crate::AppState;
@@ -260,6 +260,7 @@ async fn agent_send(
                             thread_id: None,
                             user_message_id: Some(msg_id.clone()),
                             metadata: None,
+                            response_group: None,
                         });
                     continue;
                 }
@@ -273,6 +274,7 @@ async fn agent_send(
                     None,
                     &conv_type,
                     None,
+                    None,
                     &state.ws,
                     &state.db,
                     &state.redis,
@@ -330,15 +332,21 @@ async fn agent_send(
             let muted = is_conversation_muted(db, mid, conversation_id).await;
             tracing::info!("push check: mid={} muted={:?}", mid, muted);
             if let Ok(false) = muted {
-                let should_push = should_send_push(db, mid, "message").await;
+                let push_type = message_push_type(db, mid, &conv_type, &content).await;
+                if push_type != "mention" && matches!(is_mentions_only(db, mid, conversation_id).await, Ok(true)) {
+                    continue;
+                }
+                let should_push = push_allowed(db, mid, push_type).await;
                 tracing::info!("push check: mid={} should_send={:?}", mid, should_push);
                 if let Ok(true) = should_push {
-                    let result = send_push_to_user(
+                    queue_message_push(
                         db,
+                        &state.redis,
                         config,
                         mid,
-                        &crate::services::push::PushPayload {
-                            notification_type: "message".into(),
+                        conversation_id,
+                        crate::services::push::PushPayload {
+                            notification_type: push_type.into(),
                             title: agent.name.clone(),
                             body: preview.clone(),
                             url: Some(format!("/?c={}&m={}", conversation_id, msg_id)),
@@ -346,7 +354,6 @@ async fn agent_send(
                         },
                     )
                     .await;
-                    tracing::info!("push result: mid={} result={:?}", mid, result);
                 }
             }
         }
@@ -2,7 +2,7 @@ use axum::{
     extract::{Multipart, Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Json, Response},
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use serde::Deserialize;
@@ -30,6 +30,14 @@ pub fn router() -> Router<AppState> {
         .route("/api/agents/{id}/stats", get(get_stats))
         .route("/api/agents/{id}/history", delete(clear_history))
         .route("/api/agents/{id}/export", get(export_history))
+        .route("/api/agents/{id}/knowledge-base", put(set_knowledge_base))
+}
+
+#[derive(Deserialize)]
+struct SetKnowledgeBaseBody {
+    /// Agent-hub listing whose knowledge base to use; `null` unlinks.
+    #[serde(rename = "listingId")]
+    listing_id: Option<Uuid>,
 }
 
 #[derive(Deserialize)]
@@ -152,7 +160,9 @@ async fn get_agent(
 
     match agent {
         Ok(Some(agent)) => {
-            Json(serde_json::to_value(&agent).unwrap_or_default()).into_response()
+            let mut value = serde_json::to_value(&agent).unwrap_or_default();
+            value["connected"] = json!(state.ws.is_agent_connected(&agent.id.to_string()));
+            Json(value).into_response()
         }
         Ok(None) => (
             StatusCode::NOT_FOUND,
@@ -777,3 +787,53 @@ async fn delete_agent(
 
     StatusCode::NO_CONTENT.into_response()
 }
+
+/// PUT /api/agents/:id/knowledge-base — Link the agent to a knowledge base
+/// from one of the caller's agent-hub listings, or unlink it.
+async fn set_knowledge_base(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<SetKnowledgeBaseBody>,
+) -> Response {
+    if let Some(listing_id) = body.listing_id {
+        let owns_listing = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM agent_listings WHERE id = $1 AND creator_id = $2)",
+        )
+        .bind(listing_id)
+        .bind(&user.id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(false);
+        if !owns_listing {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Listing not found"})),
+            )
+                .into_response();
+        }
+    }
+
+    let result = sqlx::query(
+        "UPDATE agents SET kb_listing_id = $3, updated_at = NOW() WHERE id = $1 AND owner_id = $2",
+    )
+    .bind(id)
+    .bind(&user.id)
+    .bind(body.listing_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => Json(json!({"kbListingId": body.listing_id})).into_response(),
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Agent not found"})),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
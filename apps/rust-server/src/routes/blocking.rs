@@ -5,16 +5,22 @@ use axum::{
     routing::{delete, get, post},
     Router,
 };
+use serde::Deserialize;
 use serde_json::json;
 
 use crate::auth::middleware::AuthUser;
 use crate::AppState;
 
+/// Most user IDs accepted by one bulk-block request.
+pub const MAX_BULK_BLOCK: usize = 1000;
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/users/{userId}/block", post(block_user))
         .route("/api/users/{userId}/block", delete(unblock_user))
         .route("/api/users/blocked", get(list_blocked))
+        .route("/api/users/blocked/bulk", post(bulk_block))
+        .route("/api/users/blocked/export", get(export_blocked))
         .route("/api/users/{userId}/mute", post(mute_user))
         .route("/api/users/{userId}/mute", delete(unmute_user))
         .route("/api/users/muted", get(list_muted))
@@ -135,6 +141,171 @@ async fn list_blocked(
     }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkBlockBody {
+    user_ids: Vec<String>,
+}
+
+/// Trim and dedupe requested block targets, dropping blanks and the caller.
+pub fn normalize_block_targets(user_ids: &[String], self_id: &str) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    user_ids
+        .iter()
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty() && *id != self_id)
+        .filter(|id| seen.insert(id.to_string()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// POST /api/users/blocked/bulk — Block many users in one transaction.
+/// Unknown and already-blocked users are skipped.
+async fn bulk_block(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<BulkBlockBody>,
+) -> Response {
+    if body.user_ids.len() > MAX_BULK_BLOCK {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("At most {} users per request", MAX_BULK_BLOCK)})),
+        )
+            .into_response();
+    }
+    let requested = normalize_block_targets(&body.user_ids, &user.id);
+
+    // Keep only existing users not already blocked by the caller
+    let targets = match sqlx::query_scalar::<_, String>(
+        r#"SELECT u.id FROM "user" u
+           WHERE u.id = ANY($2)
+             AND NOT EXISTS (
+                 SELECT 1 FROM friendships f
+                 WHERE f.requester_id = $1 AND f.addressee_id = u.id AND f.status = 'blocked'
+             )"#,
+    )
+    .bind(&user.id)
+    .bind(&requested)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(ids) => ids,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    if !targets.is_empty() {
+        let result: Result<(), sqlx::Error> = async {
+            let mut tx = state.db.begin().await?;
+            // Drop friendships and pending requests in either direction
+            sqlx::query(
+                r#"DELETE FROM friendships
+                   WHERE status <> 'blocked'
+                     AND ((requester_id = $1 AND addressee_id = ANY($2))
+                       OR (addressee_id = $1 AND requester_id = ANY($2)))"#,
+            )
+            .bind(&user.id)
+            .bind(&targets)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                r#"INSERT INTO friendships (requester_id, addressee_id, status)
+                   SELECT $1, t, 'blocked'::friendship_status FROM UNNEST($2::text[]) AS t
+                   ON CONFLICT (requester_id, addressee_id)
+                   DO UPDATE SET status = 'blocked', updated_at = NOW()"#,
+            )
+            .bind(&user.id)
+            .bind(&targets)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await
+        }
+        .await;
+
+        if let Err(e) = result {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+
+        // Member lists of conversations shared with a blocked user are stale now
+        let shared = sqlx::query_scalar::<_, uuid::Uuid>(
+            r#"SELECT DISTINCT a.conversation_id
+               FROM conversation_user_members a
+               JOIN conversation_user_members b ON b.conversation_id = a.conversation_id
+               WHERE a.user_id = $1 AND b.user_id = ANY($2)"#,
+        )
+        .bind(&user.id)
+        .bind(&targets)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+        for conv_id in shared {
+            state.ws.invalidate_conv_member_cache(&conv_id.to_string());
+        }
+    }
+
+    Json(json!({
+        "blocked": targets.len(),
+        "skipped": body.user_ids.len() - targets.len(),
+        "userIds": targets,
+    }))
+    .into_response()
+}
+
+/// GET /api/users/blocked/export — Full blocklist; `userIds` can be posted
+/// back to the bulk endpoint.
+async fn export_blocked(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Response {
+    let results = sqlx::query_as::<_, (String, String, Option<String>, chrono::NaiveDateTime)>(
+        r#"SELECT u.id, u.name, u.username, f.updated_at
+           FROM friendships f
+           JOIN "user" u ON u.id = f.addressee_id
+           WHERE f.requester_id = $1 AND f.status = 'blocked'
+           ORDER BY f.updated_at"#,
+    )
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await;
+
+    match results {
+        Ok(rows) => {
+            let user_ids: Vec<&String> = rows.iter().map(|(id, _, _, _)| id).collect();
+            let users: Vec<serde_json::Value> = rows
+                .iter()
+                .map(|(id, name, username, blocked_at)| {
+                    json!({
+                        "id": id,
+                        "name": name,
+                        "username": username,
+                        "blockedAt": blocked_at.and_utc().to_rfc3339(),
+                    })
+                })
+                .collect();
+            Json(json!({
+                "exportedAt": chrono::Utc::now().to_rfc3339(),
+                "userIds": user_ids,
+                "users": users,
+            }))
+            .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
 // ── Mute / Unmute ──────────────────────────────────────────────────────
 
 /// POST /api/users/:userId/mute — Mute a user
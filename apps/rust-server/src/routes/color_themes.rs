@@ -0,0 +1,82 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use serde_json::{json, Value};
+
+use crate::auth::middleware::AuthUser;
+use crate::services::color_theme::{
+    parse_theme_choice, resolved_user_theme, save_user_theme, ThemeChoice, BUILTIN_THEMES,
+};
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/color-themes", get(list_color_themes))
+        .route("/api/color-themes/preview", post(preview_color_theme))
+        .route("/api/user/color-theme", get(get_color_theme).put(set_color_theme))
+}
+
+/// GET /api/color-themes — Built-in color themes with their token sets
+async fn list_color_themes() -> Json<Value> {
+    Json(json!({ "themes": BUILTIN_THEMES.iter().map(|t| t.to_json()).collect::<Vec<_>>() }))
+}
+
+/// Validate a theme selection body, answering 400 with per-field errors.
+fn choice_from_body(body: &Value) -> Result<ThemeChoice, (StatusCode, Json<Value>)> {
+    parse_theme_choice(body).map_err(|fields| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid theme", "fields": fields })),
+        )
+    })
+}
+
+/// POST /api/color-themes/preview — Resolve a theme selection without saving it
+async fn preview_color_theme(_user: AuthUser, Json(body): Json<Value>) -> Response {
+    match choice_from_body(&body) {
+        Ok(choice) => Json(json!({ "theme": choice.to_json() })).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// GET /api/user/color-theme
+async fn get_color_theme(State(state): State<AppState>, user: AuthUser) -> Response {
+    match resolved_user_theme(&state.db, &user.id).await {
+        Ok(theme) => Json(json!({ "theme": theme })).into_response(),
+        Err(e) => {
+            tracing::error!("Load color theme failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to load theme" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// PUT /api/user/color-theme — Apply a built-in (`themeId`) or `custom` theme
+async fn set_color_theme(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<Value>,
+) -> Response {
+    let choice = match choice_from_body(&body) {
+        Ok(c) => c,
+        Err(err) => return err.into_response(),
+    };
+    match save_user_theme(&state.db, &user.id, &choice).await {
+        Ok(()) => Json(json!({ "theme": choice.to_json() })).into_response(),
+        Err(e) => {
+            tracing::error!("Save color theme failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to save theme" })),
+            )
+                .into_response()
+        }
+    }
+}
@@ -17,7 +17,7 @@ use tokio_stream::wrappers::ReceiverStream;
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
-use crate::services::{llm, openrouter, tts};
+use crate::services::{billing, idempotency, llm, openrouter, tts, wallet};
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
@@ -32,6 +32,7 @@ pub fn router() -> Router<AppState> {
             "/api/communities/{id}",
             get(get_community).put(update_community).delete(delete_community),
         )
+        .route("/api/communities/{id}/reactivate", post(reactivate_community))
         // Members
         .route("/api/communities/{id}/join", post(join))
         .route("/api/communities/{id}/leave", post(leave))
@@ -41,10 +42,18 @@ pub fn router() -> Router<AppState> {
             "/api/communities/{id}/agents",
             get(list_agents).post(add_agent),
         )
+        .route(
+            "/api/communities/{id}/agents/order",
+            axum::routing::put(reorder_agents),
+        )
         .route(
             "/api/communities/{id}/agents/{listing_id}",
             axum::routing::delete(remove_agent).patch(update_agent_listen_mode),
         )
+        .route(
+            "/api/communities/{id}/agents/{listing_id}/preview",
+            get(preview_agent),
+        )
         // Chat
         .route(
             "/api/communities/{id}/messages",
@@ -227,6 +236,7 @@ struct CommunityAgentRow {
     avatar_url: Option<String>,
     description: String,
     model: String,
+    sort_order: i32,
     added_at: DateTime<Utc>,
 }
 
@@ -498,6 +508,16 @@ async fn create(
         );
     }
 
+    for (label, fee) in [
+        ("Join fee", body.join_fee),
+        ("Monthly fee", body.monthly_fee),
+        ("Agent call fee", body.agent_call_fee),
+    ] {
+        if let Err(reason) = state.config.validate_fee(label, fee.unwrap_or(0)) {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": reason })));
+        }
+    }
+
     let community_type = body.community_type.as_deref().unwrap_or("community");
     if community_type != "official" && community_type != "community" {
         return (
@@ -728,12 +748,14 @@ async fn get_community(
             } else {
                 (None, None)
             };
-            (StatusCode::OK, Json(community_json_with_identity(
+            let mut j = community_json_with_identity(
                 &r,
                 my_display_name.as_deref(),
                 my_avatar_url.as_deref(),
                 my_role.as_deref(),
-            )))
+            );
+            j["currency"] = crate::routes::wallet::currency_json(&state.config);
+            (StatusCode::OK, Json(j))
         }
         Ok(None) => (
             StatusCode::NOT_FOUND,
@@ -838,28 +860,15 @@ async fn update_community(
         }
     }
 
-    if let Some(fee) = body.join_fee {
-        if fee < 0 {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "error": "Join fee cannot be negative" })),
-            );
-        }
-    }
-    if let Some(fee) = body.monthly_fee {
-        if fee < 0 {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "error": "Monthly fee cannot be negative" })),
-            );
-        }
-    }
-    if let Some(fee) = body.agent_call_fee {
-        if fee < 0 {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "error": "Agent call fee cannot be negative" })),
-            );
+    for (label, fee) in [
+        ("Join fee", body.join_fee),
+        ("Monthly fee", body.monthly_fee),
+        ("Agent call fee", body.agent_call_fee),
+    ] {
+        if let Some(fee) = fee {
+            if let Err(reason) = state.config.validate_fee(label, fee) {
+                return (StatusCode::BAD_REQUEST, Json(json!({ "error": reason })));
+            }
         }
     }
 
@@ -1054,6 +1063,127 @@ async fn delete_community(
     }
 }
 
+// ---------------------------------------------------------------------------
+// POST /api/communities/:id/reactivate — Restore an archived community (creator only)
+// ---------------------------------------------------------------------------
+
+/// Archiving deletes the community conversation, so reactivation creates a
+/// fresh one and re-adds current members. Agents are not restored and must be
+/// added again. Communities suspended by an administrator cannot be reactivated.
+async fn reactivate_community(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<Value>) {
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Reactivate community: begin tx failed: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" })));
+        }
+    };
+
+    let row = sqlx::query_as::<_, (String, String, String)>(
+        "SELECT creator_id, name, status::text FROM communities WHERE id = $1 FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let (creator_id, name, status) = match row {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Community not found" })),
+            );
+        }
+        Err(e) => {
+            tracing::error!("Reactivate community: fetch failed: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" })));
+        }
+    };
+
+    if creator_id != user.id {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "error": "Only the creator can reactivate this community" })),
+        );
+    }
+    match status.as_str() {
+        "archived" => {}
+        "suspended" => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "This community was suspended by an administrator and cannot be reactivated" })),
+            );
+        }
+        _ => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({ "error": "Community is not archived" })),
+            );
+        }
+    }
+
+    // Recreate the group conversation removed on archive
+    let conv_id = Uuid::new_v4();
+    if let Err(e) = sqlx::query(
+        r#"INSERT INTO conversations (id, title, "type", user_id, mention_only)
+           VALUES ($1, $2, 'community', $3, TRUE)"#,
+    )
+    .bind(conv_id)
+    .bind(&name)
+    .bind(&user.id)
+    .execute(&mut *tx)
+    .await
+    {
+        tracing::error!("Reactivate community: create conversation failed: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" })));
+    }
+
+    if let Err(e) = sqlx::query(
+        r#"UPDATE communities SET status = 'active', conversation_id = $1, updated_at = NOW()
+           WHERE id = $2"#,
+    )
+    .bind(conv_id)
+    .bind(id)
+    .execute(&mut *tx)
+    .await
+    {
+        tracing::error!("Reactivate community: update status failed: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" })));
+    }
+
+    // Re-add members to the new conversation (creator as admin)
+    if let Err(e) = sqlx::query(
+        r#"INSERT INTO conversation_user_members (conversation_id, user_id, role)
+           SELECT $1, cm.user_id,
+                  (CASE WHEN cm.role::text = 'creator' THEN 'admin' ELSE 'member' END)::conversation_user_role
+           FROM community_members cm
+           WHERE cm.community_id = $2
+           ON CONFLICT DO NOTHING"#,
+    )
+    .bind(conv_id)
+    .bind(id)
+    .execute(&mut *tx)
+    .await
+    {
+        tracing::error!("Reactivate community: restore members failed: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" })));
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Reactivate community: commit failed: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" })));
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({ "id": id, "status": "active", "conversationId": conv_id })),
+    )
+}
+
 // ---------------------------------------------------------------------------
 // POST /api/communities/:id/join — Join community
 // ---------------------------------------------------------------------------
@@ -1070,6 +1200,36 @@ async fn join(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<Uuid>,
+    headers: axum::http::HeaderMap,
+    body: Option<Json<JoinBody>>,
+) -> (StatusCode, Json<Value>) {
+    // Idempotency-Key: a retried join replays the first result instead of
+    // running the billing transaction again.
+    let Some(key) = idempotency::key_from_headers(&headers) else {
+        return join_inner(&state, &user, id, body).await;
+    };
+    let scope = format!("community_join:{}", id);
+    match idempotency::claim(&state.redis, &scope, &user.id, &key).await {
+        idempotency::Claim::Replay(status, cached) => return (status, Json(cached)),
+        idempotency::Claim::InProgress => {
+            return (StatusCode::CONFLICT, Json(idempotency::in_progress_body()));
+        }
+        idempotency::Claim::Acquired => {}
+    }
+
+    let (status, Json(resp)) = join_inner(&state, &user, id, body).await;
+    if status.is_success() {
+        idempotency::complete(&state.redis, &scope, &user.id, &key, status, &resp).await;
+    } else {
+        idempotency::release(&state.redis, &scope, &user.id, &key).await;
+    }
+    (status, Json(resp))
+}
+
+async fn join_inner(
+    state: &AppState,
+    user: &AuthUser,
+    id: Uuid,
     body: Option<Json<JoinBody>>,
 ) -> (StatusCode, Json<Value>) {
     let join_body = body.map(|b| b.0);
@@ -1669,6 +1829,47 @@ struct AddAgentBody {
     listen_mode: Option<String>,
 }
 
+/// Whether adding `agent_id` would take a community past `max_agents`. Listings
+/// and directly added agents count together; an agent already present doesn't
+/// count against itself, so re-adding it is always allowed.
+pub async fn community_agent_cap_reached<'e, E>(
+    executor: E,
+    community_id: Uuid,
+    conversation_id: Option<Uuid>,
+    agent_id: Uuid,
+    max_agents: i64,
+) -> Result<bool, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+{
+    let count = sqlx::query_scalar::<_, i64>(
+        r#"SELECT (SELECT COUNT(*) FROM community_agents
+                   WHERE community_id = $1 AND listing_id <> $3)
+                + (SELECT COUNT(*) FROM conversation_members
+                   WHERE conversation_id = $2 AND agent_id IS NOT NULL AND agent_id <> $3)"#,
+    )
+    .bind(community_id)
+    .bind(conversation_id)
+    .bind(agent_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(count >= max_agents)
+}
+
+/// Whether the agent being added to a community may be used there. A connected
+/// agent (`agentId`) answers to the listings that sell it; `listingId` to its own.
+pub async fn check_community_agent_context(
+    db: &sqlx::PgPool,
+    agent_id: Option<Uuid>,
+    listing_id: Option<Uuid>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match (agent_id, listing_id) {
+        (Some(id), _) => crate::routes::agent_hub::check_agent_listing_context(db, id, "community").await,
+        (None, Some(id)) => crate::routes::agent_hub::check_listing_context(db, id, "community").await,
+        (None, None) => Ok(()),
+    }
+}
+
 async fn add_agent(
     State(state): State<AppState>,
     user: AuthUser,
@@ -1707,53 +1908,140 @@ async fn add_agent(
         None => return (StatusCode::BAD_REQUEST, Json(json!({"error": "agentId is required"}))),
     };
 
-    // Verify agent exists
-    let exists = sqlx::query_scalar::<_, bool>(
-        "SELECT EXISTS(SELECT 1 FROM agents WHERE id = $1)",
-    )
-    .bind(agent_id)
-    .fetch_one(&state.db)
-    .await
-    .unwrap_or(false);
+    // Marketplace listings may restrict which conversation types they are used in
+    if let Err(resp) = check_community_agent_context(&state.db, body.agent_id, body.listing_id).await {
+        return resp;
+    }
 
-    if !exists {
-        return (StatusCode::NOT_FOUND, Json(json!({"error": "Agent not found"})));
+    // Verify agent exists (direct path)
+    if body.agent_id.is_some() {
+        let exists = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM agents WHERE id = $1)",
+        )
+        .bind(agent_id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(false);
+
+        if !exists {
+            return (StatusCode::NOT_FOUND, Json(json!({"error": "Agent not found"})));
+        }
     }
 
-    // Add agent to conversation members
-    let conv_id = sqlx::query_scalar::<_, Uuid>(
-        "SELECT conversation_id FROM communities WHERE id = $1",
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("add_agent: begin tx failed: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Database error"})));
+        }
+    };
+
+    // Lock the community row so concurrent adds are counted one at a time
+    let conv_id = match sqlx::query_scalar::<_, Option<Uuid>>(
+        "SELECT conversation_id FROM communities WHERE id = $1 FOR UPDATE",
     )
     .bind(id)
-    .fetch_optional(&state.db)
+    .fetch_one(&mut *tx)
     .await
-    .ok()
-    .flatten();
+    {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("add_agent: lock community failed: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Database error"})));
+        }
+    };
 
-    if let Some(cid) = conv_id {
-        let listen = body.listen_mode.as_deref().unwrap_or("all");
+    // Check agent limit — re-adding an agent already present doesn't count
+    let max_agents = state.config.max_community_agents;
+    match community_agent_cap_reached(&mut *tx, id, conv_id, agent_id, max_agents).await {
+        Ok(true) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": format!("Community has reached the maximum of {} agents", max_agents)
+                })),
+            );
+        }
+        Ok(false) => {}
+        Err(e) => {
+            tracing::error!("add_agent: count agents failed: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Database error"})));
+        }
+    }
+
+    if body.agent_id.is_none() {
+        // Listing path: attach the marketplace listing to the community, after existing agents
         let result = sqlx::query(
-            r#"INSERT INTO conversation_members (conversation_id, agent_id, listen_mode, display_name, member_avatar_url)
-               VALUES ($1, $2, $3::agent_listen_mode, $4, $5)
-               ON CONFLICT (conversation_id, agent_id) WHERE agent_id IS NOT NULL
-               DO UPDATE SET listen_mode = EXCLUDED.listen_mode,
-                             display_name = COALESCE(EXCLUDED.display_name, conversation_members.display_name),
-                             member_avatar_url = COALESCE(EXCLUDED.member_avatar_url, conversation_members.member_avatar_url)"#,
+            r#"INSERT INTO community_agents (community_id, listing_id, sort_order)
+               SELECT $1, id, COALESCE(
+                   (SELECT MAX(sort_order) + 1 FROM community_agents WHERE community_id = $1), 0)
+               FROM agent_listings WHERE id = $2 AND status = 'active'
+               ON CONFLICT (community_id, listing_id) DO NOTHING"#,
         )
-        .bind(cid)
+        .bind(id)
         .bind(agent_id)
-        .bind(listen)
-        .bind(&body.display_name)
-        .bind(&body.member_avatar_url)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await;
 
-        if let Err(e) = result {
-            tracing::error!("add_agent: INSERT conversation_members failed: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to add agent"})));
+        match result {
+            Ok(r) if r.rows_affected() > 0 => {
+                if let Err(e) = tx.commit().await {
+                    tracing::error!("add_agent: commit failed: {}", e);
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to add agent"})));
+                }
+                return (StatusCode::CREATED, Json(json!({"ok": true})));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("add_agent: INSERT community_agents failed: {}", e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to add agent"})));
+            }
         }
-    } else {
+
+        // Not an active listing — fall back to treating the id as an agent
+        let exists = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM agents WHERE id = $1)",
+        )
+        .bind(agent_id)
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap_or(false);
+
+        if !exists {
+            return (StatusCode::NOT_FOUND, Json(json!({"error": "Agent not found"})));
+        }
+    }
+
+    // Add agent to conversation members
+    let Some(cid) = conv_id else {
         return (StatusCode::NOT_FOUND, Json(json!({"error": "Community has no conversation"})));
+    };
+
+    let listen = body.listen_mode.as_deref().unwrap_or("all");
+    let result = sqlx::query(
+        r#"INSERT INTO conversation_members (conversation_id, agent_id, listen_mode, display_name, member_avatar_url)
+           VALUES ($1, $2, $3::agent_listen_mode, $4, $5)
+           ON CONFLICT (conversation_id, agent_id) WHERE agent_id IS NOT NULL
+           DO UPDATE SET listen_mode = EXCLUDED.listen_mode,
+                         display_name = COALESCE(EXCLUDED.display_name, conversation_members.display_name),
+                         member_avatar_url = COALESCE(EXCLUDED.member_avatar_url, conversation_members.member_avatar_url)"#,
+    )
+    .bind(cid)
+    .bind(agent_id)
+    .bind(listen)
+    .bind(&body.display_name)
+    .bind(&body.member_avatar_url)
+    .execute(&mut *tx)
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!("add_agent: INSERT conversation_members failed: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to add agent"})));
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("add_agent: commit failed: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to add agent"})));
     }
 
     (StatusCode::CREATED, Json(json!({"ok": true})))
@@ -1818,49 +2106,156 @@ async fn remove_agent(
 }
 
 // ---------------------------------------------------------------------------
-// GET /api/communities/:id/agents — List agents
+// PUT /api/communities/:id/agents/order — Reorder listing agents (creator only)
 // ---------------------------------------------------------------------------
 
-async fn list_agents(
+#[derive(Deserialize)]
+struct ReorderAgentsBody {
+    #[serde(rename = "listingIds")]
+    listing_ids: Vec<Uuid>,
+}
+
+async fn reorder_agents(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<Uuid>,
+    Json(body): Json<ReorderAgentsBody>,
 ) -> (StatusCode, Json<Value>) {
-    // Query agents from conversation_members (direct add) + community_agents (legacy listing)
-    let conv_id = sqlx::query_scalar::<_, Uuid>(
-        "SELECT conversation_id FROM communities WHERE id = $1",
+    let is_creator = match sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM communities WHERE id = $1 AND creator_id = $2)",
     )
     .bind(id)
-    .fetch_optional(&state.db)
+    .bind(&user.id)
+    .fetch_one(&state.db)
     .await
-    .ok()
-    .flatten();
-
-    // Direct agents from conversation_members
-    let direct_agents = if let Some(cid) = conv_id {
-        sqlx::query_as::<_, (Uuid, String, Option<String>, Option<String>, Option<String>, Option<String>, String)>(
-            r#"SELECT a.id, a.name, a.avatar_url, cm.display_name, cm.member_avatar_url, cm.listen_mode::text, a.owner_id
-               FROM conversation_members cm
-               JOIN agents a ON a.id = cm.agent_id
-               WHERE cm.conversation_id = $1"#,
-        )
-        .bind(cid)
-        .fetch_all(&state.db)
-        .await
-        .unwrap_or_default()
-    } else {
-        vec![]
+    {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("reorder_agents: creator check failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
     };
 
-    // Legacy agents from community_agents + agent_listings
-    let rows = sqlx::query_as::<_, CommunityAgentRow>(
-        r#"SELECT ca.id, ca.listing_id, l.agent_name, l.avatar_url,
-                  l.description, l.model, ca.added_at
-           FROM community_agents ca
-           JOIN agent_listings l ON ca.listing_id = l.id
-           WHERE ca.community_id = $1
-           ORDER BY ca.added_at ASC"#,
-    )
-    .bind(id)
+    if !is_creator {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "error": "Only the creator can reorder agents" })),
+        );
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    if !body.listing_ids.iter().all(|lid| seen.insert(*lid)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "listingIds must not contain duplicates" })),
+        );
+    }
+
+    let existing = match sqlx::query_scalar::<_, Uuid>(
+        "SELECT listing_id FROM community_agents WHERE community_id = $1",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(ids) => ids.into_iter().collect::<std::collections::HashSet<_>>(),
+        Err(e) => {
+            tracing::error!("reorder_agents: fetch agents failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    };
+
+    let unknown: Vec<&Uuid> = body.listing_ids.iter().filter(|lid| !existing.contains(lid)).collect();
+    if !unknown.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "Some agents are not in this community",
+                "listingIds": unknown,
+            })),
+        );
+    }
+
+    // Listed agents take positions 0..n; any left out follow them in added order
+    let result = sqlx::query(
+        r#"UPDATE community_agents ca
+           SET sort_order = COALESCE(
+               (SELECT o.ord - 1 FROM unnest($2::uuid[]) WITH ORDINALITY AS o(listing_id, ord)
+                WHERE o.listing_id = ca.listing_id),
+               $3)
+           WHERE ca.community_id = $1"#,
+    )
+    .bind(id)
+    .bind(&body.listing_ids)
+    .bind(body.listing_ids.len() as i32)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "success": true, "listingIds": body.listing_ids })),
+        ),
+        Err(e) => {
+            tracing::error!("reorder_agents: update failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to reorder agents" })),
+            )
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// GET /api/communities/:id/agents — List agents
+// ---------------------------------------------------------------------------
+
+async fn list_agents(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<Value>) {
+    // Query agents from conversation_members (direct add) + community_agents (legacy listing)
+    let conv_id = sqlx::query_scalar::<_, Uuid>(
+        "SELECT conversation_id FROM communities WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .ok()
+    .flatten();
+
+    // Direct agents from conversation_members
+    let direct_agents = if let Some(cid) = conv_id {
+        sqlx::query_as::<_, (Uuid, String, Option<String>, Option<String>, Option<String>, Option<String>, String)>(
+            r#"SELECT a.id, a.name, a.avatar_url, cm.display_name, cm.member_avatar_url, cm.listen_mode::text, a.owner_id
+               FROM conversation_members cm
+               JOIN agents a ON a.id = cm.agent_id
+               WHERE cm.conversation_id = $1"#,
+        )
+        .bind(cid)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default()
+    } else {
+        vec![]
+    };
+
+    // Legacy agents from community_agents + agent_listings
+    let rows = sqlx::query_as::<_, CommunityAgentRow>(
+        r#"SELECT ca.id, ca.listing_id, l.agent_name, l.avatar_url,
+                  l.description, l.model, ca.sort_order, ca.added_at
+           FROM community_agents ca
+           JOIN agent_listings l ON ca.listing_id = l.id
+           WHERE ca.community_id = $1
+           ORDER BY ca.sort_order ASC, ca.added_at ASC"#,
+    )
+    .bind(id)
     .fetch_all(&state.db)
     .await;
 
@@ -1876,6 +2271,7 @@ async fn list_agents(
                         "avatarUrl": r.avatar_url,
                         "description": r.description,
                         "model": r.model,
+                        "sortOrder": r.sort_order,
                         "addedAt": r.added_at.to_rfc3339(),
                     })
                 })
@@ -1911,6 +2307,87 @@ async fn list_agents(
     }
 }
 
+// ---------------------------------------------------------------------------
+// GET /api/communities/:id/agents/:listing_id/preview — Free agent preview
+// ---------------------------------------------------------------------------
+
+#[derive(sqlx::FromRow)]
+struct AgentPreviewRow {
+    agent_name: String,
+    avatar_url: Option<String>,
+    description: String,
+    model: String,
+    welcome_message: Option<String>,
+    example_conversations: Value,
+    agent_call_fee: i32,
+}
+
+/// What a member gets for the agent-call fee, without calling the LLM or charging.
+async fn preview_agent(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((community_id, listing_id)): Path<(Uuid, Uuid)>,
+) -> (StatusCode, Json<Value>) {
+    match is_member_or_creator(&state.db, community_id, &user.id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "You must be a member" })),
+            );
+        }
+        Err(e) => {
+            tracing::error!("preview_agent: membership check failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            );
+        }
+    }
+
+    // Joining through community_agents ensures the agent belongs to this community
+    let row = sqlx::query_as::<_, AgentPreviewRow>(
+        r#"SELECT l.agent_name, l.avatar_url, l.description, l.model, l.welcome_message,
+                  l.example_conversations, c.agent_call_fee
+           FROM community_agents ca
+           JOIN agent_listings l ON l.id = ca.listing_id
+           JOIN communities c ON c.id = ca.community_id
+           WHERE ca.community_id = $1 AND ca.listing_id = $2"#,
+    )
+    .bind(community_id)
+    .bind(listing_id)
+    .fetch_optional(&state.db)
+    .await;
+
+    match row {
+        Ok(Some(r)) => (
+            StatusCode::OK,
+            Json(json!({
+                "listingId": listing_id,
+                "agentName": r.agent_name,
+                "avatarUrl": r.avatar_url,
+                "description": r.description,
+                "model": r.model,
+                "welcomeMessage": r.welcome_message,
+                "exampleConversations": crate::routes::agent_hub::stored_example_conversations(&r.example_conversations),
+                "agentCallFee": r.agent_call_fee,
+                "currency": crate::routes::wallet::currency_json(&state.config),
+            })),
+        ),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Agent is not in this community" })),
+        ),
+        Err(e) => {
+            tracing::error!("preview_agent: fetch listing failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            )
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // POST /api/communities/:id/messages — Send text message
 // ---------------------------------------------------------------------------
@@ -2001,12 +2478,142 @@ struct AgentChatInfo {
     model: String,
     input_char_limit: i32,
     tts_voice: Option<String>,
+    fallback_models: Vec<String>,
+    model_provider: String,
+}
+
+/// Messages of community history sent to an agent: the community
+/// conversation's `history_limit`, or 30 when unset, clamped to the global cap.
+pub async fn community_history_limit(db: &sqlx::PgPool, community_id: Uuid) -> i32 {
+    sqlx::query_scalar::<_, Option<i32>>(
+        r#"SELECT c.history_limit FROM conversations c
+           JOIN communities co ON co.conversation_id = c.id
+           WHERE co.id = $1"#,
+    )
+    .bind(community_id)
+    .fetch_optional(db)
+    .await
+    .ok()
+    .flatten()
+    .flatten()
+    .unwrap_or(30)
+    .clamp(0, crate::config::MAX_HISTORY_LIMIT)
+}
+
+/// Steps 7–8 of `agent_chat`: store the user's message and load the context window.
+async fn prepare_agent_chat_context(
+    db: &sqlx::PgPool,
+    community_id: Uuid,
+    user_id: &str,
+    content: &str,
+) -> Result<(Uuid, Vec<(Option<String>, Option<Uuid>, String)>), (StatusCode, Json<Value>)> {
+    // 7. Store user message
+    let user_msg_id = sqlx::query_scalar::<_, Uuid>(
+        r#"INSERT INTO community_messages (community_id, user_id, content, message_type)
+           VALUES ($1, $2, $3, 'text')
+           RETURNING id"#,
+    )
+    .bind(community_id)
+    .bind(user_id)
+    .bind(content)
+    .fetch_one(db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Agent chat: store user message failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to store message" })),
+        )
+    })?;
+
+    // 8. Load recent messages for context
+    let history_limit = community_history_limit(db, community_id).await;
+
+    let history = sqlx::query_as::<_, (Option<String>, Option<Uuid>, String)>(
+        r#"SELECT user_id, agent_listing_id, content FROM (
+               SELECT user_id, agent_listing_id, content, created_at
+               FROM community_messages
+               WHERE community_id = $1 AND message_type = 'text'
+               ORDER BY created_at DESC LIMIT $2
+           ) sub ORDER BY created_at ASC"#,
+    )
+    .bind(community_id)
+    .bind(history_limit as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|e| {
+        tracing::error!("agent_chat: load history failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load message history" })),
+        )
+    })?;
+
+    Ok((user_msg_id, history))
+}
+
+/// Settle a held agent-call fee once the stream ends: charge it (crediting the
+/// community creator's share) if the agent replied, otherwise give it back.
+/// Returns whether the user was charged.
+async fn settle_agent_call_hold(
+    db: &sqlx::PgPool,
+    redis: &deadpool_redis::Pool,
+    hold: Option<(wallet::Hold, Option<(String, String)>)>,
+    replied: bool,
+    community_id: Uuid,
+    agent_name: &str,
+) -> bool {
+    let Some((hold, idem)) = hold else {
+        return false;
+    };
+    if !replied {
+        release_agent_call_hold(db, redis, &hold, idem.as_ref()).await;
+        return false;
+    }
+
+    let creator_id = sqlx::query_scalar::<_, String>("SELECT creator_id FROM communities WHERE id = $1")
+        .bind(community_id)
+        .fetch_optional(db)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("agent_chat: fetch creator_id failed: {}", e);
+            None
+        });
+    let settlement = wallet::Settlement {
+        tx_type: "community_agent_call",
+        description: format!("Agent call: {}", agent_name),
+        related_app_id: None,
+        creator: creator_id.map(|cid| (cid, "Community agent call earning".to_string())),
+    };
+    match wallet::commit_hold(db, &hold, &settlement).await {
+        Ok(committed) => committed,
+        Err(e) => {
+            tracing::error!("agent_chat: commit hold {} failed: {}", hold.id, e);
+            false
+        }
+    }
+}
+
+/// Return a held agent-call fee and forget the Idempotency-Key so the client can retry.
+async fn release_agent_call_hold(
+    db: &sqlx::PgPool,
+    redis: &deadpool_redis::Pool,
+    hold: &wallet::Hold,
+    idem: Option<&(String, String)>,
+) {
+    if let Err(e) = wallet::release_hold(db, hold).await {
+        tracing::error!("agent_chat: release hold {} failed: {}", hold.id, e);
+    }
+    if let Some((scope, key)) = idem {
+        idempotency::release(redis, scope, &hold.user_id, key).await;
+    }
 }
 
 async fn agent_chat(
     State(state): State<AppState>,
     user: AuthUser,
     Path(community_id): Path<Uuid>,
+    headers: axum::http::HeaderMap,
     Json(body): Json<AgentChatBody>,
 ) -> Result<Sse<impl futures::Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<Value>)>
 {
@@ -2053,7 +2660,8 @@ async fn agent_chat(
 
     // 3. Fetch agent listing info
     let listing = sqlx::query_as::<_, AgentChatInfo>(
-        r#"SELECT agent_name, system_prompt, model, input_char_limit, tts_voice
+        r#"SELECT agent_name, system_prompt, model, input_char_limit, tts_voice, fallback_models,
+                  model_provider
            FROM agent_listings WHERE id = $1 AND status = 'active'"#,
     )
     .bind(body.listing_id)
@@ -2084,9 +2692,18 @@ async fn agent_chat(
         ));
     }
 
-    // 5. Ensure OpenRouter API key (check BEFORE billing)
-    let openrouter_key = state.config.openrouter_api_key.as_deref().ok_or_else(|| {
-        tracing::error!("Agent chat: OPENROUTER_API_KEY not configured");
+    // 5. Ensure the listing's provider API key (check BEFORE billing)
+    let provider = if listing.model_provider == "anthropic" {
+        llm::LlmProvider::Anthropic
+    } else {
+        llm::LlmProvider::OpenAI
+    };
+    let provider_key = match provider {
+        llm::LlmProvider::Anthropic => state.config.anthropic_api_key.as_deref(),
+        llm::LlmProvider::OpenAI => state.config.openrouter_api_key.as_deref(),
+    };
+    let provider_key = provider_key.ok_or_else(|| {
+        tracing::error!("Agent chat: no API key configured for provider {}", listing.model_provider);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({ "error": "LLM service not configured" })),
@@ -2108,164 +2725,77 @@ async fn agent_chat(
         )
     })?;
 
+    let mut hold: Option<(wallet::Hold, Option<(String, String)>)> = None;
     if community_fee > 0 {
-        // Wrap entire billing flow in a transaction
-        let mut tx = state.db.begin().await.map_err(|e| {
-            tracing::error!("agent_chat: begin billing tx failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Database error" })),
-            )
-        })?;
-
-        // Atomic deduction
-        let deducted = sqlx::query_scalar::<_, i32>(
-            r#"UPDATE coin_balances
-               SET balance = balance - $2, updated_at = NOW()
-               WHERE user_id = $1 AND balance >= $2
-               RETURNING balance"#,
-        )
-        .bind(&user.id)
-        .bind(community_fee)
-        .fetch_optional(&mut *tx)
-        .await
-        .map_err(|e| {
-            tracing::error!("agent_chat: deduct fee failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Payment failed" })),
-            )
-        })?;
+        // Idempotency-Key: a retried call replays the cached billing result
+        // instead of charging again.
+        let idem_key = idempotency::key_from_headers(&headers);
+        let scope = format!("community_agent_chat:{}", community_id);
+        if let Some(key) = &idem_key {
+            match idempotency::claim(&state.redis, &scope, &user.id, key).await {
+                idempotency::Claim::Replay(status, cached) if !status.is_success() => {
+                    return Err((status, Json(cached)));
+                }
+                idempotency::Claim::Replay(_, cached) => {
+                    // Replay as the stream's closing `done` event so SSE clients handle it unchanged
+                    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Event, Infallible>>(1);
+                    let _ = tx.try_send(Ok(Event::default().data(cached.to_string())));
+                    return Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()));
+                }
+                idempotency::Claim::InProgress => {
+                    return Err((StatusCode::CONFLICT, Json(idempotency::in_progress_body())));
+                }
+                idempotency::Claim::Acquired => {}
+            }
+        }
 
-        if deducted.is_none() {
-            return Err((
+        // Reserve the fee now; it is settled once the stream finishes and
+        // returned to the user if the LLM call fails.
+        let held = match wallet::hold_coins(&state.db, &user.id, community_fee).await {
+            Ok(h) => Ok(h),
+            Err(wallet::HoldError::InsufficientBalance) => Err((
                 StatusCode::PAYMENT_REQUIRED,
                 Json(json!({ "error": "Insufficient balance for agent call" })),
-            ));
-        }
-
-        // Record transaction
-        sqlx::query(
-            r#"INSERT INTO coin_transactions (user_id, type, amount, description)
-               VALUES ($1, 'community_agent_call', $2, $3)"#,
-        )
-        .bind(&user.id)
-        .bind(-community_fee)
-        .bind(format!("Agent call: {}", listing.agent_name))
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| {
-            tracing::error!("agent_chat: record transaction failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Failed to record transaction" })),
-            )
-        })?;
-
-        // Creator gets 70%
-        let creator_id = sqlx::query_scalar::<_, String>(
-            "SELECT creator_id FROM communities WHERE id = $1",
-        )
-        .bind(community_id)
-        .fetch_optional(&mut *tx)
-        .await
-        .map_err(|e| {
-            tracing::error!("agent_chat: fetch creator_id failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Database error" })),
-            )
-        })?;
-
-        if let Some(cid) = creator_id {
-            let creator_share = community_fee * 7 / 10;
-            sqlx::query(
-                r#"INSERT INTO coin_balances (user_id, balance, updated_at)
-                   VALUES ($1, $2, NOW())
-                   ON CONFLICT (user_id) DO UPDATE
-                   SET balance = coin_balances.balance + $2, updated_at = NOW()"#,
-            )
-            .bind(&cid)
-            .bind(creator_share)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                tracing::error!("agent_chat: credit creator balance failed: {}", e);
-                (
+            )),
+            Err(wallet::HoldError::Database(e)) => {
+                tracing::error!("agent_chat: hold fee failed: {}", e);
+                Err((
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({ "error": "Failed to credit creator" })),
-                )
-            })?;
+                    Json(json!({ "error": "Payment failed" })),
+                ))
+            }
+        };
 
-            sqlx::query(
-                r#"INSERT INTO coin_transactions (user_id, type, amount, description)
-                   VALUES ($1, 'earning', $2, 'Community agent call earning')"#,
-            )
-            .bind(&cid)
-            .bind(creator_share)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                tracing::error!("agent_chat: record creator earning failed: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({ "error": "Failed to record transaction" })),
-                )
-            })?;
+        if let Some(key) = &idem_key {
+            match &held {
+                Ok(_) => {
+                    let cached = json!({ "type": "done", "charged": true, "duplicate": true });
+                    idempotency::complete(&state.redis, &scope, &user.id, key, StatusCode::OK, &cached).await;
+                }
+                Err(_) => idempotency::release(&state.redis, &scope, &user.id, key).await,
+            }
         }
-
-        tx.commit().await.map_err(|e| {
-            tracing::error!("agent_chat: commit billing tx failed: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Billing commit failed" })),
-            )
-        })?;
+        hold = Some((held?, idem_key.map(|k| (scope, k))));
     }
 
-    // 7. Store user message
-    let user_msg_id = sqlx::query_scalar::<_, Uuid>(
-        r#"INSERT INTO community_messages (community_id, user_id, content, message_type)
-           VALUES ($1, $2, $3, 'text')
-           RETURNING id"#,
-    )
-    .bind(community_id)
-    .bind(&user.id)
-    .bind(&body.content)
-    .fetch_one(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Agent chat: store user message failed: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": "Failed to store message" })),
-        )
-    })?;
-
-    // 8. Load last 30 messages for context
-    let history = sqlx::query_as::<_, (Option<String>, Option<Uuid>, String)>(
-        r#"SELECT user_id, agent_listing_id, content FROM (
-               SELECT user_id, agent_listing_id, content, created_at
-               FROM community_messages
-               WHERE community_id = $1 AND message_type = 'text'
-               ORDER BY created_at DESC LIMIT 30
-           ) sub ORDER BY created_at ASC"#,
-    )
-    .bind(community_id)
-    .fetch_all(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("agent_chat: load history failed: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": "Failed to load message history" })),
-        )
-    })?;
+    // Steps 7–8 can fail after the fee is held; give it back if they do
+    let prepared = prepare_agent_chat_context(&state.db, community_id, &user.id, &body.content).await;
+    let (user_msg_id, history) = match prepared {
+        Ok(v) => v,
+        Err(err) => {
+            if let Some((h, idem)) = &hold {
+                release_agent_call_hold(&state.db, &state.redis, h, idem.as_ref()).await;
+            }
+            return Err(err);
+        }
+    };
 
     // 9. Build LLM messages
+    let kb_chunks =
+        crate::services::embedding::kb_context_chunks(&state.db, &state.config, body.listing_id, &body.content).await;
     let mut llm_messages = vec![llm::ChatMessage {
         role: "system".into(),
-        content: listing.system_prompt.clone(),
+        content: crate::services::embedding::compose_system_prompt(&listing.system_prompt, None, &kb_chunks),
     }];
 
     for (uid, agent_lid, content) in &history {
@@ -2292,7 +2822,11 @@ async fn agent_chat(
     // 10. Setup SSE stream
     let (tx, rx) = tokio::sync::mpsc::channel::<Result<Event, Infallible>>(32);
     let db = state.db.clone();
-    let api_key = openrouter_key.to_string();
+    let redis = state.redis.clone();
+    let agent_name = listing.agent_name.clone();
+    let api_key = provider_key.to_string();
+    let retry_policy = openrouter::RetryPolicy::from_config(&state.config);
+    let fallback_models = listing.fallback_models.clone();
     let listing_id = body.listing_id;
     let s3_clone = state.s3.clone();
     let config_clone = state.config.clone();
@@ -2311,19 +2845,70 @@ async fn agent_chat(
             )))
             .await;
 
-        // Call OpenRouter
-        let mut stream = match openrouter::call_stream(&api_key, &or_opts).await {
+        // Call the LLM — Anthropic directly, or OpenRouter with the fallback chain.
+        // Errors carry the provider's Retry-After (OpenRouter only).
+        let started = match provider {
+            llm::LlmProvider::Anthropic => {
+                let opts = llm::LlmCallOptions {
+                    provider: llm::LlmProvider::Anthropic,
+                    model: llm::anthropic_model_id(&or_opts.model),
+                    api_key: api_key.clone(),
+                    messages: or_opts.messages.clone(),
+                    max_tokens: or_opts.max_tokens,
+                    temperature: or_opts.temperature,
+                };
+                llm::call_llm_stream(&opts).await.map_err(|e| (e, None))
+            }
+            llm::LlmProvider::OpenAI => {
+                match openrouter::call_stream_with_fallback(
+                    &api_key,
+                    &or_opts,
+                    &fallback_models,
+                    &retry_policy,
+                )
+                .await
+                {
+                    Ok((s, model)) => {
+                        if model != or_opts.model {
+                            let _ = tx
+                                .send(Ok(Event::default().data(
+                                    json!({
+                                        "type": "model_fallback",
+                                        "requestedModel": or_opts.model,
+                                        "model": model,
+                                    })
+                                    .to_string(),
+                                )))
+                                .await;
+                        }
+                        Ok(s)
+                    }
+                    Err(e) => Err((e.to_string(), e.retry_after)),
+                }
+            }
+        };
+
+        let mut stream = match started {
             Ok(s) => s,
-            Err(e) => {
-                tracing::error!("Agent chat: OpenRouter failed: {}", e);
+            Err((e, retry_after)) => {
+                tracing::error!("Agent chat: LLM call failed: {}", e);
+                settle_agent_call_hold(&db, &redis, hold, false, community_id, &agent_name).await;
                 let _ = tx
                     .send(Ok(Event::default().data(
-                        json!({"type": "error", "message": "LLM request failed"}).to_string(),
+                        json!({
+                            "type": "error",
+                            "message": "LLM request failed",
+                            "retryAfter": retry_after,
+                        })
+                        .to_string(),
                     )))
                     .await;
+                let _ = tx
+                    .send(Ok(Event::default().data(billing::usage_event(0, false).to_string())))
+                    .await;
                 let _ = tx
                     .send(Ok(Event::default().data(
-                        json!({"type": "done"}).to_string(),
+                        json!({"type": "done", "charged": false}).to_string(),
                     )))
                     .await;
                 return;
@@ -2342,7 +2927,7 @@ async fn agent_chat(
                         buffer = buffer[pos + 1..].to_string();
 
                         if let Some(data) = line.strip_prefix("data: ") {
-                            let text = llm::parse_openai_chunk(data);
+                            let text = llm::parse_chunk(&provider, data);
                             if let Some(ref t) = text {
                                 full_content.push_str(t);
                                 let _ = tx
@@ -2385,11 +2970,23 @@ async fn agent_chat(
 
         }
 
+        // Only a delivered reply costs the user
+        let charged_amount = hold.as_ref().map_or(0, |(h, _)| h.amount);
+        let charged =
+            settle_agent_call_hold(&db, &redis, hold, msg_id.is_some(), community_id, &agent_name).await;
+        let charged_amount = if charged { charged_amount } else { 0 };
+
+        let _ = tx
+            .send(Ok(Event::default().data(
+                billing::usage_event(charged_amount, false).to_string(),
+            )))
+            .await;
+
         // Send done event BEFORE TTS (so the user sees the reply immediately)
         let _ = tx
-            .send(Ok(
-                Event::default().data(json!({"type": "done"}).to_string())
-            ))
+            .send(Ok(Event::default().data(
+                json!({"type": "done", "charged": charged}).to_string(),
+            )))
             .await;
 
         // TTS: generate audio in background (non-blocking, silent on failure)
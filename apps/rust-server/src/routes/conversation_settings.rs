@@ -10,6 +10,7 @@ use serde_json::json;
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
+use crate::config::MAX_HISTORY_LIMIT;
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
@@ -34,6 +35,8 @@ struct ConversationSettings {
     chat_bg_url: Option<String>,
     pinned_buttons: Option<Vec<String>>,
     kanban_board_id: Option<Uuid>,
+    /// Effective agent history window for this conversation.
+    history_limit: i32,
 }
 
 /// Use a wrapper so we can distinguish "field absent" from "field = null".
@@ -47,10 +50,13 @@ struct UpdateSettingsBody {
     pinned_buttons: Option<Option<Vec<String>>>,
     #[serde(deserialize_with = "deserialize_optional_field", default)]
     kanban_board_id: Option<Option<Uuid>>,
+    /// Conversation-wide (owner only). Explicit null resets to the server default.
+    #[serde(deserialize_with = "deserialize_optional_field", default)]
+    history_limit: Option<Option<i32>>,
 }
 
 /// Deserialise a field that may be absent, null, or present.
-fn deserialize_optional_field<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+pub(crate) fn deserialize_optional_field<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
 where
     D: serde::Deserializer<'de>,
     T: Deserialize<'de>,
@@ -58,6 +64,45 @@ where
     Ok(Some(Option::deserialize(deserializer)?))
 }
 
+/// Rejected `historyLimit` value; renders as a 400 with the allowed range.
+struct HistoryLimitOutOfRange;
+
+impl IntoResponse for HistoryLimitOutOfRange {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!(
+                    "historyLimit must be between 0 and {}. Each history message is re-sent to the agent on every reply, so larger windows increase token cost and latency.",
+                    MAX_HISTORY_LIMIT
+                )
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Reject history windows above the cap — every history message is re-sent to the
+/// agent on each reply, so the window directly drives per-reply token cost.
+fn validate_history_limit(limit: i32) -> Result<i32, HistoryLimitOutOfRange> {
+    if !(0..=MAX_HISTORY_LIMIT).contains(&limit) {
+        return Err(HistoryLimitOutOfRange);
+    }
+    Ok(limit)
+}
+
+/// Effective history window for a conversation (per-conversation override or server default).
+async fn effective_history_limit(state: &AppState, conversation_id: Uuid) -> i32 {
+    sqlx::query_scalar::<_, Option<i32>>("SELECT history_limit FROM conversations WHERE id = $1")
+        .bind(conversation_id)
+        .fetch_optional(&state.db)
+        .await
+        .ok()
+        .flatten()
+        .flatten()
+        .unwrap_or(state.config.default_history_limit)
+}
+
 async fn get_settings(
     State(state): State<AppState>,
     user: AuthUser,
@@ -71,12 +116,14 @@ async fn get_settings(
     .fetch_optional(&state.db)
     .await;
 
+    let history_limit = effective_history_limit(&state, conversation_id).await;
+
     match row {
         Ok(Some((chat_bg_url, pinned_buttons, kanban_board_id))) => {
-            (StatusCode::OK, Json(json!(ConversationSettings { chat_bg_url, pinned_buttons, kanban_board_id }))).into_response()
+            (StatusCode::OK, Json(json!(ConversationSettings { chat_bg_url, pinned_buttons, kanban_board_id, history_limit }))).into_response()
         }
         Ok(None) => {
-            (StatusCode::OK, Json(json!(ConversationSettings { chat_bg_url: None, pinned_buttons: None, kanban_board_id: None }))).into_response()
+            (StatusCode::OK, Json(json!(ConversationSettings { chat_bg_url: None, pinned_buttons: None, kanban_board_id: None, history_limit }))).into_response()
         }
         Err(e) => {
             tracing::error!("get_conversation_settings: {}", e);
@@ -95,6 +142,40 @@ async fn update_settings(
     let pins_val = body.pinned_buttons;
     let kanban_val = body.kanban_board_id;
 
+    // history_limit lives on the conversation itself, so only the owner may change it
+    if let Some(limit) = body.history_limit {
+        let is_owner = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM conversations WHERE id = $1 AND user_id = $2)",
+        )
+        .bind(conversation_id)
+        .bind(&user.id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(false);
+
+        if !is_owner {
+            return (StatusCode::FORBIDDEN, Json(json!({"error": "Only conversation owner can change historyLimit"}))).into_response();
+        }
+
+        let limit = match limit {
+            Some(l) => match validate_history_limit(l) {
+                Ok(l) => Some(l),
+                Err(e) => return e.into_response(),
+            },
+            None => None,
+        };
+
+        if let Err(e) = sqlx::query("UPDATE conversations SET history_limit = $1 WHERE id = $2")
+            .bind(limit)
+            .bind(conversation_id)
+            .execute(&state.db)
+            .await
+        {
+            tracing::error!("update_conversation_settings history_limit: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Database error"}))).into_response();
+        }
+    }
+
     // Fetch current values, merge with provided fields, then upsert
     let current = sqlx::query_as::<_, (Option<String>, Option<Vec<String>>, Option<Uuid>)>(
         "SELECT chat_bg_url, pinned_buttons, kanban_board_id FROM conversation_user_settings WHERE user_id = $1 AND conversation_id = $2",
@@ -128,11 +209,14 @@ async fn update_settings(
     .execute(&state.db)
     .await;
 
+    let history_limit = effective_history_limit(&state, conversation_id).await;
+
     match result {
         Ok(_) => (StatusCode::OK, Json(json!({
             "chatBgUrl": final_bg,
             "pinnedButtons": final_pins,
             "kanbanBoardId": final_kanban,
+            "historyLimit": history_limit,
         }))).into_response(),
         Err(e) => {
             tracing::error!("update_conversation_settings: {}", e);
@@ -248,8 +332,8 @@ async fn get_general(
     Path(conversation_id): Path<Uuid>,
 ) -> Response {
     // Verify ownership / membership
-    let row = sqlx::query_as::<_, (i32,)>(
-        r#"SELECT COALESCE(history_limit, 5)
+    let row = sqlx::query_as::<_, (Option<i32>,)>(
+        r#"SELECT history_limit
            FROM conversations
            WHERE id = $1 AND (
              user_id = $2
@@ -263,6 +347,7 @@ async fn get_general(
 
     match row {
         Ok(Some((history_limit,))) => {
+            let history_limit = history_limit.unwrap_or(state.config.default_history_limit);
             Json(json!({ "historyLimit": history_limit })).into_response()
         }
         Ok(None) => {
@@ -302,17 +387,20 @@ async fn update_general(
     }
 
     if let Some(limit) = body.history_limit {
-        let clamped = limit.clamp(0, 50);
+        let limit = match validate_history_limit(limit) {
+            Ok(l) => l,
+            Err(e) => return e.into_response(),
+        };
         let result = sqlx::query(
             "UPDATE conversations SET history_limit = $1 WHERE id = $2",
         )
-        .bind(clamped)
+        .bind(limit)
         .bind(conversation_id)
         .execute(&state.db)
         .await;
 
         match result {
-            Ok(_) => Json(json!({ "historyLimit": clamped })).into_response(),
+            Ok(_) => Json(json!({ "historyLimit": limit })).into_response(),
             Err(e) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))).into_response()
             }
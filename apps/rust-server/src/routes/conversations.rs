@@ -2,7 +2,7 @@ use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Json, Response},
-    routing::{delete, get, put},
+    routing::{delete, get, post, put},
     Router,
 };
 use chrono::NaiveDateTime;
@@ -13,6 +13,7 @@ use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
 use crate::db::models::Conversation;
+use crate::services::push_trigger::PUSH_MODES;
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
@@ -25,6 +26,7 @@ pub fn router() -> Router<AppState> {
             "/api/conversations/{id}",
             get(get_conversation)
                 .put(update_conversation)
+                .patch(rename_conversation)
                 .delete(delete_conversation),
         )
         .route(
@@ -32,8 +34,16 @@ pub fn router() -> Router<AppState> {
             delete(clear_messages),
         )
         .route("/api/conversations/{id}/read", put(mark_read))
+        .route("/api/conversations/read-all", post(mark_all_read))
         .route("/api/conversations/{id}/mute", put(toggle_mute))
+        .route("/api/conversations/{id}/agent-streams/mute", put(toggle_agent_streams_mute))
+        .route("/api/conversations/{id}/push-mode", put(set_push_mode))
+        .route("/api/conversations/{id}/pin", post(pin_conversation))
+        .route("/api/conversations/{id}/unpin", post(unpin_conversation))
+        .route("/api/conversations/{id}/archive", post(archive_conversation))
+        .route("/api/conversations/{id}/unarchive", post(unarchive_conversation))
         .route("/api/conversations/{id}/status", get(get_status))
+        .route("/api/conversations/{id}/stats", get(get_stats))
         .route("/api/conversations/hidden", get(list_hidden_conversations))
         .route("/api/conversations/{id}/unhide", put(unhide_conversation))
         .route("/api/conversations/{id}/subscription", get(get_subscription))
@@ -51,9 +61,14 @@ struct CreateConversationBody {
     title: Option<String>,
 }
 
+/// Most conversations a user can have pinned at once.
+pub const MAX_PINNED_CONVERSATIONS: i64 = 10;
+
 #[derive(Deserialize)]
 struct ListQuery {
     q: Option<String>,
+    /// `true` lists only archived conversations; otherwise they are excluded.
+    archived: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -64,11 +79,22 @@ struct UpdateConversationBody {
     mention_only: Option<bool>,
 }
 
+#[derive(Deserialize)]
+struct RenameConversationBody {
+    title: String,
+}
+
 #[derive(Deserialize)]
 struct MuteBody {
     muted: bool,
 }
 
+#[derive(Deserialize)]
+struct PushModeBody {
+    #[serde(rename = "pushMode")]
+    push_mode: String,
+}
+
 /// Row type for the list conversations query (conversation + agent + last message).
 #[derive(Debug, FromRow)]
 struct ConversationListRow {
@@ -81,6 +107,7 @@ struct ConversationListRow {
     agent_id: Option<Uuid>,
     mention_only: bool,
     pinned_at: Option<NaiveDateTime>,
+    archived_at: Option<NaiveDateTime>,
     created_at: NaiveDateTime,
     updated_at: NaiveDateTime,
     // Agent fields
@@ -199,7 +226,8 @@ async fn create_conversation(
     }
 }
 
-/// Create human-to-human direct conversation (requires friendship)
+/// Create human-to-human direct conversation; the target's `who_can_message`
+/// setting decides whether friendship is required
 async fn create_human_direct(
     state: &AppState,
     user: &AuthUser,
@@ -213,21 +241,37 @@ async fn create_human_direct(
             .into_response();
     }
 
-    // Check friendship (must be accepted)
-    let friendship = sqlx::query_as::<_, (Uuid,)>(
-        r#"SELECT id FROM friendships
-           WHERE status = 'accepted'
+    // Friendship status between the two: accepted, blocked, or none
+    let statuses = sqlx::query_scalar::<_, String>(
+        r#"SELECT status::text FROM friendships
+           WHERE status IN ('accepted', 'blocked')
              AND ((requester_id = $1 AND addressee_id = $2) OR (requester_id = $2 AND addressee_id = $1))"#,
     )
     .bind(&user.id)
     .bind(target_user_id)
-    .fetch_optional(&state.db)
-    .await;
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
 
-    if !matches!(friendship, Ok(Some(_))) {
+    if statuses.iter().any(|s| s == "blocked") {
         return (
             StatusCode::FORBIDDEN,
-            Json(json!({"error": "You must be friends to start a direct conversation"})),
+            Json(json!({"error": "Cannot start a direct conversation with this user"})),
+        )
+            .into_response();
+    }
+
+    let are_friends = statuses.iter().any(|s| s == "accepted");
+    let privacy = crate::routes::users::privacy_settings(&state.db, target_user_id).await;
+    if !privacy.allows_direct_message(are_friends) {
+        let error = if privacy.who_can_message == "nobody" {
+            "This user is not accepting direct messages"
+        } else {
+            "You must be friends to start a direct conversation"
+        };
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": error, "code": "privacy"})),
         )
             .into_response();
     }
@@ -318,7 +362,8 @@ async fn list_conversations(
                 c.user_id,
                 c.agent_id,
                 c.mention_only,
-                c.pinned_at,
+                cr_me.pinned_at,
+                cr_me.archived_at,
                 c.created_at,
                 c.updated_at,
                 a.name AS agent_name,
@@ -348,6 +393,7 @@ async fn list_conversations(
             LEFT JOIN account_subscribers asub ON asub.conversation_id = c.id AND asub.user_id = $1
             LEFT JOIN account_subscribers asub_any ON asub_any.conversation_id = c.id
             LEFT JOIN accounts owner_acc ON owner_acc.id = asub_any.account_id AND owner_acc.owner_id = $1
+            LEFT JOIN conversation_reads cr_me ON cr_me.conversation_id = c.id AND cr_me.user_id = $1
             LEFT JOIN LATERAL (
                 SELECT m.id, m.seq, m.role, m.content, m.status, m.metadata, m.created_at, m.updated_at, m.sender_agent_id
                 FROM messages m
@@ -360,15 +406,17 @@ async fn list_conversations(
                 SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = c.id AND cum.user_id = $1
             ))
               AND (c.title ILIKE $2 OR a.name ILIKE $2)
+              AND (cr_me.archived_at IS NOT NULL) = $3
               AND NOT EXISTS (
                 SELECT 1 FROM conversation_user_members h
                 WHERE h.conversation_id = c.id AND h.user_id = $1
                   AND h.hidden_at IS NOT NULL AND h.hidden_at >= c.updated_at
               )
-            ORDER BY c.pinned_at DESC NULLS LAST, c.updated_at DESC"#,
+            ORDER BY cr_me.pinned_at DESC NULLS LAST, c.updated_at DESC"#,
         )
         .bind(&user.id)
         .bind(&pattern)
+        .bind(params.archived.unwrap_or(false))
         .fetch_all(&state.db)
         .await
     } else {
@@ -380,7 +428,8 @@ async fn list_conversations(
                 c.user_id,
                 c.agent_id,
                 c.mention_only,
-                c.pinned_at,
+                cr_me.pinned_at,
+                cr_me.archived_at,
                 c.created_at,
                 c.updated_at,
                 a.name AS agent_name,
@@ -410,6 +459,7 @@ async fn list_conversations(
             LEFT JOIN account_subscribers asub ON asub.conversation_id = c.id AND asub.user_id = $1
             LEFT JOIN account_subscribers asub_any ON asub_any.conversation_id = c.id
             LEFT JOIN accounts owner_acc ON owner_acc.id = asub_any.account_id AND owner_acc.owner_id = $1
+            LEFT JOIN conversation_reads cr_me ON cr_me.conversation_id = c.id AND cr_me.user_id = $1
             LEFT JOIN LATERAL (
                 SELECT m.id, m.seq, m.role, m.content, m.status, m.metadata, m.created_at, m.updated_at, m.sender_agent_id
                 FROM messages m
@@ -421,14 +471,16 @@ async fn list_conversations(
             WHERE (c.user_id = $1 OR EXISTS (
                 SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = c.id AND cum.user_id = $1
             ))
+              AND (cr_me.archived_at IS NOT NULL) = $2
               AND NOT EXISTS (
                 SELECT 1 FROM conversation_user_members h
                 WHERE h.conversation_id = c.id AND h.user_id = $1
                   AND h.hidden_at IS NOT NULL AND h.hidden_at >= c.updated_at
               )
-            ORDER BY c.pinned_at DESC NULLS LAST, c.updated_at DESC"#,
+            ORDER BY cr_me.pinned_at DESC NULLS LAST, c.updated_at DESC"#,
         )
         .bind(&user.id)
+        .bind(params.archived.unwrap_or(false))
         .fetch_all(&state.db)
         .await
     };
@@ -704,6 +756,7 @@ async fn list_conversations(
                 "peerUserId": peer_user_id,
                 "mentionOnly": row.mention_only,
                 "pinnedAt": row.pinned_at.map(|t| t.and_utc().to_rfc3339()),
+                "archivedAt": row.archived_at.map(|t| t.and_utc().to_rfc3339()),
                 "createdAt": row.created_at.and_utc().to_rfc3339(),
                 "updatedAt": row.updated_at.and_utc().to_rfc3339(),
                 "agentName": agent_name,
@@ -728,7 +781,9 @@ async fn get_conversation(
     Path(id): Path<Uuid>,
 ) -> Response {
     let result = sqlx::query_as::<_, Conversation>(
-        "SELECT * FROM conversations WHERE id = $1 AND user_id = $2",
+        r#"SELECT c.*, cr.pinned_at AS user_pinned_at FROM conversations c
+           LEFT JOIN conversation_reads cr ON cr.conversation_id = c.id AND cr.user_id = $2
+           WHERE c.id = $1 AND c.user_id = $2"#,
     )
     .bind(id)
     .bind(&user.id)
@@ -757,32 +812,50 @@ async fn update_conversation(
     Path(id): Path<Uuid>,
     Json(body): Json<UpdateConversationBody>,
 ) -> Response {
+    // Pins are per user and capped, so they go through set_pinned_for_user first.
+    if let Some(pinned) = body.pinned {
+        match set_pinned_for_user(&state.db, id, &user.id, pinned).await {
+            Ok(PinOutcome::Updated(_)) => {}
+            Ok(PinOutcome::LimitReached) => return pin_limit_reached(),
+            Ok(PinOutcome::NotFound) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({"error": "Conversation not found"})),
+                )
+                    .into_response();
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": e.to_string()})),
+                )
+                    .into_response();
+            }
+        }
+    }
+
     // Build dynamic update. We always set updated_at.
     // For title: use COALESCE($3, title) when not provided.
-    // For pinned_at: if body.pinned is Some(true) => NOW(), Some(false) => NULL, None => keep.
     //
     // We handle this with a single query using CASE expressions.
     let result = sqlx::query_as::<_, Conversation>(
-        r#"UPDATE conversations SET
-            title = CASE WHEN $3::boolean THEN $4 ELSE title END,
-            pinned_at = CASE
-                WHEN $5::boolean AND $6::boolean THEN NOW()
-                WHEN $5::boolean AND NOT $6::boolean THEN NULL
-                ELSE pinned_at
-            END,
-            mention_only = CASE WHEN $7::boolean THEN $8 ELSE mention_only END,
-            updated_at = NOW()
-           WHERE id = $1 AND user_id = $2
-           RETURNING *"#,
+        r#"WITH updated AS (
+            UPDATE conversations SET
+                title = CASE WHEN $3::boolean THEN $4 ELSE title END,
+                mention_only = CASE WHEN $5::boolean THEN $6 ELSE mention_only END,
+                updated_at = NOW()
+               WHERE id = $1 AND user_id = $2
+               RETURNING *
+           )
+           SELECT updated.*, cr.pinned_at AS user_pinned_at FROM updated
+           LEFT JOIN conversation_reads cr ON cr.conversation_id = updated.id AND cr.user_id = $2"#,
     )
     .bind(id)
     .bind(&user.id)
     .bind(body.title.is_some())    // $3: whether title was provided
     .bind(&body.title)             // $4: the new title value
-    .bind(body.pinned.is_some())   // $5: whether pinned was provided
-    .bind(body.pinned.unwrap_or(false)) // $6: the pinned value
-    .bind(body.mention_only.is_some()) // $7: whether mention_only was provided
-    .bind(body.mention_only.unwrap_or(true)) // $8: the mention_only value
+    .bind(body.mention_only.is_some()) // $5: whether mention_only was provided
+    .bind(body.mention_only.unwrap_or(true)) // $6: the mention_only value
     .fetch_optional(&state.db)
     .await;
 
@@ -801,6 +874,336 @@ async fn update_conversation(
     }
 }
 
+pub const MAX_CONVERSATION_TITLE_CHARS: usize = 120;
+
+/// Whether members need an admin role to rename a conversation of this type.
+/// Only one-to-one conversations are open to every member.
+pub fn rename_requires_admin(conv_type: &str) -> bool {
+    !matches!(conv_type, "direct" | "h2a" | "h2h")
+}
+
+/// Trimmed title, or an error unless it is 1–120 characters long.
+pub fn validate_conversation_title(title: &str) -> Result<String, String> {
+    let title = title.trim();
+    let len = title.chars().count();
+    if len == 0 || len > MAX_CONVERSATION_TITLE_CHARS {
+        return Err(format!(
+            "title must be between 1 and {} characters",
+            MAX_CONVERSATION_TITLE_CHARS
+        ));
+    }
+    Ok(title.to_string())
+}
+
+/// PATCH /api/conversations/:id - Rename a conversation and notify all members.
+/// Owners and members of direct chats may rename; in groups and communities
+/// only the owner and admins can.
+async fn rename_conversation(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<RenameConversationBody>,
+) -> Response {
+    let title = match validate_conversation_title(&body.title) {
+        Ok(t) => t,
+        Err(reason) => {
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": reason}))).into_response();
+        }
+    };
+
+    let access = sqlx::query_as::<_, (String, bool, Option<String>)>(
+        r#"SELECT c.type::text, c.user_id = $2,
+                  (SELECT cum.role::text FROM conversation_user_members cum
+                   WHERE cum.conversation_id = c.id AND cum.user_id = $2)
+           FROM conversations c WHERE c.id = $1"#,
+    )
+    .bind(id)
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await;
+
+    let (conv_type, is_owner, member_role) = match access {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Conversation not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+    if !is_owner {
+        let Some(role) = member_role else {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Conversation not found"})),
+            )
+                .into_response();
+        };
+        if rename_requires_admin(&conv_type) && role != "admin" && role != "vice_admin" {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "Only admins can rename this conversation"})),
+            )
+                .into_response();
+        }
+    }
+
+    if let Err(e) = sqlx::query("UPDATE conversations SET title = $1, updated_at = NOW() WHERE id = $2")
+        .bind(&title)
+        .bind(id)
+        .execute(&state.db)
+        .await
+    {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+
+    let conversation_id = id.to_string();
+    let member_ids = crate::ws::handler::get_conv_member_ids(&state.ws, &state.db, &conversation_id, "").await;
+    state.ws.broadcast_to_members(&member_ids, &json!({
+        "type": "conversation_renamed",
+        "conversationId": &conversation_id,
+        "title": &title,
+    }), &state.redis);
+
+    Json(json!({"id": id, "title": title})).into_response()
+}
+
+fn pin_limit_reached() -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({"error": format!("You can pin at most {} conversations", MAX_PINNED_CONVERSATIONS)})),
+    )
+        .into_response()
+}
+
+/// POST /api/conversations/:id/pin - Pin to the top of the list
+async fn pin_conversation(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    set_pinned(&state, &user, id, true).await
+}
+
+/// POST /api/conversations/:id/unpin
+async fn unpin_conversation(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    set_pinned(&state, &user, id, false).await
+}
+
+async fn set_pinned(state: &AppState, user: &AuthUser, id: Uuid, pinned: bool) -> Response {
+    let pinned_at = match set_pinned_for_user(&state.db, id, &user.id, pinned).await {
+        Ok(PinOutcome::Updated(pinned_at)) => pinned_at,
+        Ok(PinOutcome::LimitReached) => return pin_limit_reached(),
+        Ok(PinOutcome::NotFound) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Conversation not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let conv = sqlx::query_as::<_, Conversation>("SELECT * FROM conversations WHERE id = $1")
+        .bind(id)
+        .fetch_one(&state.db)
+        .await;
+    match conv {
+        Ok(mut conv) => {
+            conv.pinned_at = pinned_at;
+            Json(json!(conv)).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
+/// Result of pinning or unpinning a conversation for one user.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PinOutcome {
+    /// The user's new `pinned_at`.
+    Updated(Option<NaiveDateTime>),
+    /// The user already has `MAX_PINNED_CONVERSATIONS` other conversations pinned.
+    LimitReached,
+    /// The user is neither the owner nor a member.
+    NotFound,
+}
+
+/// Pin or unpin a conversation for one user. Like archive state, pins live on
+/// that user's `conversation_reads` row. The cap is checked by the upsert
+/// itself, after locking the user's row, so concurrent pins can't exceed it.
+pub async fn set_pinned_for_user(
+    db: &sqlx::PgPool,
+    conversation_id: Uuid,
+    user_id: &str,
+    pinned: bool,
+) -> Result<PinOutcome, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    if pinned {
+        sqlx::query(r#"SELECT 1 FROM "user" WHERE id = $1 FOR UPDATE"#)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let pinned_at = sqlx::query_scalar::<_, Option<NaiveDateTime>>(
+        r#"INSERT INTO conversation_reads (id, user_id, conversation_id, last_read_seq, pinned_at, updated_at)
+           SELECT gen_random_uuid(), $2, c.id, 0, CASE WHEN $3 THEN NOW() END, NOW()
+           FROM conversations c
+           WHERE c.id = $1
+             AND (c.user_id = $2 OR EXISTS (
+               SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = c.id AND cum.user_id = $2
+             ))
+             AND (NOT $3 OR (
+               SELECT COUNT(*) FROM conversation_reads cr
+               WHERE cr.user_id = $2 AND cr.pinned_at IS NOT NULL AND cr.conversation_id <> $1
+             ) < $4)
+           ON CONFLICT (user_id, conversation_id) DO UPDATE SET
+             pinned_at = CASE WHEN $3 THEN COALESCE(conversation_reads.pinned_at, NOW()) ELSE NULL END,
+             updated_at = NOW()
+           RETURNING pinned_at"#,
+    )
+    .bind(conversation_id)
+    .bind(user_id)
+    .bind(pinned)
+    .bind(MAX_PINNED_CONVERSATIONS)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let outcome = match pinned_at {
+        Some(pinned_at) => PinOutcome::Updated(pinned_at),
+        None => {
+            let has_access = sqlx::query_scalar::<_, bool>(
+                r#"SELECT EXISTS(
+                     SELECT 1 FROM conversations c
+                     WHERE c.id = $1
+                       AND (c.user_id = $2 OR EXISTS (
+                         SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = c.id AND cum.user_id = $2
+                       ))
+                   )"#,
+            )
+            .bind(conversation_id)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if has_access {
+                PinOutcome::LimitReached
+            } else {
+                PinOutcome::NotFound
+            }
+        }
+    };
+
+    tx.commit().await?;
+    Ok(outcome)
+}
+
+/// POST /api/conversations/:id/archive - Hide from the default list and sync
+async fn archive_conversation(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    set_archived(&state, &user, id, true).await
+}
+
+/// POST /api/conversations/:id/unarchive
+async fn unarchive_conversation(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    set_archived(&state, &user, id, false).await
+}
+
+async fn set_archived(state: &AppState, user: &AuthUser, id: Uuid, archived: bool) -> Response {
+    match set_archived_for_user(&state.db, id, &user.id, archived).await {
+        Ok(Some(archived_at)) => {
+            let conv = sqlx::query_as::<_, Conversation>("SELECT * FROM conversations WHERE id = $1")
+                .bind(id)
+                .fetch_one(&state.db)
+                .await;
+            match conv {
+                Ok(conv) => {
+                    let mut body = json!(conv);
+                    body["archived_at"] = json!(archived_at);
+                    Json(body).into_response()
+                }
+                Err(e) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": e.to_string()})),
+                )
+                    .into_response(),
+            }
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Conversation not found"})),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
+/// Archive or unarchive a conversation for one user. Archive state lives on
+/// that user's `conversation_reads` row, so other members are unaffected.
+/// Returns the new `archived_at`, or `None` if the user is neither the owner
+/// nor a member.
+pub async fn set_archived_for_user(
+    db: &sqlx::PgPool,
+    conversation_id: Uuid,
+    user_id: &str,
+    archived: bool,
+) -> Result<Option<Option<NaiveDateTime>>, sqlx::Error> {
+    sqlx::query_scalar::<_, Option<NaiveDateTime>>(
+        r#"INSERT INTO conversation_reads (id, user_id, conversation_id, last_read_seq, archived_at, updated_at)
+           SELECT gen_random_uuid(), $2, c.id, 0, CASE WHEN $3 THEN NOW() END, NOW()
+           FROM conversations c
+           WHERE c.id = $1
+             AND (c.user_id = $2 OR EXISTS (
+               SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = c.id AND cum.user_id = $2
+             ))
+           ON CONFLICT (user_id, conversation_id) DO UPDATE SET
+             archived_at = CASE WHEN $3 THEN COALESCE(conversation_reads.archived_at, NOW()) ELSE NULL END,
+             updated_at = NOW()
+           RETURNING archived_at"#,
+    )
+    .bind(conversation_id)
+    .bind(user_id)
+    .bind(archived)
+    .fetch_optional(db)
+    .await
+}
+
 /// DELETE /api/conversations/:id - Delete conversation (messages cascade)
 async fn delete_conversation(
     State(state): State<AppState>,
@@ -1021,6 +1424,45 @@ async fn mark_read(
     }
 }
 
+/// Advance the user's read position to the latest message in every
+/// conversation they belong to, in one statement. Per-message read receipts
+/// are not written. Returns how many conversations had unread messages.
+pub async fn mark_all_conversations_read(db: &sqlx::PgPool, user_id: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"INSERT INTO conversation_reads (id, user_id, conversation_id, last_read_seq, updated_at)
+           SELECT gen_random_uuid(), $1, m.conversation_id, MAX(m.seq), NOW()
+           FROM messages m
+           WHERE m.conversation_id IN (
+               SELECT id FROM conversations WHERE user_id = $1
+               UNION
+               SELECT conversation_id FROM conversation_user_members WHERE user_id = $1
+           )
+           GROUP BY m.conversation_id
+           ON CONFLICT (user_id, conversation_id)
+           DO UPDATE SET last_read_seq = EXCLUDED.last_read_seq, updated_at = NOW()
+           WHERE conversation_reads.last_read_seq < EXCLUDED.last_read_seq"#,
+    )
+    .bind(user_id)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// POST /api/conversations/read-all - Mark every conversation as read
+async fn mark_all_read(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Response {
+    match mark_all_conversations_read(&state.db, &user.id).await {
+        Ok(updated) => Json(json!({"updated": updated})).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
 /// PUT /api/conversations/{id}/mute - Toggle mute on a conversation
 async fn toggle_mute(
     State(state): State<AppState>,
@@ -1078,6 +1520,248 @@ async fn toggle_mute(
     }
 }
 
+/// PUT /api/conversations/{id}/agent-streams/mute - Stop receiving live agent
+/// stream events in this conversation. Agents still reply for other members;
+/// the saved message is available through the normal message fetch.
+async fn toggle_agent_streams_mute(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<MuteBody>,
+) -> Response {
+    // Owners and members of shared conversations can both opt out
+    let conv = sqlx::query_as::<_, (Uuid,)>(
+        r#"SELECT c.id FROM conversations c
+           WHERE c.id = $1
+             AND (c.user_id = $2 OR EXISTS (
+                SELECT 1 FROM conversation_user_members cum
+                WHERE cum.conversation_id = c.id AND cum.user_id = $2
+             ))"#,
+    )
+    .bind(id)
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await;
+
+    match conv {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Conversation not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    }
+
+    let upsert_result = sqlx::query(
+        r#"INSERT INTO conversation_reads (id, user_id, conversation_id, last_read_seq, agent_streams_muted, updated_at)
+           VALUES (gen_random_uuid(), $1, $2, 0, $3, NOW())
+           ON CONFLICT (user_id, conversation_id)
+           DO UPDATE SET agent_streams_muted = $3, updated_at = NOW()"#,
+    )
+    .bind(&user.id)
+    .bind(id)
+    .bind(body.muted)
+    .execute(&state.db)
+    .await;
+
+    match upsert_result {
+        Ok(_) => {
+            state
+                .ws
+                .set_agent_stream_optout(&id.to_string(), &user.id, body.muted);
+            Json(json!({"agentStreamsMuted": body.muted})).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
+/// PUT /api/conversations/{id}/push-mode - Choose between push notifications for
+/// every message ("all", the default) or only messages that @-mention the user ("mentions").
+async fn set_push_mode(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<PushModeBody>,
+) -> Response {
+    if !PUSH_MODES.contains(&body.push_mode.as_str()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("pushMode must be one of: {}", PUSH_MODES.join(", "))})),
+        )
+            .into_response();
+    }
+
+    let conv = sqlx::query_as::<_, (Uuid,)>(
+        r#"SELECT c.id FROM conversations c
+           WHERE c.id = $1
+             AND (c.user_id = $2 OR EXISTS (
+                SELECT 1 FROM conversation_user_members cum
+                WHERE cum.conversation_id = c.id AND cum.user_id = $2
+             ))"#,
+    )
+    .bind(id)
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await;
+
+    match conv {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Conversation not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    }
+
+    let upsert_result = sqlx::query(
+        r#"INSERT INTO conversation_reads (id, user_id, conversation_id, last_read_seq, push_mode, updated_at)
+           VALUES (gen_random_uuid(), $1, $2, 0, $3, NOW())
+           ON CONFLICT (user_id, conversation_id)
+           DO UPDATE SET push_mode = $3, updated_at = NOW()"#,
+    )
+    .bind(&user.id)
+    .bind(id)
+    .bind(&body.push_mode)
+    .execute(&state.db)
+    .await;
+
+    match upsert_result {
+        Ok(_) => Json(json!({"pushMode": body.push_mode})).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
+/// Total message count and a `{role: count}` map from per-role counts.
+pub fn role_breakdown(rows: &[(String, i64)]) -> (i64, serde_json::Map<String, Value>) {
+    let mut by_role = serde_json::Map::new();
+    let mut total = 0;
+    for (role, count) in rows {
+        total += count;
+        by_role.insert(role.clone(), json!(count));
+    }
+    (total, by_role)
+}
+
+/// GET /api/conversations/{id}/stats - Aggregate size summary for the info panel
+async fn get_stats(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    // Same access rule as get_messages: owner or user member
+    let allowed = sqlx::query_scalar::<_, bool>(
+        r#"SELECT EXISTS (SELECT 1 FROM conversations WHERE id = $1 AND (
+            user_id = $2
+            OR EXISTS (SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = $1 AND cum.user_id = $2)
+        ))"#,
+    )
+    .bind(id)
+    .bind(&user.id)
+    .fetch_one(&state.db)
+    .await;
+    match allowed {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Conversation not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    }
+
+    let per_role = sqlx::query_as::<_, (String, i64, NaiveDateTime, NaiveDateTime)>(
+        r#"SELECT role::text, COUNT(*), MIN(created_at), MAX(created_at)
+           FROM messages WHERE conversation_id = $1
+           GROUP BY role"#,
+    )
+    .bind(id)
+    .fetch_all(&state.db);
+
+    let totals = sqlx::query_as::<_, (i64, i64, i64, i64)>(
+        r#"SELECT
+             (SELECT COUNT(*) FROM (
+                SELECT user_id FROM conversations WHERE id = $1
+                UNION SELECT user_id FROM conversation_user_members WHERE conversation_id = $1
+             ) u),
+             (SELECT COUNT(*) FROM (
+                SELECT agent_id FROM conversations WHERE id = $1 AND agent_id IS NOT NULL
+                UNION SELECT agent_id FROM conversation_members WHERE conversation_id = $1
+             ) a),
+             COUNT(att.id),
+             COALESCE(SUM(att.file_size), 0)::bigint
+           FROM attachments att
+           JOIN messages m ON m.id = att.message_id
+           WHERE m.conversation_id = $1"#,
+    )
+    .bind(id)
+    .fetch_one(&state.db);
+
+    let (per_role, totals) = tokio::join!(per_role, totals);
+    let (per_role, (user_count, agent_count, attachment_count, attachment_bytes)) = match (per_role, totals) {
+        (Ok(r), Ok(t)) => (r, t),
+        (Err(e), _) | (_, Err(e)) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let counts: Vec<(String, i64)> = per_role.iter().map(|r| (r.0.clone(), r.1)).collect();
+    let (message_count, by_role) = role_breakdown(&counts);
+    let first_at = per_role.iter().map(|r| r.2).min();
+    let last_at = per_role.iter().map(|r| r.3).max();
+
+    Json(json!({
+        "conversationId": id,
+        "messageCount": message_count,
+        "messagesByRole": by_role,
+        "firstMessageAt": first_at.map(|t| t.and_utc().to_rfc3339()),
+        "lastMessageAt": last_at.map(|t| t.and_utc().to_rfc3339()),
+        "participantCount": user_count + agent_count,
+        "userCount": user_count,
+        "agentCount": agent_count,
+        "attachmentCount": attachment_count,
+        "attachmentBytes": attachment_bytes,
+    }))
+    .into_response()
+}
+
 /// GET /api/conversations/{id}/status - Get conversation status info
 async fn get_status(
     State(state): State<AppState>,
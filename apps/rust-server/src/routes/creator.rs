@@ -18,6 +18,7 @@ pub fn router() -> Router<AppState> {
         .route("/api/creator/agents", get(creator_agents))
         .route("/api/creator/dashboard", get(dashboard))
         .route("/api/creator/revenue", get(revenue))
+        .route("/api/creator/analytics", get(analytics))
         .route("/api/creator/ratings", get(ratings))
         .route("/api/creator/users", get(users))
         .route("/api/creator/downloads", get(downloads))
@@ -115,6 +116,18 @@ fn default_days() -> i32 {
     30
 }
 
+#[derive(Deserialize)]
+struct AnalyticsQuery {
+    range: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ListingCounterRow {
+    total_messages: i64,
+    total_revenue: i64,
+    sales_count: i64,
+}
+
 // ---------------------------------------------------------------------------
 // GET /api/creator/agents — All listings owned by the creator
 // ---------------------------------------------------------------------------
@@ -438,6 +451,135 @@ async fn revenue(
     )
 }
 
+// ---------------------------------------------------------------------------
+// GET /api/creator/analytics — Daily messages, revenue and new buyers
+// ---------------------------------------------------------------------------
+
+async fn analytics(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<AnalyticsQuery>,
+) -> (StatusCode, Json<Value>) {
+    let range = params.range.as_deref().unwrap_or("30d");
+    let Some(days) = parse_analytics_range(range) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "range must be one of 7d, 30d, 90d" })),
+        );
+    };
+
+    let start = chrono::Utc::now().date_naive() - chrono::Duration::days(days - 1);
+    let since = start.and_hms_opt(0, 0, 0).unwrap_or_default();
+
+    // User messages sent to the creator's listings (free trial and paid)
+    let messages = sqlx::query_as::<_, DailyCountRow>(
+        r#"SELECT date_trunc('day', mm.created_at)::date AS date, COUNT(*)::int8 AS count
+           FROM marketplace_messages mm
+           JOIN marketplace_conversations mc ON mc.id = mm.conversation_id
+           JOIN agent_listings al ON al.id = mc.listing_id
+           WHERE al.creator_id = $1 AND mm.role = 'user' AND mm.created_at >= $2
+           GROUP BY 1"#,
+    )
+    .bind(&user.id)
+    .bind(since)
+    .fetch_all(&state.db)
+    .await;
+
+    // Creator earnings attributed to their listings
+    let revenue = sqlx::query_as::<_, DailyCountRow>(
+        r#"SELECT date_trunc('day', created_at)::date AS date, COALESCE(SUM(amount), 0)::int8 AS count
+           FROM coin_transactions
+           WHERE user_id = $1 AND type = 'earning' AND created_at >= $2
+             AND related_app_id IN (SELECT id FROM agent_listings WHERE creator_id = $1)
+           GROUP BY 1"#,
+    )
+    .bind(&user.id)
+    .bind(since)
+    .fetch_all(&state.db)
+    .await;
+
+    // Buyers counted on the day of their first paid message to any of the creator's listings
+    let new_buyers = sqlx::query_as::<_, DailyCountRow>(
+        r#"WITH first_purchase AS (
+               SELECT user_id, MIN(created_at) AS first_at
+               FROM coin_transactions
+               WHERE type = 'purchase'
+                 AND related_app_id IN (SELECT id FROM agent_listings WHERE creator_id = $1)
+               GROUP BY user_id
+           )
+           SELECT date_trunc('day', first_at)::date AS date, COUNT(*)::int8 AS count
+           FROM first_purchase
+           WHERE first_at >= $2
+           GROUP BY 1"#,
+    )
+    .bind(&user.id)
+    .bind(since)
+    .fetch_all(&state.db)
+    .await;
+
+    let (messages, revenue, new_buyers) = match (messages, revenue, new_buyers) {
+        (Ok(m), Ok(r), Ok(b)) => (m, r, b),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            tracing::error!("Creator analytics: series query failed: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" })));
+        }
+    };
+
+    // Lifetime counters maintained on the listings themselves
+    let totals = sqlx::query_as::<_, ListingCounterRow>(
+        r#"SELECT COALESCE(SUM(total_messages), 0)::int8 AS total_messages,
+                  COALESCE(SUM(total_revenue), 0)::int8 AS total_revenue,
+                  COALESCE(SUM(sales_count), 0)::int8 AS sales_count
+           FROM agent_listings
+           WHERE creator_id = $1"#,
+    )
+    .bind(&user.id)
+    .fetch_one(&state.db)
+    .await;
+
+    let totals = match totals {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!("Creator analytics: totals query failed: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" })));
+        }
+    };
+
+    let to_pairs = |rows: &[DailyCountRow]| -> Vec<(NaiveDate, i64)> {
+        rows.iter().map(|r| (r.date, r.count)).collect()
+    };
+    let messages = fill_daily_series(start, days, &to_pairs(&messages));
+    let revenue = fill_daily_series(start, days, &to_pairs(&revenue));
+    let new_buyers = fill_daily_series(start, days, &to_pairs(&new_buyers));
+
+    let series: Vec<Value> = messages
+        .iter()
+        .zip(&revenue)
+        .zip(&new_buyers)
+        .map(|(((date, m), (_, r)), (_, b))| {
+            json!({
+                "date": date.format("%Y-%m-%d").to_string(),
+                "messages": m,
+                "revenue": r,
+                "newBuyers": b,
+            })
+        })
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "range": range,
+            "series": series,
+            "totals": {
+                "messages": totals.total_messages,
+                "revenue": totals.total_revenue,
+                "sales": totals.sales_count,
+            },
+        })),
+    )
+}
+
 // ---------------------------------------------------------------------------
 // GET /api/creator/ratings — Aggregated rating stats
 // ---------------------------------------------------------------------------
@@ -968,3 +1110,25 @@ fn categorize_earning(description: &str) -> &'static str {
         "Spaces"
     }
 }
+
+/// Map an analytics `range` value to a number of days.
+pub fn parse_analytics_range(range: &str) -> Option<i64> {
+    match range {
+        "7d" => Some(7),
+        "30d" => Some(30),
+        "90d" => Some(90),
+        _ => None,
+    }
+}
+
+/// Expand sparse per-day values into one entry per day starting at `start`,
+/// filling missing days with zero so charts stay continuous.
+pub fn fill_daily_series(start: NaiveDate, days: i64, rows: &[(NaiveDate, i64)]) -> Vec<(NaiveDate, i64)> {
+    let by_date: std::collections::HashMap<NaiveDate, i64> = rows.iter().copied().collect();
+    (0..days)
+        .map(|offset| {
+            let date = start + chrono::Duration::days(offset);
+            (date, by_date.get(&date).copied().unwrap_or(0))
+        })
+        .collect()
+}
@@ -5,6 +5,7 @@ use axum::{
     routing::{delete, get, post},
     Router,
 };
+use deadpool_redis::redis::AsyncCommands;
 use serde::Deserialize;
 use serde_json::json;
 use uuid::Uuid;
@@ -23,6 +24,129 @@ pub fn router() -> Router<AppState> {
         .route("/api/friends/requests/{id}", delete(cancel_friend_request))
 }
 
+/// Redis counter of friend requests `user_id` sent on `day` (`YYYYMMDD`, UTC).
+pub fn daily_request_key(user_id: &str, day: &str) -> String {
+    format!("friend_req_daily:{}:{}", user_id, day)
+}
+
+/// Redis marker set when `addressee_id` rejects a request from `requester_id`.
+pub fn reject_cooldown_key(requester_id: &str, addressee_id: &str) -> String {
+    format!("friend_req_rejected:{}:{}", requester_id, addressee_id)
+}
+
+/// Whether `sent_today` requests already reach `limit` (0 means unlimited).
+pub fn daily_limit_reached(sent_today: u32, limit: u32) -> bool {
+    limit > 0 && sent_today >= limit
+}
+
+/// Claim one of today's friend-request slots behind `key`. The counter is
+/// `INCR`ed first and the returned value decides, so concurrent requests can't
+/// all pass a stale read; a claim over `limit` is given back. `Some(true)` when
+/// the request may proceed, `Some(false)` at the cap, `None` if Redis is down.
+pub async fn claim_daily_request(redis: &deadpool_redis::Pool, key: &str, limit: u32) -> Option<bool> {
+    let mut conn = redis.get().await.ok()?;
+    let count: u32 = conn.incr(key, 1u32).await.ok()?;
+    if count == 1 {
+        let _: Result<(), _> = conn.expire(key, 86400).await;
+    }
+    if daily_limit_reached(count - 1, limit) {
+        let _: Result<i64, _> = conn.decr(key, 1u32).await;
+        return Some(false);
+    }
+    Some(true)
+}
+
+/// Give back a slot taken by [`claim_daily_request`] when the request wasn't sent.
+pub async fn release_daily_request(redis: &deadpool_redis::Pool, key: &str) {
+    if let Ok(mut conn) = redis.get().await {
+        let _: Result<i64, _> = conn.decr(key, 1u32).await;
+    }
+}
+
+/// Seconds a mutual-friends count stays cached.
+const MUTUAL_FRIENDS_CACHE_TTL_SECS: u64 = 300;
+
+/// Cache key for the mutual-friends count of a pair; the same for either order.
+pub fn mutual_friends_key(a: &str, b: &str) -> String {
+    let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+    format!("mutual_friends:{}:{}", lo, hi)
+}
+
+/// Number of accepted friends `user_id` shares with each of `others`, excluding
+/// anyone in a block with either side, in a single query. Users with no mutual
+/// friends are absent from the map.
+pub async fn mutual_friends_counts(
+    db: &sqlx::PgPool,
+    user_id: &str,
+    others: &[String],
+) -> Result<std::collections::HashMap<String, i64>, sqlx::Error> {
+    if others.is_empty() {
+        return Ok(std::collections::HashMap::new());
+    }
+    let rows = sqlx::query_as::<_, (String, i64)>(
+        r#"WITH mine AS (
+               SELECT CASE WHEN requester_id = $1 THEN addressee_id ELSE requester_id END AS id
+               FROM friendships
+               WHERE status = 'accepted' AND (requester_id = $1 OR addressee_id = $1)
+           ), theirs AS (
+               SELECT o.other_id,
+                      CASE WHEN f.requester_id = o.other_id THEN f.addressee_id ELSE f.requester_id END AS id
+               FROM (SELECT DISTINCT unnest($2::text[]) AS other_id) o
+               JOIN friendships f
+                 ON f.status = 'accepted'
+                AND (f.requester_id = o.other_id OR f.addressee_id = o.other_id)
+               WHERE o.other_id <> $1
+           )
+           SELECT theirs.other_id, COUNT(*)
+           FROM theirs JOIN mine USING (id)
+           WHERE NOT EXISTS (
+               SELECT 1 FROM friendships x
+               WHERE x.status = 'blocked'
+                 AND ((x.requester_id = theirs.id AND x.addressee_id IN ($1, theirs.other_id))
+                   OR (x.addressee_id = theirs.id AND x.requester_id IN ($1, theirs.other_id)))
+           )
+           GROUP BY theirs.other_id"#,
+    )
+    .bind(user_id)
+    .bind(others)
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Number of accepted friends `a` and `b` share. Cached briefly in Redis; 0 on
+/// error, and errors are not cached.
+pub async fn mutual_friends_count(
+    db: &sqlx::PgPool,
+    redis: &deadpool_redis::Pool,
+    a: &str,
+    b: &str,
+) -> i64 {
+    if a == b {
+        return 0;
+    }
+    let key = mutual_friends_key(a, b);
+    let mut conn = redis.get().await.ok();
+    if let Some(conn) = conn.as_mut() {
+        if let Ok(Some(cached)) = conn.get::<_, Option<i64>>(&key).await {
+            return cached;
+        }
+    }
+
+    let count = match mutual_friends_counts(db, a, &[b.to_string()]).await {
+        Ok(counts) => counts.get(b).copied().unwrap_or(0),
+        Err(e) => {
+            tracing::error!("Mutual friends count failed: {}", e);
+            return 0;
+        }
+    };
+
+    if let Some(conn) = conn.as_mut() {
+        let _: Result<(), _> = conn.set_ex(&key, count, MUTUAL_FRIENDS_CACHE_TTL_SECS).await;
+    }
+    count
+}
+
 #[derive(Deserialize)]
 struct SendRequestBody {
     username: String,
@@ -34,9 +158,10 @@ async fn send_friend_request(
     user: AuthUser,
     Json(body): Json<SendRequestBody>,
 ) -> Response {
-    // Find addressee by username
+    // Find addressee by username; undiscoverable users look nonexistent
     let addressee = sqlx::query_as::<_, (String, String, Option<String>)>(
-        r#"SELECT id, name, image FROM "user" WHERE LOWER(username) = LOWER($1)"#,
+        r#"SELECT id, name, image FROM "user"
+           WHERE LOWER(username) = LOWER($1) AND discoverable_by_username"#,
     )
     .bind(&body.username)
     .fetch_optional(&state.db)
@@ -83,7 +208,51 @@ async fn send_friend_request(
     if matches!(blocked, Ok((c,)) if c > 0) {
         return (
             StatusCode::FORBIDDEN,
-            Json(json!({"error": "Cannot send friend request"})),
+            Json(json!({"error": "Cannot send friend request", "code": "blocked"})),
+        )
+            .into_response();
+    }
+
+    if !crate::routes::users::privacy_settings(&state.db, &addressee_id).await.allows_friend_requests() {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "This user is not accepting friend requests", "code": "privacy"})),
+        )
+            .into_response();
+    }
+
+    // Rate limits are enforced only while Redis is reachable
+    let daily_key = daily_request_key(&user.id, &chrono::Utc::now().format("%Y%m%d").to_string());
+    if let Ok(mut conn) = state.redis.get().await {
+        let rejected_ttl: i64 = conn
+            .ttl(reject_cooldown_key(&user.id, &addressee_id))
+            .await
+            .unwrap_or(-2);
+        if rejected_ttl > 0 {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({
+                    "error": "This user recently declined your friend request",
+                    "code": "recently_rejected",
+                    "retryAfterSecs": rejected_ttl,
+                })),
+            )
+                .into_response();
+        }
+    }
+
+    let daily_slot =
+        claim_daily_request(&state.redis, &daily_key, state.config.friend_request_daily_limit).await;
+    if daily_slot == Some(false) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({
+                "error": format!(
+                    "Daily friend request limit reached. Max {} per day.",
+                    state.config.friend_request_daily_limit
+                ),
+                "code": "daily_limit",
+            })),
         )
             .into_response();
     }
@@ -100,6 +269,9 @@ async fn send_friend_request(
     .await;
 
     if let Ok(Some(_)) = existing {
+        if daily_slot == Some(true) {
+            release_daily_request(&state.redis, &daily_key).await;
+        }
         return (
             StatusCode::CONFLICT,
             Json(json!({"error": "Friend request already exists"})),
@@ -166,6 +338,9 @@ async fn send_friend_request(
                 .into_response()
         }
         Err(e) => {
+            if daily_slot == Some(true) {
+                release_daily_request(&state.redis, &daily_key).await;
+            }
             let msg = e.to_string();
             if msg.contains("unique") || msg.contains("duplicate") {
                 (
@@ -220,17 +395,28 @@ async fn reject_friend_request(
     user: AuthUser,
     Path(id): Path<Uuid>,
 ) -> Response {
-    let result = sqlx::query(
-        r#"DELETE FROM friendships WHERE id = $1 AND addressee_id = $2 AND status = 'pending'"#,
+    let result = sqlx::query_scalar::<_, String>(
+        r#"DELETE FROM friendships WHERE id = $1 AND addressee_id = $2 AND status = 'pending'
+           RETURNING requester_id"#,
     )
     .bind(id)
     .bind(&user.id)
-    .execute(&state.db)
+    .fetch_optional(&state.db)
     .await;
 
     match result {
-        Ok(r) if r.rows_affected() > 0 => StatusCode::NO_CONTENT.into_response(),
-        Ok(_) => (
+        Ok(Some(requester_id)) => {
+            let cooldown_secs = state.config.friend_request_reject_cooldown_hours * 3600;
+            if cooldown_secs > 0 {
+                if let Ok(mut conn) = state.redis.get().await {
+                    let _: Result<(), _> = conn
+                        .set_ex(reject_cooldown_key(&requester_id, &user.id), 1, cooldown_secs)
+                        .await;
+                }
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => (
             StatusCode::NOT_FOUND,
             Json(json!({"error": "Friend request not found"})),
         )
@@ -349,33 +535,46 @@ async fn list_requests(
     .await
     .unwrap_or_default();
 
-    let incoming_json: Vec<serde_json::Value> = incoming
-        .into_iter()
-        .map(|(fid, uid, name, image, username, is_verified)| {
-            json!({
-                "id": fid,
-                "userId": uid,
-                "name": name,
-                "image": image,
-                "username": username,
-                "isVerified": is_verified,
-            })
-        })
+    let others: Vec<String> = incoming
+        .iter()
+        .chain(&outgoing)
+        .map(|(_, uid, ..)| uid.clone())
         .collect();
+    let mutual_counts = mutual_friends_counts(&state.db, &user.id, &others)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Mutual friends counts failed: {}", e);
+            Default::default()
+        });
+    let mutual_for = |uid: &str| mutual_counts.get(uid).copied().unwrap_or(0);
 
-    let outgoing_json: Vec<serde_json::Value> = outgoing
-        .into_iter()
-        .map(|(fid, uid, name, image, username, is_verified)| {
-            json!({
-                "id": fid,
-                "userId": uid,
-                "name": name,
-                "image": image,
-                "username": username,
-                "isVerified": is_verified,
-            })
-        })
-        .collect();
+    let mut incoming_json: Vec<serde_json::Value> = Vec::with_capacity(incoming.len());
+    for (fid, uid, name, image, username, is_verified) in incoming {
+        let mutual = mutual_for(&uid);
+        incoming_json.push(json!({
+            "id": fid,
+            "userId": uid,
+            "name": name,
+            "image": image,
+            "username": username,
+            "isVerified": is_verified,
+            "mutualFriends": mutual,
+        }));
+    }
+
+    let mut outgoing_json: Vec<serde_json::Value> = Vec::with_capacity(outgoing.len());
+    for (fid, uid, name, image, username, is_verified) in outgoing {
+        let mutual = mutual_for(&uid);
+        outgoing_json.push(json!({
+            "id": fid,
+            "userId": uid,
+            "name": name,
+            "image": image,
+            "username": username,
+            "isVerified": is_verified,
+            "mutualFriends": mutual,
+        }));
+    }
 
     Json(json!({
         "incoming": incoming_json,
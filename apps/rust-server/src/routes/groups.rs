@@ -25,9 +25,26 @@ pub fn router() -> Router<AppState> {
             "/api/conversations/{id}/members/{agentId}",
             delete(remove_member),
         )
+        .route(
+            "/api/conversations/{id}/agent-requests",
+            post(request_agent_join),
+        )
+        .route(
+            "/api/agent-requests/{requestId}/approve",
+            post(approve_agent_request),
+        )
+        .route("/api/agent-requests/{requestId}/deny", post(deny_agent_request))
         // Group admin endpoints
         .route("/api/groups/{id}/invite-link", post(generate_invite_link))
         .route("/api/groups/join/{token}", post(join_via_invite))
+        .route(
+            "/api/groups/{id}/requests/{userId}/approve",
+            post(approve_join_request),
+        )
+        .route(
+            "/api/groups/{id}/requests/{userId}/reject",
+            post(reject_join_request),
+        )
         .route("/api/groups/{id}/kick/{userId}", post(kick_user))
         .route("/api/groups/{id}/settings", patch(update_settings))
         .route("/api/groups/{id}/promote/{userId}", post(promote_user))
@@ -38,6 +55,7 @@ pub fn router() -> Router<AppState> {
         )
         .route("/api/groups/{id}/leave", post(leave_group))
         .route("/api/groups/{id}/add-user", post(add_user_to_group))
+        .route("/api/groups/{id}/add-users", post(add_users_to_group))
         // Agent permission endpoints
         .route(
             "/api/conversations/{id}/agents/{agentId}/listen-mode",
@@ -214,6 +232,32 @@ async fn is_conversation_member(db: &sqlx::PgPool, conv_id: Uuid, user_id: &str)
     matches!(owner, Ok(Some(_)))
 }
 
+/// Defaults used when a conversation has no `group_settings` row.
+const DEFAULT_MAX_USERS: i32 = 50;
+const DEFAULT_MAX_AGENTS: i32 = 10;
+
+/// Helper to get a group's configured (max users, max agents)
+async fn group_limits(db: &sqlx::PgPool, conv_id: Uuid) -> (i64, i64) {
+    let limits = sqlx::query_as::<_, (i32, i32)>(
+        "SELECT max_users, max_agents FROM group_settings WHERE conversation_id = $1",
+    )
+    .bind(conv_id)
+    .fetch_optional(db)
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or((DEFAULT_MAX_USERS, DEFAULT_MAX_AGENTS));
+    (limits.0 as i64, limits.1 as i64)
+}
+
+fn user_limit_reached(max_users: i64) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({"error": format!("Group has reached the maximum of {} users", max_users)})),
+    )
+        .into_response()
+}
+
 /// Helper to get user's role in a group
 async fn get_user_role(db: &sqlx::PgPool, conv_id: Uuid, user_id: &str) -> Option<String> {
     sqlx::query_as::<_, (String,)>(
@@ -228,6 +272,41 @@ async fn get_user_role(db: &sqlx::PgPool, conv_id: Uuid, user_id: &str) -> Optio
     .map(|(r,)| r)
 }
 
+/// All user member IDs of a group.
+async fn group_member_ids(db: &sqlx::PgPool, conv_id: Uuid) -> Vec<String> {
+    sqlx::query_as::<_, (String,)>(
+        "SELECT user_id FROM conversation_user_members WHERE conversation_id = $1",
+    )
+    .bind(conv_id)
+    .fetch_all(db)
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(uid,)| uid)
+    .collect()
+}
+
+/// Build an `agent_settings_changed` event. `changes` is merged into the payload.
+pub fn agent_settings_changed_event(conv_id: Uuid, agent_id: Uuid, changes: serde_json::Value) -> serde_json::Value {
+    let mut event = json!({
+        "type": "agent_settings_changed",
+        "conversationId": conv_id.to_string(),
+        "agentId": agent_id.to_string(),
+    });
+    if let (Some(event), serde_json::Value::Object(changes)) = (event.as_object_mut(), changes) {
+        event.extend(changes);
+    }
+    event
+}
+
+/// Tell every member that an agent's group settings changed so open settings
+/// panels stay in sync.
+async fn broadcast_agent_settings_changed(state: &AppState, conv_id: Uuid, agent_id: Uuid, changes: serde_json::Value) {
+    let event = agent_settings_changed_event(conv_id, agent_id, changes);
+    let member_ids = group_member_ids(&state.db, conv_id).await;
+    state.ws.broadcast_to_members(&member_ids, &event, &state.redis);
+}
+
 /// Insert a system message into a conversation and broadcast it to all members.
 async fn insert_system_message(state: &AppState, conv_id: Uuid, content: &str) {
     let conv_id_str = conv_id.to_string();
@@ -261,17 +340,7 @@ async fn insert_system_message(state: &AppState, conv_id: Uuid, content: &str) {
         .execute(&state.db)
         .await;
 
-    // Fetch all user member IDs
-    let member_ids: Vec<String> = sqlx::query_as::<_, (String,)>(
-        "SELECT user_id FROM conversation_user_members WHERE conversation_id = $1",
-    )
-    .bind(conv_id)
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default()
-    .into_iter()
-    .map(|(uid,)| uid)
-    .collect();
+    let member_ids = group_member_ids(&state.db, conv_id).await;
 
     let msg_event = json!({
         "type": "new_message",
@@ -457,16 +526,24 @@ async fn add_member(
     .fetch_one(&state.db)
     .await;
 
+    let (_, max_agents) = group_limits(&state.db, id).await;
     if let Ok((c,)) = count {
-        if c >= 10 {
+        if c >= max_agents {
             return (
                 StatusCode::BAD_REQUEST,
-                Json(json!({"error": "Group has reached the maximum of 10 agents"})),
+                Json(json!({"error": format!("Group has reached the maximum of {} agents", max_agents)})),
             )
                 .into_response();
         }
     }
 
+    // Marketplace listings may restrict which conversation types they are used in
+    if let Err(resp) =
+        crate::routes::agent_hub::check_agent_listing_context(&state.db, body.agent_id, "group").await
+    {
+        return resp.into_response();
+    }
+
     // Verify agent belongs to user
     let agent = sqlx::query_as::<_, (Uuid,)>(
         "SELECT id FROM agents WHERE id = $1 AND owner_id = $2",
@@ -537,6 +614,343 @@ async fn add_member(
     }
 }
 
+/// POST /api/conversations/:id/agent-requests — Ask an agent's owner to add it to the group
+async fn request_agent_join(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<AddMemberBody>,
+) -> Response {
+    if !is_conversation_member(&state.db, id, &user.id).await {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Conversation not found"})),
+        )
+            .into_response();
+    }
+
+    let agent = sqlx::query_as::<_, (String, String)>(
+        "SELECT owner_id, name FROM agents WHERE id = $1",
+    )
+    .bind(body.agent_id)
+    .fetch_optional(&state.db)
+    .await;
+
+    let (owner_id, agent_name) = match agent {
+        Ok(Some(a)) => a,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Agent not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    if owner_id == user.id {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "You own this agent; add it directly"})),
+        )
+            .into_response();
+    }
+
+    let existing = sqlx::query_as::<_, (Uuid,)>(
+        "SELECT id FROM conversation_members WHERE conversation_id = $1 AND agent_id = $2",
+    )
+    .bind(id)
+    .bind(body.agent_id)
+    .fetch_optional(&state.db)
+    .await;
+
+    if matches!(existing, Ok(Some(_))) {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Agent is already a member"})),
+        )
+            .into_response();
+    }
+
+    if let Err(resp) =
+        crate::routes::agent_hub::check_agent_listing_context(&state.db, body.agent_id, "group").await
+    {
+        return resp.into_response();
+    }
+
+    let request_id = sqlx::query_scalar::<_, Uuid>(
+        r#"INSERT INTO group_agent_requests (conversation_id, agent_id, requested_by, owner_id)
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (conversation_id, agent_id) WHERE status = 'pending' DO NOTHING
+           RETURNING id"#,
+    )
+    .bind(id)
+    .bind(body.agent_id)
+    .bind(&user.id)
+    .bind(&owner_id)
+    .fetch_optional(&state.db)
+    .await;
+
+    let request_id = match request_id {
+        Ok(Some(rid)) => rid,
+        Ok(None) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "A request for this agent is already pending"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            tracing::error!("Group agent request failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to create request"})),
+            )
+                .into_response();
+        }
+    };
+
+    let requester_name = sqlx::query_scalar::<_, String>(r#"SELECT name FROM "user" WHERE id = $1"#)
+        .bind(&user.id)
+        .fetch_optional(&state.db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "Someone".to_string());
+    let group_title = sqlx::query_scalar::<_, Option<String>>("SELECT title FROM conversations WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+
+    state.ws.send_to_user_or_queue(
+        &owner_id,
+        &json!({
+            "type": "group_agent_request",
+            "requestId": request_id.to_string(),
+            "conversationId": id.to_string(),
+            "conversationTitle": group_title,
+            "agentId": body.agent_id.to_string(),
+            "agentName": agent_name,
+            "requestedBy": user.id,
+            "requestedByName": requester_name,
+        }),
+        &state.redis,
+    );
+
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({"requestId": request_id, "pending": true})),
+    )
+        .into_response()
+}
+
+/// Load a pending agent request owned by `owner_id`:
+/// (conversation_id, agent_id, requested_by).
+async fn pending_agent_request(
+    db: &sqlx::PgPool,
+    request_id: Uuid,
+    owner_id: &str,
+) -> Result<(Uuid, Uuid, String), Response> {
+    let row = sqlx::query_as::<_, (Uuid, Uuid, String, String)>(
+        r#"SELECT conversation_id, agent_id, requested_by, owner_id
+           FROM group_agent_requests WHERE id = $1 AND status = 'pending'"#,
+    )
+    .bind(request_id)
+    .fetch_optional(db)
+    .await;
+
+    match row {
+        Ok(Some((conv_id, agent_id, requested_by, owner))) if owner == owner_id => {
+            Ok((conv_id, agent_id, requested_by))
+        }
+        Ok(Some(_)) => Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Only the agent owner can answer this request"})),
+        )
+            .into_response()),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Request not found"})),
+        )
+            .into_response()),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response()),
+    }
+}
+
+/// POST /api/agent-requests/:requestId/approve — Agent owner approves adding it to a group
+async fn approve_agent_request(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(request_id): Path<Uuid>,
+) -> Response {
+    let (conv_id, agent_id, requested_by) =
+        match pending_agent_request(&state.db, request_id, &user.id).await {
+            Ok(r) => r,
+            Err(resp) => return resp,
+        };
+
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM conversation_members WHERE conversation_id = $1",
+    )
+    .bind(conv_id)
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or(0);
+    let (_, max_agents) = group_limits(&state.db, conv_id).await;
+    if count >= max_agents {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Group has reached the maximum of {} agents", max_agents)})),
+        )
+            .into_response();
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let decided = sqlx::query(
+        r#"UPDATE group_agent_requests SET status = 'approved', decided_at = NOW()
+           WHERE id = $1 AND status = 'pending'"#,
+    )
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await;
+
+    if !matches!(decided, Ok(ref r) if r.rows_affected() == 1) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Request not found"})),
+        )
+            .into_response();
+    }
+
+    // The owner stays the agent's owner in the group and keeps control of its settings
+    let inserted = sqlx::query(
+        r#"INSERT INTO conversation_members (conversation_id, agent_id, owner_user_id, listen_mode)
+           SELECT $1, $2, $3, 'all_mentions'
+           WHERE NOT EXISTS (
+               SELECT 1 FROM conversation_members WHERE conversation_id = $1 AND agent_id = $2
+           )"#,
+    )
+    .bind(conv_id)
+    .bind(agent_id)
+    .bind(&user.id)
+    .execute(&mut *tx)
+    .await;
+
+    let added = match inserted {
+        Ok(r) => r.rows_affected() > 0,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    if let Err(e) = tx.commit().await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+
+    if added {
+        let agent_name = sqlx::query_scalar::<_, String>("SELECT name FROM agents WHERE id = $1")
+            .bind(agent_id)
+            .fetch_optional(&state.db)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| "An agent".to_string());
+
+        insert_system_message(&state, conv_id, &format!("Agent {} was added to the group", agent_name)).await;
+    }
+
+    state.ws.send_to_user_or_queue(
+        &requested_by,
+        &json!({
+            "type": "group_agent_request_approved",
+            "requestId": request_id.to_string(),
+            "conversationId": conv_id.to_string(),
+            "agentId": agent_id.to_string(),
+        }),
+        &state.redis,
+    );
+
+    Json(json!({"approved": true})).into_response()
+}
+
+/// POST /api/agent-requests/:requestId/deny — Agent owner declines a group request
+async fn deny_agent_request(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(request_id): Path<Uuid>,
+) -> Response {
+    let (conv_id, agent_id, requested_by) =
+        match pending_agent_request(&state.db, request_id, &user.id).await {
+            Ok(r) => r,
+            Err(resp) => return resp,
+        };
+
+    let result = sqlx::query(
+        r#"UPDATE group_agent_requests SET status = 'denied', decided_at = NOW()
+           WHERE id = $1 AND status = 'pending'"#,
+    )
+    .bind(request_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            state.ws.send_to_user_or_queue(
+                &requested_by,
+                &json!({
+                    "type": "group_agent_request_denied",
+                    "requestId": request_id.to_string(),
+                    "conversationId": conv_id.to_string(),
+                    "agentId": agent_id.to_string(),
+                }),
+                &state.redis,
+            );
+            Json(json!({"denied": true})).into_response()
+        }
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Request not found"})),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
 async fn remove_member(
     State(state): State<AppState>,
     user: AuthUser,
@@ -596,11 +1010,23 @@ fn generate_invite_token() -> String {
     hex::encode(bytes)[..16].to_string()
 }
 
+#[derive(Deserialize, Default)]
+struct InviteLinkBody {
+    #[serde(rename = "expiresInHours")]
+    expires_in_hours: Option<i32>,
+    #[serde(rename = "maxUses")]
+    max_uses: Option<i32>,
+}
+
 /// POST /api/groups/:id/invite-link — Generate/regenerate invite link
+///
+/// Optional `expiresInHours` and `maxUses` limit how long and how often the
+/// link can be used. Regenerating resets the use count.
 async fn generate_invite_link(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<Uuid>,
+    body: Option<Json<InviteLinkBody>>,
 ) -> Response {
     let role = get_user_role(&state.db, id, &user.id).await;
     if !matches!(role.as_deref(), Some("admin")) {
@@ -611,18 +1037,49 @@ async fn generate_invite_link(
             .into_response();
     }
 
+    let body = body.map(|Json(b)| b).unwrap_or_default();
+    if body.expires_in_hours.is_some_and(|h| h <= 0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "expiresInHours must be positive"})),
+        )
+            .into_response();
+    }
+    if body.max_uses.is_some_and(|m| m <= 0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "maxUses must be positive"})),
+        )
+            .into_response();
+    }
+
     let token = generate_invite_token();
-    let result = sqlx::query(
-        r#"UPDATE group_settings SET invite_link = $1, invite_enabled = TRUE
-           WHERE conversation_id = $2"#,
+    let result = sqlx::query_as::<_, (Option<chrono::NaiveDateTime>,)>(
+        r#"UPDATE group_settings SET invite_link = $1, invite_enabled = TRUE,
+               invite_expires_at = CASE WHEN $3::int IS NULL THEN NULL
+                                        ELSE NOW() + make_interval(hours => $3) END,
+               invite_max_uses = $4,
+               invite_use_count = 0
+           WHERE conversation_id = $2
+           RETURNING invite_expires_at"#,
     )
     .bind(&token)
     .bind(id)
-    .execute(&state.db)
+    .bind(body.expires_in_hours)
+    .bind(body.max_uses)
+    .fetch_optional(&state.db)
     .await;
 
     match result {
-        Ok(_) => Json(json!({"inviteLink": token})).into_response(),
+        Ok(row) => {
+            let expires_at = row.and_then(|(e,)| e).map(|e| e.and_utc().to_rfc3339());
+            Json(json!({
+                "inviteLink": token,
+                "expiresAt": expires_at,
+                "maxUses": body.max_uses,
+            }))
+            .into_response()
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": e.to_string()})),
@@ -631,23 +1088,166 @@ async fn generate_invite_link(
     }
 }
 
+/// Outcome of redeeming a group invite link.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InviteRedemption {
+    NotFound,
+    Disabled,
+    Expired,
+    Exhausted,
+    GroupFull(i64),
+    /// `new_member` is false when the user was already in the group.
+    Joined { conversation_id: Uuid, new_member: bool },
+    /// `new_request` is false when a request was already pending.
+    Requested { conversation_id: Uuid, new_request: bool },
+}
+
+/// Redeem a group invite link for `user_id` in one transaction.
+///
+/// The invite's `group_settings` row is locked `FOR UPDATE`, so concurrent joins
+/// through the same link serialize and the use count and user cap can't be
+/// overshot. A use is only counted when a member or join request is created.
+pub async fn redeem_group_invite(
+    db: &sqlx::PgPool,
+    token: &str,
+    user_id: &str,
+) -> Result<InviteRedemption, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let group = sqlx::query_as::<_, (Uuid, bool, bool, bool, bool, i32)>(
+        r#"SELECT conversation_id, invite_enabled,
+                  COALESCE(invite_expires_at < NOW(), FALSE),
+                  COALESCE(invite_use_count >= invite_max_uses, FALSE),
+                  approval_required, max_users
+           FROM group_settings WHERE invite_link = $1
+           FOR UPDATE"#,
+    )
+    .bind(token)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((conv_id, invite_enabled, expired, exhausted, approval_required, max_users)) = group
+    else {
+        return Ok(InviteRedemption::NotFound);
+    };
+
+    if !invite_enabled {
+        return Ok(InviteRedemption::Disabled);
+    }
+
+    // Existing members (and pending requesters) pass through even when the link
+    // is used up or the group is full
+    let is_member = sqlx::query_scalar::<_, bool>(
+        r#"SELECT EXISTS(SELECT 1 FROM conversation_user_members WHERE conversation_id = $1 AND user_id = $2)
+              OR EXISTS(SELECT 1 FROM conversations WHERE id = $1 AND user_id = $2)"#,
+    )
+    .bind(conv_id)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if is_member {
+        return Ok(InviteRedemption::Joined { conversation_id: conv_id, new_member: false });
+    }
+    if approval_required {
+        let pending = sqlx::query_scalar::<_, bool>(
+            r#"SELECT EXISTS(SELECT 1 FROM group_join_requests
+                             WHERE conversation_id = $1 AND user_id = $2 AND status = 'pending')"#,
+        )
+        .bind(conv_id)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if pending {
+            return Ok(InviteRedemption::Requested { conversation_id: conv_id, new_request: false });
+        }
+    }
+
+    if expired {
+        return Ok(InviteRedemption::Expired);
+    }
+    if exhausted {
+        return Ok(InviteRedemption::Exhausted);
+    }
+
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM conversation_user_members WHERE conversation_id = $1",
+    )
+    .bind(conv_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if count >= max_users as i64 {
+        return Ok(InviteRedemption::GroupFull(max_users as i64));
+    }
+
+    let outcome = if approval_required {
+        // Re-requesting after a rejection reopens the request; a pending one is left as-is
+        let created = sqlx::query(
+            r#"INSERT INTO group_join_requests (conversation_id, user_id)
+               VALUES ($1, $2)
+               ON CONFLICT (conversation_id, user_id) DO UPDATE
+               SET status = 'pending', created_at = NOW(), decided_at = NULL, decided_by = NULL
+               WHERE group_join_requests.status <> 'pending'"#,
+        )
+        .bind(conv_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        InviteRedemption::Requested { conversation_id: conv_id, new_request: created }
+    } else {
+        // ON CONFLICT = joined concurrently
+        let created = sqlx::query(
+            r#"INSERT INTO conversation_user_members (conversation_id, user_id, role)
+               VALUES ($1, $2, 'member')
+               ON CONFLICT (conversation_id, user_id) DO NOTHING"#,
+        )
+        .bind(conv_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        InviteRedemption::Joined { conversation_id: conv_id, new_member: created }
+    };
+
+    if matches!(
+        outcome,
+        InviteRedemption::Joined { new_member: true, .. }
+            | InviteRedemption::Requested { new_request: true, .. }
+    ) {
+        sqlx::query(
+            "UPDATE group_settings SET invite_use_count = invite_use_count + 1 WHERE conversation_id = $1",
+        )
+        .bind(conv_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(outcome)
+}
+
 /// POST /api/groups/join/:token — Join group via invite link
 async fn join_via_invite(
     State(state): State<AppState>,
     user: AuthUser,
     Path(token): Path<String>,
 ) -> Response {
-    // Find group by invite token
-    let group = sqlx::query_as::<_, (Uuid, bool)>(
-        r#"SELECT conversation_id, invite_enabled FROM group_settings WHERE invite_link = $1"#,
-    )
-    .bind(&token)
-    .fetch_optional(&state.db)
-    .await;
+    let outcome = match redeem_group_invite(&state.db, &token, &user.id).await {
+        Ok(o) => o,
+        Err(e) => {
+            tracing::error!("Group join via invite failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to join group"})),
+            )
+                .into_response();
+        }
+    };
 
-    let (conv_id, invite_enabled) = match group {
-        Ok(Some(g)) => g,
-        Ok(None) => {
+    match outcome {
+        InviteRedemption::NotFound => {
             // Fall back to community invite code
             let community_invite = sqlx::query_as::<_, (Uuid, Uuid)>(
                 r#"SELECT id, community_id FROM community_invites WHERE code = $1"#,
@@ -692,24 +1292,165 @@ async fn join_via_invite(
                 .ok()
                 .flatten();
 
-                if let Some(cid) = conv {
-                    let _ = sqlx::query(
-                        "INSERT INTO conversation_user_members (conversation_id, user_id, role) VALUES ($1, $2, 'member') ON CONFLICT DO NOTHING",
-                    )
-                    .bind(cid)
-                    .bind(&user.id)
-                    .execute(&state.db)
-                    .await;
+                if let Some(cid) = conv {
+                    let _ = sqlx::query(
+                        "INSERT INTO conversation_user_members (conversation_id, user_id, role) VALUES ($1, $2, 'member') ON CONFLICT DO NOTHING",
+                    )
+                    .bind(cid)
+                    .bind(&user.id)
+                    .execute(&state.db)
+                    .await;
+
+                    return Json(json!({"conversationId": cid, "joined": true})).into_response();
+                }
+
+                return Json(json!({"joined": true})).into_response();
+            }
+
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Invalid invite link"})),
+            )
+                .into_response()
+        }
+        InviteRedemption::Disabled => (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Invites are disabled for this group"})),
+        )
+            .into_response(),
+        InviteRedemption::Expired => (
+            StatusCode::GONE,
+            Json(json!({"error": "This invite link has expired"})),
+        )
+            .into_response(),
+        InviteRedemption::Exhausted => invite_exhausted(),
+        InviteRedemption::GroupFull(max_users) => user_limit_reached(max_users),
+        InviteRedemption::Requested { conversation_id, new_request } => {
+            if new_request {
+                notify_join_request(&state, conversation_id, &user.id).await;
+            }
+            (
+                StatusCode::ACCEPTED,
+                Json(json!({"conversationId": conversation_id, "joined": false, "pending": true})),
+            )
+                .into_response()
+        }
+        InviteRedemption::Joined { conversation_id, new_member } => {
+            state.ws.invalidate_conv_member_cache(&conversation_id.to_string());
+
+            if new_member {
+                // Look up the joining user's name
+                let joiner_name = sqlx::query_as::<_, (String,)>(
+                    r#"SELECT name FROM "user" WHERE id = $1"#,
+                )
+                .bind(&user.id)
+                .fetch_optional(&state.db)
+                .await
+                .ok()
+                .flatten()
+                .map(|(n,)| n)
+                .unwrap_or_else(|| "Someone".to_string());
+
+                insert_system_message(&state, conversation_id, &format!("{} joined the group", joiner_name)).await;
+            }
+
+            Json(json!({"conversationId": conversation_id, "joined": true})).into_response()
+        }
+    }
+}
+
+/// Tell a group's admins about a new join request.
+async fn notify_join_request(state: &AppState, conv_id: Uuid, user_id: &str) {
+    let requester_name = sqlx::query_scalar::<_, String>(r#"SELECT name FROM "user" WHERE id = $1"#)
+        .bind(user_id)
+        .fetch_optional(&state.db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "Someone".to_string());
+
+    let moderators = sqlx::query_scalar::<_, String>(
+        r#"SELECT user_id FROM conversation_user_members
+           WHERE conversation_id = $1 AND role IN ('admin', 'vice_admin')"#,
+    )
+    .bind(conv_id)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    let event = json!({
+        "type": "group_join_request",
+        "conversationId": conv_id.to_string(),
+        "userId": user_id,
+        "userName": requester_name,
+    });
+    for moderator in &moderators {
+        state.ws.send_to_user_or_queue(moderator, &event, &state.redis);
+    }
+}
+
+fn invite_exhausted() -> Response {
+    (
+        StatusCode::GONE,
+        Json(json!({"error": "This invite link has reached its usage limit"})),
+    )
+        .into_response()
+}
+
+/// POST /api/groups/:id/requests/:userId/approve — Approve a pending join request
+async fn approve_join_request(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, target_id)): Path<(Uuid, String)>,
+) -> Response {
+    let role = get_user_role(&state.db, id, &user.id).await;
+    if !matches!(role.as_deref(), Some("admin") | Some("vice_admin")) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Insufficient permissions"})),
+        )
+            .into_response();
+    }
+
+    let (max_users, _) = group_limits(&state.db, id).await;
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM conversation_user_members WHERE conversation_id = $1",
+    )
+    .bind(id)
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or(0);
+    if count >= max_users {
+        return user_limit_reached(max_users);
+    }
 
-                    return Json(json!({"conversationId": cid, "joined": true})).into_response();
-                }
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
 
-                return Json(json!({"joined": true})).into_response();
-            }
+    let decided = sqlx::query(
+        r#"UPDATE group_join_requests SET status = 'approved', decided_at = NOW(), decided_by = $3
+           WHERE conversation_id = $1 AND user_id = $2 AND status = 'pending'"#,
+    )
+    .bind(id)
+    .bind(&target_id)
+    .bind(&user.id)
+    .execute(&mut *tx)
+    .await;
 
+    match decided {
+        Ok(r) if r.rows_affected() > 0 => {}
+        Ok(_) => {
             return (
                 StatusCode::NOT_FOUND,
-                Json(json!({"error": "Invalid invite link"})),
+                Json(json!({"error": "No pending request from this user"})),
             )
                 .into_response();
         }
@@ -720,67 +1461,107 @@ async fn join_via_invite(
             )
                 .into_response();
         }
-    };
-
-    if !invite_enabled {
-        return (
-            StatusCode::FORBIDDEN,
-            Json(json!({"error": "Invites are disabled for this group"})),
-        )
-            .into_response();
     }
 
-    // Check user limit
-    let count = sqlx::query_as::<_, (i64,)>(
-        "SELECT COUNT(*) FROM conversation_user_members WHERE conversation_id = $1",
+    let inserted = sqlx::query(
+        r#"INSERT INTO conversation_user_members (conversation_id, user_id, role)
+           VALUES ($1, $2, 'member')
+           ON CONFLICT (conversation_id, user_id) DO NOTHING"#,
     )
-    .bind(conv_id)
-    .fetch_one(&state.db)
+    .bind(id)
+    .bind(&target_id)
+    .execute(&mut *tx)
     .await;
 
-    if let Ok((c,)) = count {
-        if c >= 50 {
+    let joined = match inserted {
+        Ok(r) => r.rows_affected() > 0,
+        Err(e) => {
             return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": "Group has reached the maximum of 50 users"})),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
             )
                 .into_response();
         }
+    };
+
+    if let Err(e) = tx.commit().await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+
+    state.ws.invalidate_conv_member_cache(&id.to_string());
+    state.ws.send_to_user_or_queue(
+        &target_id,
+        &json!({
+            "type": "group_join_approved",
+            "conversationId": id.to_string(),
+        }),
+        &state.redis,
+    );
+
+    if joined {
+        let joiner_name = sqlx::query_as::<_, (String,)>(
+            r#"SELECT name FROM "user" WHERE id = $1"#,
+        )
+        .bind(&target_id)
+        .fetch_optional(&state.db)
+        .await
+        .ok()
+        .flatten()
+        .map(|(n,)| n)
+        .unwrap_or_else(|| "Someone".to_string());
+
+        insert_system_message(&state, id, &format!("{} joined the group", joiner_name)).await;
+    }
+
+    Json(json!({"approved": true})).into_response()
+}
+
+/// POST /api/groups/:id/requests/:userId/reject — Reject a pending join request
+async fn reject_join_request(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, target_id)): Path<(Uuid, String)>,
+) -> Response {
+    let role = get_user_role(&state.db, id, &user.id).await;
+    if !matches!(role.as_deref(), Some("admin") | Some("vice_admin")) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Insufficient permissions"})),
+        )
+            .into_response();
     }
 
-    // Add user as member (ON CONFLICT = already a member)
     let result = sqlx::query(
-        r#"INSERT INTO conversation_user_members (conversation_id, user_id, role)
-           VALUES ($1, $2, 'member')
-           ON CONFLICT (conversation_id, user_id) DO NOTHING"#,
+        r#"UPDATE group_join_requests SET status = 'rejected', decided_at = NOW(), decided_by = $3
+           WHERE conversation_id = $1 AND user_id = $2 AND status = 'pending'"#,
     )
-    .bind(conv_id)
+    .bind(id)
+    .bind(&target_id)
     .bind(&user.id)
     .execute(&state.db)
     .await;
 
     match result {
-        Ok(r) => {
-            state.ws.invalidate_conv_member_cache(&conv_id.to_string());
-
-            if r.rows_affected() > 0 {
-                // Look up the joining user's name
-                let joiner_name = sqlx::query_as::<_, (String,)>(
-                    r#"SELECT name FROM "user" WHERE id = $1"#,
-                )
-                .bind(&user.id)
-                .fetch_optional(&state.db)
-                .await
-                .ok()
-                .flatten()
-                .map(|(n,)| n)
-                .unwrap_or_else(|| "Someone".to_string());
-
-                insert_system_message(&state, conv_id, &format!("{} joined the group", joiner_name)).await;
-            }
-
-            Json(json!({"conversationId": conv_id, "joined": true})).into_response()
+        Ok(r) if r.rows_affected() > 0 => {
+            state.ws.send_to_user_or_queue(
+                &target_id,
+                &json!({
+                    "type": "group_join_rejected",
+                    "conversationId": id.to_string(),
+                }),
+                &state.redis,
+            );
+            Json(json!({"rejected": true})).into_response()
         }
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "No pending request from this user"})),
+        )
+            .into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": e.to_string()})),
@@ -895,6 +1676,12 @@ struct UpdateSettingsBody {
     invite_enabled: Option<bool>,
     #[serde(rename = "mentionOnly")]
     mention_only: Option<bool>,
+    #[serde(rename = "approvalRequired")]
+    approval_required: Option<bool>,
+    #[serde(rename = "maxUsers")]
+    max_users: Option<i32>,
+    #[serde(rename = "maxAgents")]
+    max_agents: Option<i32>,
 }
 
 /// PATCH /api/groups/:id/settings — Update group settings (admin only)
@@ -908,6 +1695,9 @@ async fn update_settings(
         && body.history_visible.is_none()
         && body.invite_enabled.is_none()
         && body.mention_only.is_none()
+        && body.approval_required.is_none()
+        && body.max_users.is_none()
+        && body.max_agents.is_none()
     {
         return (
             StatusCode::BAD_REQUEST,
@@ -925,6 +1715,50 @@ async fn update_settings(
             .into_response();
     }
 
+    // Limits may go up to the global ceiling but never below the current headcount
+    if let Some(max_users) = body.max_users {
+        let ceiling = state.config.group_max_users_ceiling;
+        let current = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM conversation_user_members WHERE conversation_id = $1",
+        )
+        .bind(id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(0);
+        if max_users < 1 || max_users > ceiling || (max_users as i64) < current {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!(
+                    "maxUsers must be between {} and {}",
+                    current.max(1),
+                    ceiling
+                )})),
+            )
+                .into_response();
+        }
+    }
+    if let Some(max_agents) = body.max_agents {
+        let ceiling = state.config.group_max_agents_ceiling;
+        let current = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM conversation_members WHERE conversation_id = $1",
+        )
+        .bind(id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(0);
+        if max_agents < 1 || max_agents > ceiling || (max_agents as i64) < current {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!(
+                    "maxAgents must be between {} and {}",
+                    current.max(1),
+                    ceiling
+                )})),
+            )
+                .into_response();
+        }
+    }
+
     // Update conversation title if provided
     if let Some(ref title) = body.title {
         let _ = sqlx::query("UPDATE conversations SET title = $1, updated_at = NOW() WHERE id = $2")
@@ -944,16 +1778,27 @@ async fn update_settings(
     }
 
     // Update group_settings
-    if body.history_visible.is_some() || body.invite_enabled.is_some() {
+    if body.history_visible.is_some()
+        || body.invite_enabled.is_some()
+        || body.approval_required.is_some()
+        || body.max_users.is_some()
+        || body.max_agents.is_some()
+    {
         let _ = sqlx::query(
             r#"UPDATE group_settings SET
                 history_visible = COALESCE($1, history_visible),
-                invite_enabled = COALESCE($2, invite_enabled)
+                invite_enabled = COALESCE($2, invite_enabled),
+                approval_required = COALESCE($4, approval_required),
+                max_users = COALESCE($5, max_users),
+                max_agents = COALESCE($6, max_agents)
                WHERE conversation_id = $3"#,
         )
         .bind(body.history_visible)
         .bind(body.invite_enabled)
         .bind(id)
+        .bind(body.approval_required)
+        .bind(body.max_users)
+        .bind(body.max_agents)
         .execute(&state.db)
         .await;
     }
@@ -1188,6 +2033,7 @@ async fn add_user_to_group(
     }
 
     // Check user limit
+    let (max_users, _) = group_limits(&state.db, id).await;
     let count = sqlx::query_as::<_, (i64,)>(
         "SELECT COUNT(*) FROM conversation_user_members WHERE conversation_id = $1",
     )
@@ -1196,12 +2042,8 @@ async fn add_user_to_group(
     .await;
 
     if let Ok((c,)) = count {
-        if c >= 50 {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": "Group has reached the maximum of 50 users"})),
-            )
-                .into_response();
+        if c >= max_users {
+            return user_limit_reached(max_users);
         }
     }
 
@@ -1251,6 +2093,168 @@ async fn add_user_to_group(
     }
 }
 
+#[derive(Deserialize)]
+struct AddUsersBody {
+    #[serde(rename = "userIds")]
+    user_ids: Vec<String>,
+}
+
+/// System message announcing a batch of added users, e.g. "A, B and C were added to the group".
+pub fn added_members_message(names: &[String]) -> String {
+    match names {
+        [] => String::new(),
+        [one] => format!("{} was added to the group", one),
+        [rest @ .., last] => format!("{} and {} were added to the group", rest.join(", "), last),
+    }
+}
+
+/// POST /api/groups/:id/add-users - Admin/vice-admin adds several users at once
+async fn add_users_to_group(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<AddUsersBody>,
+) -> Response {
+    let caller_role = get_user_role(&state.db, id, &user.id).await;
+    if !matches!(caller_role.as_deref(), Some("admin") | Some("vice_admin")) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Only admin or vice-admin can add users"})),
+        )
+            .into_response();
+    }
+
+    // Dedupe while keeping the caller's order for the results
+    let mut user_ids: Vec<String> = Vec::with_capacity(body.user_ids.len());
+    for uid in body.user_ids {
+        if !user_ids.contains(&uid) {
+            user_ids.push(uid);
+        }
+    }
+    if user_ids.is_empty() || user_ids.len() > 100 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "userIds must contain between 1 and 100 users"})),
+        )
+            .into_response();
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    // Serialize concurrent additions to this group so the cap holds
+    let max_users = sqlx::query_scalar::<_, i32>(
+        "SELECT max_users FROM group_settings WHERE conversation_id = $1 FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or(DEFAULT_MAX_USERS) as i64;
+
+    let existing_users = sqlx::query_as::<_, (String, String)>(
+        r#"SELECT id, name FROM "user" WHERE id = ANY($1)"#,
+    )
+    .bind(&user_ids)
+    .fetch_all(&mut *tx)
+    .await;
+    let existing_members = sqlx::query_scalar::<_, String>(
+        "SELECT user_id FROM conversation_user_members WHERE conversation_id = $1 AND user_id = ANY($2)",
+    )
+    .bind(id)
+    .bind(&user_ids)
+    .fetch_all(&mut *tx)
+    .await;
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM conversation_user_members WHERE conversation_id = $1",
+    )
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let (existing_users, existing_members, count) = match (existing_users, existing_members, count) {
+        (Ok(u), Ok(m), Ok(c)) => (u, m, c),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let mut results = Vec::with_capacity(user_ids.len());
+    let mut to_add: Vec<(String, String)> = Vec::new();
+    for uid in &user_ids {
+        if existing_members.contains(uid) {
+            results.push(json!({"userId": uid, "status": "skipped", "reason": "Already a member"}));
+        } else if let Some((_, name)) = existing_users.iter().find(|(u, _)| u == uid) {
+            results.push(json!({"userId": uid, "status": "added"}));
+            to_add.push((uid.clone(), name.clone()));
+        } else {
+            results.push(json!({"userId": uid, "status": "error", "reason": "User not found"}));
+        }
+    }
+
+    if count + to_add.len() as i64 > max_users {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!(
+                "Adding {} users would exceed the maximum of {} users",
+                to_add.len(),
+                max_users
+            )})),
+        )
+            .into_response();
+    }
+
+    if !to_add.is_empty() {
+        let ids: Vec<String> = to_add.iter().map(|(uid, _)| uid.clone()).collect();
+        let inserted = sqlx::query(
+            r#"INSERT INTO conversation_user_members (conversation_id, user_id, role)
+               SELECT $1, uid, 'member' FROM UNNEST($2::text[]) AS uid
+               ON CONFLICT (conversation_id, user_id) DO NOTHING"#,
+        )
+        .bind(id)
+        .bind(&ids)
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = inserted {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+
+    if !to_add.is_empty() {
+        state.ws.invalidate_conv_member_cache(&id.to_string());
+        let names: Vec<String> = to_add.into_iter().map(|(_, name)| name).collect();
+        insert_system_message(&state, id, &added_members_message(&names)).await;
+    }
+
+    Json(json!({"results": results})).into_response()
+}
+
 // ===== Agent Permission Endpoints =====
 
 #[derive(Deserialize)]
@@ -1314,7 +2318,10 @@ async fn update_listen_mode(
         .await;
 
     match result {
-        Ok(_) => Json(json!({"listenMode": body.listen_mode})).into_response(),
+        Ok(_) => {
+            broadcast_agent_settings_changed(&state, id, agent_id, json!({"listenMode": body.listen_mode})).await;
+            Json(json!({"listenMode": body.listen_mode})).into_response()
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": e.to_string()})),
@@ -1474,6 +2481,8 @@ async fn set_allowed_users(
             .into_response();
     }
 
+    broadcast_agent_settings_changed(&state, id, agent_id, json!({"allowedUsers": body.user_ids})).await;
+
     Json(json!({"allowedUsers": body.user_ids})).into_response()
 }
 
@@ -1534,6 +2543,7 @@ async fn withdraw_agent(
 
     match result {
         Ok(_) => {
+            broadcast_agent_settings_changed(&state, id, agent_id, json!({"withdrawn": true})).await;
             let name = agent_name.unwrap_or_else(|| "An agent".to_string());
             insert_system_message(&state, id, &format!("Agent {} was removed from the group", name)).await;
             Json(json!({"withdrawn": true})).into_response()
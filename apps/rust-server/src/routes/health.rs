@@ -1,17 +1,77 @@
-use axum::{http::StatusCode, response::Json, routing::get, Router};
+use std::future::Future;
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, response::Json, routing::get, Router};
 use serde_json::{json, Value};
 
 use crate::AppState;
 
+/// Per-dependency timeout for readiness checks.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
 pub fn router() -> Router<AppState> {
-    Router::new().route("/health", get(health_check))
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/livez", get(liveness))
 }
 
-async fn health_check() -> (StatusCode, Json<Value>) {
+/// Ready when every dependency is "ok" or not configured ("disabled").
+pub fn is_ready<'a>(statuses: impl IntoIterator<Item = &'a str>) -> bool {
+    statuses.into_iter().all(|s| s == "ok" || s == "disabled")
+}
+
+/// Run a check with `CHECK_TIMEOUT`, mapping the outcome to "ok", "error" or "timeout".
+async fn check<F, E>(fut: F) -> &'static str
+where
+    F: Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    match tokio::time::timeout(CHECK_TIMEOUT, fut).await {
+        Ok(Ok(())) => "ok",
+        Ok(Err(e)) => {
+            tracing::warn!("Health check failed: {}", e);
+            "error"
+        }
+        Err(_) => "timeout",
+    }
+}
+
+/// GET /livez — process is up; touches no dependencies.
+async fn liveness() -> StatusCode {
+    StatusCode::OK
+}
+
+/// GET /health — readiness probe for Postgres, Redis and R2 (503 if any fails).
+async fn health_check(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let database = check(async {
+        sqlx::query("SELECT 1").execute(&state.db).await.map(|_| ())
+    });
+    let redis = check(async {
+        let mut conn = state.redis.get().await.map_err(|e| e.to_string())?;
+        deadpool_redis::redis::cmd("PING")
+            .query_async::<String>(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    });
+    let r2 = async {
+        match &state.s3 {
+            Some(s3) => check(crate::services::r2::ping(s3, &state.config.r2_bucket)).await,
+            None => "disabled",
+        }
+    };
+    let (database, redis, r2) = tokio::join!(database, redis, r2);
+
+    let ready = is_ready([database, redis, r2]);
     (
-        StatusCode::OK,
+        if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE },
         Json(json!({
-            "status": "ok",
+            "status": if ready { "ok" } else { "degraded" },
+            "checks": {
+                "database": database,
+                "redis": redis,
+                "r2": r2,
+            },
             "timestamp": chrono::Utc::now().to_rfc3339(),
         })),
     )
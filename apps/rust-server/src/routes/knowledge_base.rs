@@ -6,6 +6,7 @@ use axum::{
     Router,
 };
 use chrono::NaiveDateTime;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use uuid::Uuid;
 
@@ -21,18 +22,32 @@ const KB_UPLOAD_FEE: i32 = 10;
 // Allowed MIME types / extensions
 const ALLOWED_EXTENSIONS: &[&str] = &["txt", "md", "csv", "json", "pdf"];
 
+// Search result bounds
+const DEFAULT_SEARCH_LIMIT: i32 = 5;
+const MAX_SEARCH_LIMIT: i32 = 20;
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route(
             "/api/agent-hub/agents/{listing_id}/knowledge-base",
             post(upload_file).get(list_files),
         )
+        .route(
+            "/api/agent-hub/agents/{listing_id}/knowledge-base/search",
+            post(search),
+        )
         .route(
             "/api/agent-hub/agents/{listing_id}/knowledge-base/{kb_id}",
             delete(delete_file),
         )
 }
 
+#[derive(Deserialize)]
+struct SearchBody {
+    query: String,
+    limit: Option<i32>,
+}
+
 // ---------------------------------------------------------------------------
 // FromRow structs
 // ---------------------------------------------------------------------------
@@ -362,6 +377,7 @@ async fn upload_file(
                 {
                     tracing::error!("KB {} failed to update status to ready: {:?}", kb_id, e);
                 }
+                crate::services::embedding::invalidate_kb_overview(&db, listing_id).await;
             }
             Err(e) => {
                 tracing::error!("KB {} embedding failed: {:?}", kb_id, e);
@@ -440,7 +456,10 @@ async fn delete_file(
     .await;
 
     match result {
-        Ok(r) if r.rows_affected() > 0 => Ok(StatusCode::NO_CONTENT),
+        Ok(r) if r.rows_affected() > 0 => {
+            crate::services::embedding::invalidate_kb_overview(&state.db, listing_id).await;
+            Ok(StatusCode::NO_CONTENT)
+        }
         Ok(_) => Err((
             StatusCode::NOT_FOUND,
             Json(json!({ "error": "Knowledge base file not found" })),
@@ -454,3 +473,53 @@ async fn delete_file(
         }
     }
 }
+
+// ---------------------------------------------------------------------------
+// POST /api/agent-hub/agents/{listing_id}/knowledge-base/search
+// ---------------------------------------------------------------------------
+
+async fn search(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(listing_id): Path<Uuid>,
+    Json(body): Json<SearchBody>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    // Creator check
+    verify_creator(&state.db, listing_id, &user.id).await?;
+
+    let query = body.query.trim();
+    if query.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "query is required" })),
+        ));
+    }
+    let limit = body.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+
+    match crate::services::embedding::search_chunks(&state.db, &state.config, listing_id, query, limit).await {
+        Ok((mode, hits)) => {
+            let results: Vec<Value> = hits
+                .into_iter()
+                .map(|h| {
+                    json!({
+                        "kbId": h.kb_id,
+                        "fileName": h.file_name,
+                        "chunkIndex": h.chunk_index,
+                        "content": h.content,
+                        "startOffset": h.start_offset,
+                        "endOffset": h.end_offset,
+                        "score": h.score,
+                    })
+                })
+                .collect();
+            Ok(Json(json!({ "mode": mode, "results": results })))
+        }
+        Err(e) => {
+            tracing::error!("KB search error: {:?}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Knowledge base search failed" })),
+            ))
+        }
+    }
+}
@@ -500,9 +500,9 @@ async fn join_lounge(
             ).bind(id).fetch_optional(&state.db).await.ok().flatten();
             if let Some(cid) = creator_id {
                 let welcome = format!("Welcome to {}! 🎉", lounge_name);
-                let seq = sqlx::query_scalar::<_, i32>(
-                    "SELECT COALESCE(MAX(seq), 0) + 1 FROM messages WHERE conversation_id = $1",
-                ).bind(conversation_id).fetch_one(&state.db).await.unwrap_or(1);
+                let seq = crate::services::message_seq::get_next_seq(&state.db, &conversation_id.to_string())
+                    .await
+                    .unwrap_or(1);
                 let _ = sqlx::query(
                     "INSERT INTO messages (conversation_id, role, content, status, sender_user_id, seq) VALUES ($1, 'user', $2, 'completed', $3, $4)",
                 ).bind(conversation_id).bind(&welcome).bind(&cid).bind(seq).execute(&state.db).await;
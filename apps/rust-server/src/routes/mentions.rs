@@ -0,0 +1,92 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/api/mentions", get(list_mentions))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MentionsQuery {
+    cursor: Option<String>,
+    limit: Option<i32>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct MentionRow {
+    message_id: Uuid,
+    conversation_id: Uuid,
+    conversation_title: Option<String>,
+    seq: i32,
+    content: String,
+    sender_user_id: Option<String>,
+    sender_agent_id: Option<Uuid>,
+    created_at: chrono::NaiveDateTime,
+}
+
+/// GET /api/mentions — messages that @mention the current user, newest first
+async fn list_mentions(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(q): Query<MentionsQuery>,
+) -> Response {
+    let limit = q.limit.unwrap_or(30).clamp(1, 100);
+    let cursor_ts = q.cursor.as_ref().and_then(|c| {
+        chrono::DateTime::parse_from_rfc3339(c).ok().map(|dt| dt.naive_utc())
+    });
+
+    // Only conversations the user still belongs to
+    let result = sqlx::query_as::<_, MentionRow>(
+        r#"SELECT mm.message_id, mm.conversation_id, c.title AS conversation_title,
+                  m.seq, m.content, m.sender_user_id, m.sender_agent_id, mm.created_at
+           FROM message_mentions mm
+           JOIN messages m ON m.id = mm.message_id
+           JOIN conversations c ON c.id = mm.conversation_id
+           WHERE mm.mentioned_user_id = $1
+             AND ($2::timestamp IS NULL OR mm.created_at < $2)
+             AND (c.user_id = $1 OR EXISTS (
+                   SELECT 1 FROM conversation_user_members cum
+                   WHERE cum.conversation_id = c.id AND cum.user_id = $1))
+           ORDER BY mm.created_at DESC
+           LIMIT $3"#,
+    )
+    .bind(&user.id)
+    .bind(cursor_ts)
+    .bind((limit + 1) as i64)
+    .fetch_all(&state.db)
+    .await;
+
+    match result {
+        Ok(mut rows) => {
+            let has_more = rows.len() > limit as usize;
+            if has_more {
+                rows.truncate(limit as usize);
+            }
+            let next_cursor = if has_more {
+                rows.last().map(|r| r.created_at.and_utc().to_rfc3339())
+            } else {
+                None
+            };
+
+            Json(json!({
+                "items": rows,
+                "nextCursor": next_cursor,
+            }))
+            .into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e.to_string() })))
+            .into_response(),
+    }
+}
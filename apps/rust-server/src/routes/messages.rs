@@ -32,7 +32,23 @@ pub fn router() -> Router<AppState> {
         )
         .route(
             "/api/conversations/{conversationId}/messages/{messageId}",
-            delete(delete_message),
+            delete(delete_message).patch(edit_message),
+        )
+        .route(
+            "/api/conversations/{conversationId}/messages/{messageId}/history",
+            get(message_edit_history),
+        )
+        .route(
+            "/api/conversations/{conversationId}/messages/{messageId}/regenerate",
+            post(regenerate_message),
+        )
+        .route(
+            "/api/conversations/{conversationId}/messages/{messageId}/versions",
+            get(list_message_versions),
+        )
+        .route(
+            "/api/conversations/{conversationId}/messages/{messageId}/select-version",
+            post(select_message_version),
         )
         .route(
             "/api/conversations/{conversationId}/messages/forward",
@@ -63,6 +79,9 @@ pub(crate) struct MessageRow {
     pub(crate) reply_to_id: Option<Uuid>,
     pub(crate) thread_id: Option<Uuid>,
     pub(crate) metadata: Option<serde_json::Value>,
+    pub(crate) reasoning: Option<String>,
+    pub(crate) parent_message_id: Option<Uuid>,
+    pub(crate) version: i32,
     pub(crate) created_at: NaiveDateTime,
     pub(crate) updated_at: NaiveDateTime,
 }
@@ -78,6 +97,7 @@ pub(crate) struct AttachmentRow {
     duration_seconds: Option<i32>,
     width: Option<i32>,
     height: Option<i32>,
+    thumbnail_path: Option<String>,
     created_at: NaiveDateTime,
 }
 
@@ -112,6 +132,50 @@ struct ConvCheck {
 
 // ── Attachment enrichment ──────────────────────────────────────────────
 
+/// Grouped reaction row: (message_id, emoji, count, caller_reacted)
+pub type ReactionCountRow = (Uuid, String, i64, bool);
+
+/// Fetch reaction summaries for a whole batch of messages in a single grouped query.
+async fn fetch_reactions(
+    db: &PgPool,
+    message_ids: &[Uuid],
+    caller_id: Option<&str>,
+) -> std::collections::HashMap<Uuid, Vec<serde_json::Value>> {
+    let rows = sqlx::query_as::<_, ReactionCountRow>(
+        r#"SELECT message_id,
+                  emoji,
+                  COUNT(*)::bigint AS count,
+                  COALESCE(BOOL_OR(user_id = $2), false) AS user_reacted
+           FROM message_reactions
+           WHERE message_id = ANY($1)
+           GROUP BY message_id, emoji
+           ORDER BY message_id, MIN(created_at)"#,
+    )
+    .bind(message_ids)
+    .bind(caller_id)
+    .fetch_all(db)
+    .await
+    .unwrap_or_default();
+
+    group_reactions(rows)
+}
+
+/// Group reaction rows into per-message summaries, preserving row order within each message.
+pub fn group_reactions(
+    rows: Vec<ReactionCountRow>,
+) -> std::collections::HashMap<Uuid, Vec<serde_json::Value>> {
+    let mut by_msg: std::collections::HashMap<Uuid, Vec<serde_json::Value>> =
+        std::collections::HashMap::new();
+    for (message_id, emoji, count, user_reacted) in rows {
+        by_msg.entry(message_id).or_default().push(json!({
+            "emoji": emoji,
+            "count": count,
+            "userReacted": user_reacted,
+        }));
+    }
+    by_msg
+}
+
 /// Fetch attachments for a batch of messages and merge them into JSON values.
 pub(crate) async fn with_attachments(
     db: &PgPool,
@@ -126,7 +190,7 @@ pub(crate) async fn with_attachments(
     let message_ids: Vec<Uuid> = items.iter().map(|m| m.id).collect();
 
     let attachments = sqlx::query_as::<_, AttachmentRow>(
-        "SELECT id, message_id, file_name, file_type, file_size, storage_path, duration_seconds, width, height, created_at
+        "SELECT id, message_id, file_name, file_type, file_size, storage_path, duration_seconds, width, height, thumbnail_path, created_at
          FROM attachments
          WHERE message_id = ANY($1)",
     )
@@ -280,6 +344,9 @@ pub(crate) async fn with_attachments(
         by_msg
     };
 
+    // Fetch reactions for the whole batch (one grouped query, not per message)
+    let reactions = fetch_reactions(db, &message_ids, caller_id).await;
+
     // Fetch reply-to message data
     let reply_ids: Vec<Uuid> = items.iter().filter_map(|m| m.reply_to_id).collect();
     let reply_data: std::collections::HashMap<Uuid, (String, String, Option<String>)> = if !reply_ids.is_empty() {
@@ -306,16 +373,20 @@ pub(crate) async fn with_attachments(
             let att_json: Vec<serde_json::Value> = atts
                 .iter()
                 .map(|a| {
-                    let url = if a.storage_path.starts_with("http://") || a.storage_path.starts_with("https://") {
-                        // Already a full URL (R2 uploads store the complete URL)
-                        a.storage_path.clone()
-                    } else if is_r2 {
-                        format!("{}/{}", config.r2_public_url, a.storage_path)
-                    } else if a.storage_path.starts_with("/uploads/") {
-                        a.storage_path.clone()
-                    } else {
-                        format!("/uploads/{}", a.storage_path)
+                    let resolve = |path: &str| {
+                        if path.starts_with("http://") || path.starts_with("https://") {
+                            // Already a full URL (R2 uploads store the complete URL)
+                            path.to_string()
+                        } else if is_r2 {
+                            format!("{}/{}", config.r2_public_url, path)
+                        } else if path.starts_with("/uploads/") {
+                            path.to_string()
+                        } else {
+                            format!("/uploads/{}", path)
+                        }
                     };
+                    let url = resolve(&a.storage_path);
+                    let thumbnail_url = a.thumbnail_path.as_deref().map(resolve);
                     json!({
                         "id": a.id,
                         "messageId": a.message_id,
@@ -326,6 +397,7 @@ pub(crate) async fn with_attachments(
                         "duration": a.duration_seconds,
                         "width": a.width,
                         "height": a.height,
+                        "thumbnailUrl": thumbnail_url,
                         "createdAt": a.created_at.and_utc().to_rfc3339(),
                     })
                 })
@@ -423,7 +495,11 @@ pub(crate) async fn with_attachments(
                     "updatedAt": m.updated_at.and_utc().to_rfc3339(),
                     "attachments": att_json,
                     "linkPreviews": link_previews.get(&m.id).cloned().unwrap_or_default(),
+                    "reactions": reactions.get(&m.id).cloned().unwrap_or_default(),
                     "metadata": m.metadata,
+                    "reasoning": m.reasoning,
+                    "parentMessageId": m.parent_message_id,
+                    "version": m.version,
                 })
             }
         })
@@ -686,9 +762,21 @@ struct MessagesQuery {
     before: Option<String>,
     after: Option<String>,
     around: Option<String>,
+    /// `YYYY-MM-DD`: window around the first message on or after that day
+    date: Option<String>,
+    tz: Option<i32>, // timezone offset in minutes, as for /messages/by-date
     limit: Option<String>,
 }
 
+/// UTC start of the day named by a `YYYY-MM-DD` string, for a client whose
+/// `getTimezoneOffset()` is `tz_offset_minutes` (-480 for UTC+8).
+pub fn parse_jump_date(date: &str, tz_offset_minutes: i32) -> Option<chrono::NaiveDateTime> {
+    chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|local| local + chrono::Duration::minutes(tz_offset_minutes as i64))
+}
+
 async fn get_messages(
     State(state): State<AppState>,
     user: AuthUser,
@@ -732,10 +820,51 @@ async fn get_messages(
         Ok(Some(_)) => {}
     }
 
-    // --- Around mode: load messages centered on target ---
-    if let Some(ref around_id) = query.around {
-        let around_uuid = match Uuid::parse_str(around_id) {
-            Ok(u) => u,
+    // --- Date mode: resolve to the first message on or after the day ---
+    let around_target = if let Some(ref date) = query.date {
+        let Some(day_start) = parse_jump_date(date, query.tz.unwrap_or(0)) else {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Invalid date, expected YYYY-MM-DD"})),
+            )
+                .into_response();
+        };
+        let first = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM messages
+             WHERE conversation_id = $1 AND created_at >= $2
+             ORDER BY created_at ASC
+             LIMIT 1",
+        )
+        .bind(id)
+        .bind(day_start)
+        .fetch_optional(&state.db)
+        .await;
+        match first {
+            Ok(Some(mid)) => Some(mid),
+            Ok(None) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({
+                        "error": "No messages on or after this date",
+                        "messages": [],
+                        "hasMore": false,
+                        "hasMoreUp": false,
+                        "hasMoreDown": false,
+                    })),
+                )
+                    .into_response();
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": e.to_string()})),
+                )
+                    .into_response();
+            }
+        }
+    } else if let Some(ref around_id) = query.around {
+        match Uuid::parse_str(around_id) {
+            Ok(u) => Some(u),
             Err(_) => {
                 return (
                     StatusCode::BAD_REQUEST,
@@ -743,8 +872,13 @@ async fn get_messages(
                 )
                     .into_response();
             }
-        };
+        }
+    } else {
+        None
+    };
 
+    // --- Around mode: load messages centered on target ---
+    if let Some(around_uuid) = around_target {
         let target = sqlx::query_as::<_, MessageRow>(
             "SELECT * FROM messages WHERE id = $1 AND conversation_id = $2",
         )
@@ -842,6 +976,7 @@ async fn get_messages(
             "hasMoreUp": has_more_up,
             "hasMoreDown": has_more_down,
             "nextCursor": next_cursor,
+            "targetId": around_uuid,
         }))
         .into_response();
     }
@@ -1764,6 +1899,618 @@ async fn delete_message(
     }
 }
 
+// ── 6. PATCH /api/conversations/{conversationId}/messages/:messageId ────
+
+#[derive(Deserialize)]
+struct EditMessageBody {
+    content: String,
+}
+
+async fn edit_message(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((conversation_id, message_id)): Path<(Uuid, Uuid)>,
+    Json(body): Json<EditMessageBody>,
+) -> Response {
+    let content = body.content.trim();
+    if content.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Content cannot be empty"})),
+        )
+            .into_response();
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    // Only the sender can edit their own message
+    let previous = sqlx::query_as::<_, (String, Option<String>)>(
+        "SELECT content, sender_user_id FROM messages WHERE id = $1 AND conversation_id = $2 FOR UPDATE",
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let previous_content = match previous {
+        Ok(Some((content, Some(sender)))) if sender == user.id => content,
+        Ok(Some(_)) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "Only the sender can edit this message"})),
+            )
+                .into_response();
+        }
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Message not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    if previous_content == content {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+
+    let recorded = sqlx::query(
+        "INSERT INTO message_edits (message_id, previous_content, edited_by) VALUES ($1, $2, $3)",
+    )
+    .bind(message_id)
+    .bind(&previous_content)
+    .bind(&user.id)
+    .execute(&mut *tx)
+    .await;
+
+    let updated = match recorded {
+        Ok(_) => {
+            sqlx::query("UPDATE messages SET content = $1, updated_at = NOW() WHERE id = $2")
+                .bind(content)
+                .bind(message_id)
+                .execute(&mut *tx)
+                .await
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = updated {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+    if let Err(e) = tx.commit().await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+
+    let member_ids = sqlx::query_as::<_, (String,)>(
+        "SELECT user_id FROM conversation_user_members WHERE conversation_id = $1",
+    )
+    .bind(conversation_id)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(id,)| id)
+    .collect::<Vec<_>>();
+
+    if !member_ids.is_empty() {
+        state.ws.broadcast_to_members(
+            &member_ids,
+            &json!({
+                "type": "message_edited",
+                "conversationId": conversation_id.to_string(),
+                "messageId": message_id.to_string(),
+                "content": content,
+            }),
+            &state.redis,
+        );
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+// ── 7. GET /api/conversations/{conversationId}/messages/:messageId/history ──
+
+async fn message_edit_history(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((conversation_id, message_id)): Path<(Uuid, Uuid)>,
+) -> Response {
+    // Restricted to the conversation owner and members
+    let conv = sqlx::query_as::<_, ConvCheck>(
+        r#"SELECT id FROM conversations WHERE id = $1 AND (
+            user_id = $2
+            OR EXISTS (SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = $1 AND cum.user_id = $2)
+        )"#,
+    )
+    .bind(conversation_id)
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await;
+
+    match conv {
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Conversation not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+        Ok(Some(_)) => {}
+    }
+
+    let rows = sqlx::query_as::<_, (Uuid, String, String, NaiveDateTime)>(
+        r#"SELECT e.id, e.previous_content, e.edited_by, e.edited_at
+           FROM message_edits e
+           JOIN messages m ON m.id = e.message_id
+           WHERE e.message_id = $1 AND m.conversation_id = $2
+           ORDER BY e.edited_at ASC"#,
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .fetch_all(&state.db)
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let edits: Vec<serde_json::Value> = rows
+                .into_iter()
+                .map(|(id, previous_content, edited_by, edited_at)| {
+                    json!({
+                        "id": id,
+                        "previousContent": previous_content,
+                        "editedBy": edited_by,
+                        "editedAt": edited_at.and_utc().to_rfc3339(),
+                    })
+                })
+                .collect();
+            Json(json!({ "messageId": message_id, "edits": edits })).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
+// ── 8. POST /api/conversations/{conversationId}/messages/:messageId/regenerate ──
+
+/// Why an agent reply can't be regenerated, if it can't.
+pub fn regenerate_blocked(is_agent_reply: bool, status: &str, has_later_user_message: bool) -> Option<&'static str> {
+    if !is_agent_reply {
+        return Some("Only agent replies can be regenerated");
+    }
+    if status == "streaming" || status == "pending" {
+        return Some("Reply is still in progress");
+    }
+    if has_later_user_message {
+        return Some("Only the latest reply can be regenerated");
+    }
+    None
+}
+
+/// Only the author of the prompt or a conversation admin (owner, group admin or
+/// vice admin) may regenerate a reply, as with cancelling a stream; other members
+/// would otherwise re-spend the author's message.
+pub fn can_regenerate(user_id: &str, prompt_user_id: &str, is_admin: bool) -> bool {
+    is_admin || user_id == prompt_user_id
+}
+
+/// Re-run the user message that prompted an agent reply. The old reply stays
+/// in place marked `superseded` (and out of agent history); the new one joins
+/// its response group at the same ordinal so clients can show both versions.
+async fn regenerate_message(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((conversation_id, message_id)): Path<(Uuid, Uuid)>,
+) -> Response {
+    let conv = sqlx::query_as::<_, (String, bool)>(
+        r#"SELECT type::text,
+                  user_id = $2 OR EXISTS (
+                      SELECT 1 FROM conversation_user_members cum
+                      WHERE cum.conversation_id = $1 AND cum.user_id = $2 AND cum.role IN ('admin', 'vice_admin')
+                  )
+           FROM conversations WHERE id = $1 AND (
+            user_id = $2
+            OR EXISTS (SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = $1 AND cum.user_id = $2)
+        )"#,
+    )
+    .bind(conversation_id)
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await;
+
+    let (conv_type, is_admin) = match conv {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Conversation not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let target = sqlx::query_as::<_, MessageRow>(
+        "SELECT * FROM messages WHERE id = $1 AND conversation_id = $2",
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .fetch_optional(&state.db)
+    .await;
+
+    let target = match target {
+        Ok(Some(m)) => m,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Message not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    // The user message this reply answered: latest user message before it, same thread
+    let prompt = sqlx::query_as::<_, (String, String, Option<Uuid>)>(
+        r#"SELECT content, sender_user_id, reply_to_id FROM messages
+           WHERE conversation_id = $1 AND seq < $2
+             AND thread_id IS NOT DISTINCT FROM $3
+             AND sender_user_id IS NOT NULL AND sender_agent_id IS NULL
+           ORDER BY seq DESC LIMIT 1"#,
+    )
+    .bind(conversation_id)
+    .bind(target.seq)
+    .bind(target.thread_id)
+    .fetch_optional(&state.db)
+    .await
+    .ok()
+    .flatten();
+
+    let has_later_user_message = sqlx::query_scalar::<_, bool>(
+        r#"SELECT EXISTS (SELECT 1 FROM messages
+           WHERE conversation_id = $1 AND seq > $2
+             AND thread_id IS NOT DISTINCT FROM $3
+             AND sender_user_id IS NOT NULL AND sender_agent_id IS NULL)"#,
+    )
+    .bind(conversation_id)
+    .bind(target.seq)
+    .bind(target.thread_id)
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or(true);
+
+    let status = target.status.to_string();
+    if let Some(reason) = regenerate_blocked(target.sender_agent_id.is_some(), &status, has_later_user_message) {
+        let code = if target.sender_agent_id.is_some() { StatusCode::CONFLICT } else { StatusCode::BAD_REQUEST };
+        return (code, Json(json!({"error": reason}))).into_response();
+    }
+    let (Some(agent_id), Some((content, prompt_user_id, reply_to_id))) = (target.sender_agent_id, prompt) else {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "No user message to regenerate from"})),
+        )
+            .into_response();
+    };
+    if !can_regenerate(&user.id, &prompt_user_id, is_admin) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Only the prompt's author or a conversation admin can regenerate this reply"})),
+        )
+            .into_response();
+    }
+
+    // New reply takes the old one's slot in its response group
+    let meta = target.metadata.clone().unwrap_or_else(|| json!({}));
+    let group = crate::ws::state::ResponseGroup {
+        id: meta
+            .get("responseGroupId")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| message_id.to_string()),
+        ordinal: meta.get("responseOrdinal").and_then(|v| v.as_u64()).unwrap_or(0) as usize,
+    };
+
+    // Supersede every existing version; replies from before versioning get
+    // attached to their prompt as version 1
+    if let Err(e) = sqlx::query(
+        r#"UPDATE messages
+           SET metadata = COALESCE(metadata, '{}'::jsonb)
+                 || jsonb_build_object('superseded', true, 'supersededAt', NOW()),
+               parent_message_id = COALESCE(parent_message_id,
+                 (SELECT id FROM messages WHERE id = $2::uuid AND conversation_id = $3))
+           WHERE id = $1
+              OR (conversation_id = $3 AND parent_message_id = $2::uuid AND sender_agent_id = $4)"#,
+    )
+    .bind(message_id)
+    .bind(&group.id)
+    .bind(conversation_id)
+    .bind(agent_id)
+    .execute(&state.db)
+    .await
+    {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+
+    let member_ids = crate::ws::handler::get_conv_member_ids(&state.ws, &state.db, &conversation_id.to_string(), "").await;
+    state.ws.broadcast_to_members(&member_ids, &json!({
+        "type": "message_superseded",
+        "conversationId": conversation_id.to_string(),
+        "messageId": message_id.to_string(),
+        "responseGroupId": &group.id,
+        "responseOrdinal": group.ordinal,
+    }), &state.redis);
+
+    let ws = state.ws.clone();
+    let db = state.db.clone();
+    let redis = state.redis.clone();
+    let config = state.config.clone();
+    let cid = conversation_id.to_string();
+    let aid = agent_id.to_string();
+    let reply_to = reply_to_id.map(|r| r.to_string());
+    let thread = target.thread_id.map(|t| t.to_string());
+    let response = json!({
+        "supersededMessageId": message_id,
+        "responseGroupId": &group.id,
+        "responseOrdinal": group.ordinal,
+    });
+    tokio::spawn(async move {
+        crate::ws::handler::do_trigger_agent_response(
+            &aid,
+            &cid,
+            crate::ws::handler::TriggerContext {
+                user_id: &prompt_user_id,
+                content: &content,
+                reply_to_id: reply_to.as_deref(),
+                thread_id: thread.as_deref(),
+                conv_type: &conv_type,
+                client_metadata: None,
+                response_group: Some(&group),
+                mention_depth: 0,
+            },
+            &ws,
+            &db,
+            &redis,
+            &config,
+        )
+        .await;
+    });
+
+    (StatusCode::ACCEPTED, Json(response)).into_response()
+}
+
+// ── 9. Agent reply versions ────────────────────────────────────────────
+
+/// Whether a reply's metadata marks it as an inactive version.
+pub fn is_superseded(metadata: Option<&serde_json::Value>) -> bool {
+    metadata
+        .and_then(|m| m.get("superseded"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Load a message and every version of it (same prompt, same agent), oldest
+/// first. A reply that was never regenerated is its own single version.
+async fn load_versions(
+    db: &PgPool,
+    conversation_id: Uuid,
+    message_id: Uuid,
+) -> Result<Option<Vec<MessageRow>>, sqlx::Error> {
+    let target = sqlx::query_as::<_, MessageRow>(
+        "SELECT * FROM messages WHERE id = $1 AND conversation_id = $2 AND sender_agent_id IS NOT NULL",
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .fetch_optional(db)
+    .await?;
+    let Some(target) = target else {
+        return Ok(None);
+    };
+    let Some(parent_id) = target.parent_message_id else {
+        return Ok(Some(vec![target]));
+    };
+    let versions = sqlx::query_as::<_, MessageRow>(
+        r#"SELECT * FROM messages
+           WHERE conversation_id = $1 AND parent_message_id = $2 AND sender_agent_id = $3
+           ORDER BY version ASC, seq ASC"#,
+    )
+    .bind(conversation_id)
+    .bind(parent_id)
+    .bind(target.sender_agent_id)
+    .fetch_all(db)
+    .await?;
+    Ok(Some(versions))
+}
+
+async fn check_conversation_access(state: &AppState, conversation_id: Uuid, user_id: &str) -> Result<(), Response> {
+    let conv = sqlx::query_as::<_, ConvCheck>(
+        r#"SELECT id FROM conversations WHERE id = $1 AND (
+            user_id = $2
+            OR EXISTS (SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = $1 AND cum.user_id = $2)
+        )"#,
+    )
+    .bind(conversation_id)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await;
+
+    match conv {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Conversation not found"})),
+        )
+            .into_response()),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response()),
+    }
+}
+
+/// GET /api/conversations/{conversationId}/messages/:messageId/versions
+async fn list_message_versions(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((conversation_id, message_id)): Path<(Uuid, Uuid)>,
+) -> Response {
+    if let Err(resp) = check_conversation_access(&state, conversation_id, &user.id).await {
+        return resp;
+    }
+
+    let versions = match load_versions(&state.db, conversation_id, message_id).await {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Agent message not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let active_id = versions
+        .iter()
+        .rev()
+        .find(|m| !is_superseded(m.metadata.as_ref()))
+        .map(|m| m.id);
+    let parent_id = versions.first().and_then(|m| m.parent_message_id);
+    let items = with_attachments(&state.db, &state.config, &versions, Some(&user.id)).await;
+
+    Json(json!({
+        "parentMessageId": parent_id,
+        "activeMessageId": active_id,
+        "versions": items,
+    }))
+    .into_response()
+}
+
+/// POST /api/conversations/{conversationId}/messages/:messageId/select-version
+/// Make this version the active one; its siblings become superseded.
+async fn select_message_version(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((conversation_id, message_id)): Path<(Uuid, Uuid)>,
+) -> Response {
+    if let Err(resp) = check_conversation_access(&state, conversation_id, &user.id).await {
+        return resp;
+    }
+
+    let versions = match load_versions(&state.db, conversation_id, message_id).await {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Agent message not found"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+    if versions.iter().any(|m| m.status.to_string() == "streaming") {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "A version is still streaming"})),
+        )
+            .into_response();
+    }
+
+    let ids: Vec<Uuid> = versions.iter().map(|m| m.id).collect();
+    let result = sqlx::query(
+        r#"UPDATE messages
+           SET metadata = CASE WHEN id = $2
+                 THEN COALESCE(metadata, '{}'::jsonb) - 'superseded' - 'supersededAt'
+                 ELSE COALESCE(metadata, '{}'::jsonb)
+                        || jsonb_build_object('superseded', true, 'supersededAt', NOW())
+               END
+           WHERE id = ANY($1)"#,
+    )
+    .bind(&ids)
+    .bind(message_id)
+    .execute(&state.db)
+    .await;
+    if let Err(e) = result {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response();
+    }
+
+    let parent_id = versions.first().and_then(|m| m.parent_message_id);
+    let member_ids = crate::ws::handler::get_conv_member_ids(&state.ws, &state.db, &conversation_id.to_string(), "").await;
+    state.ws.broadcast_to_members(&member_ids, &json!({
+        "type": "message_version_selected",
+        "conversationId": conversation_id.to_string(),
+        "parentMessageId": parent_id,
+        "messageId": message_id.to_string(),
+    }), &state.redis);
+
+    Json(json!({"activeMessageId": message_id, "parentMessageId": parent_id})).into_response()
+}
+
 // ── Helpers ────────────────────────────────────────────────────────────
 
 fn clone_message_row(m: &MessageRow) -> MessageRow {
@@ -1779,6 +2526,9 @@ fn clone_message_row(m: &MessageRow) -> MessageRow {
         reply_to_id: m.reply_to_id,
         thread_id: m.thread_id,
         metadata: m.metadata.clone(),
+        reasoning: m.reasoning.clone(),
+        parent_message_id: m.parent_message_id,
+        version: m.version,
         created_at: m.created_at,
         updated_at: m.updated_at,
     }
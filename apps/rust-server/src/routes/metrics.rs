@@ -0,0 +1,56 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+
+use crate::services::metrics;
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/metrics", get(prometheus_metrics))
+}
+
+/// Whether an `Authorization` header carries the configured metrics token.
+/// Always false when no token is configured.
+pub fn metrics_authorized(token: Option<&str>, authorization: Option<&str>) -> bool {
+    let (Some(token), Some(presented)) = (token, authorization.and_then(|h| h.strip_prefix("Bearer "))) else {
+        return false;
+    };
+    // Constant-time comparison so the token can't be probed byte by byte
+    token.len() == presented.len()
+        && token
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// GET /metrics — Prometheus text exposition. Requires `Authorization: Bearer
+/// $METRICS_TOKEN`; 404 when no token is configured.
+async fn prometheus_metrics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if state.config.metrics_token.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let authorization = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+    if !metrics_authorized(state.config.metrics_token.as_deref(), authorization) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    // Point-in-time gauges are sampled at scrape time
+    let connections: usize = state.ws.user_connections.iter().map(|c| c.value().len()).sum();
+    ::metrics::gauge!(metrics::WS_CONNECTIONS).set(connections as f64);
+    ::metrics::gauge!(metrics::WS_CONNECTED_USERS).set(state.ws.user_connections.len() as f64);
+    ::metrics::gauge!(metrics::ACTIVE_STREAMS).set(state.ws.active_streams.len() as f64);
+
+    match metrics::render() {
+        Some(body) => (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            body,
+        )
+            .into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, "metrics recorder not installed").into_response(),
+    }
+}
@@ -23,6 +23,7 @@ pub mod community;
 pub mod oauth;
 pub mod api_v1;
 pub mod themes;
+pub mod color_themes;
 pub mod stickers;
 pub mod admin;
 pub mod reports;
@@ -57,6 +58,10 @@ pub mod v1_kanban;
 pub mod v1_resources;
 pub mod hud;
 pub mod search;
+pub mod rate_limit;
+pub mod mentions;
+pub mod metrics;
+pub mod scheduled_messages;
 
 use axum::Router;
 use crate::AppState;
@@ -91,6 +96,7 @@ pub fn api_router() -> Router<AppState> {
         .merge(oauth::router())
         .merge(api_v1::router())
         .merge(themes::router())
+        .merge(color_themes::router())
         .merge(stickers::router())
         .merge(admin::router())
         .merge(reports::router())
@@ -123,6 +129,10 @@ pub fn api_router() -> Router<AppState> {
         .merge(v1_resources::router())
         .merge(hud::router())
         .merge(search::router())
+        .merge(rate_limit::router())
+        .merge(mentions::router())
+        .merge(metrics::router())
+        .merge(scheduled_messages::router())
 }
 
 /// Legacy wrapper — kept for backward compatibility.
@@ -10,6 +10,8 @@ use serde_json::json;
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
+use crate::db::models::NotificationPreference;
+use crate::routes::conversation_settings::deserialize_optional_field;
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
@@ -19,11 +21,42 @@ pub fn router() -> Router<AppState> {
     )
 }
 
+fn preferences_json(p: &NotificationPreference) -> serde_json::Value {
+    json!({
+        "id": p.id,
+        "userId": p.user_id,
+        "globalEnabled": p.global_enabled,
+        "messageEnabled": p.message_enabled,
+        "mentionEnabled": p.mention_enabled,
+        "communityEnabled": p.community_enabled,
+        "friendRequestEnabled": p.friend_request_enabled,
+        "voiceCallEnabled": p.voice_call_enabled,
+        "playgroundInviteEnabled": p.playground_invite_enabled,
+        "playgroundTurnEnabled": p.playground_turn_enabled,
+        "playgroundResultEnabled": p.playground_result_enabled,
+        "quietHoursStart": p.quiet_hours_start,
+        "quietHoursEnd": p.quiet_hours_end,
+        "quietHoursUtcOffset": p.quiet_hours_utc_offset,
+        "alwaysPushMobile": p.always_push_mobile,
+    })
+}
+
+/// Whether `t` is a "HH:MM" time of day.
+pub fn is_valid_quiet_hours_time(t: &str) -> bool {
+    let Some((h, m)) = t.split_once(':') else {
+        return false;
+    };
+    h.len() == 2
+        && m.len() == 2
+        && h.parse::<u32>().is_ok_and(|h| h < 24)
+        && m.parse::<u32>().is_ok_and(|m| m < 60)
+}
+
 async fn get_preferences(
     State(state): State<AppState>,
     user: AuthUser,
 ) -> Response {
-    let prefs = sqlx::query_as::<_, crate::db::models::NotificationPreference>(
+    let prefs = sqlx::query_as::<_, NotificationPreference>(
         "SELECT * FROM notification_preferences WHERE user_id = $1",
     )
     .bind(&user.id)
@@ -31,19 +64,7 @@ async fn get_preferences(
     .await;
 
     match prefs {
-        Ok(Some(p)) => Json(json!({
-            "id": p.id,
-            "userId": p.user_id,
-            "globalEnabled": p.global_enabled,
-            "messageEnabled": p.message_enabled,
-            "playgroundInviteEnabled": p.playground_invite_enabled,
-            "playgroundTurnEnabled": p.playground_turn_enabled,
-            "playgroundResultEnabled": p.playground_result_enabled,
-            "quietHoursStart": p.quiet_hours_start,
-            "quietHoursEnd": p.quiet_hours_end,
-            "alwaysPushMobile": p.always_push_mobile,
-        }))
-        .into_response(),
+        Ok(Some(p)) => Json(preferences_json(&p)).into_response(),
         Ok(None) => {
             // Return defaults
             Json(json!({
@@ -54,8 +75,13 @@ async fn get_preferences(
                 "playgroundInviteEnabled": true,
                 "playgroundTurnEnabled": true,
                 "playgroundResultEnabled": true,
+                "mentionEnabled": true,
+                "communityEnabled": true,
+                "friendRequestEnabled": true,
+                "voiceCallEnabled": true,
                 "quietHoursStart": null,
                 "quietHoursEnd": null,
+                "quietHoursUtcOffset": null,
                 "alwaysPushMobile": false,
             }))
             .into_response()
@@ -74,6 +100,14 @@ struct UpdatePreferencesBody {
     global_enabled: Option<bool>,
     #[serde(rename = "messageEnabled")]
     message_enabled: Option<bool>,
+    #[serde(rename = "mentionEnabled")]
+    mention_enabled: Option<bool>,
+    #[serde(rename = "communityEnabled")]
+    community_enabled: Option<bool>,
+    #[serde(rename = "friendRequestEnabled")]
+    friend_request_enabled: Option<bool>,
+    #[serde(rename = "voiceCallEnabled")]
+    voice_call_enabled: Option<bool>,
     #[serde(rename = "playgroundInviteEnabled")]
     playground_invite_enabled: Option<bool>,
     #[serde(rename = "playgroundTurnEnabled")]
@@ -84,6 +118,9 @@ struct UpdatePreferencesBody {
     quiet_hours_start: Option<String>,
     #[serde(rename = "quietHoursEnd")]
     quiet_hours_end: Option<String>,
+    /// Minutes east of UTC, e.g. 480 for UTC+8. `null` clears it.
+    #[serde(rename = "quietHoursUtcOffset", deserialize_with = "deserialize_optional_field", default)]
+    quiet_hours_utc_offset: Option<Option<i32>>,
     #[serde(rename = "alwaysPushMobile")]
     always_push_mobile: Option<bool>,
 }
@@ -93,18 +130,39 @@ async fn update_preferences(
     user: AuthUser,
     Json(body): Json<UpdatePreferencesBody>,
 ) -> Response {
+    for t in [&body.quiet_hours_start, &body.quiet_hours_end].into_iter().flatten() {
+        if !is_valid_quiet_hours_time(t) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Quiet hours must be in HH:MM format"})),
+            )
+                .into_response();
+        }
+    }
+    if body.quiet_hours_utc_offset.flatten().is_some_and(|o| !(-720..=840).contains(&o)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "quietHoursUtcOffset must be between -720 and 840 minutes"})),
+        )
+            .into_response();
+    }
+
     let pref_id = Uuid::new_v4();
 
     // Upsert: insert or update on conflict
-    let result = sqlx::query_as::<_, crate::db::models::NotificationPreference>(
+    let result = sqlx::query_as::<_, NotificationPreference>(
         r#"INSERT INTO notification_preferences
                (id, user_id, global_enabled, message_enabled,
                 playground_invite_enabled, playground_turn_enabled, playground_result_enabled,
-                quiet_hours_start, quiet_hours_end, always_push_mobile)
+                quiet_hours_start, quiet_hours_end, always_push_mobile,
+                mention_enabled, community_enabled, friend_request_enabled, voice_call_enabled,
+                quiet_hours_utc_offset)
            VALUES ($1, $2,
                    COALESCE($3, true), COALESCE($4, true),
                    COALESCE($5, true), COALESCE($6, true), COALESCE($7, true),
-                   $8, $9, COALESCE($10, false))
+                   $8, $9, COALESCE($10, false),
+                   COALESCE($11, true), COALESCE($12, true), COALESCE($13, true), COALESCE($14, true),
+                   $15)
            ON CONFLICT (user_id) DO UPDATE SET
                global_enabled = COALESCE($3, notification_preferences.global_enabled),
                message_enabled = COALESCE($4, notification_preferences.message_enabled),
@@ -113,7 +171,12 @@ async fn update_preferences(
                playground_result_enabled = COALESCE($7, notification_preferences.playground_result_enabled),
                quiet_hours_start = $8,
                quiet_hours_end = $9,
-               always_push_mobile = COALESCE($10, notification_preferences.always_push_mobile)
+               always_push_mobile = COALESCE($10, notification_preferences.always_push_mobile),
+               mention_enabled = COALESCE($11, notification_preferences.mention_enabled),
+               community_enabled = COALESCE($12, notification_preferences.community_enabled),
+               friend_request_enabled = COALESCE($13, notification_preferences.friend_request_enabled),
+               voice_call_enabled = COALESCE($14, notification_preferences.voice_call_enabled),
+               quiet_hours_utc_offset = CASE WHEN $16 THEN $15 ELSE notification_preferences.quiet_hours_utc_offset END
            RETURNING *"#,
     )
     .bind(pref_id)
@@ -126,22 +189,17 @@ async fn update_preferences(
     .bind(&body.quiet_hours_start)
     .bind(&body.quiet_hours_end)
     .bind(body.always_push_mobile)
+    .bind(body.mention_enabled)
+    .bind(body.community_enabled)
+    .bind(body.friend_request_enabled)
+    .bind(body.voice_call_enabled)
+    .bind(body.quiet_hours_utc_offset.flatten())
+    .bind(body.quiet_hours_utc_offset.is_some())
     .fetch_one(&state.db)
     .await;
 
     match result {
-        Ok(p) => Json(json!({
-            "id": p.id,
-            "userId": p.user_id,
-            "globalEnabled": p.global_enabled,
-            "messageEnabled": p.message_enabled,
-            "playgroundInviteEnabled": p.playground_invite_enabled,
-            "playgroundTurnEnabled": p.playground_turn_enabled,
-            "playgroundResultEnabled": p.playground_result_enabled,
-            "quietHoursStart": p.quiet_hours_start,
-            "quietHoursEnd": p.quiet_hours_end,
-        }))
-        .into_response(),
+        Ok(p) => Json(preferences_json(&p)).into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": e.to_string()})),
@@ -70,7 +70,8 @@ async fn subscribe(
            ON CONFLICT (user_id, endpoint)
            DO UPDATE SET p256dh = EXCLUDED.p256dh,
                          auth = EXCLUDED.auth,
-                         device_info = EXCLUDED.device_info"#,
+                         device_info = EXCLUDED.device_info,
+                         last_used_at = NOW()"#,
     )
     .bind(sub_id)
     .bind(&user.id)
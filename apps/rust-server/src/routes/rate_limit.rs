@@ -0,0 +1,21 @@
+use axum::{extract::State, response::Json, routing::get, Router};
+use serde_json::{json, Value};
+
+use crate::auth::middleware::AuthUser;
+use crate::ws::handler::ws_rate_limit_status;
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/api/ratelimit/status", get(rate_limit_status))
+}
+
+/// GET /api/ratelimit/status — The caller's WebSocket message budget for the current minute
+async fn rate_limit_status(State(state): State<AppState>, user: AuthUser) -> Json<Value> {
+    let status = ws_rate_limit_status(&user.id, &state.redis, &state.ws).await;
+    Json(json!({
+        "count": status.count,
+        "limit": status.limit,
+        "remaining": status.remaining(),
+        "resetInSeconds": status.reset_in_secs,
+    }))
+}
@@ -1,23 +1,79 @@
 use axum::{
+    extract::State,
     http::StatusCode,
     response::{IntoResponse, Json, Response},
     routing::post,
     Router,
 };
+use serde::Deserialize;
 use serde_json::json;
 
+use crate::config::Config;
+use crate::utils::api_error::{api_error, RequestId};
 use crate::AppState;
 
+/// Runtimes the sandbox accepts, by canonical name.
+pub const SUPPORTED_LANGUAGES: &[&str] = &["python", "node", "bash"];
+
 pub fn router() -> Router<AppState> {
     Router::new().route("/api/sandbox/execute", post(execute_sandbox))
 }
 
-async fn execute_sandbox() -> Response {
+#[derive(Deserialize)]
+struct ExecuteBody {
+    language: Option<String>,
+}
+
+/// Map a requested language (or common alias) to its runtime name.
+pub fn resolve_language(language: &str) -> Option<&'static str> {
+    match language.trim().to_ascii_lowercase().as_str() {
+        "python" | "python3" | "py" => Some("python"),
+        "node" | "javascript" | "js" => Some("node"),
+        "bash" | "sh" | "shell" => Some("bash"),
+        _ => None,
+    }
+}
+
+/// POST /api/sandbox/execute — placeholder; no code is run on the server.
+/// A real executor must kill runs past a wall-clock timeout and cap captured
+/// stdout/stderr before this returns anything but 501.
+async fn execute_sandbox(
+    State(state): State<AppState>,
+    request_id: RequestId,
+    body: Option<Json<ExecuteBody>>,
+) -> Response {
+    let language = body.and_then(|Json(b)| b.language);
+    sandbox_response(&state.config, language.as_deref(), &request_id)
+}
+
+/// Resolve `language` (default: node) and its configured limits into the execute response.
+pub fn sandbox_response(config: &Config, language: Option<&str>, request_id: &RequestId) -> Response {
+    let requested = language.unwrap_or("node");
+    let Some((language, limits)) = resolve_language(requested)
+        .and_then(|l| config.sandbox_limits(l).map(|limits| (l, limits)))
+    else {
+        return api_error(
+            StatusCode::BAD_REQUEST,
+            "unsupported_language",
+            format!(
+                "Unsupported language '{}'. Supported: {}",
+                requested,
+                SUPPORTED_LANGUAGES.join(", ")
+            ),
+            request_id,
+        );
+    };
+
     (
         StatusCode::NOT_IMPLEMENTED,
         Json(json!({
             "error": "Sandbox execution is not implemented",
             "status": 501,
+            "language": language,
+            "limits": {
+                "timeoutSecs": limits.timeout_secs,
+                "memoryMb": limits.memory_mb,
+            },
         })),
     )
         .into_response()
@@ -0,0 +1,267 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::ws::state::WsState;
+use crate::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/conversations/{id}/messages/schedule", post(schedule_message))
+        .route("/api/conversations/{id}/scheduled", get(list_scheduled))
+        .route("/api/conversations/{id}/scheduled/{scheduledId}", delete(cancel_scheduled))
+}
+
+/// Furthest ahead a message may be scheduled.
+pub const MAX_SCHEDULE_AHEAD_DAYS: i64 = 30;
+/// Pending scheduled messages one user may hold in a conversation.
+pub const MAX_PENDING_PER_CONVERSATION: i64 = 50;
+/// Due messages claimed per dispatcher tick.
+const DISPATCH_BATCH: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScheduleBody {
+    content: String,
+    send_at: DateTime<Utc>,
+    thread_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+struct ScheduledRow {
+    id: Uuid,
+    conversation_id: Uuid,
+    content: String,
+    thread_id: Option<Uuid>,
+    send_at: NaiveDateTime,
+    status: String,
+    created_at: NaiveDateTime,
+}
+
+/// Reject send times in the past or more than `MAX_SCHEDULE_AHEAD_DAYS` ahead.
+pub fn validate_send_at(send_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<(), String> {
+    if send_at <= now {
+        return Err("sendAt must be in the future".into());
+    }
+    if send_at - now > chrono::Duration::days(MAX_SCHEDULE_AHEAD_DAYS) {
+        return Err(format!(
+            "sendAt must be within {} days",
+            MAX_SCHEDULE_AHEAD_DAYS
+        ));
+    }
+    Ok(())
+}
+
+async fn has_access(db: &PgPool, conversation_id: Uuid, user_id: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>(
+        r#"SELECT EXISTS (SELECT 1 FROM conversations WHERE id = $1 AND (
+            user_id = $2
+            OR EXISTS (SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = $1 AND cum.user_id = $2)
+        ))"#,
+    )
+    .bind(conversation_id)
+    .bind(user_id)
+    .fetch_one(db)
+    .await
+}
+
+fn not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Conversation not found"})),
+    )
+        .into_response()
+}
+
+fn db_error(e: sqlx::Error) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"error": e.to_string()})),
+    )
+        .into_response()
+}
+
+/// POST /api/conversations/{id}/messages/schedule — queue a message for later
+async fn schedule_message(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<ScheduleBody>,
+) -> Response {
+    let content = crate::ws::handler::sanitize_content(&body.content);
+    if content.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "content is required"})),
+        )
+            .into_response();
+    }
+    if let Err(reason) = validate_send_at(body.send_at, Utc::now()) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": reason}))).into_response();
+    }
+
+    match has_access(&state.db, id, &user.id).await {
+        Ok(true) => {}
+        Ok(false) => return not_found(),
+        Err(e) => return db_error(e),
+    }
+
+    let pending = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM scheduled_messages WHERE conversation_id = $1 AND user_id = $2 AND status = 'pending'",
+    )
+    .bind(id)
+    .bind(&user.id)
+    .fetch_one(&state.db)
+    .await;
+    match pending {
+        Ok(n) if n >= MAX_PENDING_PER_CONVERSATION => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(json!({"error": format!(
+                    "At most {} scheduled messages per conversation",
+                    MAX_PENDING_PER_CONVERSATION
+                )})),
+            )
+                .into_response();
+        }
+        Ok(_) => {}
+        Err(e) => return db_error(e),
+    }
+
+    let row = sqlx::query_as::<_, ScheduledRow>(
+        r#"INSERT INTO scheduled_messages (conversation_id, user_id, content, thread_id, send_at)
+           VALUES ($1, $2, $3, $4, $5)
+           RETURNING id, conversation_id, content, thread_id, send_at, status, created_at"#,
+    )
+    .bind(id)
+    .bind(&user.id)
+    .bind(&content)
+    .bind(body.thread_id)
+    .bind(body.send_at.naive_utc())
+    .fetch_one(&state.db)
+    .await;
+
+    match row {
+        Ok(r) => (StatusCode::CREATED, Json(json!(r))).into_response(),
+        Err(e) => db_error(e),
+    }
+}
+
+/// GET /api/conversations/{id}/scheduled — the caller's pending messages, soonest first
+async fn list_scheduled(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    match has_access(&state.db, id, &user.id).await {
+        Ok(true) => {}
+        Ok(false) => return not_found(),
+        Err(e) => return db_error(e),
+    }
+
+    let rows = sqlx::query_as::<_, ScheduledRow>(
+        r#"SELECT id, conversation_id, content, thread_id, send_at, status, created_at
+           FROM scheduled_messages
+           WHERE conversation_id = $1 AND user_id = $2 AND status = 'pending'
+           ORDER BY send_at ASC"#,
+    )
+    .bind(id)
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await;
+
+    match rows {
+        Ok(items) => Json(json!({ "scheduled": items })).into_response(),
+        Err(e) => db_error(e),
+    }
+}
+
+/// DELETE /api/conversations/{id}/scheduled/{scheduledId} — cancel a pending message
+async fn cancel_scheduled(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, scheduled_id)): Path<(Uuid, Uuid)>,
+) -> Response {
+    let result = sqlx::query(
+        r#"UPDATE scheduled_messages SET status = 'cancelled'
+           WHERE id = $1 AND conversation_id = $2 AND user_id = $3 AND status = 'pending'"#,
+    )
+    .bind(scheduled_id)
+    .bind(id)
+    .bind(&user.id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => Json(json!({"success": true})).into_response(),
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Scheduled message not found"})),
+        )
+            .into_response(),
+        Err(e) => db_error(e),
+    }
+}
+
+/// Claim due messages and send each through the normal agent dispatch path,
+/// as if the user had sent it over the WebSocket. Returns how many were sent.
+pub async fn dispatch_due(
+    ws_state: &WsState,
+    db: &PgPool,
+    redis: &deadpool_redis::Pool,
+    config: &crate::config::Config,
+) -> Result<u64, sqlx::Error> {
+    // Mark as sent before dispatching so a slow send is never picked up twice
+    let due = sqlx::query_as::<_, (Uuid, String, Uuid, String, Option<Uuid>)>(
+        r#"UPDATE scheduled_messages SET status = 'sent', sent_at = NOW()
+           WHERE id IN (
+               SELECT id FROM scheduled_messages
+               WHERE status = 'pending' AND send_at <= NOW()
+               ORDER BY send_at
+               LIMIT $1
+               FOR UPDATE SKIP LOCKED
+           )
+           RETURNING id, user_id, conversation_id, content, thread_id"#,
+    )
+    .bind(DISPATCH_BATCH)
+    .fetch_all(db)
+    .await?;
+
+    let sent = due.len() as u64;
+    for (id, user_id, conversation_id, content, thread_id) in due {
+        let conversation_id = conversation_id.to_string();
+        let mentions = crate::services::mention::resolve_mentions_from_content(
+            db, &conversation_id, &content, None,
+        )
+        .await;
+        tracing::info!("Sending scheduled message {} conv={}", id, conversation_id);
+        crate::ws::handler::trigger_agent_response(
+            &user_id,
+            &conversation_id,
+            &content,
+            false,
+            None,
+            thread_id.map(|t| t.to_string()),
+            &mentions,
+            None,
+            None,
+            ws_state,
+            db,
+            redis,
+            config,
+        )
+        .await;
+    }
+    Ok(sent)
+}
@@ -2,14 +2,19 @@ use axum::{
     extract::{Multipart, Path, State},
     http::StatusCode,
     response::{IntoResponse, Json, Response},
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
+use deadpool_redis::redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
+use crate::services::chunked_upload::{self, UploadSession};
+use crate::services::exif::strip_exif;
 use crate::services::message_seq::get_next_seq;
+use crate::services::thumbnail::{make_thumbnail, Thumbnail};
 use crate::ws::handler::trigger_agent_response;
 use crate::AppState;
 
@@ -23,6 +28,120 @@ const BLOCKED_TYPES: &[&str] = &[
     "application/x-csh",
 ];
 
+/// MIME types accepted as message attachments. Entries ending in `/` match a whole family.
+const ALLOWED_ATTACHMENT_TYPES: &[&str] = &[
+    "image/",
+    "audio/",
+    "video/",
+    "text/",
+    "application/pdf",
+    "application/json",
+    "application/zip",
+    "application/x-zip-compressed",
+    "application/gzip",
+    "application/x-tar",
+    "application/x-7z-compressed",
+    "application/rtf",
+    "application/msword",
+    "application/vnd.ms-excel",
+    "application/vnd.ms-powerpoint",
+    "application/vnd.openxmlformats-officedocument.",
+    "application/vnd.oasis.opendocument.",
+];
+
+/// Types inside an allowed family that can run script when opened in a browser.
+const DENIED_ATTACHMENT_TYPES: &[&str] = &["text/html", "image/svg+xml", "text/javascript", "text/x-shellscript"];
+
+/// Whether a file with this Content-Type may be attached to a message.
+pub fn is_allowed_attachment_type(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    if mime.is_empty() || DENIED_ATTACHMENT_TYPES.contains(&mime.as_str()) {
+        return false;
+    }
+    ALLOWED_ATTACHMENT_TYPES.iter().any(|allowed| {
+        if allowed.ends_with('/') || allowed.ends_with('.') {
+            mime.starts_with(allowed)
+        } else {
+            mime == *allowed
+        }
+    })
+}
+
+/// Types whose content is a ZIP or OLE container; magic bytes may only reveal the container.
+const ZIP_CONTAINER_TYPES: &[&str] = &[
+    "application/zip",
+    "application/x-zip-compressed",
+    "application/epub+zip",
+    "application/vnd.openxmlformats-officedocument.",
+    "application/vnd.oasis.opendocument.",
+];
+const OLE_CONTAINER_TYPES: &[&str] = &[
+    "application/x-ole-storage",
+    "application/msword",
+    "application/vnd.ms-excel",
+    "application/vnd.ms-powerpoint",
+];
+
+/// Coarse kind used to decide whether a declared and a sniffed type agree.
+fn type_family(mime: &str) -> &str {
+    let in_list = |list: &[&str]| list.iter().any(|t| if t.ends_with('.') { mime.starts_with(t) } else { mime == *t });
+    if mime.starts_with("image/") {
+        "image"
+    } else if mime.starts_with("audio/") || mime.starts_with("video/") {
+        "media"
+    } else if mime.starts_with("text/") {
+        "text"
+    } else if in_list(ZIP_CONTAINER_TYPES) {
+        "zip"
+    } else if in_list(OLE_CONTAINER_TYPES) {
+        "ole"
+    } else {
+        mime
+    }
+}
+
+/// Determine an attachment's real type from its magic bytes.
+///
+/// Rejects content whose sniffed type is not allowed or contradicts the declared
+/// type. Returns the type to store: the sniffed one, or the declared one when
+/// sniffing only identified a generic container (e.g. a .docx seen as ZIP) or
+/// the format has no signature (plain text, JSON).
+pub fn sniff_attachment_type(declared: &str, data: &[u8]) -> Result<String, String> {
+    let declared = declared
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    let Some(kind) = infer::get(data) else {
+        return if declared.starts_with("text/") || declared == "application/json" {
+            Ok(declared)
+        } else {
+            Err(format!("File content does not match declared type '{}'", declared))
+        };
+    };
+
+    let sniffed = kind.mime_type();
+    if !is_allowed_attachment_type(sniffed) {
+        return Err(format!("File content type '{}' is not allowed", sniffed));
+    }
+    if type_family(sniffed) != type_family(&declared) {
+        return Err(format!(
+            "File content ({}) does not match declared type '{}'",
+            sniffed, declared
+        ));
+    }
+
+    let generic_container = matches!(sniffed, "application/zip" | "application/x-ole-storage");
+    Ok(if generic_container { declared } else { sniffed.to_string() })
+}
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route(
@@ -31,43 +150,143 @@ pub fn router() -> Router<AppState> {
         )
         .route("/api/attachments/{id}", get(get_attachment))
         .route("/api/uploads", post(generic_upload))
+        .route("/api/uploads/presign", post(presign_upload))
+        .route("/api/uploads/confirm", post(confirm_upload))
+        .route("/api/uploads/init", post(init_chunked_upload))
+        .route(
+            "/api/uploads/{id}",
+            get(chunked_upload_status).delete(cancel_chunked_upload),
+        )
+        .route("/api/uploads/{id}/chunk/{n}", put(upload_chunk))
+        .route("/api/uploads/{id}/complete", post(complete_chunked_upload))
 }
 
-async fn upload_file(
-    State(state): State<AppState>,
-    user: AuthUser,
-    Path(conversation_id): Path<Uuid>,
-    mut multipart: Multipart,
-) -> Response {
-    // Verify the user is a member of this conversation
+/// A file stored for a message attachment, before its DB row exists.
+struct UploadedFile {
+    id: Uuid,
+    file_name: String,
+    content_type: String,
+    file_size: i32,
+    storage_path: String,
+    width: Option<i32>,
+    height: Option<i32>,
+    thumbnail_path: Option<String>,
+}
+
+/// Whether the user may post attachments to the conversation.
+async fn can_upload_to(state: &AppState, conversation_id: Uuid, user_id: &str) -> bool {
     let is_member = sqlx::query_as::<_, (Uuid,)>(
         "SELECT id FROM conversation_user_members WHERE conversation_id = $1 AND user_id = $2",
     )
     .bind(conversation_id)
-    .bind(&user.id)
+    .bind(user_id)
     .fetch_optional(&state.db)
     .await;
 
-    if !matches!(is_member, Ok(Some(_))) {
-        // Fallback: check conversations.user_id for backward compatibility
-        let is_owner = sqlx::query_as::<_, (Uuid,)>(
-            "SELECT id FROM conversations WHERE id = $1 AND user_id = $2",
-        )
-        .bind(conversation_id)
-        .bind(&user.id)
-        .fetch_optional(&state.db)
-        .await;
+    if matches!(is_member, Ok(Some(_))) {
+        return true;
+    }
 
-        if !matches!(is_owner, Ok(Some(_))) {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(json!({"error": "Conversation not found"})),
-            )
-                .into_response();
+    // Fallback: check conversations.user_id for backward compatibility
+    let is_owner = sqlx::query_as::<_, (Uuid,)>(
+        "SELECT id FROM conversations WHERE id = $1 AND user_id = $2",
+    )
+    .bind(conversation_id)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await;
+
+    matches!(is_owner, Ok(Some(_)))
+}
+
+/// Why `count` files totalling `total_size` bytes can't be attached to one message, if they can't.
+fn attachment_limit_error(config: &crate::config::Config, count: usize, total_size: usize) -> Option<String> {
+    if count > config.max_attachments_per_message {
+        return Some(format!("Maximum {} files per message", config.max_attachments_per_message));
+    }
+    if total_size > config.max_message_attachments_size {
+        return Some(format!(
+            "Attachments exceed the maximum combined size ({} bytes)",
+            config.max_message_attachments_size
+        ));
+    }
+    None
+}
+
+/// A file after the upload pipeline, ready to store.
+pub struct ProcessedUpload {
+    pub data: bytes::Bytes,
+    /// Whether EXIF was removed, i.e. `data` differs from what the client sent.
+    pub stripped: bool,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub thumbnail: Option<Thumbnail>,
+}
+
+/// Pipeline every upload path runs on an image before storing it: drop EXIF
+/// (GPS, device info) when `strip_metadata` is set, read the dimensions and
+/// build a downscaled preview. Other types pass through untouched.
+pub async fn process_upload(strip_metadata: bool, content_type: &str, data: bytes::Bytes) -> ProcessedUpload {
+    let mut processed = ProcessedUpload { data, stripped: false, width: None, height: None, thumbnail: None };
+    if !content_type.starts_with("image/") {
+        return processed;
+    }
+
+    if strip_metadata {
+        let bytes = processed.data.clone();
+        if let Ok(Some(stripped)) = tokio::task::spawn_blocking(move || strip_exif(&bytes)).await {
+            processed.data = bytes::Bytes::from(stripped);
+            processed.stripped = true;
         }
     }
 
+    (processed.width, processed.height) = image::ImageReader::new(std::io::Cursor::new(&processed.data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok())
+        .map(|(w, h)| (i32::try_from(w).ok(), i32::try_from(h).ok()))
+        .unwrap_or((None, None));
+
+    // Undecodable images simply get no preview
+    let bytes = processed.data.clone();
+    processed.thumbnail = tokio::task::spawn_blocking(move || make_thumbnail(&bytes))
+        .await
+        .ok()
+        .flatten();
+    processed
+}
+
+/// Store an attachment's thumbnail next to it as `{stored_stem}_thumb.{ext}`.
+async fn store_thumbnail(
+    state: &AppState,
+    conversation_id: Uuid,
+    attachment_id: Uuid,
+    stored_stem: &str,
+    thumbnail: Thumbnail,
+) -> Option<String> {
+    let thumb_name = format!("{}_thumb.{}", stored_stem, thumbnail.extension);
+    store_attachment(state, conversation_id, &thumb_name, thumbnail.data, thumbnail.content_type)
+        .await
+        .map_err(|e| tracing::warn!("Thumbnail store failed for {}: {}", attachment_id, e))
+        .ok()
+}
+
+async fn upload_file(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(conversation_id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> Response {
+    if !can_upload_to(&state, conversation_id, &user.id).await {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Conversation not found"})),
+        )
+            .into_response();
+    }
+
     let max_size = state.config.max_file_size;
+    let mut total_size: usize = 0;
 
     // --- Phase 1: Read all multipart fields ---
     let mut caption = String::new();
@@ -131,7 +350,7 @@ async fn upload_file(
         // Treat any other field as a file
         let content_type = field.content_type().unwrap_or("").to_string();
 
-        if BLOCKED_TYPES.contains(&content_type.as_str()) {
+        if !is_allowed_attachment_type(&content_type) {
             return (
                 StatusCode::BAD_REQUEST,
                 Json(json!({
@@ -141,6 +360,10 @@ async fn upload_file(
                 .into_response();
         }
 
+        if let Some(e) = attachment_limit_error(&state.config, files_data.len() + 1, total_size) {
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
+        }
+
         let file_name = field
             .file_name()
             .unwrap_or("upload")
@@ -171,13 +394,19 @@ async fn upload_file(
                 .into_response();
         }
 
-        if files_data.len() >= 9 {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": "Maximum 9 files per message"})),
-            )
-                .into_response();
+        total_size += data.len();
+        if let Some(e) = attachment_limit_error(&state.config, files_data.len() + 1, total_size) {
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
         }
+
+        // Trust the bytes, not the client's label
+        let content_type = match sniff_attachment_type(&content_type, &data) {
+            Ok(t) => t,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
+            }
+        };
+
         files_data.push((file_name, content_type, data));
     }
 
@@ -191,84 +420,58 @@ async fn upload_file(
     }
 
     // Upload each file and collect attachment info
-    let mut uploaded_files: Vec<(Uuid, String, String, i32, String, Option<i32>, Option<i32>)> = Vec::new(); // (att_id, file_name, content_type, file_size, storage_path, width, height)
+    let mut uploaded_files: Vec<UploadedFile> = Vec::new();
+
+    for (file_name, content_type, data) in files_data {
+        let processed = process_upload(state.config.strip_image_metadata, &content_type, data).await;
 
-    for (file_name, content_type, data) in &files_data {
         let attachment_id = Uuid::new_v4();
         let ext = file_name.rsplit('.').next().unwrap_or("bin");
-        let stored_name = format!(
-            "{}_{}.{}",
-            attachment_id,
-            chrono::Utc::now().timestamp(),
-            ext
-        );
-        let r2_key = format!("attachments/{}/{}", conversation_id, stored_name);
-
-        // Try R2 upload first, fallback to local storage
-        let storage_path = if let Some(s3) = &state.s3 {
-            match crate::services::r2::upload_to_r2(
-                s3,
-                &state.config.r2_bucket,
-                &r2_key,
-                data.to_vec(),
-                content_type,
-                &state.config.r2_public_url,
-            )
-            .await
-            {
-                Ok(url) => url,
-                Err(_) => {
-                    let dir = std::path::Path::new(&state.config.upload_dir)
-                        .join("attachments")
-                        .join(conversation_id.to_string());
-                    let _ = tokio::fs::create_dir_all(&dir).await;
-                    let local_path = dir.join(&stored_name);
-                    if let Err(e) = tokio::fs::write(&local_path, &data).await {
-                        return (
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            Json(json!({"error": format!("Failed to store file: {}", e)})),
-                        )
-                            .into_response();
-                    }
-                    format!("/uploads/attachments/{}/{}", conversation_id, stored_name)
-                }
-            }
-        } else {
-            let dir = std::path::Path::new(&state.config.upload_dir)
-                .join("attachments")
-                .join(conversation_id.to_string());
-            let _ = tokio::fs::create_dir_all(&dir).await;
-            let local_path = dir.join(&stored_name);
-            if let Err(e) = tokio::fs::write(&local_path, &data).await {
+        let stored_stem = format!("{}_{}", attachment_id, chrono::Utc::now().timestamp());
+        let stored_name = format!("{}.{}", stored_stem, ext);
+
+        let storage_path = match store_attachment(&state, conversation_id, &stored_name, processed.data.to_vec(), &content_type).await {
+            Ok(path) => path,
+            Err(e) => {
                 return (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     Json(json!({"error": format!("Failed to store file: {}", e)})),
                 )
                     .into_response();
             }
-            format!(
-                "/uploads/attachments/{}/{}",
-                conversation_id, stored_name
-            )
         };
 
-        let file_size = data.len() as i32;
-
-        // Extract image dimensions for image/* MIME types
-        let (width, height) = if content_type.starts_with("image/") {
-            image::ImageReader::new(std::io::Cursor::new(data))
-                .with_guessed_format()
-                .ok()
-                .and_then(|reader| reader.into_dimensions().ok())
-                .map(|(w, h)| (i32::try_from(w).ok(), i32::try_from(h).ok()))
-                .unwrap_or((None, None))
-        } else {
-            (None, None)
+        let thumbnail_path = match processed.thumbnail {
+            Some(t) => store_thumbnail(&state, conversation_id, attachment_id, &stored_stem, t).await,
+            None => None,
         };
 
-        uploaded_files.push((attachment_id, file_name.clone(), content_type.clone(), file_size, storage_path, width, height));
+        uploaded_files.push(UploadedFile {
+            id: attachment_id,
+            file_name,
+            content_type,
+            file_size: processed.data.len() as i32,
+            storage_path,
+            width: processed.width,
+            height: processed.height,
+            thumbnail_path,
+        });
     }
 
+    post_attachment_message(&state, &user.id, conversation_id, caption, duration_seconds, thread_id, &uploaded_files).await
+}
+
+/// Create ONE message carrying `uploaded_files` as attachments, broadcast it and
+/// trigger the agent. Returns the 201 response for the upload.
+async fn post_attachment_message(
+    state: &AppState,
+    user_id: &str,
+    conversation_id: Uuid,
+    caption: String,
+    duration_seconds: Option<i32>,
+    thread_id: Option<Uuid>,
+    uploaded_files: &[UploadedFile],
+) -> Response {
     // --- Phase 3: Create ONE message + multiple attachments ---
     let conv_id_str = conversation_id.to_string();
     let seq = match get_next_seq(&state.db, &conv_id_str).await {
@@ -292,7 +495,7 @@ async fn upload_file(
     .bind(conversation_id)
     .bind(seq)
     .bind(&caption)
-    .bind(&user.id)
+    .bind(user_id)
     .bind(thread_id)
     .fetch_one(&state.db)
     .await;
@@ -310,21 +513,22 @@ async fn upload_file(
 
     // Create attachment records for each uploaded file
     let mut attachments_json = Vec::new();
-    for (attachment_id, file_name, content_type, file_size, storage_path, width, height) in &uploaded_files {
+    for file in uploaded_files {
         let att_result = sqlx::query_as::<_, crate::db::models::Attachment>(
-            r#"INSERT INTO attachments (id, message_id, file_name, file_type, file_size, storage_path, duration_seconds, width, height)
-               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            r#"INSERT INTO attachments (id, message_id, file_name, file_type, file_size, storage_path, duration_seconds, width, height, thumbnail_path)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
                RETURNING *"#,
         )
-        .bind(attachment_id)
+        .bind(file.id)
         .bind(message_id)
-        .bind(file_name)
-        .bind(content_type)
-        .bind(file_size)
-        .bind(storage_path)
+        .bind(&file.file_name)
+        .bind(&file.content_type)
+        .bind(file.file_size)
+        .bind(&file.storage_path)
         .bind(duration_seconds)
-        .bind(width)
-        .bind(height)
+        .bind(file.width)
+        .bind(file.height)
+        .bind(&file.thumbnail_path)
         .fetch_one(&state.db)
         .await;
 
@@ -349,6 +553,7 @@ async fn upload_file(
             "duration": attachment.duration_seconds,
             "width": attachment.width,
             "height": attachment.height,
+            "thumbnailUrl": attachment.thumbnail_path,
             "createdAt": attachment.created_at.and_utc().to_rfc3339(),
         }));
     }
@@ -366,7 +571,7 @@ async fn upload_file(
         let sender_info = sqlx::query_as::<_, (Option<String>, Option<String>, Option<String>, bool)>(
             r#"SELECT name, username, image, is_verified FROM "user" WHERE id = $1"#,
         )
-        .bind(&user.id)
+        .bind(user_id)
         .fetch_optional(&state.db)
         .await
         .ok()
@@ -398,7 +603,7 @@ async fn upload_file(
                 "role": "user",
                 "content": &caption,
                 "status": "completed",
-                "senderUserId": &user.id,
+                "senderUserId": user_id,
                 "senderUserName": sender_name,
                 "senderUsername": sender_username,
                 "senderUserImage": sender_image,
@@ -416,7 +621,7 @@ async fn upload_file(
     // Build message content for the agent: caption + all attachment references
     let attachment_refs: Vec<String> = uploaded_files
         .iter()
-        .map(|(_, fname, _, _, spath, _, _)| format!("[Attachment: {}]({})", fname, spath))
+        .map(|f| format!("[Attachment: {}]({})", f.file_name, f.storage_path))
         .collect();
     let agent_content = if caption.is_empty() {
         attachment_refs.join("\n")
@@ -424,7 +629,7 @@ async fn upload_file(
         format!("{}\n\n{}", caption, attachment_refs.join("\n"))
     };
 
-    let user_id = user.id.clone();
+    let user_id = user_id.to_string();
     let ws = state.ws.clone();
     let db = state.db.clone();
     let redis = state.redis.clone();
@@ -500,6 +705,7 @@ async fn get_attachment(
             "storagePath": a.storage_path,
             "width": a.width,
             "height": a.height,
+            "thumbnailPath": a.thumbnail_path,
             "createdAt": a.created_at.and_utc().to_rfc3339(),
         }))
         .into_response(),
@@ -516,6 +722,43 @@ async fn get_attachment(
     }
 }
 
+/// Store an attachment file in R2, falling back to local `upload_dir`.
+/// Returns the public URL (R2) or `/uploads/...` path (local).
+async fn store_attachment(
+    state: &AppState,
+    conversation_id: Uuid,
+    stored_name: &str,
+    data: Vec<u8>,
+    content_type: &str,
+) -> Result<String, std::io::Error> {
+    let r2_key = format!("attachments/{}/{}", conversation_id, stored_name);
+
+    let data = if let Some(s3) = &state.s3 {
+        match crate::services::r2::upload_to_r2(
+            s3,
+            &state.config.r2_bucket,
+            &r2_key,
+            data.clone(),
+            content_type,
+            &state.config.r2_public_url,
+        )
+        .await
+        {
+            Ok(url) => return Ok(url),
+            Err(_) => data,
+        }
+    } else {
+        data
+    };
+
+    let dir = std::path::Path::new(&state.config.upload_dir)
+        .join("attachments")
+        .join(conversation_id.to_string());
+    let _ = tokio::fs::create_dir_all(&dir).await;
+    tokio::fs::write(dir.join(stored_name), &data).await?;
+    Ok(format!("/uploads/attachments/{}/{}", conversation_id, stored_name))
+}
+
 /// POST /api/uploads — Generic file upload (authenticated), returns { url }.
 /// Used by lounge voice sample upload and other non-conversation file uploads.
 async fn generic_upload(
@@ -553,6 +796,14 @@ async fn generic_upload(
                 .into_response();
         }
 
+        // Trust the bytes, not the client's label
+        let content_type = match sniff_attachment_type(&content_type, &data) {
+            Ok(t) => t,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
+            }
+        };
+
         let ext = file_name.rsplit('.').next().unwrap_or("bin");
         let stored_name = format!("{}_{}.{}", Uuid::new_v4(), chrono::Utc::now().timestamp(), ext);
         let r2_key = format!("uploads/{}/{}", user.id, stored_name);
@@ -600,3 +851,732 @@ async fn generic_upload(
 
     (StatusCode::BAD_REQUEST, Json(json!({"error": "No file uploaded"}))).into_response()
 }
+
+// ---------------------------------------------------------------------------
+// Chunked uploads
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitUploadBody {
+    file_name: String,
+    content_type: Option<String>,
+    size: u64,
+    chunk_size: Option<u64>,
+}
+
+fn upload_unavailable() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({"error": "Upload service unavailable"})),
+    )
+        .into_response()
+}
+
+fn upload_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Upload not found or expired"})),
+    )
+        .into_response()
+}
+
+fn session_json(session: &UploadSession) -> serde_json::Value {
+    json!({
+        "uploadId": session.id,
+        "fileName": session.file_name,
+        "contentType": session.content_type,
+        "size": session.total_size,
+        "chunkSize": session.chunk_size,
+        "totalChunks": session.total_chunks(),
+        "receivedChunks": session.received_chunks,
+    })
+}
+
+/// Load a session owned by `user_id`; other users' uploads look missing.
+async fn owned_session(state: &AppState, user_id: &str, id: Uuid) -> Result<UploadSession, Response> {
+    match chunked_upload::load_session(&state.redis, id).await {
+        Ok(Some(session)) if session.user_id == user_id => Ok(session),
+        Ok(_) => Err(upload_not_found()),
+        Err(_) => Err(upload_unavailable()),
+    }
+}
+
+/// POST /api/uploads/init — Start a chunked upload, returns { uploadId, chunkSize, totalChunks }.
+async fn init_chunked_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<InitUploadBody>,
+) -> Response {
+    let content_type = body
+        .content_type
+        .filter(|c| !c.is_empty())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    // The bytes are sniffed on completion; reject what could never pass now
+    if !is_allowed_attachment_type(&content_type) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("File type '{}' is not allowed", content_type)})),
+        )
+            .into_response();
+    }
+
+    let max_size = state.config.max_chunked_upload_size;
+    if body.size == 0 || body.size > max_size {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("size must be between 1 and {} bytes", max_size)})),
+        )
+            .into_response();
+    }
+
+    let chunk_size = body.chunk_size.unwrap_or(chunked_upload::DEFAULT_CHUNK_SIZE);
+    if !(chunked_upload::MIN_CHUNK_SIZE..=chunked_upload::MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!(
+                    "chunkSize must be between {} and {} bytes",
+                    chunked_upload::MIN_CHUNK_SIZE,
+                    chunked_upload::MAX_CHUNK_SIZE
+                )
+            })),
+        )
+            .into_response();
+    }
+
+    let file_name = body.file_name.trim();
+    let session = UploadSession {
+        id: Uuid::new_v4(),
+        user_id: user.id.clone(),
+        file_name: if file_name.is_empty() { "upload".into() } else { file_name.to_string() },
+        content_type,
+        total_size: body.size,
+        chunk_size,
+        received_chunks: 0,
+    };
+
+    if chunked_upload::save_session(&state.redis, &session).await.is_err() {
+        return upload_unavailable();
+    }
+
+    let mut response = session_json(&session);
+    response["expiresInSeconds"] = json!(chunked_upload::SESSION_TTL_SECS);
+    (StatusCode::CREATED, Json(response)).into_response()
+}
+
+/// GET /api/uploads/{id} — Progress of a chunked upload, used to resume after a disconnect.
+async fn chunked_upload_status(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    match owned_session(&state, &user.id, id).await {
+        Ok(session) => Json(session_json(&session)).into_response(),
+        Err(resp) => resp,
+    }
+}
+
+/// PUT /api/uploads/{id}/chunk/{n} — Append chunk `n` (raw body).
+/// Chunks must arrive in order; re-sending an already received chunk is accepted.
+async fn upload_chunk(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, n)): Path<(Uuid, u32)>,
+    data: bytes::Bytes,
+) -> Response {
+    let mut session = match owned_session(&state, &user.id, id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    let Some(expected_len) = chunked_upload::expected_chunk_len(session.total_size, session.chunk_size, n) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Chunk index must be below {}", session.total_chunks())})),
+        )
+            .into_response();
+    };
+
+    if n > session.received_chunks {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": format!("Expected chunk {}", session.received_chunks),
+                "receivedChunks": session.received_chunks,
+            })),
+        )
+            .into_response();
+    }
+
+    if data.len() as u64 != expected_len {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Chunk {} must be {} bytes, got {}", n, expected_len, data.len())
+            })),
+        )
+            .into_response();
+    }
+
+    let path = chunked_upload::temp_path(&state.config.upload_dir, id);
+    let offset = n as u64 * session.chunk_size;
+    if let Err(e) = chunked_upload::write_chunk(&path, offset, &data).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": format!("Failed to store chunk: {}", e)})),
+        )
+            .into_response();
+    }
+
+    if n == session.received_chunks {
+        session.received_chunks += 1;
+    }
+    if chunked_upload::save_session(&state.redis, &session).await.is_err() {
+        return upload_unavailable();
+    }
+
+    Json(json!({
+        "receivedChunks": session.received_chunks,
+        "totalChunks": session.total_chunks(),
+    }))
+    .into_response()
+}
+
+/// POST /api/uploads/{id}/complete — Move the assembled file to storage, returns { url }.
+async fn complete_chunked_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let session = match owned_session(&state, &user.id, id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    if !session.is_complete() {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": format!(
+                    "Upload incomplete: {} of {} chunks received",
+                    session.received_chunks,
+                    session.total_chunks()
+                ),
+                "receivedChunks": session.received_chunks,
+            })),
+        )
+            .into_response();
+    }
+
+    let path = chunked_upload::temp_path(&state.config.upload_dir, id);
+    let on_disk = tokio::fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+    if on_disk != session.total_size {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Assembled file size does not match; re-send the chunks"})),
+        )
+            .into_response();
+    }
+
+    let head = match read_file_prefix(&path, SNIFF_PREFIX_LEN).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed to read assembled file: {}", e)})),
+            )
+                .into_response();
+        }
+    };
+    let content_type = match sniff_attachment_type(&session.content_type, &head) {
+        Ok(t) => t,
+        Err(e) => {
+            let _ = chunked_upload::delete_session(&state.redis, id).await;
+            let _ = tokio::fs::remove_file(&path).await;
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
+        }
+    };
+
+    // Claim the session so a concurrent complete cannot store the file twice
+    match chunked_upload::delete_session(&state.redis, id).await {
+        Ok(true) => {}
+        Ok(false) => return upload_not_found(),
+        Err(_) => return upload_unavailable(),
+    }
+
+    let ext = session.file_name.rsplit('.').next().unwrap_or("bin");
+    let stored_stem = format!("{}_{}", Uuid::new_v4(), chrono::Utc::now().timestamp());
+    let stored_name = format!("{}.{}", stored_stem, ext);
+
+    // Images go through the same pipeline as attachments, so they are held to the same cap
+    let mut processed = None;
+    if content_type.starts_with("image/") {
+        if let Some(e) = attachment_limit_error(&state.config, 1, session.total_size as usize) {
+            let _ = tokio::fs::remove_file(&path).await;
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
+        }
+        let data = match tokio::fs::read(&path).await {
+            Ok(d) => bytes::Bytes::from(d),
+            Err(e) => {
+                let _ = chunked_upload::save_session(&state.redis, &session).await;
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": format!("Failed to read assembled file: {}", e)})),
+                )
+                    .into_response();
+            }
+        };
+        let p = process_upload(state.config.strip_image_metadata, &content_type, data).await;
+        if p.stripped {
+            if let Err(e) = tokio::fs::write(&path, &p.data).await {
+                let _ = tokio::fs::remove_file(&path).await;
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": format!("Failed to store file: {}", e)})),
+                )
+                    .into_response();
+            }
+        }
+        processed = Some(p);
+    }
+
+    match store_assembled_file(&state, &user.id, &stored_name, &path, &content_type).await {
+        Ok(url) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            let (file_size, width, height, thumbnail_url) = match processed {
+                Some(p) => {
+                    let thumbnail_url = match p.thumbnail {
+                        Some(t) => {
+                            let thumb_name = format!("{}_thumb.{}", stored_stem, t.extension);
+                            store_user_file(&state, &user.id, &thumb_name, t.data, t.content_type)
+                                .await
+                                .map_err(|e| tracing::warn!("Thumbnail store failed for upload {}: {}", id, e))
+                                .ok()
+                        }
+                        None => None,
+                    };
+                    (p.data.len() as u64, p.width, p.height, thumbnail_url)
+                }
+                None => (session.total_size, None, None, None),
+            };
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "url": url,
+                    "fileName": session.file_name,
+                    "fileType": content_type,
+                    "fileSize": file_size,
+                    "width": width,
+                    "height": height,
+                    "thumbnailUrl": thumbnail_url,
+                })),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            // Restore the session so the client can retry completion
+            let _ = chunked_upload::save_session(&state.redis, &session).await;
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed to store file: {}", e)})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// DELETE /api/uploads/{id} — Abandon a chunked upload and discard received chunks.
+async fn cancel_chunked_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Response {
+    if let Err(resp) = owned_session(&state, &user.id, id).await {
+        return resp;
+    }
+    let _ = chunked_upload::delete_session(&state.redis, id).await;
+    let _ = tokio::fs::remove_file(chunked_upload::temp_path(&state.config.upload_dir, id)).await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Up to the first `len` bytes of a file, for sniffing its type.
+async fn read_file_prefix(path: &std::path::Path, len: u64) -> std::io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+    let file = tokio::fs::File::open(path).await?;
+    let mut head = Vec::new();
+    file.take(len).read_to_end(&mut head).await?;
+    Ok(head)
+}
+
+/// Store a small file under the user's uploads, in R2 or falling back to local `upload_dir`.
+async fn store_user_file(
+    state: &AppState,
+    user_id: &str,
+    stored_name: &str,
+    data: Vec<u8>,
+    content_type: &str,
+) -> Result<String, anyhow::Error> {
+    if let Some(s3) = &state.s3 {
+        let r2_key = format!("uploads/{}/{}", user_id, stored_name);
+        match crate::services::r2::upload_to_r2(
+            s3,
+            &state.config.r2_bucket,
+            &r2_key,
+            data.clone(),
+            content_type,
+            &state.config.r2_public_url,
+        )
+        .await
+        {
+            Ok(url) => return Ok(url),
+            Err(e) => tracing::warn!("R2 upload failed, storing locally: {}", e),
+        }
+    }
+
+    let dir = std::path::Path::new(&state.config.upload_dir).join("uploads").join(user_id);
+    tokio::fs::create_dir_all(&dir).await?;
+    tokio::fs::write(dir.join(stored_name), &data).await?;
+    Ok(format!("/uploads/uploads/{}/{}", user_id, stored_name))
+}
+
+/// Move an assembled temp file to R2 (multipart), falling back to local `upload_dir`.
+async fn store_assembled_file(
+    state: &AppState,
+    user_id: &str,
+    stored_name: &str,
+    path: &std::path::Path,
+    content_type: &str,
+) -> Result<String, anyhow::Error> {
+    if let Some(s3) = &state.s3 {
+        let r2_key = format!("uploads/{}/{}", user_id, stored_name);
+        match crate::services::r2::upload_file_to_r2(
+            s3,
+            &state.config.r2_bucket,
+            &r2_key,
+            path,
+            content_type,
+            &state.config.r2_public_url,
+        )
+        .await
+        {
+            Ok(url) => return Ok(url),
+            Err(e) => tracing::warn!("R2 multipart upload failed, storing locally: {}", e),
+        }
+    }
+
+    let dir = std::path::Path::new(&state.config.upload_dir).join("uploads").join(user_id);
+    tokio::fs::create_dir_all(&dir).await?;
+    let dest = dir.join(stored_name);
+    if tokio::fs::rename(path, &dest).await.is_err() {
+        // Temp dir may be on another filesystem
+        tokio::fs::copy(path, &dest).await?;
+    }
+    Ok(format!("/uploads/uploads/{}/{}", user_id, stored_name))
+}
+
+// ---------------------------------------------------------------------------
+// Direct-to-R2 uploads
+// ---------------------------------------------------------------------------
+
+/// How long a presigned PUT URL (and its pending record) stays valid.
+const PRESIGN_EXPIRY_SECS: u64 = 900;
+const PRESIGN_KEY_PREFIX: &str = "presigned_upload:";
+/// Bytes fetched back from R2 to sniff a directly uploaded file's type.
+const SNIFF_PREFIX_LEN: u64 = 64 * 1024;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PresignBody {
+    conversation_id: Uuid,
+    file_name: String,
+    content_type: String,
+    size: u64,
+}
+
+/// Upload announced via presign, awaiting confirmation.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PendingDirectUpload {
+    user_id: String,
+    conversation_id: Uuid,
+    attachment_id: Uuid,
+    file_name: String,
+    content_type: String,
+    size: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfirmBody {
+    storage_key: String,
+    #[serde(default)]
+    caption: String,
+    duration_seconds: Option<i32>,
+    thread_id: Option<Uuid>,
+}
+
+/// POST /api/uploads/presign — Presigned PUT URL for uploading an attachment directly to R2.
+/// Without R2 this returns `{ direct: false, uploadUrl }` pointing at the server-side upload.
+async fn presign_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<PresignBody>,
+) -> Response {
+    if !can_upload_to(&state, body.conversation_id, &user.id).await {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Conversation not found"})),
+        )
+            .into_response();
+    }
+
+    let Some(s3) = &state.s3 else {
+        return Json(json!({
+            "direct": false,
+            "uploadUrl": format!("/api/conversations/{}/upload", body.conversation_id),
+        }))
+        .into_response();
+    };
+
+    if !is_allowed_attachment_type(&body.content_type) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("File type '{}' is not allowed", body.content_type)})),
+        )
+            .into_response();
+    }
+
+    let max_size = state.config.max_file_size as u64;
+    if body.size == 0 || body.size > max_size {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("size must be between 1 and {} bytes", max_size)})),
+        )
+            .into_response();
+    }
+
+    let attachment_id = Uuid::new_v4();
+    let ext = body.file_name.rsplit('.').next().unwrap_or("bin");
+    let stored_name = format!("{}_{}.{}", attachment_id, chrono::Utc::now().timestamp(), ext);
+    let storage_key = format!("attachments/{}/{}", body.conversation_id, stored_name);
+
+    let (upload_url, headers) = match crate::services::r2::presign_put(
+        s3,
+        &state.config.r2_bucket,
+        &storage_key,
+        &body.content_type,
+        body.size as i64,
+        std::time::Duration::from_secs(PRESIGN_EXPIRY_SECS),
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed to presign upload: {}", e)})),
+            )
+                .into_response();
+        }
+    };
+
+    let pending = PendingDirectUpload {
+        user_id: user.id.clone(),
+        conversation_id: body.conversation_id,
+        attachment_id,
+        file_name: body.file_name,
+        content_type: body.content_type,
+        size: body.size,
+    };
+    let saved = match state.redis.get().await {
+        Ok(mut conn) => conn
+            .set_ex::<_, _, ()>(
+                format!("{}{}", PRESIGN_KEY_PREFIX, storage_key),
+                serde_json::to_string(&pending).unwrap_or_default(),
+                PRESIGN_EXPIRY_SECS,
+            )
+            .await
+            .is_ok(),
+        Err(_) => false,
+    };
+    if !saved {
+        return upload_unavailable();
+    }
+
+    let headers: serde_json::Map<String, serde_json::Value> = headers
+        .into_iter()
+        .map(|(k, v)| (k, json!(v)))
+        .collect();
+
+    Json(json!({
+        "direct": true,
+        "method": "PUT",
+        "uploadUrl": upload_url,
+        "headers": headers,
+        "storageKey": storage_key,
+        "publicUrl": format!("{}/{}", state.config.r2_public_url, storage_key),
+        "expiresInSeconds": PRESIGN_EXPIRY_SECS,
+    }))
+    .into_response()
+}
+
+/// POST /api/uploads/confirm — Record a directly uploaded file as a message attachment.
+/// The object must exist in R2 with the size announced at presign time, and its
+/// magic bytes must match the declared type.
+async fn confirm_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<ConfirmBody>,
+) -> Response {
+    let Some(s3) = &state.s3 else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Direct uploads are not enabled"})),
+        )
+            .into_response();
+    };
+
+    let key = format!("{}{}", PRESIGN_KEY_PREFIX, body.storage_key);
+    let Ok(mut conn) = state.redis.get().await else {
+        return upload_unavailable();
+    };
+    let pending = conn
+        .get::<_, Option<String>>(&key)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|v| serde_json::from_str::<PendingDirectUpload>(&v).ok());
+    let pending = match pending {
+        Some(p) if p.user_id == user.id => p,
+        _ => return upload_not_found(),
+    };
+
+    match crate::services::r2::object_size(s3, &state.config.r2_bucket, &body.storage_key).await {
+        Ok(Some(size)) if size as u64 == pending.size => {}
+        Ok(Some(_)) => {
+            let _ = crate::services::r2::delete_object(s3, &state.config.r2_bucket, &body.storage_key).await;
+            let _: Result<(), _> = conn.del(&key).await;
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Uploaded file size does not match the presigned size"})),
+            )
+                .into_response();
+        }
+        Ok(None) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "File has not been uploaded yet"})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"error": format!("Failed to verify upload: {}", e)})),
+            )
+                .into_response();
+        }
+    }
+
+    let head = match crate::services::r2::read_object_prefix(s3, &state.config.r2_bucket, &body.storage_key, SNIFF_PREFIX_LEN).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"error": format!("Failed to verify upload: {}", e)})),
+            )
+                .into_response();
+        }
+    };
+    let content_type = match sniff_attachment_type(&pending.content_type, &head) {
+        Ok(t) => t,
+        Err(e) => {
+            let _ = crate::services::r2::delete_object(s3, &state.config.r2_bucket, &body.storage_key).await;
+            let _: Result<(), _> = conn.del(&key).await;
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
+        }
+    };
+
+    if let Some(e) = attachment_limit_error(&state.config, 1, pending.size as usize) {
+        let _ = crate::services::r2::delete_object(s3, &state.config.r2_bucket, &body.storage_key).await;
+        let _: Result<(), _> = conn.del(&key).await;
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
+    }
+
+    // Claim the pending record so the attachment is only recorded once
+    let claimed: i64 = conn.del(&key).await.unwrap_or(0);
+    drop(conn);
+    if claimed == 0 {
+        return upload_not_found();
+    }
+
+    let mut file = UploadedFile {
+        id: pending.attachment_id,
+        file_name: pending.file_name,
+        content_type,
+        file_size: pending.size as i32,
+        storage_path: format!("{}/{}", state.config.r2_public_url, body.storage_key),
+        width: None,
+        height: None,
+        thumbnail_path: None,
+    };
+
+    // Images get the same pipeline as server-side uploads; a stripped copy replaces the original
+    if file.content_type.starts_with("image/") {
+        let data = match crate::services::r2::read_object_prefix(s3, &state.config.r2_bucket, &body.storage_key, pending.size).await {
+            Ok(bytes) => bytes::Bytes::from(bytes),
+            Err(e) => {
+                let _ = crate::services::r2::delete_object(s3, &state.config.r2_bucket, &body.storage_key).await;
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({"error": format!("Failed to process upload: {}", e)})),
+                )
+                    .into_response();
+            }
+        };
+        let processed = process_upload(state.config.strip_image_metadata, &file.content_type, data).await;
+
+        let stored_name = body.storage_key.rsplit('/').next().unwrap_or(&body.storage_key);
+        let stored_stem = stored_name.rsplit_once('.').map_or(stored_name, |(stem, _)| stem);
+
+        if processed.stripped {
+            match store_attachment(&state, pending.conversation_id, stored_name, processed.data.to_vec(), &file.content_type).await {
+                Ok(path) => {
+                    if path != file.storage_path {
+                        // Fell back to local storage; don't leave the original in the bucket
+                        let _ = crate::services::r2::delete_object(s3, &state.config.r2_bucket, &body.storage_key).await;
+                    }
+                    file.storage_path = path;
+                    file.file_size = processed.data.len() as i32;
+                }
+                Err(e) => {
+                    let _ = crate::services::r2::delete_object(s3, &state.config.r2_bucket, &body.storage_key).await;
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": format!("Failed to store file: {}", e)})),
+                    )
+                        .into_response();
+                }
+            }
+        }
+
+        file.width = processed.width;
+        file.height = processed.height;
+        file.thumbnail_path = match processed.thumbnail {
+            Some(t) => store_thumbnail(&state, pending.conversation_id, file.id, stored_stem, t).await,
+            None => None,
+        };
+    }
+
+    post_attachment_message(
+        &state,
+        &user.id,
+        pending.conversation_id,
+        body.caption,
+        body.duration_seconds.filter(|d| (0..=3600).contains(d)),
+        body.thread_id,
+        &[file],
+    )
+    .await
+}
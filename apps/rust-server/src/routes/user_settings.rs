@@ -12,6 +12,9 @@ use uuid::Uuid;
 use crate::auth::middleware::AuthUser;
 use crate::AppState;
 
+/// Office theme used when a user has not chosen one.
+pub const DEFAULT_OFFICE_THEME: &str = "cozy-studio-v2";
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/user/settings", get(get_settings).put(update_settings))
@@ -57,17 +60,17 @@ async fn update_settings(
 ) -> Response {
     let raw_key = body.gemini_api_key.as_deref().map(|k| k.trim()).filter(|k| !k.is_empty());
 
-    // Encrypt if encryption key is configured, otherwise store plaintext (dev)
-    let stored_key = raw_key.map(|k| {
-        if let Some(ref enc_key) = state.config.settings_encryption_key {
-            encrypt_value(enc_key, k).unwrap_or_else(|e| {
-                tracing::error!("Failed to encrypt API key: {}", e);
-                k.to_string()
-            })
-        } else {
-            k.to_string()
+    let stored_key = match raw_key.map(|k| encrypt_api_key(&state.config, k)).transpose() {
+        Ok(k) => k,
+        Err(e) => {
+            tracing::error!("Failed to encrypt API key: {}", e);
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "error": "API key storage is not available" })),
+            )
+                .into_response();
         }
-    });
+    };
 
     let result = sqlx::query(
         r#"INSERT INTO user_settings (user_id, gemini_api_key, updated_at)
@@ -437,13 +440,53 @@ async fn get_plan_limit(db: &sqlx::PgPool, user_id: &str, column: &str) -> i32 {
         .unwrap_or(3) // Default: free-tier gets 3 boards
 }
 
-/// Decrypt a user's Gemini API key from DB. Used by other routes (e.g. notes ask_ai).
+/// Encrypt an API key for storage with the current key version. Fails when no
+/// encryption key is configured rather than storing the key in plaintext.
+pub fn encrypt_api_key(config: &crate::config::Config, plaintext: &str) -> Result<String, String> {
+    let Some(ref enc_key) = config.settings_encryption_key else {
+        return Err("SETTINGS_ENCRYPTION_KEY not configured".into());
+    };
+    encrypt_value(enc_key, config.settings_encryption_key_version, plaintext)
+}
+
+/// Decrypt a stored API key (user settings or listing keys), using whichever
+/// configured key version the ciphertext names.
 pub fn decrypt_api_key(config: &crate::config::Config, stored: &str) -> String {
-    if let Some(ref enc_key) = config.settings_encryption_key {
-        decrypt_value(enc_key, stored).unwrap_or_else(|_| stored.to_string())
-    } else {
-        stored.to_string()
+    let keys = encryption_keys(config);
+    if keys.is_empty() {
+        return stored.to_string();
     }
+    decrypt_value(&keys, stored).unwrap_or_else(|_| stored.to_string())
+}
+
+/// Whether `stored` should be rewritten with the current key version.
+pub fn needs_reencrypt(config: &crate::config::Config, stored: &str) -> bool {
+    config.settings_encryption_key.is_some()
+        && !stored.starts_with(&version_prefix(config.settings_encryption_key_version))
+}
+
+/// Decrypt `stored` and encrypt it again with the current key version.
+pub fn reencrypt_api_key(config: &crate::config::Config, stored: &str) -> Result<String, String> {
+    let Some(ref current) = config.settings_encryption_key else {
+        return Err("SETTINGS_ENCRYPTION_KEY not configured".into());
+    };
+    let plaintext = decrypt_value(&encryption_keys(config), stored)?;
+    encrypt_value(current, config.settings_encryption_key_version, &plaintext)
+}
+
+/// Configured keys as (version, hex key), current first.
+fn encryption_keys(config: &crate::config::Config) -> Vec<(u32, &str)> {
+    let mut keys = Vec::new();
+    if let Some(ref current) = config.settings_encryption_key {
+        let version = config.settings_encryption_key_version;
+        keys.push((version, current.as_str()));
+        if let Some(ref previous) = config.settings_encryption_key_previous {
+            if version > 1 {
+                keys.push((version - 1, previous.as_str()));
+            }
+        }
+    }
+    keys
 }
 
 // ---------------------------------------------------------------------------
@@ -454,13 +497,21 @@ use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
 use aes_gcm::aead::Aead;
 use base64::Engine;
 
-/// Encrypt a plaintext string. Returns "enc:" + base64(nonce + ciphertext).
-fn encrypt_value(hex_key: &str, plaintext: &str) -> Result<String, String> {
+fn version_prefix(version: u32) -> String {
+    format!("enc:v{}:", version)
+}
+
+fn cipher_for(hex_key: &str) -> Result<Aes256Gcm, String> {
     let key_bytes = hex::decode(hex_key).map_err(|e| format!("bad hex key: {}", e))?;
     if key_bytes.len() != 32 {
         return Err(format!("key must be 32 bytes, got {}", key_bytes.len()));
     }
-    let cipher = Aes256Gcm::new_from_slice(&key_bytes).map_err(|e| e.to_string())?;
+    Aes256Gcm::new_from_slice(&key_bytes).map_err(|e| e.to_string())
+}
+
+/// Encrypt a plaintext string. Returns "enc:v{version}:" + base64(nonce + ciphertext).
+pub fn encrypt_value(hex_key: &str, version: u32, plaintext: &str) -> Result<String, String> {
+    let cipher = cipher_for(hex_key)?;
 
     let mut nonce_bytes = [0u8; 12];
     use rand::RngCore;
@@ -472,21 +523,42 @@ fn encrypt_value(hex_key: &str, plaintext: &str) -> Result<String, String> {
     let mut combined = Vec::with_capacity(12 + ciphertext.len());
     combined.extend_from_slice(&nonce_bytes);
     combined.extend_from_slice(&ciphertext);
-    Ok(format!("enc:{}", base64::engine::general_purpose::STANDARD.encode(&combined)))
+    Ok(format!(
+        "{}{}",
+        version_prefix(version),
+        base64::engine::general_purpose::STANDARD.encode(&combined)
+    ))
 }
 
-/// Decrypt "enc:" + base64(nonce + ciphertext). Falls back to plaintext if no "enc:" prefix.
-fn decrypt_value(hex_key: &str, stored: &str) -> Result<String, String> {
-    let encoded = match stored.strip_prefix("enc:") {
-        Some(e) => e,
-        None => return Ok(stored.to_string()),
+/// Decrypt "enc:v{N}:" + base64(nonce + ciphertext) with key version N from `keys`.
+/// Legacy unversioned "enc:" values are tried against every key. Falls back to
+/// plaintext if there is no "enc:" prefix.
+pub fn decrypt_value(keys: &[(u32, &str)], stored: &str) -> Result<String, String> {
+    let Some(rest) = stored.strip_prefix("enc:") else {
+        return Ok(stored.to_string());
     };
 
-    let key_bytes = hex::decode(hex_key).map_err(|e| format!("bad hex key: {}", e))?;
-    if key_bytes.len() != 32 {
-        return Err(format!("key must be 32 bytes, got {}", key_bytes.len()));
+    let versioned = rest
+        .strip_prefix('v')
+        .and_then(|r| r.split_once(':'))
+        .and_then(|(v, data)| v.parse::<u32>().ok().map(|v| (v, data)));
+    match versioned {
+        Some((version, encoded)) => {
+            let (_, key) = keys
+                .iter()
+                .find(|(v, _)| *v == version)
+                .ok_or_else(|| format!("no key configured for version {}", version))?;
+            decrypt_with(key, encoded)
+        }
+        None => keys
+            .iter()
+            .find_map(|(_, key)| decrypt_with(key, rest).ok())
+            .ok_or_else(|| "no configured key decrypts value".to_string()),
     }
-    let cipher = Aes256Gcm::new_from_slice(&key_bytes).map_err(|e| e.to_string())?;
+}
+
+fn decrypt_with(hex_key: &str, encoded: &str) -> Result<String, String> {
+    let cipher = cipher_for(hex_key)?;
 
     let combined = base64::engine::general_purpose::STANDARD.decode(encoded)
         .map_err(|e| format!("bad base64: {}", e))?;
@@ -544,6 +616,31 @@ async fn set_office_theme(
         return (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid themeId"}))).into_response();
     }
 
+    // Only published themes the user may use: free ones, purchased ones, or their own
+    let theme = sqlx::query_as::<_, (i32, String, bool)>(
+        r#"SELECT t.price, t.author_id,
+                  EXISTS(SELECT 1 FROM theme_purchases p WHERE p.user_id = $2 AND p.theme_id = t.id)
+           FROM themes t WHERE t.id = $1 AND t.published = true"#,
+    )
+    .bind(theme_id)
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await;
+
+    match theme {
+        Ok(Some((price, author_id, purchased))) => {
+            if price > 0 && !purchased && author_id != user.id {
+                return (StatusCode::FORBIDDEN, Json(json!({"error": "Theme not owned"}))).into_response();
+            }
+        }
+        Ok(None) => {
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": "Unknown themeId"}))).into_response();
+        }
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))).into_response();
+        }
+    }
+
     let result = sqlx::query(
         r#"UPDATE "user" SET office_theme_id = $1 WHERE id = $2"#,
     )
@@ -613,7 +710,7 @@ async fn get_office_visit(
 
     let (target_name, target_image, target_theme_id) = target.unwrap_or_default();
 
-    let effective_theme = target_theme_id.as_deref().unwrap_or("cozy-studio-v2");
+    let effective_theme = target_theme_id.as_deref().unwrap_or(DEFAULT_OFFICE_THEME);
 
     let agents = sqlx::query_as::<_, (Uuid, String, Option<String>, i32)>(
         r#"SELECT a.id, a.name, a.avatar_url, osb.slot_index
@@ -7,22 +7,84 @@ use axum::{
 };
 use chrono::NaiveDateTime;
 use serde::Deserialize;
-use serde_json::json;
+use serde_json::{json, Value};
 
 use crate::auth::middleware::AuthUser;
-use crate::utils::username::validate_username;
+use crate::utils::username::{username_candidates, validate_username};
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/users/username", post(set_username))
         .route("/api/users/username/check", get(check_username))
+        .route("/api/users/username-available", get(check_username))
         .route("/api/users/search", get(search_users))
         .route("/api/users/me", get(get_me))
+        .route("/api/users/me/privacy", get(get_privacy).put(update_privacy))
         .route("/api/users/{userId}", get(get_user_by_id))
         .route("/api/users/{userId}/agents", get(get_user_agents))
 }
 
+/// Accepted `who_can_message` values.
+pub const WHO_CAN_MESSAGE: &[&str] = &["everyone", "friends", "nobody"];
+
+/// A user's contact privacy settings.
+pub struct PrivacySettings {
+    /// Whether the user shows up in username search and can be friend-requested by username.
+    pub discoverable_by_username: bool,
+    /// Who may start a direct conversation: everyone, friends, or nobody.
+    /// "nobody" also refuses friend requests.
+    pub who_can_message: String,
+}
+
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        Self {
+            discoverable_by_username: true,
+            who_can_message: "friends".into(),
+        }
+    }
+}
+
+impl PrivacySettings {
+    /// Whether someone may start a direct conversation with this user.
+    pub fn allows_direct_message(&self, are_friends: bool) -> bool {
+        match self.who_can_message.as_str() {
+            "everyone" => true,
+            "nobody" => false,
+            _ => are_friends,
+        }
+    }
+
+    pub fn allows_friend_requests(&self) -> bool {
+        self.who_can_message != "nobody"
+    }
+}
+
+/// Load `user_id`'s privacy settings, falling back to defaults.
+pub async fn privacy_settings(db: &sqlx::PgPool, user_id: &str) -> PrivacySettings {
+    sqlx::query_as::<_, (bool, String)>(
+        r#"SELECT discoverable_by_username, who_can_message FROM "user" WHERE id = $1"#,
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await
+    .ok()
+    .flatten()
+    .map(|(discoverable_by_username, who_can_message)| PrivacySettings {
+        discoverable_by_username,
+        who_can_message,
+    })
+    .unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdatePrivacyBody {
+    discoverable_by_username: Option<bool>,
+    who_can_message: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct SetUsernameBody {
     username: String,
@@ -30,9 +92,13 @@ struct SetUsernameBody {
 
 #[derive(Deserialize)]
 struct CheckUsernameQuery {
+    #[serde(alias = "u")]
     username: String,
 }
 
+/// Suggestions returned when a requested username is taken.
+const MAX_USERNAME_SUGGESTIONS: usize = 3;
+
 #[derive(Deserialize)]
 struct SearchQuery {
     q: String,
@@ -42,19 +108,95 @@ struct SearchQuery {
 
 /// GET /api/users/me — Get current user info including username
 async fn get_me(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     user: AuthUser,
 ) -> Response {
+    let office_theme_id = sqlx::query_scalar::<_, Option<String>>(
+        r#"SELECT office_theme_id FROM "user" WHERE id = $1"#,
+    )
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await
+    .ok()
+    .flatten()
+    .flatten()
+    .unwrap_or_else(|| crate::routes::user_settings::DEFAULT_OFFICE_THEME.to_string());
+    let color_theme = crate::services::color_theme::resolved_user_theme(&state.db, &user.id)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Load color theme for {} failed: {}", user.id, e);
+            Value::Null
+        });
+
     Json(json!({
         "id": user.id,
         "email": user.email,
         "name": user.name,
         "username": user.username,
         "isVerified": user.is_verified,
+        "officeThemeId": office_theme_id,
+        "colorTheme": color_theme,
+    }))
+    .into_response()
+}
+
+/// GET /api/users/me/privacy — Current user's privacy settings
+async fn get_privacy(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Response {
+    let privacy = privacy_settings(&state.db, &user.id).await;
+    Json(json!({
+        "discoverableByUsername": privacy.discoverable_by_username,
+        "whoCanMessage": privacy.who_can_message,
     }))
     .into_response()
 }
 
+/// PUT /api/users/me/privacy — Update privacy settings; omitted fields are kept
+async fn update_privacy(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<UpdatePrivacyBody>,
+) -> Response {
+    if let Some(ref who) = body.who_can_message {
+        if !WHO_CAN_MESSAGE.contains(&who.as_str()) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!("whoCanMessage must be one of: {}", WHO_CAN_MESSAGE.join(", "))})),
+            )
+                .into_response();
+        }
+    }
+
+    let result = sqlx::query_as::<_, (bool, String)>(
+        r#"UPDATE "user" SET
+               discoverable_by_username = COALESCE($2, discoverable_by_username),
+               who_can_message = COALESCE($3, who_can_message),
+               updated_at = NOW()
+           WHERE id = $1
+           RETURNING discoverable_by_username, who_can_message"#,
+    )
+    .bind(&user.id)
+    .bind(body.discoverable_by_username)
+    .bind(&body.who_can_message)
+    .fetch_one(&state.db)
+    .await;
+
+    match result {
+        Ok((discoverable, who)) => Json(json!({
+            "discoverableByUsername": discoverable,
+            "whoCanMessage": who,
+        }))
+        .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+            .into_response(),
+    }
+}
+
 /// POST /api/users/username — Set username (one-time)
 async fn set_username(
     State(state): State<AppState>,
@@ -120,6 +262,8 @@ async fn set_username(
 }
 
 /// GET /api/users/username/check?username=xxx — Check if username is available
+/// (also served as /api/users/username-available?u=xxx). Taken names come with
+/// available suggestions.
 async fn check_username(
     State(state): State<AppState>,
     _user: AuthUser,
@@ -137,7 +281,27 @@ async fn check_username(
     .await;
 
     match exists {
-        Ok((true,)) => Json(json!({"available": false, "error": "Username is already taken"})).into_response(),
+        Ok((true,)) => {
+            let candidates = username_candidates(&params.username);
+            let taken = sqlx::query_scalar::<_, String>(
+                r#"SELECT LOWER(username) FROM "user" WHERE LOWER(username) = ANY($1)"#,
+            )
+            .bind(&candidates)
+            .fetch_all(&state.db)
+            .await
+            .unwrap_or_default();
+            let suggestions: Vec<String> = candidates
+                .into_iter()
+                .filter(|c| !taken.contains(c))
+                .take(MAX_USERNAME_SUGGESTIONS)
+                .collect();
+            Json(json!({
+                "available": false,
+                "error": "Username is already taken",
+                "suggestions": suggestions,
+            }))
+            .into_response()
+        }
         Ok((false,)) => Json(json!({"available": true})).into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -149,9 +313,10 @@ async fn check_username(
 
 /// GET /api/users/search?q=prefix — Search users by username prefix
 /// Pass `exact=true` to match username exactly instead of prefix.
+/// Users who opted out of discovery only appear to their friends.
 async fn search_users(
     State(state): State<AppState>,
-    _user: AuthUser,
+    user: AuthUser,
     Query(params): Query<SearchQuery>,
 ) -> Response {
     let limit = params.limit.unwrap_or(20).min(50);
@@ -160,24 +325,34 @@ async fn search_users(
     let results = if exact {
         let q = params.q.to_lowercase();
         sqlx::query_as::<_, (String, String, Option<String>, Option<String>, bool)>(
-            r#"SELECT id, name, image, username, is_verified FROM "user"
+            r#"SELECT id, name, image, username, is_verified FROM "user" u
                WHERE LOWER(username) = $1
+                 AND (u.discoverable_by_username OR EXISTS (
+                     SELECT 1 FROM friendships f WHERE f.status = 'accepted'
+                       AND ((f.requester_id = $3 AND f.addressee_id = u.id) OR (f.requester_id = u.id AND f.addressee_id = $3))
+                 ))
                LIMIT $2"#,
         )
         .bind(&q)
         .bind(limit)
+        .bind(&user.id)
         .fetch_all(&state.db)
         .await
     } else {
         let pattern = format!("{}%", params.q.to_lowercase());
         sqlx::query_as::<_, (String, String, Option<String>, Option<String>, bool)>(
-            r#"SELECT id, name, image, username, is_verified FROM "user"
+            r#"SELECT id, name, image, username, is_verified FROM "user" u
                WHERE username ILIKE $1
+                 AND (u.discoverable_by_username OR EXISTS (
+                     SELECT 1 FROM friendships f WHERE f.status = 'accepted'
+                       AND ((f.requester_id = $3 AND f.addressee_id = u.id) OR (f.requester_id = u.id AND f.addressee_id = $3))
+                 ))
                ORDER BY username
                LIMIT $2"#,
         )
         .bind(&pattern)
         .bind(limit)
+        .bind(&user.id)
         .fetch_all(&state.db)
         .await
     };
@@ -209,7 +384,7 @@ async fn search_users(
 /// GET /api/users/:userId — Get public user profile by ID
 async fn get_user_by_id(
     State(state): State<AppState>,
-    _user: AuthUser,
+    user: AuthUser,
     Path(user_id): Path<String>,
 ) -> Response {
     let result = sqlx::query_as::<_, (String, String, Option<String>, Option<String>, Option<String>, Option<String>, NaiveDateTime, bool)>(
@@ -221,6 +396,11 @@ async fn get_user_by_id(
 
     match result {
         Ok(Some((id, name, image, username, bio, cover_image, created_at, is_verified))) => {
+            let mutual_friends = if id == user.id {
+                None
+            } else {
+                Some(crate::routes::friends::mutual_friends_count(&state.db, &state.redis, &user.id, &id).await)
+            };
             Json(json!({
                 "id": id,
                 "name": name,
@@ -230,6 +410,7 @@ async fn get_user_by_id(
                 "coverImage": cover_image,
                 "createdAt": created_at.and_utc().to_rfc3339(),
                 "isVerified": is_verified,
+                "mutualFriends": mutual_friends,
             }))
             .into_response()
         }
@@ -17,10 +17,10 @@ use uuid::Uuid;
 use crate::auth::caller_identity::CallerIdentity;
 use crate::routes::messages::{with_attachments, CursorTimestamp, MessageRow};
 use crate::services::message_seq::get_next_seq;
-use crate::services::push::send_push_to_user;
-use crate::services::push_trigger::{is_conversation_muted, should_send_push};
+use crate::services::push::queue_message_push;
+use crate::services::push_trigger::{is_conversation_muted, is_mentions_only, message_push_type, push_allowed};
 use crate::ws::handler::{
-    filter_agents_for_dispatch, get_conv_member_ids, do_trigger_agent_response, AgentFilterConfig,
+    filter_agents_for_dispatch, get_conv_member_ids, do_trigger_agent_response, AgentFilterConfig, TriggerContext,
 };
 use crate::ws::state::QueuedResponse;
 use crate::AppState;
@@ -380,19 +380,24 @@ async fn v1_send_message(
                                 thread_id: None,
                                 user_message_id: Some(msg_id.clone()),
                                 metadata: None,
+                                response_group: None,
                             });
                         continue;
                     }
 
                     do_trigger_agent_response(
-                        &user_id,
                         &dispatch_agent_id,
                         conversation_id,
-                        content,
-                        None,
-                        None,
-                        &conv_type,
-                        None,
+                        TriggerContext {
+                            user_id: &user_id,
+                            content,
+                            reply_to_id: None,
+                            thread_id: None,
+                            conv_type: &conv_type,
+                            client_metadata: None,
+                            response_group: None,
+                            mention_depth: 0,
+                        },
                         &state.ws,
                         &state.db,
                         &state.redis,
@@ -441,14 +446,20 @@ async fn v1_send_message(
                 }
                 let muted = is_conversation_muted(db, mid, conversation_id).await;
                 if let Ok(false) = muted {
-                    let should_push = should_send_push(db, mid, "message").await;
+                    let push_type = message_push_type(db, mid, &conv_type, content).await;
+                    if push_type != "mention" && matches!(is_mentions_only(db, mid, conversation_id).await, Ok(true)) {
+                        continue;
+                    }
+                    let should_push = push_allowed(db, mid, push_type).await;
                     if let Ok(true) = should_push {
-                        let _ = send_push_to_user(
+                        queue_message_push(
                             db,
+                            &state.redis,
                             config,
                             mid,
-                            &crate::services::push::PushPayload {
-                                notification_type: "message".into(),
+                            conversation_id,
+                            crate::services::push::PushPayload {
+                                notification_type: push_type.into(),
                                 title: sender_name.to_string(),
                                 body: preview.clone(),
                                 url: Some(format!("/?c={}&m={}", conversation_id, msg_id)),
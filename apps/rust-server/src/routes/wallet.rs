@@ -1,6 +1,7 @@
 use axum::{
+    body::Bytes,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::Json,
     routing::{get, post},
     Router,
@@ -11,13 +12,19 @@ use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
+use crate::config::Config;
+use crate::services::crypto;
 use crate::AppState;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/wallet/balance", get(get_balance))
+        .route("/api/wallet/rate", get(get_rate))
         .route("/api/wallet/transactions", get(get_transactions))
         .route("/api/wallet/topup", post(topup))
+        .route("/api/wallet/topup/webhook", post(topup_webhook))
+        .route("/api/wallet/payout", post(request_payout))
+        .route("/api/wallet/payouts", get(list_payouts))
         .route("/api/apps/{id}/purchase", post(purchase))
         .route("/api/purchases/{purchaseId}/refund", post(refund))
 }
@@ -37,7 +44,29 @@ async fn get_balance(
     .unwrap_or(None)
     .unwrap_or(0);
 
-    (StatusCode::OK, Json(json!({ "balance": balance })))
+    (
+        StatusCode::OK,
+        Json(json!({
+            "balance": balance,
+            "currencyValue": state.config.coins_to_currency(balance),
+            "currency": currency_json(&state.config),
+        })),
+    )
+}
+
+// ---------- GET /api/wallet/rate ----------
+
+/// Coin display currency and conversion rate, shared by responses that show fees.
+pub fn currency_json(config: &Config) -> Value {
+    json!({
+        "code": config.coin_currency,
+        "coinToCurrencyRate": config.coin_to_currency_rate,
+        "minPaidActionCoins": config.min_paid_action_coins,
+    })
+}
+
+async fn get_rate(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    (StatusCode::OK, Json(currency_json(&state.config)))
 }
 
 // ---------- GET /api/wallet/transactions ----------
@@ -46,18 +75,68 @@ async fn get_balance(
 struct TransactionsQuery {
     page: Option<i64>,
     limit: Option<i64>,
+    /// Cursor from a previous response's `nextCursor`; takes precedence over `page`.
+    before: Option<String>,
+    #[serde(rename = "type")]
+    tx_type: Option<String>,
 }
 
 #[derive(sqlx::FromRow)]
-struct TxRow {
-    id: Uuid,
-    user_id: String,
+pub struct TxRow {
+    pub id: Uuid,
+    pub user_id: String,
     #[sqlx(rename = "type")]
-    tx_type: String,
-    amount: i32,
-    related_app_id: Option<Uuid>,
-    description: Option<String>,
-    created_at: NaiveDateTime,
+    pub tx_type: String,
+    pub amount: i32,
+    pub related_app_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Encode a transaction's position as an opaque pagination cursor:
+/// the RFC 3339 timestamp (microseconds) and the row id, joined by `_`.
+/// The id breaks ties between transactions written in the same microsecond.
+pub fn encode_tx_cursor(created_at: NaiveDateTime, id: Uuid) -> String {
+    format!("{}_{}", created_at.format("%Y-%m-%dT%H:%M:%S%.6fZ"), id)
+}
+
+/// Decode a cursor produced by `encode_tx_cursor`.
+pub fn decode_tx_cursor(cursor: &str) -> Option<(NaiveDateTime, Uuid)> {
+    let (ts, id) = cursor.split_once('_')?;
+    let ts = chrono::DateTime::parse_from_rfc3339(ts).ok()?.naive_utc();
+    let id = Uuid::parse_str(id).ok()?;
+    Some((ts, id))
+}
+
+/// Fetch a page of a user's transactions, newest first.
+///
+/// With a `before` cursor, rows strictly after `(created_at, id)` in that order are
+/// returned, so transactions sharing a timestamp are neither skipped nor repeated.
+pub async fn fetch_transactions_page(
+    db: &sqlx::PgPool,
+    user_id: &str,
+    tx_type: Option<&str>,
+    before: Option<(NaiveDateTime, Uuid)>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<TxRow>, sqlx::Error> {
+    sqlx::query_as::<_, TxRow>(
+        r#"SELECT id, user_id, type::text, amount, related_app_id, description, created_at
+           FROM coin_transactions
+           WHERE user_id = $1
+             AND ($2::text IS NULL OR type::text = $2)
+             AND ($3::timestamp IS NULL OR (created_at, id) < ($3, $4))
+           ORDER BY created_at DESC, id DESC
+           LIMIT $5 OFFSET $6"#,
+    )
+    .bind(user_id)
+    .bind(tx_type)
+    .bind(before.map(|(ts, _)| ts))
+    .bind(before.map(|(_, id)| id))
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await
 }
 
 async fn get_transactions(
@@ -65,14 +144,31 @@ async fn get_transactions(
     user: AuthUser,
     Query(q): Query<TransactionsQuery>,
 ) -> (StatusCode, Json<Value>) {
-    let limit = q.limit.unwrap_or(20).min(100);
+    let limit = q.limit.unwrap_or(20).clamp(1, 100);
     let page = q.page.unwrap_or(1).max(1);
-    let offset = (page - 1) * limit;
+    let tx_type = q.tx_type.as_deref().map(str::trim).filter(|t| !t.is_empty());
+
+    let before = match q.before.as_deref() {
+        Some(c) => match decode_tx_cursor(c) {
+            Some(pos) => Some(pos),
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "Invalid cursor" })),
+                );
+            }
+        },
+        None => None,
+    };
+    // Offset paging is kept for older clients; cursors always start after `before`
+    let offset = if before.is_some() { 0 } else { (page - 1) * limit };
 
     let total = match sqlx::query_scalar::<_, i64>(
-        "SELECT COUNT(*) FROM coin_transactions WHERE user_id = $1",
+        r#"SELECT COUNT(*) FROM coin_transactions
+           WHERE user_id = $1 AND ($2::text IS NULL OR type::text = $2)"#,
     )
     .bind(&user.id)
+    .bind(tx_type)
     .fetch_one(&state.db)
     .await
     {
@@ -86,27 +182,25 @@ async fn get_transactions(
         }
     };
 
-    let rows = match sqlx::query_as::<_, TxRow>(
-        r#"SELECT id, user_id, type::text, amount, related_app_id, description, created_at
-           FROM coin_transactions
-           WHERE user_id = $1
-           ORDER BY created_at DESC
-           LIMIT $2 OFFSET $3"#,
-    )
-    .bind(&user.id)
-    .bind(limit)
-    .bind(offset)
-    .fetch_all(&state.db)
-    .await
-    {
-        Ok(rows) => rows,
-        Err(e) => {
-            tracing::error!("Fetch transactions failed: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Failed to fetch transactions" })),
-            );
-        }
+    // Fetch one extra row to know whether another page exists
+    let mut rows =
+        match fetch_transactions_page(&state.db, &user.id, tx_type, before, limit + 1, offset).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::error!("Fetch transactions failed: {}", e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to fetch transactions" })),
+                );
+            }
+        };
+
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    let next_cursor = if has_more {
+        rows.last().map(|r| encode_tx_cursor(r.created_at, r.id))
+    } else {
+        None
     };
 
     let transactions: Vec<Value> = rows
@@ -131,6 +225,8 @@ async fn get_transactions(
             "total": total,
             "page": page,
             "limit": limit,
+            "hasMore": has_more,
+            "nextCursor": next_cursor,
         })),
     )
 }
@@ -197,6 +293,331 @@ async fn topup(
     }
 }
 
+// ---------- POST /api/wallet/topup/webhook ----------
+
+const SIGNATURE_HEADER: &str = "x-signature";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TopupWebhookPayload {
+    transaction_id: String,
+    user_id: String,
+    amount: i32,
+}
+
+/// Payment provider callback. The raw body is signed with HMAC-SHA256 using
+/// `TOPUP_WEBHOOK_SECRET`; each provider transaction ID is credited once.
+async fn topup_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<Value>) {
+    let Some(secret) = state.config.topup_webhook_secret.as_deref() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "Top-up webhook not configured" })),
+        );
+    };
+
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if !crypto::verify_hmac_sha256(secret, &body, signature) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Invalid signature" })),
+        );
+    }
+
+    let payload: TopupWebhookPayload = match serde_json::from_slice(&body) {
+        Ok(p) => p,
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Invalid payload" })),
+            );
+        }
+    };
+    if payload.amount <= 0 || payload.transaction_id.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid payload" })),
+        );
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Topup webhook: begin tx failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Top-up failed" })),
+            );
+        }
+    };
+
+    // Record the provider transaction first — a conflict means it was already credited
+    let inserted = sqlx::query(
+        r#"INSERT INTO topup_webhook_events (provider_tx_id, user_id, amount)
+           VALUES ($1, $2, $3)
+           ON CONFLICT (provider_tx_id) DO NOTHING"#,
+    )
+    .bind(&payload.transaction_id)
+    .bind(&payload.user_id)
+    .bind(payload.amount)
+    .execute(&mut *tx)
+    .await;
+
+    match inserted {
+        Ok(r) if r.rows_affected() == 0 => {
+            return (
+                StatusCode::OK,
+                Json(json!({ "success": true, "duplicate": true })),
+            );
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!("Topup webhook: record event failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Top-up failed" })),
+            );
+        }
+    }
+
+    let new_balance = match sqlx::query_scalar::<_, i32>(
+        r#"INSERT INTO coin_balances (user_id, balance, updated_at)
+           VALUES ($1, $2, NOW())
+           ON CONFLICT (user_id) DO UPDATE
+           SET balance = coin_balances.balance + $2, updated_at = NOW()
+           RETURNING balance"#,
+    )
+    .bind(&payload.user_id)
+    .bind(payload.amount)
+    .fetch_one(&mut *tx)
+    .await
+    {
+        Ok(b) => b,
+        Err(e) => {
+            tracing::error!("Topup webhook: credit balance failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Top-up failed" })),
+            );
+        }
+    };
+
+    if let Err(e) = sqlx::query(
+        r#"INSERT INTO coin_transactions (user_id, type, amount, receipt_id, description)
+           VALUES ($1, 'topup', $2, $3, 'Coin top-up')"#,
+    )
+    .bind(&payload.user_id)
+    .bind(payload.amount)
+    .bind(&payload.transaction_id)
+    .execute(&mut *tx)
+    .await
+    {
+        tracing::error!("Topup webhook: record transaction failed: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Top-up failed" })),
+        );
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Topup webhook: commit failed: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Top-up failed" })),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({ "success": true, "duplicate": false, "balance": new_balance })),
+    )
+}
+
+// ---------- POST /api/wallet/payout ----------
+
+#[derive(Deserialize)]
+struct PayoutBody {
+    amount: i32,
+}
+
+async fn request_payout(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<PayoutBody>,
+) -> (StatusCode, Json<Value>) {
+    let min_payout = state.config.min_payout_coins;
+    if body.amount < min_payout {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Minimum payout is {} coins", min_payout),
+                "minPayoutCoins": min_payout,
+            })),
+        );
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Payout request: begin tx failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Payout request failed" })),
+            );
+        }
+    };
+
+    // Atomic deduction — only succeeds if balance covers the amount
+    let new_balance = match sqlx::query_scalar::<_, i32>(
+        r#"UPDATE coin_balances
+           SET balance = balance - $2, updated_at = NOW()
+           WHERE user_id = $1 AND balance >= $2
+           RETURNING balance"#,
+    )
+    .bind(&user.id)
+    .bind(body.amount)
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(Some(b)) => b,
+        Ok(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Insufficient balance" })),
+            );
+        }
+        Err(e) => {
+            tracing::error!("Payout request: deduct failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Payout request failed" })),
+            );
+        }
+    };
+
+    let tx_id = match sqlx::query_scalar::<_, Uuid>(
+        r#"INSERT INTO coin_transactions (user_id, type, amount, description)
+           VALUES ($1, 'payout_requested', $2, $3)
+           RETURNING id"#,
+    )
+    .bind(&user.id)
+    .bind(-body.amount)
+    .bind(format!("Payout request: {} coins", body.amount))
+    .fetch_one(&mut *tx)
+    .await
+    {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::error!("Payout request: record transaction failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Payout request failed" })),
+            );
+        }
+    };
+
+    let payout = match sqlx::query_as::<_, PayoutRow>(
+        r#"INSERT INTO payout_requests (user_id, amount, transaction_id)
+           VALUES ($1, $2, $3)
+           RETURNING id, amount, status, created_at, updated_at"#,
+    )
+    .bind(&user.id)
+    .bind(body.amount)
+    .bind(tx_id)
+    .fetch_one(&mut *tx)
+    .await
+    {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::error!("Payout request: insert request failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Payout request failed" })),
+            );
+        }
+    };
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Payout request: commit failed: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Payout request failed" })),
+        );
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "payout": payout_json(&payout, &state.config),
+            "balance": new_balance,
+        })),
+    )
+}
+
+// ---------- GET /api/wallet/payouts ----------
+
+#[derive(sqlx::FromRow)]
+struct PayoutRow {
+    id: Uuid,
+    amount: i32,
+    status: String,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+fn payout_json(p: &PayoutRow, config: &Config) -> Value {
+    json!({
+        "id": p.id,
+        "amount": p.amount,
+        "currencyValue": config.coins_to_currency(p.amount),
+        "status": p.status,
+        "createdAt": p.created_at.and_utc().to_rfc3339(),
+        "updatedAt": p.updated_at.and_utc().to_rfc3339(),
+    })
+}
+
+async fn list_payouts(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> (StatusCode, Json<Value>) {
+    let rows = sqlx::query_as::<_, PayoutRow>(
+        r#"SELECT id, amount, status, created_at, updated_at
+           FROM payout_requests
+           WHERE user_id = $1
+           ORDER BY created_at DESC
+           LIMIT 100"#,
+    )
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let payouts: Vec<Value> = rows.iter().map(|p| payout_json(p, &state.config)).collect();
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "payouts": payouts,
+                    "minPayoutCoins": state.config.min_payout_coins,
+                })),
+            )
+        }
+        Err(e) => {
+            tracing::error!("List payouts failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to fetch payouts" })),
+            )
+        }
+    }
+}
+
 // ---------- POST /api/apps/{id}/purchase ----------
 
 async fn purchase(
@@ -2,8 +2,14 @@
 //!
 //! Provides:
 //! - `check_billing()` — determine if user can send a message (free trial or paid)
-//! - `deduct_coins()` — atomic coin deduction with 70/30 creator split
-//! - `record_message()` — increment counters after a message is processed
+//! - `release_trial_message()` — return a claimed free-trial message when no reply is delivered
+//! - `release_preview()` — return a claimed free preview when no reply is delivered
+//! - `hold_amount()` — coins to hold while a paid reply streams
+//! - `usage_event()` — the SSE `usage` event shared by the paid chat endpoints
+//! - `record_message()` — increment counters after a reply is delivered
+//!
+//! Charges themselves go through `services::wallet` holds, so a failed stream
+//! never costs the user.
 
 use sqlx::PgPool;
 use uuid::Uuid;
@@ -20,6 +26,10 @@ pub struct BillingResult {
     pub cost: i32,
     /// Whether this message is within the free trial quota.
     pub is_free_trial: bool,
+    /// Whether this message is the buyer's free first-message preview.
+    pub is_preview: bool,
+    /// Free-trial messages the user has left on this listing, not counting this one.
+    pub trial_remaining: i32,
     /// User-safe reason if `allowed == false`.
     pub reason: Option<String>,
 }
@@ -32,19 +42,26 @@ pub struct BillingResult {
 ///
 /// Logic:
 /// 1. Fetch `price_per_message` and `free_trial_messages` from `agent_listings`.
-/// 2. Fetch `message_count` from `marketplace_conversations`.
-/// 3. If `price_per_message == 0` → free listing, always allowed.
-/// 4. If `message_count < free_trial_messages` → free trial, allowed at cost 0.
-/// 5. Otherwise check `coin_balances.balance >= price_per_message`.
+/// 2. If `price_per_message == 0` → free listing, always allowed.
+/// 3. Fetch the buyer's `messages_used` from `marketplace_trial_usage`.
+/// 4. If `preview_enabled` → claim the free preview atomically (`claim_preview`);
+///    if it was unused → free preview, allowed at cost 0.
+/// 5. If `messages_used < free_trial_messages` → claim a free-trial message
+///    atomically (`claim_trial_message`), allowed at cost 0.
+/// 6. Otherwise check `coin_balances.balance >= price_per_message`.
+///
+/// The trial is per buyer and listing, across all of their conversations with
+/// it, so starting a new conversation does not restart it. It never resets on
+/// its own; only an admin can clear a buyer's usage.
 ///
-/// `conversation_id` is `None` for new users who haven't started a conversation yet.
-/// In that case message_count = 0 (correct free trial start).
+/// `conversation_id` is `None` when the user starts a new conversation.
 /// When `Some`, the conversation must match `user_id` + `listing_id` or it's rejected.
 pub async fn check_billing(
     db: &PgPool,
     user_id: &str,
     listing_id: Uuid,
     conversation_id: Option<Uuid>,
+    preview_enabled: bool,
 ) -> Result<BillingResult, String> {
     // 1. Fetch listing pricing
     let listing = sqlx::query_as::<_, (i32, i32)>(
@@ -65,6 +82,8 @@ pub async fn check_billing(
                 allowed: false,
                 cost: 0,
                 is_free_trial: false,
+                is_preview: false,
+                trial_remaining: 0,
                 reason: Some("Listing not found".into()),
             });
         }
@@ -76,57 +95,83 @@ pub async fn check_billing(
             allowed: true,
             cost: 0,
             is_free_trial: false,
+            is_preview: false,
+            trial_remaining: 0,
             reason: None,
         });
     }
 
-    // 2. Fetch conversation message count
-    let message_count = match conversation_id {
-        Some(cid) => {
-            // Existing conversation — must match user_id AND listing_id
-            let row = sqlx::query_scalar::<_, i32>(
-                "SELECT message_count FROM marketplace_conversations WHERE id = $1 AND user_id = $2 AND listing_id = $3",
-            )
-            .bind(cid)
-            .bind(user_id)
-            .bind(listing_id)
-            .fetch_optional(db)
-            .await
-            .map_err(|e| {
-                tracing::error!("check_billing: fetch message_count failed: {}", e);
-                "Database error".to_string()
-            })?;
-
-            match row {
-                Some(count) => count,
-                None => {
-                    // conversation_id provided but doesn't match user+listing → reject
-                    return Ok(BillingResult {
-                        allowed: false,
-                        cost: 0,
-                        is_free_trial: false,
-                        reason: Some("Conversation not found".into()),
-                    });
-                }
-            }
-        }
-        None => {
-            // New user, no conversation yet → message_count = 0 (free trial start)
-            0
+    // Existing conversation — must match user_id AND listing_id
+    if let Some(cid) = conversation_id {
+        let owned = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM marketplace_conversations WHERE id = $1 AND user_id = $2 AND listing_id = $3)",
+        )
+        .bind(cid)
+        .bind(user_id)
+        .bind(listing_id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| {
+            tracing::error!("check_billing: verify conversation failed: {}", e);
+            "Database error".to_string()
+        })?;
+
+        if !owned {
+            return Ok(BillingResult {
+                allowed: false,
+                cost: 0,
+                is_free_trial: false,
+                is_preview: false,
+                trial_remaining: 0,
+                reason: Some("Conversation not found".into()),
+            });
         }
-    };
+    }
+
+    // 3. Fetch the buyer's trial usage on this listing
+    let messages_used = sqlx::query_scalar::<_, i32>(
+        "SELECT messages_used FROM marketplace_trial_usage WHERE listing_id = $1 AND user_id = $2",
+    )
+    .bind(listing_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await
+    .map_err(|e| {
+        tracing::error!("check_billing: fetch trial usage failed: {}", e);
+        "Database error".to_string()
+    })?
+    .unwrap_or(0);
+    let remaining = trial_remaining(free_trial_messages, messages_used);
 
-    // 3. Free trial check
-    if message_count < free_trial_messages {
+    // 4. Free preview check — spent before the listing's own trial, and claimed
+    //    atomically like the trial below
+    if preview_enabled && claim_preview(db, listing_id, user_id).await? {
         return Ok(BillingResult {
             allowed: true,
             cost: 0,
-            is_free_trial: true,
+            is_free_trial: false,
+            is_preview: true,
+            trial_remaining: remaining,
             reason: None,
         });
     }
 
-    // 4. Check balance
+    // 5. Free trial check — claimed here so concurrent messages can't both
+    //    take the last free one; the caller returns it if no reply is delivered
+    if remaining > 0 {
+        if let Some(messages_used) = claim_trial_message(db, listing_id, user_id, free_trial_messages).await? {
+            return Ok(BillingResult {
+                allowed: true,
+                cost: 0,
+                is_free_trial: true,
+                is_preview: false,
+                trial_remaining: trial_remaining(free_trial_messages, messages_used),
+                reason: None,
+            });
+        }
+    }
+
+    // 6. Check balance
     let balance = sqlx::query_scalar::<_, i32>(
         "SELECT balance FROM coin_balances WHERE user_id = $1",
     )
@@ -144,6 +189,8 @@ pub async fn check_billing(
             allowed: false,
             cost: price_per_message,
             is_free_trial: false,
+            is_preview: false,
+            trial_remaining: 0,
             reason: Some("Insufficient balance".into()),
         });
     }
@@ -152,131 +199,183 @@ pub async fn check_billing(
         allowed: true,
         cost: price_per_message,
         is_free_trial: false,
+        is_preview: false,
+        trial_remaining: 0,
         reason: None,
     })
 }
 
-// ---------------------------------------------------------------------------
-// deduct_coins
-// ---------------------------------------------------------------------------
+/// Free-trial messages left for a buyer who has used `messages_used` of them.
+pub fn trial_remaining(free_trial_messages: i32, messages_used: i32) -> i32 {
+    (free_trial_messages - messages_used).max(0)
+}
 
-/// Atomically deduct coins from the user and credit the creator (70/30 split).
-///
-/// Uses a DB transaction:
-/// 1. Deduct `price` from buyer balance (fails if insufficient).
-/// 2. Record purchase transaction.
-/// 3. Credit creator balance with 70% share.
-/// 4. Record earning transaction for creator.
-///
-/// Returns the buyer's new balance on success.
-pub async fn deduct_coins(
+/// Whether a buyer gets the free first-message preview on a listing: the
+/// preview is switched on, the listing is paid, and they haven't used it yet.
+pub fn preview_applies(preview_enabled: bool, price_per_message: i32, preview_used: bool) -> bool {
+    preview_enabled && price_per_message > 0 && !preview_used
+}
+
+/// Whether the buyer still has the free preview on the listing. Used for the
+/// listing detail; `check_billing` decides what the next message actually costs.
+pub async fn preview_available(
     db: &PgPool,
-    user_id: &str,
     listing_id: Uuid,
-    price: i32,
-) -> Result<i32, String> {
-    if price <= 0 {
-        return Err("Price must be positive".into());
+    user_id: &str,
+    preview_enabled: bool,
+) -> Result<bool, String> {
+    if !preview_enabled {
+        return Ok(false);
     }
-
-    // Fetch creator_id
-    let creator_id = sqlx::query_scalar::<_, String>(
-        "SELECT creator_id FROM agent_listings WHERE id = $1",
+    let row = sqlx::query_as::<_, (i32, bool)>(
+        r#"SELECT al.price_per_message,
+                  COALESCE((SELECT tu.preview_used FROM marketplace_trial_usage tu
+                            WHERE tu.listing_id = al.id AND tu.user_id = $2), false)
+           FROM agent_listings al WHERE al.id = $1"#,
     )
     .bind(listing_id)
+    .bind(user_id)
     .fetch_optional(db)
     .await
     .map_err(|e| {
-        tracing::error!("deduct_coins: fetch creator_id failed: {}", e);
-        "Database error".to_string()
-    })?
-    .ok_or_else(|| "Listing not found".to_string())?;
-
-    let creator_share = price * 7 / 10;
-
-    // === Begin transaction ===
-    let mut tx = db.begin().await.map_err(|e| {
-        tracing::error!("deduct_coins: begin tx failed: {}", e);
+        tracing::error!("preview_available: fetch preview usage failed: {}", e);
         "Database error".to_string()
     })?;
+    Ok(row.is_some_and(|(price, used)| preview_applies(preview_enabled, price, used)))
+}
+
+// ---------------------------------------------------------------------------
+// Free-trial claims
+// ---------------------------------------------------------------------------
 
-    // 1. Atomic deduction — only succeeds if balance >= price
-    let new_balance = sqlx::query_scalar::<_, i32>(
-        r#"UPDATE coin_balances
-           SET balance = balance - $2, updated_at = NOW()
-           WHERE user_id = $1 AND balance >= $2
-           RETURNING balance"#,
+/// Take one free-trial message on the listing for the buyer, in a single
+/// statement that only succeeds while `messages_used < free_trial_messages`.
+/// Returns the buyer's new `messages_used`, or `None` if the trial is used up.
+pub async fn claim_trial_message(
+    db: &PgPool,
+    listing_id: Uuid,
+    user_id: &str,
+    free_trial_messages: i32,
+) -> Result<Option<i32>, String> {
+    if free_trial_messages <= 0 {
+        return Ok(None);
+    }
+    sqlx::query_scalar::<_, i32>(
+        r#"INSERT INTO marketplace_trial_usage (listing_id, user_id, messages_used)
+           VALUES ($1, $2, 1)
+           ON CONFLICT (listing_id, user_id) DO UPDATE
+           SET messages_used = marketplace_trial_usage.messages_used + 1, updated_at = NOW()
+           WHERE marketplace_trial_usage.messages_used < $3
+           RETURNING messages_used"#,
     )
+    .bind(listing_id)
     .bind(user_id)
-    .bind(price)
-    .fetch_optional(&mut *tx)
+    .bind(free_trial_messages)
+    .fetch_optional(db)
     .await
     .map_err(|e| {
-        tracing::error!("deduct_coins: deduction failed: {}", e);
-        "Payment failed".to_string()
-    })?
-    .ok_or_else(|| "Insufficient balance".to_string())?;
+        tracing::error!("claim_trial_message: update trial usage failed: {}", e);
+        "Database error".to_string()
+    })
+}
 
-    // 2. Record purchase transaction (buyer, negative amount)
-    if let Err(e) = sqlx::query(
-        r#"INSERT INTO coin_transactions (user_id, type, amount, related_app_id, description)
-           VALUES ($1, 'purchase', $2, $3, 'Agent Hub message payment')"#,
+/// Give back a free-trial message claimed by `check_billing` when no reply
+/// was delivered for it.
+pub async fn release_trial_message(
+    db: &PgPool,
+    listing_id: Uuid,
+    user_id: &str,
+) -> Result<(), String> {
+    sqlx::query(
+        r#"UPDATE marketplace_trial_usage
+           SET messages_used = GREATEST(messages_used - 1, 0), updated_at = NOW()
+           WHERE listing_id = $1 AND user_id = $2"#,
     )
-    .bind(user_id)
-    .bind(-price)
     .bind(listing_id)
-    .execute(&mut *tx)
+    .bind(user_id)
+    .execute(db)
     .await
-    {
-        tracing::error!("deduct_coins: record purchase tx failed: {}", e);
-        return Err("Payment failed".into());
-    }
+    .map_err(|e| {
+        tracing::error!("release_trial_message: update trial usage failed: {}", e);
+        "Database error".to_string()
+    })?;
+    Ok(())
+}
 
-    // 3. Credit creator balance
-    if let Err(e) = sqlx::query(
-        r#"INSERT INTO coin_balances (user_id, balance, updated_at)
-           VALUES ($1, $2, NOW())
-           ON CONFLICT (user_id) DO UPDATE
-           SET balance = coin_balances.balance + $2, updated_at = NOW()"#,
+/// Take the buyer's free preview on the listing, in a single statement that
+/// only succeeds while it is unused. Returns whether this call got it.
+pub async fn claim_preview(
+    db: &PgPool,
+    listing_id: Uuid,
+    user_id: &str,
+) -> Result<bool, String> {
+    sqlx::query_scalar::<_, bool>(
+        r#"INSERT INTO marketplace_trial_usage (listing_id, user_id, preview_used)
+           VALUES ($1, $2, TRUE)
+           ON CONFLICT (listing_id, user_id) DO UPDATE
+           SET preview_used = TRUE, updated_at = NOW()
+           WHERE NOT marketplace_trial_usage.preview_used
+           RETURNING preview_used"#,
     )
-    .bind(&creator_id)
-    .bind(creator_share)
-    .execute(&mut *tx)
+    .bind(listing_id)
+    .bind(user_id)
+    .fetch_optional(db)
     .await
-    {
-        tracing::error!("deduct_coins: credit creator failed: {}", e);
-        return Err("Payment failed".into());
-    }
+    .map(|row| row.is_some())
+    .map_err(|e| {
+        tracing::error!("claim_preview: update trial usage failed: {}", e);
+        "Database error".to_string()
+    })
+}
 
-    // 4. Record earning transaction (creator, positive amount)
-    if let Err(e) = sqlx::query(
-        r#"INSERT INTO coin_transactions (user_id, type, amount, related_app_id, description)
-           VALUES ($1, 'earning', $2, $3, 'Agent Hub message earning')"#,
+/// Give back a preview claimed by `check_billing` when no reply was delivered for it.
+pub async fn release_preview(
+    db: &PgPool,
+    listing_id: Uuid,
+    user_id: &str,
+) -> Result<(), String> {
+    sqlx::query(
+        r#"UPDATE marketplace_trial_usage
+           SET preview_used = FALSE, updated_at = NOW()
+           WHERE listing_id = $1 AND user_id = $2"#,
     )
-    .bind(&creator_id)
-    .bind(creator_share)
     .bind(listing_id)
-    .execute(&mut *tx)
+    .bind(user_id)
+    .execute(db)
     .await
-    {
-        tracing::error!("deduct_coins: record earning tx failed: {}", e);
-        return Err("Payment failed".into());
-    }
-
-    // === Commit ===
-    tx.commit().await.map_err(|e| {
-        tracing::error!("deduct_coins: commit failed: {}", e);
-        "Payment failed".to_string()
+    .map_err(|e| {
+        tracing::error!("release_preview: update trial usage failed: {}", e);
+        "Database error".to_string()
     })?;
+    Ok(())
+}
 
-    Ok(new_balance)
+// ---------------------------------------------------------------------------
+// Holds and usage
+// ---------------------------------------------------------------------------
+
+/// Coins to hold (see `services::wallet`) before streaming a reply, or `None`
+/// when the message is free — a free listing, the free preview, or within the free trial.
+pub fn hold_amount(result: &BillingResult) -> Option<i32> {
+    (!result.is_free_trial && !result.is_preview && result.cost > 0).then_some(result.cost)
+}
+
+/// SSE `usage` event sent just before `done` by the paid chat endpoints:
+/// coins actually charged for the reply and whether it used the free trial.
+pub fn usage_event(charged: i32, free_trial: bool) -> serde_json::Value {
+    serde_json::json!({
+        "type": "usage",
+        "coins": charged,
+        "freeTrial": free_trial,
+    })
 }
 
 // ---------------------------------------------------------------------------
 // record_message
 // ---------------------------------------------------------------------------
 
-/// Increment message counters after a message is processed.
+/// Increment message counters after a reply is delivered, so only call it
+/// once the reply is stored.
 ///
 /// Updates:
 /// - `marketplace_conversations.message_count += 1`
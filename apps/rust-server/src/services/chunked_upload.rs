@@ -0,0 +1,136 @@
+//! Resumable uploads for large files.
+//!
+//! A client opens a session, sends the file as ordered chunks that are written
+//! into a temp file under `upload_dir`, then completes the session to move the
+//! assembled file to final storage. Session state lives in Redis and expires
+//! after `SESSION_TTL_SECS` without activity; the matching temp files are
+//! removed by `sweep_stale_temp_files()`.
+
+use std::path::{Path, PathBuf};
+
+use deadpool_redis::redis::AsyncCommands;
+use deadpool_redis::Pool;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+const KEY_PREFIX: &str = "chunked_upload:";
+pub const SESSION_TTL_SECS: u64 = 86400; // 24 hours
+
+pub const MIN_CHUNK_SIZE: u64 = 256 * 1024;
+pub const MAX_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+pub const DEFAULT_CHUNK_SIZE: u64 = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadSession {
+    pub id: Uuid,
+    pub user_id: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub total_size: u64,
+    pub chunk_size: u64,
+    /// Chunks `0..received_chunks` have been written.
+    pub received_chunks: u32,
+}
+
+impl UploadSession {
+    pub fn total_chunks(&self) -> u32 {
+        chunk_count(self.total_size, self.chunk_size)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received_chunks >= self.total_chunks()
+    }
+}
+
+/// Number of chunks needed for `total_size` bytes.
+pub fn chunk_count(total_size: u64, chunk_size: u64) -> u32 {
+    if chunk_size == 0 {
+        return 0;
+    }
+    total_size.div_ceil(chunk_size) as u32
+}
+
+/// Exact byte length chunk `index` must have, or `None` if it is out of range.
+pub fn expected_chunk_len(total_size: u64, chunk_size: u64, index: u32) -> Option<u64> {
+    if index >= chunk_count(total_size, chunk_size) {
+        return None;
+    }
+    let offset = index as u64 * chunk_size;
+    Some(chunk_size.min(total_size - offset))
+}
+
+/// Temp file the chunks of an upload are assembled into.
+pub fn temp_path(upload_dir: &str, id: Uuid) -> PathBuf {
+    temp_dir(upload_dir).join(format!("{}.part", id))
+}
+
+fn temp_dir(upload_dir: &str) -> PathBuf {
+    Path::new(upload_dir).join("tmp").join("chunked")
+}
+
+fn key(id: Uuid) -> String {
+    format!("{}{}", KEY_PREFIX, id)
+}
+
+/// Store the session and reset its TTL.
+pub async fn save_session(redis: &Pool, session: &UploadSession) -> Result<(), anyhow::Error> {
+    let mut conn = redis.get().await?;
+    let value = serde_json::to_string(session)?;
+    conn.set_ex::<_, _, ()>(key(session.id), value, SESSION_TTL_SECS).await?;
+    Ok(())
+}
+
+pub async fn load_session(redis: &Pool, id: Uuid) -> Result<Option<UploadSession>, anyhow::Error> {
+    let mut conn = redis.get().await?;
+    let value: Option<String> = conn.get(key(id)).await?;
+    Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+}
+
+/// Delete the session. Returns `false` if it was already gone, so only one
+/// caller can finalize an upload.
+pub async fn delete_session(redis: &Pool, id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut conn = redis.get().await?;
+    let removed: i64 = conn.del(key(id)).await?;
+    Ok(removed > 0)
+}
+
+/// Write `data` at `offset` in the temp file, creating it if needed.
+/// Re-sending a chunk overwrites the same range.
+pub async fn write_chunk(path: &Path, offset: u64, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+        .await?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    file.write_all(data).await?;
+    file.flush().await
+}
+
+/// Remove temp files untouched for longer than the session TTL.
+pub async fn sweep_stale_temp_files(upload_dir: &str) -> usize {
+    let Ok(mut entries) = tokio::fs::read_dir(temp_dir(upload_dir)).await else {
+        return 0;
+    };
+    let max_age = std::time::Duration::from_secs(SESSION_TTL_SECS);
+    let mut removed = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let stale = entry
+            .metadata()
+            .await
+            .ok()
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+        if stale && tokio::fs::remove_file(entry.path()).await.is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
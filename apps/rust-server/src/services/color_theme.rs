@@ -0,0 +1,205 @@
+//! App color themes: built-in palettes and per-user custom ones.
+//!
+//! Separate from office themes (`routes/themes.rs`), which are uploaded
+//! renderer bundles. A user's choice lives in `user_themes` as either a
+//! built-in `theme_id` or a custom `{name, tokens}` palette.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+use sqlx::PgPool;
+
+/// Color tokens every theme defines, in display order.
+pub const TOKEN_NAMES: &[&str] = &["background", "surface", "text", "mutedText", "accent", "border"];
+
+/// Theme used when a user has not chosen one.
+pub const DEFAULT_COLOR_THEME: &str = "light";
+
+const MAX_CUSTOM_NAME_CHARS: usize = 50;
+
+pub struct BuiltinTheme {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// Values for `TOKEN_NAMES`, in the same order.
+    pub tokens: [&'static str; 6],
+}
+
+pub const BUILTIN_THEMES: &[BuiltinTheme] = &[
+    BuiltinTheme {
+        id: "light",
+        name: "Light",
+        tokens: ["#ffffff", "#f4f4f5", "#18181b", "#71717a", "#6366f1", "#e4e4e7"],
+    },
+    BuiltinTheme {
+        id: "dark",
+        name: "Dark",
+        tokens: ["#09090b", "#18181b", "#fafafa", "#a1a1aa", "#818cf8", "#27272a"],
+    },
+    BuiltinTheme {
+        id: "midnight",
+        name: "Midnight",
+        tokens: ["#0b1020", "#131a2e", "#e2e8f0", "#94a3b8", "#38bdf8", "#1e293b"],
+    },
+];
+
+impl BuiltinTheme {
+    pub fn to_json(&self) -> Value {
+        let tokens: Map<String, Value> = TOKEN_NAMES
+            .iter()
+            .zip(self.tokens)
+            .map(|(name, value)| (name.to_string(), json!(value)))
+            .collect();
+        json!({ "id": self.id, "name": self.name, "custom": false, "tokens": tokens })
+    }
+}
+
+pub fn builtin_theme(id: &str) -> Option<&'static BuiltinTheme> {
+    BUILTIN_THEMES.iter().find(|t| t.id == id)
+}
+
+/// `#rgb` or `#rrggbb`.
+pub fn is_hex_color(value: &str) -> bool {
+    value
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// A custom theme that passed validation; token values are lowercased.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomTheme {
+    pub name: String,
+    pub tokens: BTreeMap<String, String>,
+}
+
+impl CustomTheme {
+    pub fn to_json(&self) -> Value {
+        json!({ "id": "custom", "name": self.name, "custom": true, "tokens": self.tokens })
+    }
+}
+
+/// Validate a `{name, tokens}` custom theme. On failure returns every problem
+/// keyed by field path, e.g. `tokens.accent`.
+pub fn validate_custom_theme(raw: &Value) -> Result<CustomTheme, BTreeMap<String, String>> {
+    let mut errors = BTreeMap::new();
+
+    let name = raw.get("name").and_then(Value::as_str).map(str::trim).unwrap_or("");
+    if name.is_empty() {
+        errors.insert("name".into(), "is required".into());
+    } else if name.chars().count() > MAX_CUSTOM_NAME_CHARS {
+        errors.insert("name".into(), format!("must be at most {} characters", MAX_CUSTOM_NAME_CHARS));
+    }
+
+    let mut tokens = BTreeMap::new();
+    match raw.get("tokens").and_then(Value::as_object) {
+        None => {
+            errors.insert("tokens".into(), "must be an object of color tokens".into());
+        }
+        Some(given) => {
+            for token in TOKEN_NAMES {
+                match given.get(*token) {
+                    None => {
+                        errors.insert(format!("tokens.{}", token), "is required".into());
+                    }
+                    Some(Value::String(v)) if is_hex_color(v) => {
+                        tokens.insert(token.to_string(), v.to_ascii_lowercase());
+                    }
+                    Some(_) => {
+                        errors.insert(
+                            format!("tokens.{}", token),
+                            "must be a hex color like #1a2b3c".into(),
+                        );
+                    }
+                }
+            }
+            for key in given.keys().filter(|k| !TOKEN_NAMES.contains(&k.as_str())) {
+                errors.insert(format!("tokens.{}", key), "is not a theme token".into());
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(CustomTheme { name: name.to_string(), tokens })
+    } else {
+        Err(errors)
+    }
+}
+
+/// A theme selection from a request body: `{"themeId": ...}` or `{"custom": {...}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThemeChoice {
+    Builtin(&'static str),
+    Custom(CustomTheme),
+}
+
+impl ThemeChoice {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Self::Builtin(id) => builtin_theme(id).map(BuiltinTheme::to_json).unwrap_or(Value::Null),
+            Self::Custom(theme) => theme.to_json(),
+        }
+    }
+}
+
+/// Parse and validate a theme selection, with errors keyed by field path.
+pub fn parse_theme_choice(body: &Value) -> Result<ThemeChoice, BTreeMap<String, String>> {
+    let theme_id = body.get("themeId").filter(|v| !v.is_null());
+    let custom = body.get("custom").filter(|v| !v.is_null());
+    match (theme_id, custom) {
+        (Some(_), Some(_)) => Err(BTreeMap::from([(
+            "themeId".into(),
+            "send either themeId or custom, not both".into(),
+        )])),
+        (Some(id), None) => match id.as_str().and_then(builtin_theme) {
+            Some(theme) => Ok(ThemeChoice::Builtin(theme.id)),
+            None => Err(BTreeMap::from([("themeId".into(), "is not a built-in theme".into())])),
+        },
+        (None, Some(raw)) => validate_custom_theme(raw)
+            .map(ThemeChoice::Custom)
+            .map_err(|errors| errors.into_iter().map(|(k, v)| (format!("custom.{}", k), v)).collect()),
+        (None, None) => Err(BTreeMap::from([("themeId".into(), "themeId or custom is required".into())])),
+    }
+}
+
+/// The user's theme as `{id, name, custom, tokens}`, falling back to the default
+/// when nothing (or nothing valid any more) is stored.
+pub async fn resolved_user_theme(db: &PgPool, user_id: &str) -> Result<Value, sqlx::Error> {
+    let row = sqlx::query_as::<_, (Option<String>, Option<String>, Option<Value>)>(
+        "SELECT theme_id, custom_name, custom_tokens FROM user_themes WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    let stored = row.and_then(|(theme_id, custom_name, custom_tokens)| match (theme_id, custom_tokens) {
+        (Some(id), _) => builtin_theme(&id).map(BuiltinTheme::to_json),
+        (None, Some(tokens)) => validate_custom_theme(&json!({ "name": custom_name, "tokens": tokens }))
+            .ok()
+            .map(|t| t.to_json()),
+        (None, None) => None,
+    });
+    Ok(stored.unwrap_or_else(|| {
+        builtin_theme(DEFAULT_COLOR_THEME).map(BuiltinTheme::to_json).unwrap_or(Value::Null)
+    }))
+}
+
+/// Store the user's theme choice, replacing any previous one.
+pub async fn save_user_theme(db: &PgPool, user_id: &str, choice: &ThemeChoice) -> Result<(), sqlx::Error> {
+    let (theme_id, custom_name, custom_tokens) = match choice {
+        ThemeChoice::Builtin(id) => (Some(*id), None, None),
+        ThemeChoice::Custom(theme) => (None, Some(theme.name.as_str()), Some(json!(theme.tokens))),
+    };
+    sqlx::query(
+        r#"INSERT INTO user_themes (user_id, theme_id, custom_name, custom_tokens, updated_at)
+           VALUES ($1, $2, $3, $4, NOW())
+           ON CONFLICT (user_id) DO UPDATE
+           SET theme_id = EXCLUDED.theme_id, custom_name = EXCLUDED.custom_name,
+               custom_tokens = EXCLUDED.custom_tokens, updated_at = NOW()"#,
+    )
+    .bind(user_id)
+    .bind(theme_id)
+    .bind(custom_name)
+    .bind(custom_tokens)
+    .execute(db)
+    .await?;
+    Ok(())
+}
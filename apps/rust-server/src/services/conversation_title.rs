@@ -0,0 +1,149 @@
+//! Auto-generated titles for untitled direct conversations.
+//!
+//! After the first agent reply lands, `maybe_generate_title()` asks a small
+//! model to summarize the opening exchange, stores the result on
+//! `conversations.title` and broadcasts `conversation_renamed`. Titles the
+//! user set themselves are never overwritten.
+
+use sqlx::PgPool;
+
+use crate::config::Config;
+use crate::services::llm::{self, ChatMessage, LlmCallOptions, LlmProvider};
+use crate::ws::state::WsState;
+
+const TITLE_MODEL: &str = "gpt-4o-mini";
+pub const TITLE_MAX_CHARS: usize = 60;
+/// Characters of each message sent to the model.
+const EXCERPT_CHARS: usize = 1000;
+
+const TITLE_PROMPT: &str = "Write a short title (at most 6 words) for a chat that starts with the exchange below. \
+Reply with the title only: no quotes, no trailing punctuation.";
+
+/// Conversation types that get auto titles — groups and communities keep theirs.
+pub fn is_auto_title_type(conv_type: &str) -> bool {
+    matches!(conv_type, "direct" | "h2a" | "h2h")
+}
+
+/// Normalize a model reply into a title: first line, unquoted, no trailing
+/// punctuation, capped at `TITLE_MAX_CHARS`. `None` if nothing is left.
+pub fn clean_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.strip_prefix("Title:").unwrap_or(line).trim();
+    let line = line
+        .trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '“' | '”' | '*'))
+        .trim_end_matches(['.', '!', '?', ':', ';', ','])
+        .trim();
+    if line.is_empty() {
+        return None;
+    }
+    let title: String = line.chars().take(TITLE_MAX_CHARS).collect();
+    Some(title.trim_end().to_string())
+}
+
+fn excerpt(s: &str) -> String {
+    s.chars().take(EXCERPT_CHARS).collect()
+}
+
+/// Title `conversation_id` from its first exchange if it is an untitled
+/// direct conversation whose first agent reply just completed.
+pub async fn maybe_generate_title(
+    db: &PgPool,
+    ws_state: &WsState,
+    redis: &deadpool_redis::Pool,
+    config: &Config,
+    conversation_id: &str,
+    member_ids: &[String],
+) {
+    if !config.auto_title_conversations {
+        return;
+    }
+    let Some(api_key) = config.openai_api_key.clone() else {
+        return;
+    };
+
+    // Untitled, and exactly one completed agent reply so far
+    let candidate = sqlx::query_as::<_, (String, Option<String>, i64)>(
+        r#"SELECT c.type::text, c.title,
+                  (SELECT COUNT(*) FROM messages m
+                   WHERE m.conversation_id = c.id AND m.sender_agent_id IS NOT NULL
+                     AND m.status = 'completed')
+           FROM conversations c WHERE c.id = $1::uuid"#,
+    )
+    .bind(conversation_id)
+    .fetch_optional(db)
+    .await;
+    let Ok(Some((conv_type, title, agent_replies))) = candidate else {
+        return;
+    };
+    if !is_auto_title_type(&conv_type)
+        || title.is_some_and(|t| !t.trim().is_empty())
+        || agent_replies != 1
+    {
+        return;
+    }
+
+    let first = |role_filter: &'static str| {
+        let sql = format!(
+            r#"SELECT content FROM messages
+               WHERE conversation_id = $1::uuid AND {} AND content <> ''
+               ORDER BY seq ASC LIMIT 1"#,
+            role_filter
+        );
+        async move {
+            sqlx::query_scalar::<_, String>(&sql)
+                .bind(conversation_id)
+                .fetch_optional(db)
+                .await
+                .ok()
+                .flatten()
+        }
+    };
+    let (Some(user_msg), Some(agent_msg)) = (
+        first("sender_user_id IS NOT NULL AND sender_agent_id IS NULL").await,
+        first("sender_agent_id IS NOT NULL AND status = 'completed'").await,
+    ) else {
+        return;
+    };
+
+    let opts = LlmCallOptions {
+        provider: LlmProvider::OpenAI,
+        model: TITLE_MODEL.to_string(),
+        api_key,
+        messages: vec![
+            ChatMessage { role: "system".into(), content: TITLE_PROMPT.into() },
+            ChatMessage {
+                role: "user".into(),
+                content: format!("User: {}\n\nAssistant: {}", excerpt(&user_msg), excerpt(&agent_msg)),
+            },
+        ],
+        max_tokens: Some(24),
+        temperature: Some(0.3),
+    };
+    let new_title = match llm::call_llm_text(&opts).await {
+        Ok(raw) => match clean_title(&raw) {
+            Some(t) => t,
+            None => return,
+        },
+        Err(e) => {
+            tracing::warn!("Conversation title generation failed: conv={} err={}", conversation_id, e);
+            return;
+        }
+    };
+
+    // Re-check the title in the UPDATE so a rename during the LLM call wins
+    let updated = sqlx::query(
+        r#"UPDATE conversations SET title = $1, updated_at = NOW()
+           WHERE id = $2::uuid AND (title IS NULL OR btrim(title) = '')"#,
+    )
+    .bind(&new_title)
+    .bind(conversation_id)
+    .execute(db)
+    .await;
+    if matches!(updated, Ok(r) if r.rows_affected() > 0) {
+        ws_state.broadcast_to_members(member_ids, &serde_json::json!({
+            "type": "conversation_renamed",
+            "conversationId": conversation_id,
+            "title": &new_title,
+        }), redis);
+    }
+}
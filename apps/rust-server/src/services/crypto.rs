@@ -0,0 +1,26 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Hex-encoded HMAC-SHA256 of `payload` under `secret`.
+pub fn hmac_sha256_hex(secret: &str, payload: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(payload);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Verify a hex HMAC-SHA256 signature in constant time.
+/// Accepts an optional `sha256=` prefix, as sent by most payment providers.
+pub fn verify_hmac_sha256(secret: &str, payload: &[u8], signature: &str) -> bool {
+    let signature = signature.trim();
+    let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+    let Ok(expected) = hex::decode(signature) else {
+        return false;
+    };
+    let Ok(mut mac) = HmacSha256::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(payload);
+    mac.verify_slice(&expected).is_ok()
+}
@@ -19,6 +19,15 @@ use crate::config::Config;
 /// - `chunk_size`: target chunk size in **characters** (~2000 chars ≈ 500 tokens)
 /// - `overlap`: number of characters to overlap between consecutive chunks (~200)
 pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
+    chunk_spans(text, chunk_size, overlap)
+        .into_iter()
+        .map(|(start, end)| text[start..end].to_string())
+        .collect()
+}
+
+/// Byte ranges of the chunks `chunk_text` produces, so each chunk can be
+/// traced back to its position in the source.
+pub fn chunk_spans(text: &str, chunk_size: usize, overlap: usize) -> Vec<(usize, usize)> {
     if text.is_empty() {
         return vec![];
     }
@@ -28,7 +37,7 @@ pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String>
     let char_count = char_offsets.len();
 
     if char_count <= chunk_size {
-        return vec![text.to_string()];
+        return vec![(0, text.len())];
     }
 
     // Helper: convert char index to byte offset (clamped to text.len())
@@ -55,9 +64,11 @@ pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String>
             end_byte
         };
 
-        let chunk = text[start_byte..actual_end_byte].trim();
-        if !chunk.is_empty() {
-            chunks.push(chunk.to_string());
+        let raw = &text[start_byte..actual_end_byte];
+        let trimmed = raw.trim();
+        if !trimmed.is_empty() {
+            let lead = raw.len() - raw.trim_start().len();
+            chunks.push((start_byte + lead, start_byte + lead + trimmed.len()));
         }
 
         // Convert actual_end_byte back to a char index for advancing
@@ -190,54 +201,63 @@ pub const EMBEDDING_MODEL: &str = "text-embedding-3-small";
 const BATCH_SIZE: usize = 100;
 
 /// Process a knowledge base record: chunk → embed → store in knowledge_base_chunks.
-/// Returns the total number of chunks created.
+/// Without an OpenAI key the chunks are stored unembedded, so only keyword
+/// search can find them. Returns the total number of chunks created.
 pub async fn process_embedding(
     db: PgPool,
     config: Config,
     kb_id: Uuid,
     raw_content: &str,
 ) -> anyhow::Result<usize> {
-    let api_key = config
-        .openai_api_key
-        .as_deref()
-        .ok_or_else(|| anyhow!("OPENAI_API_KEY not configured"))?;
-
-    // 1. Chunk the text
-    let chunks = chunk_text(raw_content, CHUNK_SIZE, CHUNK_OVERLAP);
-    if chunks.is_empty() {
+    // 1. Chunk the text, keeping each chunk's source offsets
+    let spans = chunk_spans(raw_content, CHUNK_SIZE, CHUNK_OVERLAP);
+    if spans.is_empty() {
         return Ok(0);
     }
-
-    let client = Client::builder()
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .timeout(std::time::Duration::from_secs(60))
-        .build()
-        .context("Failed to build HTTP client")?;
-    let mut all_embeddings: Vec<Vec<f32>> = Vec::with_capacity(chunks.len());
+    let chunks: Vec<String> = spans
+        .iter()
+        .map(|&(start, end)| raw_content[start..end].to_string())
+        .collect();
+    let char_starts: Vec<usize> = raw_content.char_indices().map(|(i, _)| i).collect();
+    let char_offset = |byte: usize| char_starts.partition_point(|&b| b < byte) as i32;
 
     // 2. Generate embeddings in batches
-    for batch in chunks.chunks(BATCH_SIZE) {
-        let batch_vec: Vec<String> = batch.to_vec();
-        let embeddings = generate_embeddings(&client, api_key, &batch_vec, EMBEDDING_MODEL).await?;
-        all_embeddings.extend(embeddings);
+    let mut all_embeddings: Vec<Option<Vector>> = Vec::with_capacity(chunks.len());
+    if let Some(api_key) = config.openai_api_key.as_deref() {
+        let client = Client::builder()
+            .connect_timeout(std::time::Duration::from_secs(10))
+            .timeout(std::time::Duration::from_secs(60))
+            .build()
+            .context("Failed to build HTTP client")?;
+        for batch in chunks.chunks(BATCH_SIZE) {
+            let batch_vec: Vec<String> = batch.to_vec();
+            let embeddings = generate_embeddings(&client, api_key, &batch_vec, EMBEDDING_MODEL).await?;
+            all_embeddings.extend(embeddings.into_iter().map(|e| Some(Vector::from(e))));
+        }
+    } else {
+        all_embeddings.resize(chunks.len(), None);
     }
 
     // 3. Insert chunks + embeddings into DB in a transaction
     let mut tx = db.begin().await.context("Failed to begin transaction")?;
 
-    for (i, (chunk, embedding)) in chunks.iter().zip(all_embeddings.iter()).enumerate() {
+    for (i, ((chunk, embedding), &(start, end))) in
+        chunks.iter().zip(all_embeddings).zip(spans.iter()).enumerate()
+    {
         let token_estimate = (chunk.len() / 4) as i32; // rough: 1 token ≈ 4 chars
-        let vec = Vector::from(embedding.clone());
 
         sqlx::query(
-            r#"INSERT INTO knowledge_base_chunks (kb_id, content, chunk_index, token_count, embedding)
-               VALUES ($1, $2, $3, $4, $5::vector)"#,
+            r#"INSERT INTO knowledge_base_chunks
+               (kb_id, content, chunk_index, token_count, embedding, start_offset, end_offset)
+               VALUES ($1, $2, $3, $4, $5::vector, $6, $7)"#,
         )
         .bind(kb_id)
         .bind(chunk)
         .bind(i as i32)
         .bind(token_estimate)
-        .bind(vec)
+        .bind(embedding)
+        .bind(char_offset(start))
+        .bind(char_offset(end))
         .execute(&mut *tx)
         .await
         .context("Failed to insert chunk")?;
@@ -248,6 +268,54 @@ pub async fn process_embedding(
     Ok(chunks.len())
 }
 
+/// Most chunks embedded per backfill run, to bound API spend.
+pub const BACKFILL_MAX_CHUNKS: i64 = 2000;
+
+/// Embed stored chunks that have no vector yet — those saved while no OpenAI
+/// key was configured, or before a failed batch. Returns how many were filled.
+pub async fn backfill_missing_embeddings(db: &PgPool, config: &Config) -> anyhow::Result<usize> {
+    let Some(api_key) = config.openai_api_key.as_deref() else {
+        return Ok(0);
+    };
+
+    let rows = sqlx::query_as::<_, (Uuid, String)>(
+        r#"SELECT id, content FROM knowledge_base_chunks
+           WHERE embedding IS NULL
+           ORDER BY created_at
+           LIMIT $1"#,
+    )
+    .bind(BACKFILL_MAX_CHUNKS)
+    .fetch_all(db)
+    .await
+    .context("Failed to load chunks missing embeddings")?;
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let client = Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .timeout(std::time::Duration::from_secs(60))
+        .build()
+        .context("Failed to build HTTP client")?;
+
+    let mut filled = 0;
+    for batch in rows.chunks(BATCH_SIZE) {
+        let texts: Vec<String> = batch.iter().map(|(_, content)| content.clone()).collect();
+        let embeddings = generate_embeddings(&client, api_key, &texts, EMBEDDING_MODEL).await?;
+        for ((id, _), embedding) in batch.iter().zip(embeddings) {
+            sqlx::query("UPDATE knowledge_base_chunks SET embedding = $2::vector WHERE id = $1")
+                .bind(id)
+                .bind(Vector::from(embedding))
+                .execute(db)
+                .await
+                .context("Failed to store backfilled embedding")?;
+            filled += 1;
+        }
+    }
+
+    Ok(filled)
+}
+
 // ---------------------------------------------------------------------------
 // RAG similarity search
 // ---------------------------------------------------------------------------
@@ -282,7 +350,7 @@ pub async fn rag_search(
         r#"SELECT c.content
            FROM knowledge_base_chunks c
            JOIN agent_knowledge_bases kb ON c.kb_id = kb.id
-           WHERE kb.listing_id = $1 AND kb.status = 'ready'
+           WHERE kb.listing_id = $1 AND kb.status = 'ready' AND c.embedding IS NOT NULL
            ORDER BY c.embedding <=> $2::vector
            LIMIT $3"#,
     )
@@ -295,3 +363,280 @@ pub async fn rag_search(
 
     Ok(rows.into_iter().map(|(content,)| content).collect())
 }
+
+/// A knowledge base chunk matched by `search_chunks`.
+pub struct ChunkHit {
+    pub kb_id: Uuid,
+    pub file_name: String,
+    pub chunk_index: i32,
+    pub content: String,
+    /// Character offsets of the chunk in the source file (`None` for chunks
+    /// stored before offsets were recorded).
+    pub start_offset: Option<i32>,
+    pub end_offset: Option<i32>,
+    /// Cosine similarity in embedding mode, fraction of query terms matched
+    /// in keyword mode.
+    pub score: f64,
+}
+
+/// Distinct lowercase query terms used for keyword ranking.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for term in query
+        .split(|c: char| !c.is_alphanumeric())
+        .map(|t| t.to_lowercase())
+        .filter(|t| t.chars().count() >= 2)
+    {
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+type HitRow = (Uuid, String, i32, String, Option<i32>, Option<i32>, f64);
+
+fn to_hit((kb_id, file_name, chunk_index, content, start_offset, end_offset, score): HitRow) -> ChunkHit {
+    ChunkHit {
+        kb_id,
+        file_name,
+        chunk_index,
+        content,
+        start_offset,
+        end_offset,
+        score,
+    }
+}
+
+/// Rank a listing's ready chunks against `query`. Uses embedding similarity
+/// when an OpenAI key is configured and embedded chunks exist; otherwise
+/// falls back to keyword overlap. Returns the mode used with the hits.
+pub async fn search_chunks(
+    db: &PgPool,
+    config: &Config,
+    listing_id: Uuid,
+    query: &str,
+    top_k: i32,
+) -> anyhow::Result<(&'static str, Vec<ChunkHit>)> {
+    if let Some(api_key) = config.openai_api_key.as_deref() {
+        let has_embeddings = sqlx::query_scalar::<_, bool>(
+            r#"SELECT EXISTS(
+                   SELECT 1 FROM knowledge_base_chunks c
+                   JOIN agent_knowledge_bases kb ON c.kb_id = kb.id
+                   WHERE kb.listing_id = $1 AND kb.status = 'ready' AND c.embedding IS NOT NULL
+               )"#,
+        )
+        .bind(listing_id)
+        .fetch_one(db)
+        .await
+        .context("Failed to check for embedded chunks")?;
+
+        if has_embeddings {
+            let client = Client::builder()
+                .connect_timeout(std::time::Duration::from_secs(10))
+                .timeout(std::time::Duration::from_secs(30))
+                .build()
+                .context("Failed to build HTTP client")?;
+            let query_embedding = generate_embeddings(&client, api_key, &[query.to_string()], EMBEDDING_MODEL)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No embedding returned for query"))?;
+
+            let rows = sqlx::query_as::<_, HitRow>(
+                r#"SELECT kb.id, kb.file_name, c.chunk_index, c.content, c.start_offset, c.end_offset,
+                          1 - (c.embedding <=> $2::vector) AS score
+                   FROM knowledge_base_chunks c
+                   JOIN agent_knowledge_bases kb ON c.kb_id = kb.id
+                   WHERE kb.listing_id = $1 AND kb.status = 'ready' AND c.embedding IS NOT NULL
+                   ORDER BY c.embedding <=> $2::vector
+                   LIMIT $3"#,
+            )
+            .bind(listing_id)
+            .bind(Vector::from(query_embedding))
+            .bind(top_k)
+            .fetch_all(db)
+            .await
+            .context("KB similarity search failed")?;
+
+            return Ok(("embedding", rows.into_iter().map(to_hit).collect()));
+        }
+    }
+
+    Ok(("keyword", keyword_search_chunks(db, listing_id, query, top_k).await?))
+}
+
+/// Keyword fallback for `search_chunks`: the `top_k` ready chunks matching the
+/// largest fraction of query terms (case-insensitive). Scoring, ordering and
+/// the limit all happen in SQL, so only the returned chunks are loaded.
+pub async fn keyword_search_chunks(
+    db: &PgPool,
+    listing_id: Uuid,
+    query: &str,
+    top_k: i32,
+) -> anyhow::Result<Vec<ChunkHit>> {
+    let terms = query_terms(query);
+    if terms.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let rows = sqlx::query_as::<_, HitRow>(
+        r#"SELECT * FROM (
+               SELECT kb.id, kb.file_name, c.chunk_index, c.content, c.start_offset, c.end_offset,
+                      (SELECT COUNT(*) FROM unnest($2::text[]) t WHERE strpos(lower(c.content), t) > 0)::float8
+                          / cardinality($2::text[]) AS score
+               FROM knowledge_base_chunks c
+               JOIN agent_knowledge_bases kb ON c.kb_id = kb.id
+               WHERE kb.listing_id = $1 AND kb.status = 'ready'
+           ) scored
+           WHERE score > 0
+           ORDER BY score DESC, chunk_index, id
+           LIMIT $3"#,
+    )
+    .bind(listing_id)
+    .bind(&terms)
+    .bind(top_k)
+    .fetch_all(db)
+    .await
+    .context("KB keyword search failed")?;
+
+    Ok(rows.into_iter().map(to_hit).collect())
+}
+
+/// Token budget for knowledge base context injected into one chat turn.
+pub const KB_CONTEXT_TOKEN_BUDGET: usize = 1500;
+/// Chunks retrieved before the token budget is applied.
+const KB_CONTEXT_TOP_K: i32 = 5;
+
+/// Keep chunks in rank order until `max_tokens` (≈ 4 chars per token) is used.
+pub fn fit_token_budget(chunks: Vec<String>, max_tokens: usize) -> Vec<String> {
+    let mut remaining = max_tokens;
+    let mut kept = Vec::new();
+    for chunk in chunks {
+        let tokens = chunk.len().div_ceil(4);
+        if tokens > remaining {
+            break;
+        }
+        remaining -= tokens;
+        kept.push(chunk);
+    }
+    kept
+}
+
+/// Chunks from `listing_id`'s knowledge base relevant to `query`, within
+/// `KB_CONTEXT_TOKEN_BUDGET`. Failures are logged and yield no context.
+pub async fn kb_context_chunks(db: &PgPool, config: &Config, listing_id: Uuid, query: &str) -> Vec<String> {
+    match search_chunks(db, config, listing_id, query, KB_CONTEXT_TOP_K).await {
+        Ok((_, hits)) => fit_token_budget(
+            hits.into_iter().map(|h| h.content).collect(),
+            KB_CONTEXT_TOKEN_BUDGET,
+        ),
+        Err(e) => {
+            tracing::warn!("KB context search failed for listing {}: {:?}", listing_id, e);
+            Vec::new()
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// First-turn knowledge base overview
+// ---------------------------------------------------------------------------
+
+/// Characters of each file's opening chunk included in the overview.
+const OVERVIEW_EXCERPT_CHARS: usize = 400;
+/// Upper bound on the whole overview so it can't crowd out the system prompt.
+const OVERVIEW_MAX_CHARS: usize = 4000;
+
+/// Build a high-level overview from `(file_name, first_chunk)` pairs:
+/// one bullet per file with a short excerpt of its opening text.
+pub fn build_kb_overview(files: &[(String, String)]) -> String {
+    let mut overview = String::from("Knowledge base overview:");
+    for (file_name, first_chunk) in files {
+        let excerpt: String = first_chunk
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(OVERVIEW_EXCERPT_CHARS)
+            .collect();
+        let entry = format!("\n- {}: {}", file_name, excerpt);
+        if overview.chars().count() + entry.chars().count() > OVERVIEW_MAX_CHARS {
+            break;
+        }
+        overview.push_str(&entry);
+    }
+    overview
+}
+
+/// Compose the system prompt for a marketplace chat turn. The KB overview
+/// (first turn only) and retrieved chunks are appended after the listing prompt.
+pub fn compose_system_prompt(base: &str, overview: Option<&str>, rag_chunks: &[String]) -> String {
+    let mut prompt = base.to_string();
+    if let Some(overview) = overview {
+        prompt.push_str("\n\n---\n");
+        prompt.push_str(overview);
+    }
+    if !rag_chunks.is_empty() {
+        prompt.push_str("\n\n---\nBelow is relevant context from the knowledge base:\n\n");
+        prompt.push_str(&rag_chunks.join("\n\n"));
+    }
+    prompt
+}
+
+/// Return the KB overview for a listing, or `None` if the listing hasn't
+/// enabled it or has no ready files. The overview is cached on the listing
+/// and cleared whenever its knowledge base changes.
+pub async fn kb_overview(db: &PgPool, listing_id: Uuid) -> anyhow::Result<Option<String>> {
+    let row = sqlx::query_as::<_, (bool, Option<String>)>(
+        "SELECT kb_overview_enabled, kb_overview FROM agent_listings WHERE id = $1",
+    )
+    .bind(listing_id)
+    .fetch_optional(db)
+    .await
+    .context("Failed to load KB overview settings")?;
+
+    let cached = match row {
+        Some((true, cached)) => cached,
+        _ => return Ok(None),
+    };
+    if cached.is_some() {
+        return Ok(cached);
+    }
+
+    let files = sqlx::query_as::<_, (String, String)>(
+        r#"SELECT kb.file_name, c.content
+           FROM agent_knowledge_bases kb
+           JOIN knowledge_base_chunks c ON c.kb_id = kb.id AND c.chunk_index = 0
+           WHERE kb.listing_id = $1 AND kb.status = 'ready'
+           ORDER BY kb.created_at ASC"#,
+    )
+    .bind(listing_id)
+    .fetch_all(db)
+    .await
+    .context("Failed to load KB files for overview")?;
+
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let overview = build_kb_overview(&files);
+    sqlx::query("UPDATE agent_listings SET kb_overview = $2 WHERE id = $1")
+        .bind(listing_id)
+        .bind(&overview)
+        .execute(db)
+        .await
+        .context("Failed to cache KB overview")?;
+
+    Ok(Some(overview))
+}
+
+/// Drop a listing's cached KB overview so it is rebuilt on next use.
+pub async fn invalidate_kb_overview(db: &PgPool, listing_id: Uuid) {
+    if let Err(e) = sqlx::query("UPDATE agent_listings SET kb_overview = NULL WHERE id = $1")
+        .bind(listing_id)
+        .execute(db)
+        .await
+    {
+        tracing::warn!("Invalidate KB overview for listing {} failed: {}", listing_id, e);
+    }
+}
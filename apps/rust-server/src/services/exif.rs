@@ -0,0 +1,57 @@
+//! Removes EXIF metadata (GPS position, camera details) from uploaded photos.
+//!
+//! JPEG and PNG files carrying EXIF are decoded, rotated upright according to
+//! their EXIF orientation, and re-encoded without it. The ICC colour profile is
+//! kept so colours don't shift. Everything else is left byte-for-byte intact.
+
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageFormat, ImageReader};
+
+const JPEG_QUALITY: u8 = 92;
+
+/// Re-encode `data` without EXIF. Returns `None` when the file should be
+/// stored unchanged: not a JPEG/PNG, no EXIF present, or not decodable.
+///
+/// Decoding is CPU-bound; call from `spawn_blocking`.
+pub fn strip_exif(data: &[u8]) -> Option<Vec<u8>> {
+    let reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?;
+    let format = reader.format()?;
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png) {
+        return None;
+    }
+
+    let mut decoder = reader.into_decoder().ok()?;
+    decoder.exif_metadata().ok()??;
+    let orientation = decoder.orientation().ok()?;
+    let icc_profile = decoder.icc_profile().ok().flatten();
+
+    let mut img = DynamicImage::from_decoder(decoder).ok()?;
+    img.apply_orientation(orientation);
+
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            let mut encoder = JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY);
+            if let Some(icc) = icc_profile {
+                let _ = encoder.set_icc_profile(icc);
+            }
+            // JPEG has no alpha channel
+            DynamicImage::ImageRgb8(img.to_rgb8())
+                .write_with_encoder(encoder)
+                .ok()?;
+        }
+        _ => {
+            let mut encoder = PngEncoder::new(&mut out);
+            if let Some(icc) = icc_profile {
+                let _ = encoder.set_icc_profile(icc);
+            }
+            img.write_with_encoder(encoder).ok()?;
+        }
+    }
+    Some(out)
+}
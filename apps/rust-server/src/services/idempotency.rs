@@ -0,0 +1,101 @@
+use axum::http::{HeaderMap, StatusCode};
+use deadpool_redis::redis::AsyncCommands;
+use deadpool_redis::Pool;
+use serde_json::{json, Value};
+
+const KEY_PREFIX: &str = "idempotency:";
+const HEADER: &str = "idempotency-key";
+const MAX_KEY_LEN: usize = 128;
+const PENDING: &str = "pending";
+const PENDING_TTL_SECONDS: u64 = 60;
+const RESULT_TTL_SECONDS: u64 = 600; // 10 minutes
+
+/// Outcome of claiming an idempotency key.
+pub enum Claim {
+    /// First time this key is seen — run the request, then `complete` or `release`.
+    Acquired,
+    /// A previous request with this key finished; return its cached response.
+    Replay(StatusCode, Value),
+    /// A request with this key is still running.
+    InProgress,
+}
+
+/// Read the `Idempotency-Key` header, ignoring empty or oversized values.
+pub fn key_from_headers(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_KEY_LEN)
+        .map(String::from)
+}
+
+fn redis_key(scope: &str, user_id: &str, key: &str) -> String {
+    format!("{}{}:{}:{}", KEY_PREFIX, scope, user_id, key)
+}
+
+/// Atomically claim `key` for this user and scope (SET NX).
+/// If Redis is unavailable the request proceeds without idempotency protection.
+pub async fn claim(redis: &Pool, scope: &str, user_id: &str, key: &str) -> Claim {
+    let Ok(mut conn) = redis.get().await else {
+        return Claim::Acquired;
+    };
+    let k = redis_key(scope, user_id, key);
+
+    let acquired: Option<String> = deadpool_redis::redis::cmd("SET")
+        .arg(&k)
+        .arg(PENDING)
+        .arg("NX")
+        .arg("EX")
+        .arg(PENDING_TTL_SECONDS)
+        .query_async(&mut *conn)
+        .await
+        .unwrap_or(Some("OK".into()));
+    if acquired.is_some() {
+        return Claim::Acquired;
+    }
+
+    match conn.get::<_, Option<String>>(&k).await {
+        Ok(Some(v)) if v != PENDING => {
+            let cached: Value = serde_json::from_str(&v).unwrap_or(Value::Null);
+            let status = cached
+                .get("status")
+                .and_then(|s| s.as_u64())
+                .and_then(|s| StatusCode::from_u16(s as u16).ok())
+                .unwrap_or(StatusCode::OK);
+            Claim::Replay(status, cached.get("body").cloned().unwrap_or(Value::Null))
+        }
+        Ok(Some(_)) => Claim::InProgress,
+        // Key expired between SET NX and GET — treat as fresh
+        _ => Claim::Acquired,
+    }
+}
+
+/// Cache the response for a claimed key so retries replay it.
+pub async fn complete(
+    redis: &Pool,
+    scope: &str,
+    user_id: &str,
+    key: &str,
+    status: StatusCode,
+    body: &Value,
+) {
+    if let Ok(mut conn) = redis.get().await {
+        let cached = json!({ "status": status.as_u16(), "body": body }).to_string();
+        let _: Result<(), _> = conn
+            .set_ex(redis_key(scope, user_id, key), cached, RESULT_TTL_SECONDS)
+            .await;
+    }
+}
+
+/// Drop a claimed key after a failure so the client can retry.
+pub async fn release(redis: &Pool, scope: &str, user_id: &str, key: &str) {
+    if let Ok(mut conn) = redis.get().await {
+        let _: Result<(), _> = conn.del(redis_key(scope, user_id, key)).await;
+    }
+}
+
+/// Response for a duplicate request whose original is still running.
+pub fn in_progress_body() -> Value {
+    json!({ "error": "A request with this Idempotency-Key is already in progress" })
+}
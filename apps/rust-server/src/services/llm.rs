@@ -3,6 +3,9 @@
 //! Provides:
 //! - `validate_api_key()` — quick HEAD/GET check per provider
 //! - `call_llm_stream()` — SSE streaming chat completion
+//! - `call_llm_text()` — the same call, collected into one string
+//! - `supported_models()` — model allowlist for marketplace listings
+//! - `parse_chunk()` — provider-agnostic SSE text delta extraction
 
 use bytes::Bytes;
 use futures::stream::Stream;
@@ -51,6 +54,109 @@ impl LlmProvider {
 /// A boxed byte-stream that yields SSE chunks.
 pub type SseStream = Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
 
+// ---------------------------------------------------------------------------
+// Model allowlist
+// ---------------------------------------------------------------------------
+
+/// Curated OpenRouter model IDs accepted for marketplace listings, grouped by
+/// provider. Extend at runtime with `EXTRA_LLM_MODELS` (see `Config`).
+const SUPPORTED_MODELS: &[(&str, &[&str])] = &[
+    ("openai", &["gpt-4o", "gpt-4o-mini", "gpt-4.1", "gpt-4.1-mini", "o3-mini"]),
+    ("anthropic", &["claude-3.5-sonnet", "claude-3.5-haiku", "claude-3.7-sonnet"]),
+    ("google", &["gemini-2.0-flash-001", "gemini-2.5-pro"]),
+    ("meta-llama", &["llama-3.1-70b-instruct", "llama-3.3-70b-instruct"]),
+    ("mistralai", &["mistral-large", "mistral-small"]),
+    ("deepseek", &["deepseek-chat", "deepseek-r1"]),
+];
+
+/// All supported `provider/model` IDs: the built-in list plus `extra`.
+pub fn supported_models(extra: &[String]) -> Vec<String> {
+    let mut models: Vec<String> = SUPPORTED_MODELS
+        .iter()
+        .flat_map(|(provider, names)| names.iter().map(move |n| format!("{}/{}", provider, n)))
+        .collect();
+    for m in extra {
+        if !models.contains(m) {
+            models.push(m.clone());
+        }
+    }
+    models
+}
+
+/// Check a listing's model ID against the allowlist. On failure returns a
+/// user-facing message naming the valid options.
+pub fn validate_model(model: &str, extra: &[String]) -> Result<(), String> {
+    let models = supported_models(extra);
+    if models.iter().any(|m| m == model) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unsupported model '{}'. Supported models: {}",
+            model,
+            models.join(", ")
+        ))
+    }
+}
+
+/// Most fallback models a listing may configure.
+pub const MAX_FALLBACK_MODELS: usize = 3;
+
+/// Validate and normalize a listing's fallback chain: each entry must be a
+/// supported model other than `primary`. Duplicates are dropped, order is kept.
+pub fn validate_fallback_models(
+    primary: &str,
+    fallbacks: &[String],
+    extra: &[String],
+) -> Result<Vec<String>, String> {
+    let mut chain: Vec<String> = Vec::new();
+    for model in fallbacks.iter().map(|m| m.trim()) {
+        if model.is_empty() || model == primary || chain.iter().any(|m| m == model) {
+            continue;
+        }
+        validate_model(model, extra)?;
+        chain.push(model.to_string());
+    }
+    if chain.len() > MAX_FALLBACK_MODELS {
+        return Err(format!("At most {} fallback models allowed", MAX_FALLBACK_MODELS));
+    }
+    Ok(chain)
+}
+
+/// Upstream used to serve a marketplace listing (`agent_listings.model_provider`).
+pub const LISTING_PROVIDERS: &[&str] = &["openrouter", "anthropic"];
+
+/// Check a listing's `model_provider` is known and can serve `model`.
+/// Direct Anthropic streaming only works for `anthropic/*` models.
+pub fn validate_model_provider(provider: &str, model: &str) -> Result<(), String> {
+    if !LISTING_PROVIDERS.contains(&provider) {
+        return Err(format!(
+            "Unsupported modelProvider '{}'. Must be one of: {}",
+            provider,
+            LISTING_PROVIDERS.join(", ")
+        ));
+    }
+    if provider == "anthropic" && !model.starts_with("anthropic/") {
+        return Err("modelProvider 'anthropic' requires an anthropic/* model".into());
+    }
+    Ok(())
+}
+
+/// Map an OpenRouter `anthropic/*` model ID to the Anthropic API model name,
+/// e.g. "anthropic/claude-3.5-sonnet" → "claude-3-5-sonnet-latest".
+/// Dated IDs ("claude-3-5-haiku-20241022") are passed through unchanged.
+pub fn anthropic_model_id(model: &str) -> String {
+    let name = model.strip_prefix("anthropic/").unwrap_or(model).replace('.', "-");
+    let dated = name
+        .rsplit('-')
+        .next()
+        .is_some_and(|s| s.len() == 8 && s.chars().all(|c| c.is_ascii_digit()));
+    if dated || name.ends_with("-latest") {
+        name
+    } else {
+        format!("{}-latest", name)
+    }
+}
+
 // ---------------------------------------------------------------------------
 // validate_api_key
 // ---------------------------------------------------------------------------
@@ -121,10 +227,35 @@ pub async fn call_llm_stream(opts: &LlmCallOptions) -> Result<SseStream, String>
         .build()
         .map_err(|e| format!("HTTP client error: {e}"))?;
 
-    match opts.provider {
-        LlmProvider::OpenAI => call_openai_stream(&client, opts).await,
-        LlmProvider::Anthropic => call_anthropic_stream(&client, opts).await,
+    let started = std::time::Instant::now();
+    let (provider, result) = match opts.provider {
+        LlmProvider::OpenAI => ("openai", call_openai_stream(&client, opts).await),
+        LlmProvider::Anthropic => ("anthropic", call_anthropic_stream(&client, opts).await),
+    };
+    crate::services::metrics::record_llm_call(provider, started, result.is_ok());
+    result
+}
+
+/// Run a streaming LLM call to completion and return the concatenated text.
+/// For short background tasks (titles, summaries) that don't forward chunks.
+pub async fn call_llm_text(opts: &LlmCallOptions) -> Result<String, String> {
+    use futures::StreamExt;
+
+    let mut stream = call_llm_stream(opts).await?;
+    let mut buffer = String::new();
+    let mut text = String::new();
+    while let Some(chunk) = stream.next().await {
+        let bytes = chunk.map_err(|e| format!("LLM stream error: {e}"))?;
+        buffer.push_str(&String::from_utf8_lossy(&bytes));
+        while let Some(pos) = buffer.find('\n') {
+            let line = buffer[..pos].trim_end_matches('\r').to_string();
+            buffer = buffer[pos + 1..].to_string();
+            if let Some(delta) = line.strip_prefix("data: ").and_then(|d| parse_chunk(&opts.provider, d)) {
+                text.push_str(&delta);
+            }
+        }
     }
+    Ok(text)
 }
 
 // ---------------------------------------------------------------------------
@@ -222,6 +353,14 @@ async fn call_anthropic_stream(
 // SSE parsing helpers
 // ---------------------------------------------------------------------------
 
+/// Extract the text delta from an SSE `data:` line in `provider`'s format.
+pub fn parse_chunk(provider: &LlmProvider, data: &str) -> Option<String> {
+    match provider {
+        LlmProvider::OpenAI => parse_openai_chunk(data),
+        LlmProvider::Anthropic => parse_anthropic_chunk(data),
+    }
+}
+
 /// Extract the text delta from an OpenAI SSE `data:` line.
 /// Returns `None` for `[DONE]` or non-content chunks.
 pub fn parse_openai_chunk(data: &str) -> Option<String> {
@@ -1,4 +1,5 @@
-//! Resolve @name patterns in message content to agent IDs.
+//! Resolve @name patterns in message content to agent and user IDs, and
+//! record them in `message_mentions`.
 
 use sqlx::PgPool;
 
@@ -18,12 +19,7 @@ pub async fn resolve_mentions_from_content(
     content: &str,
     exclude_agent_id: Option<&str>,
 ) -> Vec<String> {
-    // Extract all @word patterns (\w covers [a-zA-Z0-9_])
-    let re = regex_lite::Regex::new(r"@(\w+)").unwrap();
-    let at_words: Vec<String> = re
-        .captures_iter(content)
-        .filter_map(|cap| cap.get(1).map(|m| m.as_str().to_string()))
-        .collect();
+    let at_words = extract_at_words(content);
 
     if at_words.is_empty() {
         return vec![];
@@ -62,3 +58,108 @@ pub async fn resolve_mentions_from_content(
 
     resolved
 }
+
+/// Distinct lowercase words following `@` in `content` (`\w` covers [a-zA-Z0-9_]).
+pub fn extract_at_words(content: &str) -> Vec<String> {
+    let re = regex_lite::Regex::new(r"@(\w+)").unwrap();
+    let mut words: Vec<String> = Vec::new();
+    for cap in re.captures_iter(content) {
+        if let Some(m) = cap.get(1) {
+            let word = m.as_str().to_lowercase();
+            if !words.contains(&word) {
+                words.push(word);
+            }
+        }
+    }
+    words
+}
+
+/// Resolve `@username` patterns to IDs of users in the conversation
+/// (owner or member), excluding `exclude_user_id`.
+pub async fn resolve_user_mentions(
+    db: &PgPool,
+    conversation_id: &str,
+    content: &str,
+    exclude_user_id: Option<&str>,
+) -> Vec<String> {
+    let at_words = extract_at_words(content);
+    if at_words.is_empty() {
+        return vec![];
+    }
+
+    sqlx::query_scalar::<_, String>(
+        r#"SELECT u.id FROM "user" u
+           WHERE LOWER(u.username) = ANY($2)
+             AND u.id IS DISTINCT FROM $3
+             AND (u.id IN (SELECT user_id FROM conversation_user_members WHERE conversation_id = $1::uuid)
+                  OR u.id = (SELECT user_id FROM conversations WHERE id = $1::uuid))"#,
+    )
+    .bind(conversation_id)
+    .bind(&at_words)
+    .bind(exclude_user_id)
+    .fetch_all(db)
+    .await
+    .unwrap_or_default()
+}
+
+/// Store who `message_id` mentions: users resolved from `content` plus the
+/// given agent IDs (non-UUID markers such as `__all__` are skipped).
+pub async fn record_message_mentions(
+    db: &PgPool,
+    message_id: &str,
+    conversation_id: &str,
+    content: &str,
+    sender_user_id: Option<&str>,
+    agent_ids: &[String],
+) {
+    let user_ids = resolve_user_mentions(db, conversation_id, content, sender_user_id).await;
+    let mut agent_uuids: Vec<uuid::Uuid> = agent_ids
+        .iter()
+        .filter_map(|id| uuid::Uuid::parse_str(id).ok())
+        .collect();
+    agent_uuids.dedup();
+    if user_ids.is_empty() && agent_uuids.is_empty() {
+        return;
+    }
+
+    let result = sqlx::query(
+        r#"INSERT INTO message_mentions (message_id, conversation_id, mentioned_user_id, mentioned_agent_id)
+           SELECT $1::uuid, $2::uuid, u, NULL FROM UNNEST($3::text[]) AS u
+           UNION ALL
+           SELECT $1::uuid, $2::uuid, NULL, a FROM UNNEST($4::uuid[]) AS a"#,
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .bind(&user_ids)
+    .bind(&agent_uuids)
+    .execute(db)
+    .await;
+    if let Err(e) = result {
+        tracing::warn!("Record mentions for message {} failed: {}", message_id, e);
+    }
+}
+
+/// Unread messages from others that mention `user_id`, per conversation, read
+/// from `message_mentions` (conversations without any are absent).
+pub async fn unread_mention_counts(
+    db: &PgPool,
+    user_id: &str,
+    conversation_ids: &[String],
+) -> Result<std::collections::HashMap<String, i64>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, i64)>(
+        r#"SELECT m.conversation_id::text, COUNT(DISTINCT m.id)
+           FROM message_mentions mm
+           JOIN messages m ON m.id = mm.message_id
+           LEFT JOIN conversation_reads r ON r.conversation_id = m.conversation_id AND r.user_id = $1
+           WHERE mm.mentioned_user_id = $1
+             AND mm.conversation_id = ANY($2::text[]::uuid[])
+             AND m.seq > COALESCE(r.last_read_seq, 0)
+             AND m.sender_user_id IS DISTINCT FROM $1
+           GROUP BY m.conversation_id"#,
+    )
+    .bind(user_id)
+    .bind(conversation_ids)
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
@@ -1,16 +1,26 @@
-/// Get the next sequence number for a conversation.
-/// Uses MAX(seq) + 1 to ensure monotonically increasing per-conversation.
-/// Accepts any sqlx executor (pool or transaction).
+/// Allocate the next sequence number for a conversation.
+///
+/// Bumps the `conversations.next_seq` counter in a single `UPDATE ... RETURNING`,
+/// so concurrent senders serialize on the conversation row and never get the same
+/// seq. The counter is raised to at least `MAX(seq)` first, which heals any rows
+/// written without going through this function.
+///
+/// Accepts any sqlx executor (pool or transaction). Inside a transaction the
+/// conversation row stays locked until commit, and a rollback returns the seq.
 pub async fn get_next_seq<'e, E>(executor: E, conversation_id: &str) -> Result<i32, sqlx::Error>
 where
     E: sqlx::Executor<'e, Database = sqlx::Postgres>,
 {
-    let row = sqlx::query_as::<_, (Option<i32>,)>(
-        r#"SELECT MAX(seq) FROM messages WHERE conversation_id = $1::uuid"#,
+    sqlx::query_scalar::<_, i32>(
+        r#"UPDATE conversations
+           SET next_seq = GREATEST(
+               next_seq,
+               (SELECT COALESCE(MAX(seq), 0) FROM messages WHERE conversation_id = $1::uuid)
+           ) + 1
+           WHERE id = $1::uuid
+           RETURNING next_seq"#,
     )
     .bind(conversation_id)
     .fetch_one(executor)
-    .await?;
-
-    Ok(row.0.unwrap_or(0) + 1)
+    .await
 }
@@ -0,0 +1,76 @@
+//! Prometheus metrics.
+//!
+//! `install()` registers the global recorder once at startup; call sites then
+//! use the `metrics` macros directly or the helpers below. `render()` produces
+//! the text exposition served at `/metrics`.
+
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+pub const HTTP_REQUESTS_TOTAL: &str = "http_requests_total";
+pub const HTTP_REQUEST_DURATION: &str = "http_request_duration_seconds";
+pub const WS_CONNECTIONS: &str = "ws_connections";
+pub const WS_CONNECTED_USERS: &str = "ws_connected_users";
+pub const ACTIVE_STREAMS: &str = "agent_active_streams";
+pub const LLM_CALLS_TOTAL: &str = "llm_calls_total";
+pub const LLM_CALL_DURATION: &str = "llm_call_duration_seconds";
+
+/// Histogram buckets (seconds) shared by request and LLM latency.
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Install the global Prometheus recorder. Safe to call more than once.
+pub fn install() {
+    if HANDLE.get().is_some() {
+        return;
+    }
+    let builder = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_duration_seconds".into()), LATENCY_BUCKETS)
+        .expect("latency buckets are non-empty");
+    match builder.install_recorder() {
+        Ok(handle) => {
+            let _ = HANDLE.set(handle);
+        }
+        Err(e) => tracing::warn!("Failed to install metrics recorder: {}", e),
+    }
+}
+
+/// Current metrics in Prometheus text format, or `None` if not installed.
+pub fn render() -> Option<String> {
+    HANDLE.get().map(|h| h.render())
+}
+
+/// Axum middleware recording request count and latency by method, route and status.
+/// Uses the matched route template (e.g. `/api/users/{id}`) to bound label cardinality.
+pub async fn track_http(req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let path = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(req).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [("method", method), ("path", path), ("status", status)];
+    metrics::counter!(HTTP_REQUESTS_TOTAL, &labels).increment(1);
+    metrics::histogram!(HTTP_REQUEST_DURATION, &labels).record(started.elapsed().as_secs_f64());
+    response
+}
+
+/// Record one LLM call (until the stream is established) for `provider`.
+pub fn record_llm_call(provider: &'static str, started: Instant, ok: bool) {
+    let outcome = if ok { "ok" } else { "error" };
+    metrics::counter!(LLM_CALLS_TOTAL, "provider" => provider, "outcome" => outcome).increment(1);
+    metrics::histogram!(LLM_CALL_DURATION, "provider" => provider).record(started.elapsed().as_secs_f64());
+}
@@ -1,14 +1,23 @@
 pub mod billing;
+pub mod chunked_upload;
+pub mod color_theme;
+pub mod conversation_title;
+pub mod crypto;
 pub mod link_preview;
 pub mod embedding;
+pub mod exif;
+pub mod idempotency;
 pub mod llm;
 pub mod message_seq;
+pub mod metrics;
 pub mod office;
 pub mod openrouter;
 pub mod pending_events;
 pub mod push;
 pub mod push_trigger;
 pub mod r2;
+pub mod thumbnail;
 pub mod tts;
 pub mod memory;
 pub mod mention;
+pub mod wallet;
@@ -10,6 +10,7 @@ use std::time::Duration;
 use crate::services::llm::{ChatMessage, SseStream};
 
 const OPENROUTER_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+const OPENROUTER_KEY_URL: &str = "https://openrouter.ai/api/v1/auth/key";
 
 /// Options for an OpenRouter streaming call.
 pub struct OpenRouterCallOptions {
@@ -21,6 +22,96 @@ pub struct OpenRouterCallOptions {
     pub temperature: Option<f32>,
 }
 
+/// Retry settings for establishing an OpenRouter stream.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt (0 disables retrying).
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled on each subsequent one.
+    pub base_delay_ms: u64,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        Self {
+            max_retries: config.openrouter_max_retries,
+            base_delay_ms: config.openrouter_retry_base_ms,
+        }
+    }
+}
+
+/// Longest provider `Retry-After` we are willing to wait out before giving up.
+const MAX_RETRY_AFTER_SECS: u64 = 30;
+
+/// Failure to establish an OpenRouter stream.
+#[derive(Debug)]
+pub struct OpenRouterError {
+    /// HTTP status returned by OpenRouter, if a response was received.
+    pub status: Option<u16>,
+    /// Seconds the provider asked us to wait (`Retry-After`), if any.
+    pub retry_after: Option<u64>,
+    message: String,
+}
+
+impl OpenRouterError {
+    fn retryable(&self) -> bool {
+        self.status.is_none_or(is_retryable_status)
+    }
+}
+
+impl std::fmt::Display for OpenRouterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Rate limits and upstream/server errors are worth retrying; other 4xx are not.
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// Statuses OpenRouter uses when a model has no available provider right now.
+pub fn is_model_unavailable_status(status: u16) -> bool {
+    matches!(status, 404 | 502 | 503)
+}
+
+/// Parse a `Retry-After` header given in seconds (HTTP-date values are ignored).
+pub fn parse_retry_after(value: &str) -> Option<u64> {
+    value.trim().parse::<u64>().ok()
+}
+
+/// Delay before retry number `attempt` (0-based): exponential backoff, but never
+/// shorter than the provider's `Retry-After`.
+pub fn backoff_delay(policy: &RetryPolicy, attempt: u32, retry_after: Option<u64>) -> Duration {
+    let backoff = Duration::from_millis(policy.base_delay_ms.saturating_mul(1u64 << attempt.min(16)));
+    match retry_after {
+        Some(secs) => backoff.max(Duration::from_secs(secs)),
+        None => backoff,
+    }
+}
+
+/// Check an OpenRouter API key against the key-info endpoint.
+/// Returns a user-safe error message on failure.
+pub async fn validate_api_key(api_key: &str) -> Result<(), String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| format!("HTTP client error: {e}"))?;
+    let resp = client
+        .get(OPENROUTER_KEY_URL)
+        .bearer_auth(api_key)
+        .send()
+        .await
+        .map_err(|_| "Failed to reach OpenRouter API".to_string())?;
+
+    match resp.status().as_u16() {
+        200 => Ok(()),
+        401 | 403 => Err("Invalid OpenRouter API key".into()),
+        _ => Err("OpenRouter API key validation failed".into()),
+    }
+}
+
 /// Start a streaming chat completion via OpenRouter.
 ///
 /// Returns an SSE byte stream. Chunks follow OpenAI format and can be
@@ -29,11 +120,96 @@ pub async fn call_stream(
     api_key: &str,
     opts: &OpenRouterCallOptions,
 ) -> Result<SseStream, String> {
+    start_stream(api_key, opts).await.map_err(|e| e.to_string())
+}
+
+/// Like `call_stream`, but retries transient failures (429/5xx, network errors)
+/// with exponential backoff. Retries only happen before the stream is
+/// established — a stream that fails midway is not restarted.
+pub async fn call_stream_with_retry(
+    api_key: &str,
+    opts: &OpenRouterCallOptions,
+    policy: &RetryPolicy,
+) -> Result<SseStream, OpenRouterError> {
+    let mut attempt = 0;
+    loop {
+        match start_stream(api_key, opts).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => {
+                let wait_too_long = e.retry_after.is_some_and(|s| s > MAX_RETRY_AFTER_SECS);
+                if attempt >= policy.max_retries || !e.retryable() || wait_too_long {
+                    return Err(e);
+                }
+                let delay = backoff_delay(policy, attempt, e.retry_after);
+                tracing::warn!(
+                    "OpenRouter attempt {} failed ({:?}), retrying in {:?}",
+                    attempt + 1,
+                    e.status,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Try the primary model, then each fallback in order, moving on only when a
+/// model is unavailable (after its own retries). Returns the stream together
+/// with the model that served it.
+pub async fn call_stream_with_fallback(
+    api_key: &str,
+    opts: &OpenRouterCallOptions,
+    fallback_models: &[String],
+    policy: &RetryPolicy,
+) -> Result<(SseStream, String), OpenRouterError> {
+    let mut result = call_stream_with_retry(api_key, opts, policy)
+        .await
+        .map(|s| (s, opts.model.clone()));
+
+    for model in fallback_models {
+        match &result {
+            Err(e) if e.status.is_some_and(is_model_unavailable_status) => {
+                tracing::warn!("OpenRouter model {} unavailable, falling back to {}", opts.model, model);
+                let fallback_opts = OpenRouterCallOptions {
+                    model: model.clone(),
+                    messages: opts.messages.clone(),
+                    max_tokens: opts.max_tokens,
+                    temperature: opts.temperature,
+                };
+                result = call_stream_with_retry(api_key, &fallback_opts, policy)
+                    .await
+                    .map(|s| (s, model.clone()));
+            }
+            _ => break,
+        }
+    }
+    result
+}
+
+async fn start_stream(
+    api_key: &str,
+    opts: &OpenRouterCallOptions,
+) -> Result<SseStream, OpenRouterError> {
+    let started = std::time::Instant::now();
+    let result = open_stream(api_key, opts).await;
+    crate::services::metrics::record_llm_call("openrouter", started, result.is_ok());
+    result
+}
+
+async fn open_stream(
+    api_key: &str,
+    opts: &OpenRouterCallOptions,
+) -> Result<SseStream, OpenRouterError> {
     let client = Client::builder()
         .timeout(Duration::from_secs(60))
         .connect_timeout(Duration::from_secs(10))
         .build()
-        .map_err(|e| format!("HTTP client error: {e}"))?;
+        .map_err(|e| OpenRouterError {
+            status: None,
+            retry_after: None,
+            message: format!("HTTP client error: {e}"),
+        })?;
 
     let body = serde_json::json!({
         "model": opts.model,
@@ -43,6 +219,14 @@ pub async fn call_stream(
         "temperature": opts.temperature.unwrap_or(0.7),
     });
 
+    // Metadata only: prompts and completions are user content and never logged
+    tracing::debug!(
+        "OpenRouter request: model={} messages={} max_tokens={:?}",
+        opts.model,
+        opts.messages.len(),
+        opts.max_tokens
+    );
+
     let resp = client
         .post(OPENROUTER_URL)
         .bearer_auth(api_key)
@@ -54,14 +238,26 @@ pub async fn call_stream(
         .await
         .map_err(|e| {
             tracing::error!("OpenRouter request failed: {}", e);
-            "LLM request failed".to_string()
+            OpenRouterError {
+                status: None,
+                retry_after: None,
+                message: "LLM request failed".into(),
+            }
         })?;
 
     if !resp.status().is_success() {
         let status = resp.status().as_u16();
-        let err_body = resp.text().await.unwrap_or_default();
-        tracing::error!("OpenRouter returned {}: {}", status, err_body);
-        return Err("LLM request failed".into());
+        let retry_after = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        tracing::error!("OpenRouter returned {} for model {}", status, opts.model);
+        return Err(OpenRouterError {
+            status: Some(status),
+            retry_after,
+            message: "LLM request failed".into(),
+        });
     }
 
     Ok(Box::pin(resp.bytes_stream()))
@@ -1,11 +1,13 @@
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use p256::ecdsa::{signature::Signer, SigningKey};
+use deadpool_redis::redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
 use crate::config::Config;
+use crate::services::push_trigger::push_allowed;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PushPayload {
     #[serde(rename = "type")]
     pub notification_type: String,
@@ -18,6 +20,8 @@ pub struct PushPayload {
 }
 
 /// Send push notification to all subscriptions for a user.
+/// Skipped when the user's notification preferences disable the payload's
+/// type or it is their quiet hours.
 /// Automatically removes expired/invalid subscriptions (410 Gone).
 pub async fn send_push_to_user(
     pool: &PgPool,
@@ -31,6 +35,10 @@ pub async fn send_push_to_user(
         return Ok(());
     }
 
+    if !push_allowed(pool, user_id, &payload.notification_type).await? {
+        return Ok(());
+    }
+
     let subs = sqlx::query_as::<_, (String, String, String, String)>(
         r#"SELECT id::text, endpoint, p256dh, auth FROM push_subscriptions WHERE user_id = $1"#,
     )
@@ -47,6 +55,7 @@ pub async fn send_push_to_user(
 
     let json_payload = serde_json::to_string(payload)?;
     let mut expired_ids: Vec<String> = Vec::new();
+    let mut delivered_ids: Vec<String> = Vec::new();
 
     let client = reqwest::Client::new();
 
@@ -67,6 +76,8 @@ pub async fn send_push_to_user(
                     expired_ids.push(id.clone());
                 } else if status >= 400 {
                     tracing::warn!(endpoint, status, "push service returned error");
+                } else {
+                    delivered_ids.push(id.clone());
                 }
             }
             Err(e) => {
@@ -76,9 +87,18 @@ pub async fn send_push_to_user(
     }
 
     // Clean up expired subscriptions
-    for id in &expired_ids {
-        sqlx::query(r#"DELETE FROM push_subscriptions WHERE id = $1::uuid"#)
-            .bind(id)
+    if !expired_ids.is_empty() {
+        let removed = sqlx::query(r#"DELETE FROM push_subscriptions WHERE id = ANY($1::uuid[])"#)
+            .bind(&expired_ids)
+            .execute(pool)
+            .await?
+            .rows_affected();
+        tracing::info!(user_id, removed, "removed expired push subscriptions");
+    }
+
+    if !delivered_ids.is_empty() {
+        sqlx::query(r#"UPDATE push_subscriptions SET last_used_at = NOW() WHERE id = ANY($1::uuid[])"#)
+            .bind(&delivered_ids)
             .execute(pool)
             .await?;
     }
@@ -86,6 +106,130 @@ pub async fn send_push_to_user(
     Ok(())
 }
 
+/// Subscriptions without a successful delivery or re-subscribe for this long are pruned.
+pub const STALE_SUBSCRIPTION_DAYS: i32 = 90;
+
+/// Delete subscriptions unused for `max_age_days`. Returns how many were removed.
+pub async fn prune_stale_subscriptions(pool: &PgPool, max_age_days: i32) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM push_subscriptions WHERE last_used_at < NOW() - make_interval(days => $1)",
+    )
+    .bind(max_age_days)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Message pushes for the same user and conversation arriving within this
+/// window are folded into one notification.
+pub const COALESCE_WINDOW_SECS: u64 = 5;
+const COALESCE_KEY_PREFIX: &str = "push_coalesce:";
+
+/// Queue a message push for coalescing instead of sending it right away.
+///
+/// The first push in a window schedules a flush after `COALESCE_WINDOW_SECS`;
+/// pushes queued before the flush are sent together as "N new messages".
+/// Callers still apply the mute, foreground and preference checks before
+/// queueing. Without Redis the push is sent immediately.
+pub async fn queue_message_push(
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+    config: &Config,
+    user_id: &str,
+    conversation_id: &str,
+    payload: PushPayload,
+) {
+    let key = format!("{}{}:{}", COALESCE_KEY_PREFIX, user_id, conversation_id);
+    let queued = match (redis.get().await, serde_json::to_string(&payload)) {
+        (Ok(mut conn), Ok(json)) => {
+            let len: Result<i64, _> = conn.rpush(&key, json).await;
+            if let Ok(1) = len {
+                // TTL outlives the flush so a crashed flusher cannot leave the key behind
+                let _: Result<(), _> = conn.expire(&key, (COALESCE_WINDOW_SECS * 2) as i64).await;
+            }
+            len.ok()
+        }
+        _ => None,
+    };
+
+    match queued {
+        // First push of the window: this caller flushes
+        Some(1) => {
+            let (pool, redis, config, user_id) =
+                (pool.clone(), redis.clone(), config.clone(), user_id.to_string());
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_secs(COALESCE_WINDOW_SECS)).await;
+                flush_coalesced(&pool, &redis, &config, &user_id, &key).await;
+            });
+        }
+        Some(_) => {}
+        None => {
+            let _ = send_push_to_user(pool, config, user_id, &payload).await;
+        }
+    }
+}
+
+async fn flush_coalesced(
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+    config: &Config,
+    user_id: &str,
+    key: &str,
+) {
+    let Ok(mut conn) = redis.get().await else {
+        return;
+    };
+    let (items,): (Vec<String>,) = match deadpool_redis::redis::pipe()
+        .atomic()
+        .lrange(key, 0, -1)
+        .del(key)
+        .ignore()
+        .query_async(&mut *conn)
+        .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!(user_id, error = %e, "failed to read coalesced pushes");
+            return;
+        }
+    };
+    drop(conn);
+
+    let payloads: Vec<PushPayload> = items
+        .iter()
+        .filter_map(|i| serde_json::from_str(i).ok())
+        .collect();
+    if let Some(payload) = coalesce_payloads(payloads) {
+        let _ = send_push_to_user(pool, config, user_id, &payload).await;
+    }
+}
+
+/// Merge queued pushes into one. A single push is returned unchanged; several
+/// become "N new messages" from their distinct senders, linking to the latest.
+pub fn coalesce_payloads(mut payloads: Vec<PushPayload>) -> Option<PushPayload> {
+    if payloads.len() <= 1 {
+        return payloads.pop();
+    }
+
+    let count = payloads.len();
+    let mut senders: Vec<&str> = Vec::new();
+    for p in &payloads {
+        if !senders.contains(&p.title.as_str()) {
+            senders.push(&p.title);
+        }
+    }
+    let title = senders.join(", ");
+
+    let last = payloads.pop()?;
+    Some(PushPayload {
+        notification_type: last.notification_type,
+        title,
+        body: format!("{} new messages", count),
+        url: last.url,
+        message_id: last.message_id,
+    })
+}
+
 /// Send a single web push notification with RFC 8291 aes128gcm encryption.
 /// Returns the HTTP status code.
 async fn send_web_push(
@@ -1,8 +1,11 @@
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use sqlx::PgPool;
 use std::sync::LazyLock;
 use std::time::Instant;
 
+use crate::db::models::NotificationPreference;
+
 const DEDUP_WINDOW_MS: u128 = 30_000; // 30 seconds
 
 /// Deduplication: suppress same-type notifications within a time window
@@ -16,60 +19,108 @@ pub async fn should_send_push(
     user_id: &str,
     notification_type: &str,
 ) -> Result<bool, sqlx::Error> {
-    let prefs = sqlx::query_as::<_, (
-        bool,
-        bool,
-        bool,
-        bool,
-        bool,
-        Option<String>,
-        Option<String>,
-        bool,
-    )>(
-        r#"SELECT global_enabled, message_enabled, playground_invite_enabled,
-                  playground_turn_enabled, playground_result_enabled,
-                  quiet_hours_start, quiet_hours_end, always_push_mobile
-           FROM notification_preferences WHERE user_id = $1"#,
+    Ok(push_allowed(pool, user_id, notification_type).await? && check_dedup(user_id, notification_type))
+}
+
+/// Preference and quiet-hours check without deduplication, for pushes that are
+/// coalesced (see `push::queue_message_push`) rather than dropped.
+pub async fn push_allowed(
+    pool: &PgPool,
+    user_id: &str,
+    notification_type: &str,
+) -> Result<bool, sqlx::Error> {
+    let prefs = sqlx::query_as::<_, NotificationPreference>(
+        "SELECT * FROM notification_preferences WHERE user_id = $1",
     )
     .bind(user_id)
     .fetch_optional(pool)
     .await?;
 
-    let prefs = match prefs {
-        Some(p) => p,
-        None => {
-            // No preferences saved yet - default is all enabled
-            return Ok(check_dedup(user_id, notification_type));
-        }
-    };
-
-    let (global_enabled, message_enabled, invite_enabled, turn_enabled, result_enabled, quiet_start, quiet_end, _always_push_mobile) = prefs;
+    // No preferences saved yet - default is all enabled
+    Ok(prefs.is_none_or(|p| preferences_allow(&p, notification_type, Utc::now())))
+}
 
+/// Whether saved preferences let a push of `notification_type` through at `now`.
+pub fn preferences_allow(
+    prefs: &NotificationPreference,
+    notification_type: &str,
+    now: DateTime<Utc>,
+) -> bool {
     // Global toggle
-    if !global_enabled {
-        return Ok(false);
+    if !prefs.global_enabled {
+        return false;
     }
 
     // Per-type toggle
     let type_enabled = match notification_type {
-        "message" => message_enabled,
-        "playground_invite" => invite_enabled,
-        "playground_turn" => turn_enabled,
-        "playground_result" => result_enabled,
+        "message" => prefs.message_enabled,
+        "mention" => prefs.mention_enabled,
+        "community" => prefs.community_enabled,
+        "friend_request" => prefs.friend_request_enabled,
+        "voice_call" => prefs.voice_call_enabled,
+        "playground_invite" => prefs.playground_invite_enabled,
+        "playground_turn" => prefs.playground_turn_enabled,
+        "playground_result" => prefs.playground_result_enabled,
         _ => true,
     };
     if !type_enabled {
-        return Ok(false);
+        return false;
     }
 
     // Quiet hours check
-    if let (Some(start), Some(end)) = (&quiet_start, &quiet_end) {
-        if is_in_quiet_hours(start, end) {
-            return Ok(false);
+    if let (Some(start), Some(end)) = (&prefs.quiet_hours_start, &prefs.quiet_hours_end) {
+        let offset = prefs
+            .quiet_hours_utc_offset
+            .unwrap_or_else(|| chrono::Local::now().offset().local_minus_utc() / 60);
+        if is_in_quiet_hours(start, end, offset, now) {
+            return false;
         }
     }
 
-    Ok(check_dedup(user_id, notification_type))
+    true
+}
+
+/// Notification type for a chat message push to `user_id`: `mention` when the
+/// message @-mentions them, `community` in community chats, otherwise `message`.
+pub async fn message_push_type(
+    pool: &PgPool,
+    user_id: &str,
+    conv_type: &str,
+    content: &str,
+) -> &'static str {
+    if content.contains('@') {
+        let username = sqlx::query_scalar::<_, Option<String>>(
+            r#"SELECT username FROM "user" WHERE id = $1"#,
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+        if let Some(username) = username {
+            if mentions_username(content, &username) {
+                return "mention";
+            }
+        }
+    }
+    if conv_type == "community" {
+        "community"
+    } else {
+        "message"
+    }
+}
+
+/// `@username` appears in `content` as a whole word (case-insensitive).
+pub fn mentions_username(content: &str, username: &str) -> bool {
+    let needle = format!("@{}", username.to_lowercase());
+    let haystack = content.to_lowercase();
+    haystack.match_indices(&needle).any(|(i, m)| {
+        haystack[i + m.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| !(c.is_alphanumeric() || c == '_'))
+    })
 }
 
 /// Deduplication check: suppress same-type pushes within DEDUP_WINDOW_MS.
@@ -106,9 +157,33 @@ pub async fn is_conversation_muted(
     Ok(row.map(|r| r.0).unwrap_or(false))
 }
 
-fn is_in_quiet_hours(start: &str, end: &str) -> bool {
-    let now = chrono::Local::now();
-    let current_minutes = now.hour() as i32 * 60 + now.minute() as i32;
+/// Push modes a user can pick per conversation (`conversation_reads.push_mode`).
+pub const PUSH_MODES: &[&str] = &["all", "mentions"];
+
+/// Whether the user only wants pushes for messages that @-mention them here.
+pub async fn is_mentions_only(
+    pool: &PgPool,
+    user_id: &str,
+    conversation_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query_as::<_, (String,)>(
+        r#"SELECT push_mode FROM conversation_reads
+           WHERE user_id = $1 AND conversation_id = $2::uuid
+           LIMIT 1"#,
+    )
+    .bind(user_id)
+    .bind(conversation_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.is_some_and(|r| r.0 == "mentions"))
+}
+
+/// Whether `now` falls inside the "HH:MM" range `start`..`end`, read in a
+/// timezone `utc_offset_minutes` east of UTC. Ranges may wrap past midnight.
+pub fn is_in_quiet_hours(start: &str, end: &str, utc_offset_minutes: i32, now: DateTime<Utc>) -> bool {
+    let local = now + chrono::Duration::minutes(utc_offset_minutes as i64);
+    let current_minutes = local.hour() as i32 * 60 + local.minute() as i32;
 
     let parse_time = |t: &str| -> i32 {
         let parts: Vec<&str> = t.split(':').collect();
@@ -1,6 +1,8 @@
 use aws_sdk_s3::Client as S3Client;
 use aws_sdk_s3::config::{Credentials, Region};
+use aws_sdk_s3::presigning::PresigningConfig;
 use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
 
 use crate::config::Config;
 
@@ -50,3 +52,163 @@ pub async fn upload_to_r2(
 
     Ok(format!("{}/{}", public_url, key))
 }
+
+/// Part size for multipart uploads (R2 requires at least 5 MiB for all but the last part).
+const MULTIPART_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Upload a file from disk to R2, using a multipart upload when it is larger
+/// than one part. Returns the public URL.
+pub async fn upload_file_to_r2(
+    s3: &S3Client,
+    bucket: &str,
+    key: &str,
+    path: &std::path::Path,
+    content_type: &str,
+    public_url: &str,
+) -> Result<String, anyhow::Error> {
+    let size = tokio::fs::metadata(path).await?.len();
+    if size <= MULTIPART_PART_SIZE {
+        let body = tokio::fs::read(path).await?;
+        return upload_to_r2(s3, bucket, key, body, content_type, public_url).await;
+    }
+
+    let upload_id = s3
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .content_type(content_type)
+        .send()
+        .await?
+        .upload_id
+        .ok_or_else(|| anyhow::anyhow!("R2 did not return an upload id"))?;
+
+    match upload_parts(s3, bucket, key, &upload_id, path).await {
+        Ok(parts) => {
+            s3.complete_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                )
+                .send()
+                .await?;
+            Ok(format!("{}/{}", public_url, key))
+        }
+        Err(e) => {
+            let _ = s3
+                .abort_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .send()
+                .await;
+            Err(e)
+        }
+    }
+}
+
+async fn upload_parts(
+    s3: &S3Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    path: &std::path::Path,
+) -> Result<Vec<CompletedPart>, anyhow::Error> {
+    use tokio::io::AsyncReadExt;
+
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut parts = Vec::new();
+    let mut part_number = 1;
+    loop {
+        let mut buf = Vec::with_capacity(MULTIPART_PART_SIZE as usize);
+        (&mut file).take(MULTIPART_PART_SIZE).read_to_end(&mut buf).await?;
+        if buf.is_empty() {
+            break;
+        }
+        let etag = s3
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(buf))
+            .send()
+            .await?
+            .e_tag;
+        parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(etag)
+                .build(),
+        );
+        part_number += 1;
+    }
+    Ok(parts)
+}
+
+/// Presign a PUT so a client can upload `key` directly to R2.
+/// Returns the URL and the headers the client must send with the request.
+pub async fn presign_put(
+    s3: &S3Client,
+    bucket: &str,
+    key: &str,
+    content_type: &str,
+    content_length: i64,
+    expires_in: std::time::Duration,
+) -> Result<(String, Vec<(String, String)>), anyhow::Error> {
+    let request = s3
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .content_type(content_type)
+        .content_length(content_length)
+        .presigned(PresigningConfig::expires_in(expires_in)?)
+        .await?;
+
+    let headers = request
+        .headers()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    Ok((request.uri().to_string(), headers))
+}
+
+/// Size of an object in R2, or `None` if it does not exist.
+pub async fn object_size(s3: &S3Client, bucket: &str, key: &str) -> Result<Option<i64>, anyhow::Error> {
+    match s3.head_object().bucket(bucket).key(key).send().await {
+        Ok(head) => Ok(Some(head.content_length().unwrap_or(0))),
+        Err(e) => {
+            let e = e.into_service_error();
+            if e.is_not_found() {
+                Ok(None)
+            } else {
+                Err(e.into())
+            }
+        }
+    }
+}
+
+pub async fn delete_object(s3: &S3Client, bucket: &str, key: &str) -> Result<(), anyhow::Error> {
+    s3.delete_object().bucket(bucket).key(key).send().await?;
+    Ok(())
+}
+
+/// Read up to `len` leading bytes of an object.
+pub async fn read_object_prefix(s3: &S3Client, bucket: &str, key: &str, len: u64) -> Result<Vec<u8>, anyhow::Error> {
+    let object = s3
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .range(format!("bytes=0-{}", len.saturating_sub(1)))
+        .send()
+        .await?;
+    Ok(object.body.collect().await?.into_bytes().to_vec())
+}
+
+/// Cheap reachability check: HEAD the bucket.
+pub async fn ping(s3: &S3Client, bucket: &str) -> Result<(), anyhow::Error> {
+    s3.head_bucket().bucket(bucket).send().await?;
+    Ok(())
+}
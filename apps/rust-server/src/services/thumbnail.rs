@@ -0,0 +1,66 @@
+//! Downscaled previews for uploaded images.
+//!
+//! Chat lists render `thumbnailUrl` and only fetch the original on open, so
+//! image-heavy conversations don't download full-resolution files while scrolling.
+
+use std::io::Cursor;
+
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageFormat, ImageReader};
+
+/// Longest edge of a generated thumbnail, in pixels.
+pub const THUMBNAIL_MAX_EDGE: u32 = 512;
+
+const JPEG_QUALITY: u8 = 80;
+
+pub struct Thumbnail {
+    pub data: Vec<u8>,
+    pub extension: &'static str,
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Build a thumbnail for image bytes. Returns `None` if the data can't be
+/// decoded or the image is already small enough to serve as-is.
+///
+/// Images with transparency are encoded as PNG, everything else as JPEG.
+/// Decoding is CPU-bound; call from `spawn_blocking`.
+pub fn make_thumbnail(data: &[u8]) -> Option<Thumbnail> {
+    let img = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .decode()
+        .ok()?;
+
+    if img.width().max(img.height()) <= THUMBNAIL_MAX_EDGE {
+        return None;
+    }
+
+    let thumb = img.thumbnail(THUMBNAIL_MAX_EDGE, THUMBNAIL_MAX_EDGE);
+    let (width, height) = (thumb.width(), thumb.height());
+    let mut out = Vec::new();
+
+    if thumb.color().has_alpha() {
+        thumb
+            .write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .ok()?;
+        return Some(Thumbnail {
+            data: out,
+            extension: "png",
+            content_type: "image/png",
+            width,
+            height,
+        });
+    }
+
+    let rgb = DynamicImage::ImageRgb8(thumb.to_rgb8());
+    rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY))
+        .ok()?;
+    Some(Thumbnail {
+        data: out,
+        extension: "jpg",
+        content_type: "image/jpeg",
+        width,
+        height,
+    })
+}
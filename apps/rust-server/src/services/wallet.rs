@@ -0,0 +1,221 @@
+//! Two-phase coin holds for charges that depend on a later outcome (e.g. an LLM call).
+//!
+//! Provides:
+//! - `hold_coins()` — move coins from `balance` into `held` before the work starts
+//! - `commit_hold()` — settle a hold as spent, recording transactions and creator share
+//! - `release_hold()` — return held coins to the balance after a failure
+//! - `release_stale_holds()` — release holds abandoned by crashed or timed-out requests
+//!
+//! Each hold is a row in `coin_holds`; commit and release only act on rows still in
+//! the `held` state, so a hold is settled exactly once.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Holds older than this are assumed abandoned and returned to the user.
+pub const HOLD_TIMEOUT_SECS: i64 = 600;
+
+// ---------------------------------------------------------------------------
+// Types
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct Hold {
+    pub id: Uuid,
+    pub user_id: String,
+    pub amount: i32,
+}
+
+#[derive(Debug)]
+pub enum HoldError {
+    /// The user's available balance does not cover the amount.
+    InsufficientBalance,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for HoldError {
+    fn from(e: sqlx::Error) -> Self {
+        HoldError::Database(e)
+    }
+}
+
+/// How a committed hold is recorded.
+pub struct Settlement {
+    /// `coin_transaction_type` recorded for the payer.
+    pub tx_type: &'static str,
+    pub description: String,
+    /// Listing the charge is for, recorded as `related_app_id` on both transactions.
+    pub related_app_id: Option<Uuid>,
+    /// Creator credited with a share of the amount, and the earning description.
+    pub creator: Option<(String, String)>,
+}
+
+/// Creator share of a settled charge (70%).
+pub fn creator_share(amount: i32) -> i32 {
+    amount * 7 / 10
+}
+
+// ---------------------------------------------------------------------------
+// hold_coins
+// ---------------------------------------------------------------------------
+
+/// Reserve `amount` coins for the user. Fails without side effects if the
+/// available balance is too low.
+pub async fn hold_coins(db: &PgPool, user_id: &str, amount: i32) -> Result<Hold, HoldError> {
+    let mut tx = db.begin().await?;
+
+    let reserved = sqlx::query_scalar::<_, i32>(
+        r#"UPDATE coin_balances
+           SET balance = balance - $2, held = held + $2, updated_at = NOW()
+           WHERE user_id = $1 AND balance >= $2
+           RETURNING balance"#,
+    )
+    .bind(user_id)
+    .bind(amount)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if reserved.is_none() {
+        return Err(HoldError::InsufficientBalance);
+    }
+
+    let id = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO coin_holds (user_id, amount) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(amount)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Hold {
+        id,
+        user_id: user_id.to_string(),
+        amount,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// commit_hold
+// ---------------------------------------------------------------------------
+
+/// Settle a hold as spent. Returns `false` if the hold was already settled.
+pub async fn commit_hold(
+    db: &PgPool,
+    hold: &Hold,
+    settlement: &Settlement,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    if !mark_hold(&mut tx, hold.id, "committed").await? {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE coin_balances SET held = held - $2, updated_at = NOW() WHERE user_id = $1")
+        .bind(&hold.user_id)
+        .bind(hold.amount)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        r#"INSERT INTO coin_transactions (user_id, type, amount, related_app_id, description)
+           VALUES ($1, $2::coin_transaction_type, $3, $4, $5)"#,
+    )
+    .bind(&hold.user_id)
+    .bind(settlement.tx_type)
+    .bind(-hold.amount)
+    .bind(settlement.related_app_id)
+    .bind(&settlement.description)
+    .execute(&mut *tx)
+    .await?;
+
+    if let Some((creator_id, earning_description)) = &settlement.creator {
+        let share = creator_share(hold.amount);
+        sqlx::query(
+            r#"INSERT INTO coin_balances (user_id, balance, updated_at)
+               VALUES ($1, $2, NOW())
+               ON CONFLICT (user_id) DO UPDATE
+               SET balance = coin_balances.balance + $2, updated_at = NOW()"#,
+        )
+        .bind(creator_id)
+        .bind(share)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"INSERT INTO coin_transactions (user_id, type, amount, related_app_id, description)
+               VALUES ($1, 'earning', $2, $3, $4)"#,
+        )
+        .bind(creator_id)
+        .bind(share)
+        .bind(settlement.related_app_id)
+        .bind(earning_description)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+// ---------------------------------------------------------------------------
+// release_hold
+// ---------------------------------------------------------------------------
+
+/// Return held coins to the user's balance. Returns `false` if the hold was
+/// already settled.
+pub async fn release_hold(db: &PgPool, hold: &Hold) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    if !mark_hold(&mut tx, hold.id, "released").await? {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        r#"UPDATE coin_balances
+           SET balance = balance + $2, held = held - $2, updated_at = NOW()
+           WHERE user_id = $1"#,
+    )
+    .bind(&hold.user_id)
+    .bind(hold.amount)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Release every hold older than `max_age_secs` that was never settled.
+pub async fn release_stale_holds(db: &PgPool, max_age_secs: i64) -> Result<u64, sqlx::Error> {
+    let stale = sqlx::query_as::<_, (Uuid, String, i32)>(
+        r#"SELECT id, user_id, amount FROM coin_holds
+           WHERE status = 'held' AND created_at < NOW() - make_interval(secs => $1)"#,
+    )
+    .bind(max_age_secs as f64)
+    .fetch_all(db)
+    .await?;
+
+    let mut released = 0;
+    for (id, user_id, amount) in stale {
+        if release_hold(db, &Hold { id, user_id, amount }).await? {
+            released += 1;
+        }
+    }
+    Ok(released)
+}
+
+async fn mark_hold(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    hold_id: Uuid,
+    status: &str,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query(
+        "UPDATE coin_holds SET status = $2, settled_at = NOW() WHERE id = $1 AND status = 'held'",
+    )
+    .bind(hold_id)
+    .bind(status)
+    .execute(&mut **tx)
+    .await?;
+    Ok(updated.rows_affected() == 1)
+}
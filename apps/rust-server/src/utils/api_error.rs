@@ -0,0 +1,132 @@
+//! Uniform JSON error bodies: `{ error, code, requestId }`.
+//!
+//! Handlers build errors with `api_error()` and the `RequestId` extractor, or
+//! keep returning the older `{"error": ...}` shape; `enrich_error_body` fills
+//! in `code` (from the status) and `requestId` (from the `x-request-id`
+//! header) on the way out.
+
+use std::convert::Infallible;
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::{FromRequestParts, Request},
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Error bodies larger than this, or of unknown length, are passed through untouched.
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// The request's `x-request-id`, as assigned by the request-id layer.
+#[derive(Debug, Clone, Default)]
+pub struct RequestId(pub Option<String>);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestId {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(
+            parts
+                .headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+        ))
+    }
+}
+
+/// Build an error response in the documented `{ error, code, requestId }` shape.
+pub fn api_error(status: StatusCode, code: &str, message: impl Into<String>, request_id: &RequestId) -> Response {
+    let mut body = json!({ "error": message.into(), "code": code });
+    if let Some(id) = &request_id.0 {
+        body["requestId"] = json!(id);
+    }
+    (status, Json(body)).into_response()
+}
+
+/// Default `code` for an error status when the handler didn't set one.
+pub fn default_error_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable_entity",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        s if s.is_server_error() => "internal_error",
+        _ => "error",
+    }
+}
+
+/// Add `code` and `requestId` to an `{"error": ...}` object, keeping any
+/// values the handler already set. Returns `false` if `body` isn't one.
+pub fn enrich_error_json(body: &mut Value, status: StatusCode, request_id: Option<&str>) -> bool {
+    let Some(obj) = body.as_object_mut() else {
+        return false;
+    };
+    if !obj.contains_key("error") {
+        return false;
+    }
+    obj.entry("code")
+        .or_insert_with(|| Value::String(default_error_code(status).to_string()));
+    if let Some(id) = request_id {
+        obj.entry("requestId")
+            .or_insert_with(|| Value::String(id.to_string()));
+    }
+    true
+}
+
+/// Middleware: enrich JSON error responses with `code` and `requestId`.
+/// Must run inside the layer that assigns `x-request-id`.
+pub async fn enrich_error_body(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(req).await;
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let fits = body
+        .size_hint()
+        .upper()
+        .is_some_and(|n| n <= MAX_ERROR_BODY_BYTES as u64);
+    if !fits {
+        return Response::from_parts(parts, body);
+    }
+    let bytes = match axum::body::to_bytes(body, MAX_ERROR_BODY_BYTES).await {
+        Ok(b) => b,
+        Err(_) => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    if !enrich_error_json(&mut value, status, request_id.as_deref()) {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+
+    let new_body = value.to_string();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Response::from_parts(parts, Body::from(new_body))
+}
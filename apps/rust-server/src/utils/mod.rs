@@ -1,3 +1,5 @@
+pub mod api_error;
 pub mod pairing_code;
 pub mod agent_app_bridge;
+pub mod pagination;
 pub mod username;
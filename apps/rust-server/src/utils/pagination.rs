@@ -0,0 +1,49 @@
+//! Page/offset pagination shared by list endpoints.
+//!
+//! Clients may send either `page` (1-based) or a raw `offset`; `page` wins
+//! when both are present. Responses carry `page`, `limit`, `totalPages` and
+//! `hasMore` so clients don't repeat the arithmetic.
+
+use serde_json::{json, Value};
+
+/// Resolved `LIMIT`/`OFFSET` for a list query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageWindow {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl PageWindow {
+    /// `limit` is clamped to `1..=max_limit`; `page` is 1-based and takes
+    /// precedence over `offset`.
+    pub fn resolve(
+        page: Option<i64>,
+        offset: Option<i64>,
+        limit: Option<i64>,
+        default_limit: i64,
+        max_limit: i64,
+    ) -> Self {
+        let limit = limit.unwrap_or(default_limit).clamp(1, max_limit);
+        let offset = match page {
+            Some(p) => (p.max(1) - 1).saturating_mul(limit),
+            None => offset.unwrap_or(0).max(0),
+        };
+        Self { limit, offset }
+    }
+
+    /// 1-based page containing the first row of this window.
+    pub fn page(&self) -> i64 {
+        self.offset / self.limit + 1
+    }
+
+    /// `{ page, limit, totalPages, hasMore }` for a result set of `total` rows.
+    pub fn meta(&self, total: i64) -> Value {
+        let total_pages = (total + self.limit - 1) / self.limit;
+        json!({
+            "page": self.page(),
+            "limit": self.limit,
+            "totalPages": total_pages,
+            "hasMore": self.offset + self.limit < total,
+        })
+    }
+}
@@ -1,8 +1,15 @@
+/// Terms that may not be used as a word in a username, so nobody can pose as
+/// staff or the platform itself.
+pub const RESERVED_USERNAME_TERMS: &[&str] = &[
+    "admin", "arinova", "moderator", "official", "support", "staff", "system",
+];
+
 /// Validate username format:
 /// - 10-32 characters (1-9 char usernames reserved for future sale)
 /// - lowercase a-z, 0-9, underscore only
 /// - must start with a letter
 /// - no consecutive underscores
+/// - no reserved words (see `is_reserved_username`)
 pub fn validate_username(username: &str) -> Result<(), &'static str> {
     if username.len() < 10 {
         return Err("Username must be at least 10 characters");
@@ -19,9 +26,39 @@ pub fn validate_username(username: &str) -> Result<(), &'static str> {
     if username.contains("__") {
         return Err("Username cannot contain consecutive underscores");
     }
+    if is_reserved_username(username) {
+        return Err("This username is reserved");
+    }
     Ok(())
 }
 
+/// Whether any word of the username is a reserved term. Words are split on
+/// `_`, `-` and `.`, and trailing digits are ignored, so `the_admin_42` and
+/// `admin2` are reserved while `badminton_fan` and `ecosystem_x` are not.
+pub fn is_reserved_username(username: &str) -> bool {
+    username
+        .split(['_', '-', '.'])
+        .map(|word| word.trim_end_matches(|c: char| c.is_ascii_digit()))
+        .any(|word| RESERVED_USERNAME_TERMS.contains(&word))
+}
+
+/// Valid alternatives to a taken username, in preference order.
+pub fn username_candidates(base: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for suffix in ["_1", "_2", "_3", "_app", "_chat", "_99", "_2026"] {
+        let keep = 32usize.saturating_sub(suffix.len()).min(base.len());
+        let mut stem = base[..keep].to_string();
+        if suffix.starts_with('_') && stem.ends_with('_') {
+            stem.pop();
+        }
+        let candidate = format!("{}{}", stem, suffix);
+        if validate_username(&candidate).is_ok() && candidate != base && !out.contains(&candidate) {
+            out.push(candidate);
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,4 +104,29 @@ mod tests {
     fn test_consecutive_underscores() {
         assert_eq!(validate_username("ripple__test").unwrap_err(), "Username cannot contain consecutive underscores");
     }
+
+    #[test]
+    fn test_reserved_terms() {
+        assert_eq!(validate_username("arinova_help").unwrap_err(), "This username is reserved");
+        assert_eq!(validate_username("the_admin_42").unwrap_err(), "This username is reserved");
+        assert_eq!(validate_username("support2_team").unwrap_err(), "This username is reserved");
+    }
+
+    #[test]
+    fn test_reserved_terms_inside_words_are_allowed() {
+        assert!(validate_username("badminton_fan").is_ok());
+        assert!(validate_username("ecosystem_x").is_ok());
+        assert!(validate_username("unofficial_guide").is_ok());
+        assert!(validate_username("staffordshire").is_ok());
+    }
+
+    #[test]
+    fn test_candidates_are_valid() {
+        let candidates = username_candidates("ripple_fan_club");
+        assert_eq!(candidates[0], "ripple_fan_club_1");
+        assert!(candidates.iter().all(|c| validate_username(c).is_ok()));
+
+        let long = "a".repeat(32);
+        assert!(username_candidates(&long).iter().all(|c| c.len() <= 32));
+    }
 }
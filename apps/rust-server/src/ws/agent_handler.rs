@@ -7,19 +7,23 @@ use axum::{
     routing::get,
     Router,
 };
+use deadpool_redis::redis::AsyncCommands;
 use futures::{SinkExt, StreamExt};
 use serde_json::{json, Value};
 use sqlx::PgPool;
+use std::collections::HashSet;
 use tokio::sync::mpsc;
 use tokio::time::{timeout, Duration};
 
 use crate::services::message_seq::get_next_seq;
-use crate::ws::handler::{filter_agents_for_dispatch, AgentFilterConfig, do_trigger_agent_response, get_conv_member_ids};
+use crate::ws::handler::{filter_agents_for_dispatch, AgentFilterConfig, do_trigger_agent_response, get_conv_member_ids, TriggerContext};
 use crate::ws::state::{AgentEvent, AgentSkill, PendingTask, QueuedResponse, WsState};
 use crate::AppState;
 
 const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
 const TASK_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+/// How long a disconnected agent's in-flight tasks wait for it to reconnect and resume
+const TASK_RESUME_GRACE: Duration = Duration::from_secs(30);
 
 pub fn router() -> Router<AppState> {
     Router::new().route("/ws/agent", get(agent_ws_upgrade))
@@ -129,10 +133,13 @@ async fn handle_agent_ws(socket: WebSocket, state: AppState, client_ip: Option<S
                         }
 
                         // Close any existing connection for this agent
-                        if let Some((_, (_, old_sender))) = state.ws.agent_connections.remove(&agent_id) {
+                        let replaced = if let Some((_, (_, old_sender))) = state.ws.agent_connections.remove(&agent_id) {
                             // The old connection will close when sender is dropped
                             drop(old_sender);
-                        }
+                            true
+                        } else {
+                            false
+                        };
 
                         // Parse skills
                         let skills: Vec<AgentSkill> = event
@@ -141,12 +148,35 @@ async fn handle_agent_ws(socket: WebSocket, state: AppState, client_ip: Option<S
                             .unwrap_or_default();
 
                         state.ws.agent_connections.insert(agent_id.clone(), (conn_id.clone(), tx.clone()));
+                        // A reconnect that supersedes a live connection is not a transition
+                        if !replaced {
+                            crate::routes::agent_health::record_health_event(&state.db, &agent_id, true, None).await;
+                        }
+
+                        let client_version = event
+                            .get("clientVersion")
+                            .and_then(|v| v.as_str())
+                            .map(|v| v.chars().take(64).collect::<String>());
+                        let _ = sqlx::query("UPDATE agents SET client_version = $2 WHERE id = $1::uuid")
+                            .bind(&agent_id)
+                            .bind(&client_version)
+                            .execute(&state.db)
+                            .await;
                         if let Some(ref ip) = client_ip {
                             state.ws.agent_connection_ips.insert(agent_id.clone(), ip.clone());
                         }
                         state.ws.agent_skills.insert(agent_id.clone(), skills.clone());
 
-                        // Clean up stale streaming messages for this agent
+                        // Tasks still waiting out the reconnect grace period can be resumed
+                        let resumable_tasks: Vec<String> = state
+                            .ws
+                            .pending_tasks
+                            .iter()
+                            .filter(|entry| entry.agent_id == agent_id)
+                            .map(|entry| entry.key().clone())
+                            .collect();
+
+                        // Clean up stale streaming messages for this agent (except resumable ones)
                         // Match by sender_agent_id (group) or conversation.agent_id (direct)
                         let cleanup = sqlx::query(
                             r#"UPDATE messages m SET status = 'error',
@@ -156,9 +186,11 @@ async fn handle_agent_ws(socket: WebSocket, state: AppState, client_ip: Option<S
                                WHERE m.conversation_id = c.id
                                  AND m.status = 'streaming'
                                  AND m.role = 'agent'
-                                 AND (m.sender_agent_id = $1::uuid OR (c.type IN ('direct', 'h2a') AND c.agent_id = $1::uuid))"#,
+                                 AND (m.sender_agent_id = $1::uuid OR (c.type IN ('direct', 'h2a') AND c.agent_id = $1::uuid))
+                                 AND m.id::text <> ALL($2)"#,
                         )
                         .bind(&agent_id)
+                        .bind(&resumable_tasks)
                         .execute(&state.db)
                         .await;
                         if let Ok(result) = cleanup {
@@ -187,7 +219,8 @@ async fn handle_agent_ws(socket: WebSocket, state: AppState, client_ip: Option<S
 
                         let _ = tx.send(serde_json::to_string(&json!({
                             "type": "auth_ok",
-                            "agentName": agent_name
+                            "agentName": agent_name,
+                            "resumableTasks": resumable_tasks
                         })).unwrap());
 
                         tracing::info!(
@@ -282,6 +315,27 @@ async fn handle_agent_ws(socket: WebSocket, state: AppState, client_ip: Option<S
                         }
                     }
                 }
+                "agent_reasoning" => {
+                    let task_id = event.get("taskId").and_then(|v| v.as_str()).unwrap_or("");
+                    let chunk = event.get("chunk").and_then(|v| v.as_str()).unwrap_or("");
+
+                    if let Some(mut task) = ws_state.pending_tasks.get_mut(task_id) {
+                        if task.agent_id == agent_id_clone {
+                            if !chunk.is_empty() {
+                                let _ = task.chunk_tx.send(AgentEvent::Reasoning(chunk.to_string()));
+                            }
+
+                            // Reasoning counts as activity — reset idle timeout
+                            task.timeout_handle.abort();
+                            let ws_state_clone = ws_state.clone();
+                            let task_id_str = task_id.to_string();
+                            task.timeout_handle = tokio::spawn(async move {
+                                tokio::time::sleep(TASK_IDLE_TIMEOUT).await;
+                                cleanup_task(&ws_state_clone, &task_id_str, Some("Task timed out (idle for 600s)"));
+                            });
+                        }
+                    }
+                }
                 "agent_complete" => {
                     let task_id = event.get("taskId").and_then(|v| v.as_str()).unwrap_or("");
                     let content = event.get("content").and_then(|v| v.as_str()).unwrap_or("");
@@ -309,6 +363,53 @@ async fn handle_agent_ws(socket: WebSocket, state: AppState, client_ip: Option<S
                         }
                     }
                 }
+                "agent_resume" => {
+                    // Reconnected agent continuing an in-flight task: re-attach to the
+                    // existing stream instead of starting a new message
+                    let task_id = event.get("taskId").and_then(|v| v.as_str()).unwrap_or("");
+
+                    let resumed = match ws_state.pending_tasks.get_mut(task_id) {
+                        Some(mut task) if task.agent_id == agent_id_clone => {
+                            task.timeout_handle.abort();
+                            let ws_state_clone = ws_state.clone();
+                            let task_id_str = task_id.to_string();
+                            task.timeout_handle = tokio::spawn(async move {
+                                tokio::time::sleep(TASK_IDLE_TIMEOUT).await;
+                                cleanup_task(&ws_state_clone, &task_id_str, Some("Task timed out (idle for 600s)"));
+                            });
+                            Some(task.accumulated.clone())
+                        }
+                        _ => None,
+                    };
+
+                    match resumed {
+                        Some(in_memory) => {
+                            // Redis holds what members have already seen
+                            let accumulated = match redis.get().await {
+                                Ok(mut conn) => conn
+                                    .get::<_, Option<String>>(&format!("stream:{}", task_id))
+                                    .await
+                                    .ok()
+                                    .flatten()
+                                    .unwrap_or(in_memory),
+                                Err(_) => in_memory,
+                            };
+                            tracing::info!("Agent task resumed: agentId={} taskId={} len={}", agent_id_clone, task_id, accumulated.len());
+                            let _ = tx.send(serde_json::to_string(&json!({
+                                "type": "task_resumed",
+                                "taskId": task_id,
+                                "accumulated": accumulated
+                            })).unwrap());
+                        }
+                        None => {
+                            let _ = tx.send(serde_json::to_string(&json!({
+                                "type": "task_resume_failed",
+                                "taskId": task_id,
+                                "error": "Task is no longer active"
+                            })).unwrap());
+                        }
+                    }
+                }
                 "agent_heartbeat" => {
                     let task_id = event.get("taskId").and_then(|v| v.as_str()).unwrap_or("");
 
@@ -529,19 +630,24 @@ async fn handle_agent_ws(socket: WebSocket, state: AppState, client_ip: Option<S
                                         thread_id: None,
                                         user_message_id: Some(msg_id.clone()),
                                         metadata: None,
+                                        response_group: None,
                                     });
                                 continue;
                             }
 
                             do_trigger_agent_response(
-                                &user_id,
                                 &dispatch_agent_id,
                                 conversation_id,
-                                content,
-                                None,
-                                None,
-                                &_conv_type,
-                                None,
+                                TriggerContext {
+                                    user_id: &user_id,
+                                    content,
+                                    reply_to_id: None,
+                                    thread_id: None,
+                                    conv_type: &_conv_type,
+                                    client_metadata: None,
+                                    response_group: None,
+                                    mention_depth: 0,
+                                },
                                 &ws_state,
                                 &db,
                                 &redis,
@@ -699,6 +805,12 @@ async fn handle_agent_ws(socket: WebSocket, state: AppState, client_ip: Option<S
             // End any active voice calls for this agent
             cleanup_agent_voice_calls(&state.db, &state.ws, &agent_id).await;
 
+            crate::routes::agent_health::record_health_event(&state.db, &agent_id, false, Some("connection closed")).await;
+            let _ = sqlx::query("UPDATE agents SET last_seen_at = NOW() WHERE id = $1::uuid")
+                .bind(&agent_id)
+                .execute(&state.db)
+                .await;
+
             tracing::info!("Agent WS disconnected: agentId={}", agent_id);
         } else {
             tracing::info!("Agent WS closed (superseded by reconnect): agentId={}", agent_id);
@@ -715,24 +827,45 @@ fn cleanup_task(ws_state: &WsState, task_id: &str, error_message: Option<&str>)
     }
 }
 
-fn cleanup_agent_tasks(ws_state: &WsState, agent_id: &str) {
-    let task_ids: Vec<String> = ws_state
+/// Detach a disconnected agent's in-flight tasks. Each task waits TASK_RESUME_GRACE for
+/// the agent to reconnect and resume; otherwise it is dropped, which closes the event
+/// channel so the stream finalizes with whatever content was accumulated.
+pub fn cleanup_agent_tasks(ws_state: &WsState, agent_id: &str) {
+    let detached: Vec<(String, String)> = ws_state
         .pending_tasks
         .iter()
         .filter(|entry| entry.agent_id == agent_id)
-        .map(|entry| entry.key().clone())
+        .map(|entry| (entry.key().clone(), entry.conversation_id.clone()))
         .collect();
 
-    for task_id in task_ids {
-        cleanup_task(ws_state, &task_id, Some("Agent disconnected"));
+    for (task_id, _) in &detached {
+        if let Some(mut task) = ws_state.pending_tasks.get_mut(task_id) {
+            task.timeout_handle.abort();
+            let ws_state_clone = ws_state.clone();
+            let task_id_str = task_id.clone();
+            task.timeout_handle = tokio::spawn(async move {
+                tokio::time::sleep(TASK_RESUME_GRACE).await;
+                if ws_state_clone.pending_tasks.remove(&task_id_str).is_some() {
+                    tracing::info!("Agent did not resume task within grace period: taskId={}", task_id_str);
+                }
+            });
+        }
+    }
+    if !detached.is_empty() {
+        tracing::info!("Detached {} in-flight tasks for agent {}", detached.len(), agent_id);
     }
 
-    // Clean up stale active_streams entries for this agent
+    // Streams of detached tasks stay active until the grace period ends; any
+    // other active_streams entry for this agent is stale
+    let resumable: HashSet<String> = detached
+        .iter()
+        .map(|(_, conversation_id)| format!("{}:{}", conversation_id, agent_id))
+        .collect();
     let suffix = format!(":{}", agent_id);
     let stale_keys: Vec<String> = ws_state
         .active_streams
         .iter()
-        .filter(|entry| entry.key().ends_with(&suffix))
+        .filter(|entry| entry.key().ends_with(&suffix) && !resumable.contains(entry.key()))
         .map(|entry| entry.key().clone())
         .collect();
 
@@ -801,6 +934,7 @@ async fn cleanup_agent_voice_calls(db: &PgPool, ws_state: &WsState, agent_id: &s
 pub fn send_task_to_agent(
     ws_state: &WsState,
     agent_id: &str,
+    conversation_id: &str,
     task_id: &str,
     task_payload: &Value,
 ) -> Option<mpsc::UnboundedReceiver<AgentEvent>> {
@@ -822,6 +956,7 @@ pub fn send_task_to_agent(
         task_id.to_string(),
         PendingTask {
             agent_id: agent_id.to_string(),
+            conversation_id: conversation_id.to_string(),
             accumulated: String::new(),
             chunk_tx: event_tx,
             timeout_handle,
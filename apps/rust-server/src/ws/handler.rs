@@ -18,10 +18,10 @@ use tokio::time::{timeout, Duration};
 use crate::auth::session::validate_session;
 use crate::services::message_seq::get_next_seq;
 use crate::services::pending_events::{clear_pending_events, get_pending_events};
-use crate::services::push::{send_push_to_user, PushPayload};
-use crate::services::push_trigger::{is_conversation_muted, should_send_push};
+use crate::services::push::{queue_message_push, PushPayload};
+use crate::services::push_trigger::{is_conversation_muted, is_mentions_only, message_push_type, push_allowed};
 use crate::ws::agent_handler::send_task_to_agent;
-use crate::ws::state::{QueuedResponse, WsState};
+use crate::ws::state::{can_cancel_stream, QueuedResponse, ResponseGroup, StreamCanceller, WsState};
 use crate::AppState;
 
 // ---------- Two-layer agent dispatch filter (pure, testable) ----------
@@ -37,6 +37,44 @@ pub struct AgentFilterConfig {
     pub allowed_user_ids: Vec<String>,
 }
 
+/// Tracing target for dispatch filter decisions. Off by default; enable with
+/// `RUST_LOG=info,arinova::dispatch=debug`.
+pub const DISPATCH_LOG_TARGET: &str = "arinova::dispatch";
+
+/// Why the group filter skips `agent` for this message, or `None` if it is dispatched.
+/// Reasons: "muted", "not_mentioned", "owner_only", "not_allowlisted", "unknown_mode".
+pub fn dispatch_skip_reason(
+    agent: &AgentFilterConfig,
+    sender_user_id: &str,
+    mentions: &[String],
+) -> Option<&'static str> {
+    let is_owner = agent.owner_user_id == sender_user_id;
+    let is_allowed = is_owner || agent.allowed_user_ids.iter().any(|u| u == sender_user_id);
+    let is_mentioned = mentions.iter().any(|m| {
+        m == "__all__" || m == "__all_agents__" || *m == agent.agent_id
+    });
+
+    match agent.listen_mode.as_str() {
+        "all" => None,
+        "all_mentions" => (!is_mentioned).then_some("not_mentioned"),
+        "owner_unmention_others_mention" => (!is_owner && !is_mentioned).then_some("not_mentioned"),
+        // Legacy "allowed_users" maps to owner_and_allowlist behavior
+        "owner_and_allowlist" | "allowed_users" => (!is_allowed).then_some("not_allowlisted"),
+        "allowlist_mentions" => {
+            if !is_mentioned {
+                Some("not_mentioned")
+            } else if !is_allowed {
+                Some("not_allowlisted")
+            } else {
+                None
+            }
+        }
+        "owner_only" => (!is_owner).then_some("owner_only"),
+        "muted" => Some("muted"),
+        _ => Some("unknown_mode"),
+    }
+}
+
 /// Pure per-agent filtering based on 6 listen modes.
 ///
 /// Modes (broad → strict):
@@ -49,50 +87,83 @@ pub struct AgentFilterConfig {
 ///   - `"muted"` — never receive
 ///
 /// For non-group (direct/h2a) conversations, always dispatch (agent is the sole recipient).
+/// Each decision is logged at debug level under [`DISPATCH_LOG_TARGET`].
 pub fn filter_agents_for_dispatch(
-    _mention_only: bool,
+    mention_only: bool,
     conv_type: &str,
     sender_user_id: &str,
     mentions: &[String],
     agents: &[AgentFilterConfig],
 ) -> Vec<String> {
+    let _span = tracing::debug_span!(
+        target: DISPATCH_LOG_TARGET,
+        "filter_agents_for_dispatch",
+        mention_only,
+        conv_type,
+        sender = sender_user_id,
+        mentions = ?mentions,
+    )
+    .entered();
+
     if conv_type != "group" && conv_type != "community" {
         // Direct / H2A conversations -> always dispatch
-        return agents.iter().map(|a| a.agent_id.clone()).collect();
+        let all: Vec<String> = agents.iter().map(|a| a.agent_id.clone()).collect();
+        tracing::debug!(target: DISPATCH_LOG_TARGET, dispatch = ?all, "non-group conversation, dispatching to all agents");
+        return all;
     }
 
     let mut filtered = Vec::new();
     for agent in agents {
-        let is_owner = agent.owner_user_id == sender_user_id;
-        let is_mentioned = mentions.contains(&"__all__".to_string())
-            || mentions.contains(&"__all_agents__".to_string())
-            || mentions.contains(&agent.agent_id);
-
-        let should_dispatch = match agent.listen_mode.as_str() {
-            "all" => true,
-            "all_mentions" => is_mentioned,
-            "owner_unmention_others_mention" => is_owner || is_mentioned,
-            "owner_and_allowlist" => {
-                is_owner || agent.allowed_user_ids.contains(&sender_user_id.to_string())
-            }
-            "allowlist_mentions" => {
-                is_mentioned && (is_owner || agent.allowed_user_ids.contains(&sender_user_id.to_string()))
+        match dispatch_skip_reason(agent, sender_user_id, mentions) {
+            None => {
+                tracing::debug!(target: DISPATCH_LOG_TARGET, agent = %agent.agent_id, listen_mode = %agent.listen_mode, "dispatch");
+                filtered.push(agent.agent_id.clone());
             }
-            "owner_only" => is_owner,
-            "muted" => false,
-            // Legacy "allowed_users" maps to owner_and_allowlist behavior
-            "allowed_users" => {
-                is_owner || agent.allowed_user_ids.contains(&sender_user_id.to_string())
+            Some(reason) => {
+                tracing::debug!(target: DISPATCH_LOG_TARGET, agent = %agent.agent_id, listen_mode = %agent.listen_mode, reason, "skip");
             }
-            _ => false,
-        };
-        if should_dispatch {
-            filtered.push(agent.agent_id.clone());
         }
     }
+    tracing::debug!(target: DISPATCH_LOG_TARGET, dispatch = ?filtered, "dispatch list");
     filtered
 }
 
+/// Window after a stream starts in which follow-up messages are merged into one queued task.
+pub const DISPATCH_COALESCE_WINDOW: Duration = Duration::from_secs(2);
+
+/// What to do with a message for an agent that may already be streaming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DispatchDecision {
+    /// No active stream — dispatch immediately.
+    Start,
+    /// Active stream — enqueue as a separate follow-up task.
+    Queue,
+    /// Burst within the coalesce window — append to the queued tail task.
+    Coalesce,
+}
+
+/// Decide how to dispatch given the age of the agent's active stream (if any)
+/// and whether the queue tail is from the same sender/thread and can absorb the message.
+pub fn decide_dispatch(stream_age: Option<Duration>, tail_coalescable: bool) -> DispatchDecision {
+    match stream_age {
+        None => DispatchDecision::Start,
+        Some(age) if age < DISPATCH_COALESCE_WINDOW && tail_coalescable => {
+            DispatchDecision::Coalesce
+        }
+        Some(_) => DispatchDecision::Queue,
+    }
+}
+
+/// Assign each dispatched agent a slot in the turn's response group, in dispatch order.
+pub fn assign_response_groups(group_id: &str, agent_ids: &[String]) -> Vec<ResponseGroup> {
+    (0..agent_ids.len())
+        .map(|ordinal| ResponseGroup {
+            id: group_id.to_string(),
+            ordinal,
+        })
+        .collect()
+}
+
 /// Safely truncate a string at a character boundary.
 /// Inject relevant agent memories into message content via embedding similarity search.
 async fn inject_agent_memories(
@@ -175,6 +246,12 @@ fn safe_truncate(s: &str, max_chars: usize) -> &str {
     }
 }
 
+/// Append as much of `delta` as keeps `buf` within `max_chars` characters.
+pub fn push_capped(buf: &mut String, delta: &str, max_chars: usize) {
+    let remaining = max_chars.saturating_sub(buf.chars().count());
+    buf.push_str(safe_truncate(delta, remaining));
+}
+
 /// Get conversation member user IDs with caching.
 /// Returns a filtered list excluding users who have blocked (or are blocked by) sender_user_id.
 pub async fn get_conv_member_ids(
@@ -263,7 +340,13 @@ pub async fn get_conv_member_ids(
 }
 
 const WS_RATE_LIMIT: i32 = 10; // messages per minute
+/// Heartbeat timeout for clients that never send `hello`.
 const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+/// Bounds for a client-declared heartbeat timeout (twice its ping interval).
+const MIN_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(300);
+/// Max characters of agent reasoning persisted on a message
+const REASONING_MAX_CHARS: usize = 16_000;
 
 pub fn router() -> Router<AppState> {
     Router::new().route("/ws", get(ws_upgrade))
@@ -311,15 +394,27 @@ async fn handle_ws(socket: WebSocket, state: AppState, cookie_header: String) {
     let (tx, mut rx) = mpsc::unbounded_channel::<String>();
 
     // Register connection
-    state
-        .ws
-        .user_connections
-        .entry(user_id.clone())
-        .or_insert_with(Vec::new)
-        .push((conn_id.clone(), tx.clone()));
+    let came_online = {
+        let mut conns = state
+            .ws
+            .user_connections
+            .entry(user_id.clone())
+            .or_default();
+        conns.push((conn_id.clone(), tx.clone()));
+        conns.len() == 1
+    };
 
     tracing::info!("WS connected: user={}", user_id);
 
+    if came_online {
+        let ws = state.ws.clone();
+        let db = state.db.clone();
+        let uid = user_id.clone();
+        tokio::spawn(async move {
+            broadcast_presence_update(&ws, &db, &uid, true).await;
+        });
+    }
+
     // Deliver pending events
     if let Ok(pending) = get_pending_events(&state.redis, &user_id).await {
         if !pending.is_empty() {
@@ -351,7 +446,12 @@ async fn handle_ws(socket: WebSocket, state: AppState, cookie_header: String) {
 
     let recv_task = tokio::spawn(async move {
         loop {
-            match timeout(HEARTBEAT_TIMEOUT, ws_receiver.next()).await {
+            let heartbeat_timeout = ws_state
+                .heartbeat_timeouts
+                .get(&conn_id_clone)
+                .map(|t| *t)
+                .unwrap_or(HEARTBEAT_TIMEOUT);
+            match timeout(heartbeat_timeout, ws_receiver.next()).await {
                 Ok(Some(Ok(Message::Text(text)))) => {
                     handle_message(
                         &text,
@@ -384,11 +484,53 @@ async fn handle_ws(socket: WebSocket, state: AppState, cookie_header: String) {
     }
 
     // Cleanup
-    cleanup_connection(&state.ws, &user_id, &conn_id);
+    if cleanup_connection(&state.ws, &user_id, &conn_id) {
+        broadcast_presence_update(&state.ws, &state.db, &user_id, false).await;
+    }
     tracing::info!("WS disconnected: user={}", user_id);
 }
 
-fn cleanup_connection(ws_state: &WsState, user_id: &str, conn_id: &str) {
+/// Describe an offline agent for users: when it was last online and, if known,
+/// the client version it last connected with.
+pub fn agent_presence_phrase(
+    last_seen_at: Option<DateTime<Utc>>,
+    client_version: Option<&str>,
+    now: DateTime<Utc>,
+) -> String {
+    let Some(seen) = last_seen_at else {
+        return "has never connected".into();
+    };
+    let secs = (now - seen).num_seconds().max(0);
+    let ago = match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => plural_ago(secs / 60, "minute"),
+        3600..=86399 => plural_ago(secs / 3600, "hour"),
+        _ => plural_ago(secs / 86400, "day"),
+    };
+    match client_version {
+        Some(v) => format!("was last online {} (client {})", ago, v),
+        None => format!("was last online {}", ago),
+    }
+}
+
+fn plural_ago(n: i64, unit: &str) -> String {
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+/// Heartbeat timeout for a client pinging every `ping_interval_ms`:
+/// two intervals, clamped to sane bounds. Falls back to the default when unset.
+pub fn heartbeat_timeout_for(ping_interval_ms: Option<u64>) -> Duration {
+    match ping_interval_ms {
+        Some(ms) if ms > 0 => Duration::from_millis(ms.saturating_mul(2))
+            .clamp(MIN_HEARTBEAT_TIMEOUT, MAX_HEARTBEAT_TIMEOUT),
+        _ => HEARTBEAT_TIMEOUT,
+    }
+}
+
+/// Returns true if this was the user's last connection (user went offline).
+fn cleanup_connection(ws_state: &WsState, user_id: &str, conn_id: &str) -> bool {
+    ws_state.heartbeat_timeouts.remove(conn_id);
+
     // Remove visibility tracking
     if let Some(visible) = ws_state.socket_visible.remove(conn_id) {
         if visible.1 {
@@ -404,8 +546,10 @@ fn cleanup_connection(ws_state: &WsState, user_id: &str, conn_id: &str) {
             drop(conns);
             ws_state.user_connections.remove(user_id);
             ws_state.foreground_counts.remove(user_id);
+            return true;
         }
     }
+    false
 }
 
 async fn handle_message(
@@ -546,13 +690,21 @@ async fn handle_message(
             }
 
             // Rate limit check
-            if !check_rate_limit(user_id, redis, ws_state).await {
+            let rate = check_rate_limit(user_id, redis, ws_state).await;
+            if !rate.allowed() {
                 send_event(tx, &json!({
                     "type": "stream_error",
                     "conversationId": conversation_id,
                     "messageId": "",
                     "seq": 0,
-                    "error": "Rate limit exceeded. Please wait before sending more messages."
+                    "code": "rate_limited",
+                    "count": rate.count,
+                    "limit": rate.limit,
+                    "resetInSeconds": rate.reset_in_secs,
+                    "error": format!(
+                        "Rate limit exceeded ({} messages per minute). Try again in {}s.",
+                        rate.limit, rate.reset_in_secs
+                    )
                 }));
                 return;
             }
@@ -607,6 +759,33 @@ async fn handle_message(
         "cancel_stream" => {
             let message_id = event.get("messageId").and_then(|v| v.as_str()).unwrap_or("");
 
+            // Only the user who triggered the stream or a conversation admin may
+            // cancel it; anyone else is silently ignored.
+            let owner = ws_state
+                .stream_cancellers
+                .get(message_id)
+                .map(|c| (c.triggered_by.clone(), c.conversation_id.clone()));
+            let conversation_id = match &owner {
+                Some((_, conv_id)) => Some(conv_id.clone()),
+                None => sqlx::query_scalar::<_, String>(
+                    "SELECT conversation_id::text FROM messages WHERE id = $1::uuid",
+                )
+                .bind(message_id)
+                .fetch_optional(db)
+                .await
+                .ok()
+                .flatten(),
+            };
+            let Some(conversation_id) = conversation_id else {
+                return;
+            };
+            let triggered_by = owner.map(|(t, _)| t).unwrap_or_default();
+            let is_admin = user_id != triggered_by
+                && is_conversation_admin(db, &conversation_id, user_id).await;
+            if !can_cancel_stream(user_id, &triggered_by, is_admin) {
+                return;
+            }
+
             // Immediately update DB so a refresh won't see stale 'streaming' status
             let _ = sqlx::query(
                 r#"UPDATE messages SET status = 'cancelled', updated_at = NOW()
@@ -616,8 +795,8 @@ async fn handle_message(
             .execute(db)
             .await;
 
-            if let Some((_, cancel_tx)) = ws_state.stream_cancellers.remove(message_id) {
-                let _ = cancel_tx.send(true);
+            if let Some((_, canceller)) = ws_state.stream_cancellers.remove(message_id) {
+                let _ = canceller.tx.send(true);
             }
         }
         "cancel_queued" => {
@@ -647,7 +826,8 @@ async fn handle_message(
         }
         "sync" => {
             let conversations = event.get("conversations").cloned().unwrap_or(json!({}));
-            handle_sync(user_id, tx, &conversations, ws_state, db, redis).await;
+            let include_archived = event.get("includeArchived").and_then(|v| v.as_bool()).unwrap_or(false);
+            handle_sync(user_id, tx, &conversations, include_archived, ws_state, db, redis).await;
         }
         "mark_read" => {
             let conversation_id = event.get("conversationId").and_then(|v| v.as_str()).unwrap_or("");
@@ -656,6 +836,15 @@ async fn handle_message(
                 handle_mark_read(user_id, conversation_id, seq, db, ws_state, redis).await;
             }
         }
+        "mark_all_read" => {
+            match crate::routes::conversations::mark_all_conversations_read(db, user_id).await {
+                Ok(updated) => send_event(tx, &json!({
+                    "type": "mark_all_read_result",
+                    "updated": updated,
+                })),
+                Err(e) => tracing::warn!("mark_all_read failed for user {}: {}", user_id, e),
+            }
+        }
         "typing" => {
             let conversation_id = event.get("conversationId").and_then(|v| v.as_str()).unwrap_or("");
             if conversation_id.is_empty() { return; }
@@ -731,6 +920,22 @@ async fn handle_message(
                 }
             }
         }
+        "presence" => {
+            let conversation_id = event.get("conversationId").and_then(|v| v.as_str()).unwrap_or("");
+            if !conversation_id.is_empty() {
+                handle_presence(user_id, conversation_id, tx, ws_state, db).await;
+            }
+        }
+        "hello" => {
+            // Client declares how often it pings; give it two intervals before timing out
+            let ping_interval_ms = event.get("pingIntervalMs").and_then(|v| v.as_u64());
+            let heartbeat = heartbeat_timeout_for(ping_interval_ms);
+            ws_state.heartbeat_timeouts.insert(conn_id.to_string(), heartbeat);
+            send_event(tx, &json!({
+                "type": "hello_ack",
+                "heartbeatTimeoutMs": heartbeat.as_millis() as u64,
+            }));
+        }
         "focus" => {
             let visible = event.get("visible").and_then(|v| v.as_bool()).unwrap_or(false);
             let prev = ws_state.socket_visible.get(conn_id).map(|v| *v).unwrap_or(false);
@@ -755,18 +960,64 @@ async fn handle_message(
     }
 }
 
+/// Whether `user_id` owns the conversation or is a group admin/vice admin.
+async fn is_conversation_admin(db: &PgPool, conversation_id: &str, user_id: &str) -> bool {
+    sqlx::query_scalar::<_, bool>(
+        r#"SELECT EXISTS (SELECT 1 FROM conversations WHERE id = $1::uuid AND user_id = $2)
+               OR EXISTS (SELECT 1 FROM conversation_user_members
+                          WHERE conversation_id = $1::uuid AND user_id = $2
+                            AND role IN ('admin', 'vice_admin'))"#,
+    )
+    .bind(conversation_id)
+    .bind(user_id)
+    .fetch_one(db)
+    .await
+    .unwrap_or(false)
+}
+
 fn send_event(tx: &mpsc::UnboundedSender<String>, event: &Value) {
     let msg = serde_json::to_string(event).unwrap_or_default();
     let _ = tx.send(msg);
 }
 
+/// A user's WebSocket message budget for the current window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    /// Messages counted in the current window (including a rejected one).
+    pub count: i32,
+    pub limit: i32,
+    /// Seconds until the window resets.
+    pub reset_in_secs: i64,
+}
+
+impl RateLimitStatus {
+    pub fn allowed(&self) -> bool {
+        self.count <= self.limit
+    }
+
+    pub fn remaining(&self) -> i32 {
+        (self.limit - self.count).max(0)
+    }
+}
+
+/// Redis key for a user's rate-limit window (one per wall-clock minute).
+fn rate_limit_key(user_id: &str, now_secs: i64) -> String {
+    format!("ws:rate:{}:{}", user_id, now_secs / 60)
+}
+
+/// Seconds left in the minute-long window containing `now_secs`.
+pub fn rate_limit_reset_in(now_secs: i64) -> i64 {
+    60 - now_secs.rem_euclid(60)
+}
+
+/// Count a message against the user's budget and report where they stand.
 async fn check_rate_limit(
     user_id: &str,
     redis: &deadpool_redis::Pool,
     ws_state: &WsState,
-) -> bool {
-    let minute = chrono::Utc::now().timestamp() / 60;
-    let key = format!("ws:rate:{}:{}", user_id, minute);
+) -> RateLimitStatus {
+    let now_secs = chrono::Utc::now().timestamp();
+    let key = rate_limit_key(user_id, now_secs);
 
     // Try Redis first
     if let Ok(mut conn) = redis.get().await {
@@ -774,7 +1025,11 @@ async fn check_rate_limit(
             if count == 1 {
                 let _ = conn.expire::<_, ()>(&key, 120).await;
             }
-            return count <= WS_RATE_LIMIT;
+            return RateLimitStatus {
+                count,
+                limit: WS_RATE_LIMIT,
+                reset_in_secs: rate_limit_reset_in(now_secs),
+            };
         }
     }
 
@@ -782,14 +1037,94 @@ async fn check_rate_limit(
     let now_ms = chrono::Utc::now().timestamp_millis();
     let mut entry = ws_state.ws_rate_limits.entry(user_id.to_string()).or_insert((0, now_ms + 60000));
     if now_ms > entry.1 {
-        *entry = (1, now_ms + 60000);
-        return true;
+        *entry = (0, now_ms + 60000);
     }
-    if entry.0 >= WS_RATE_LIMIT {
-        return false;
+    let count = if entry.0 >= WS_RATE_LIMIT {
+        // Over budget: report the rejected message without counting it
+        entry.0 + 1
+    } else {
+        entry.0 += 1;
+        entry.0
+    };
+    RateLimitStatus {
+        count,
+        limit: WS_RATE_LIMIT,
+        reset_in_secs: ((entry.1 - now_ms) / 1000).max(0),
     }
-    entry.0 += 1;
-    true
+}
+
+/// Read the user's current budget without counting a message.
+pub async fn ws_rate_limit_status(
+    user_id: &str,
+    redis: &deadpool_redis::Pool,
+    ws_state: &WsState,
+) -> RateLimitStatus {
+    let now_secs = chrono::Utc::now().timestamp();
+
+    if let Ok(mut conn) = redis.get().await {
+        if let Ok(count) = conn.get::<_, Option<i32>>(rate_limit_key(user_id, now_secs)).await {
+            return RateLimitStatus {
+                count: count.unwrap_or(0),
+                limit: WS_RATE_LIMIT,
+                reset_in_secs: rate_limit_reset_in(now_secs),
+            };
+        }
+    }
+
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let (count, reset_at) = ws_state
+        .ws_rate_limits
+        .get(user_id)
+        .map(|e| *e)
+        .filter(|(_, reset_at)| now_ms <= *reset_at)
+        .unwrap_or((0, now_ms + 60000));
+    RateLimitStatus {
+        count,
+        limit: WS_RATE_LIMIT,
+        reset_in_secs: ((reset_at - now_ms) / 1000).max(0),
+    }
+}
+
+/// Missed messages sent per conversation in a `sync_response`.
+const SYNC_MISSED_PER_CONVERSATION: i64 = 100;
+
+/// Conversations a syncing client is behind on, in `conv_ids` order, paired with
+/// the client's last seen seq. Conversations the client didn't report are skipped.
+pub fn sync_missed_targets(
+    conv_ids: &[String],
+    client_conversations: &Value,
+    max_seqs: &std::collections::HashMap<String, i32>,
+) -> (Vec<String>, Vec<i32>) {
+    conv_ids
+        .iter()
+        .filter_map(|cid| {
+            let client_last_seq = client_conversations.get(cid)?.as_i64()? as i32;
+            (client_last_seq < max_seqs.get(cid).copied().unwrap_or(0)).then(|| (cid.clone(), client_last_seq))
+        })
+        .unzip()
+}
+
+/// Conversations to include in a sync for `user_id`, as `(id, pinned_at)`.
+/// Pinned conversations come first, matching the conversation list order.
+/// Pin and archive state are the user's own (`conversation_reads`).
+pub async fn sync_conversation_rows(
+    db: &PgPool,
+    user_id: &str,
+    include_archived: bool,
+) -> Result<Vec<(String, Option<chrono::NaiveDateTime>)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, Option<chrono::NaiveDateTime>)>(
+        r#"SELECT c.id::text, cr.pinned_at FROM conversations c
+           LEFT JOIN conversation_reads cr ON cr.conversation_id = c.id AND cr.user_id = $1
+           WHERE (c.user_id = $1 OR EXISTS (
+               SELECT 1 FROM conversation_user_members cum WHERE cum.conversation_id = c.id AND cum.user_id = $1
+           ))
+             AND ($2 OR cr.archived_at IS NULL)
+           ORDER BY cr.pinned_at DESC NULLS LAST, c.updated_at DESC"#,
+    )
+    .bind(user_id)
+    .bind(include_archived)
+    .fetch_all(db)
+    .await
 }
 
 /// Handle sync request: returns missed messages + conversation summaries
@@ -797,21 +1132,18 @@ async fn handle_sync(
     user_id: &str,
     tx: &mpsc::UnboundedSender<String>,
     client_conversations: &Value,
+    include_archived: bool,
     ws_state: &WsState,
     db: &PgPool,
     redis: &deadpool_redis::Pool,
 ) {
-    let conv_rows = sqlx::query_as::<_, (String,)>(
-        r#"SELECT id::text FROM conversations WHERE user_id = $1
-           UNION
-           SELECT conversation_id::text FROM conversation_user_members WHERE user_id = $1"#,
-    )
-    .bind(user_id)
-    .fetch_all(db)
-    .await;
+    let conv_rows = sync_conversation_rows(db, user_id, include_archived).await;
 
-    let conv_ids: Vec<String> = match conv_rows {
-        Ok(rows) => rows.into_iter().map(|r| r.0).collect(),
+    let (conv_ids, pinned_ids): (Vec<String>, std::collections::HashSet<String>) = match conv_rows {
+        Ok(rows) => {
+            let pinned = rows.iter().filter(|r| r.1.is_some()).map(|r| r.0.clone()).collect();
+            (rows.into_iter().map(|r| r.0).collect(), pinned)
+        }
         Err(e) => {
             tracing::error!("Sync error: {}", e);
             return;
@@ -828,8 +1160,8 @@ async fn handle_sync(
     }
 
     // Get read positions
-    let reads = sqlx::query_as::<_, (String, i32, bool)>(
-        r#"SELECT conversation_id::text, last_read_seq, muted
+    let reads = sqlx::query_as::<_, (String, i32, bool, String)>(
+        r#"SELECT conversation_id::text, last_read_seq, muted, push_mode
            FROM conversation_reads
            WHERE user_id = $1"#,
     )
@@ -838,117 +1170,138 @@ async fn handle_sync(
     .await
     .unwrap_or_default();
 
-    let read_map: std::collections::HashMap<String, (i32, bool)> = reads
+    let read_map: std::collections::HashMap<String, (i32, bool, String)> = reads
         .into_iter()
-        .map(|(cid, seq, muted)| (cid, (seq, muted)))
+        .map(|(cid, seq, muted, push_mode)| (cid, (seq, muted, push_mode)))
         .collect();
 
-    let mut summaries = Vec::new();
-    let mut missed_messages = Vec::new();
-
-    for conv_id in &conv_ids {
-        // Get max seq
-        let max_seq_row = sqlx::query_as::<_, (Option<i32>,)>(
-            r#"SELECT MAX(seq) FROM messages WHERE conversation_id = $1::uuid"#,
-        )
-        .bind(conv_id)
-        .fetch_one(db)
-        .await;
-
-        let max_seq = max_seq_row.map(|r| r.0.unwrap_or(0)).unwrap_or(0);
-
-        // Get last message
-        let last_msg = sqlx::query_as::<_, (String, String, String, chrono::NaiveDateTime)>(
-            r#"SELECT content, role::text, status::text, created_at
-               FROM messages WHERE conversation_id = $1::uuid
-               ORDER BY seq DESC LIMIT 1"#,
-        )
-        .bind(conv_id)
-        .fetch_optional(db)
+    // Unread messages from others that @-mention this user, per conversation
+    let mention_map = crate::services::mention::unread_mention_counts(db, user_id, &conv_ids)
         .await
-        .ok()
-        .flatten();
-
-        let (last_read_seq, muted) = read_map.get(conv_id).copied().unwrap_or((0, false));
-        let unread_count = (max_seq - last_read_seq).max(0);
-
-        let last_message = last_msg.map(|(content, role, status, created_at)| {
-            json!({
-                "content": content,
-                "role": role,
-                "status": status,
-                "createdAt": created_at.and_utc().to_rfc3339()
-            })
+        .unwrap_or_else(|e| {
+            tracing::error!("Sync: unread mentions query failed: {}", e);
+            std::collections::HashMap::new()
         });
 
-        summaries.push(json!({
-            "conversationId": conv_id,
-            "unreadCount": unread_count,
-            "maxSeq": max_seq,
-            "muted": muted,
-            "lastMessage": last_message
+    // Last message (and so max seq) of every conversation in one round trip;
+    // each lateral lookup is a single probe of idx_messages_conversation_seq.
+    let last_rows = sqlx::query_as::<_, (String, i32, String, String, String, chrono::NaiveDateTime)>(
+        r#"SELECT c.id::text, m.seq, m.content, m.role::text, m.status::text, m.created_at
+           FROM unnest($1::text[]::uuid[]) AS c(id)
+           CROSS JOIN LATERAL (
+               SELECT seq, content, role, status, created_at
+               FROM messages WHERE conversation_id = c.id
+               ORDER BY seq DESC LIMIT 1
+           ) m"#,
+    )
+    .bind(&conv_ids)
+    .fetch_all(db)
+    .await
+    .unwrap_or_else(|e| {
+        tracing::error!("Sync: last messages query failed: {}", e);
+        Vec::new()
+    });
+
+    let mut max_seqs: std::collections::HashMap<String, i32> = std::collections::HashMap::new();
+    let mut last_messages: std::collections::HashMap<String, Value> = std::collections::HashMap::new();
+    for (cid, seq, content, role, status, created_at) in last_rows {
+        max_seqs.insert(cid.clone(), seq);
+        last_messages.insert(cid, json!({
+            "content": content,
+            "role": role,
+            "status": status,
+            "createdAt": created_at.and_utc().to_rfc3339()
         }));
+    }
 
-        // Missed messages for conversations the client knows about
-        if let Some(client_last_seq) = client_conversations
-            .get(conv_id)
-            .and_then(|v| v.as_i64())
-        {
-            let client_last_seq = client_last_seq as i32;
-            if client_last_seq < max_seq {
-                let missed = sqlx::query_as::<_, (String, String, i32, String, String, String, chrono::NaiveDateTime, Option<String>)>(
-                    r#"SELECT id::text, conversation_id::text, seq, role::text, content, status::text, created_at, thread_id::text
-                       FROM messages
-                       WHERE conversation_id = $1::uuid AND seq > $2
-                       ORDER BY seq ASC LIMIT 100"#,
-                )
-                .bind(conv_id)
-                .bind(client_last_seq)
-                .fetch_all(db)
-                .await
-                .unwrap_or_default();
+    let summaries: Vec<Value> = conv_ids
+        .iter()
+        .map(|conv_id| {
+            let max_seq = max_seqs.get(conv_id).copied().unwrap_or(0);
+            let (last_read_seq, muted, push_mode) = read_map
+                .get(conv_id)
+                .map(|(seq, muted, mode)| (*seq, *muted, mode.as_str()))
+                .unwrap_or((0, false, "all"));
+            json!({
+                "conversationId": conv_id,
+                "unreadCount": (max_seq - last_read_seq).max(0),
+                "unreadMentions": mention_map.get(conv_id).copied().unwrap_or(0),
+                "maxSeq": max_seq,
+                "muted": muted,
+                "pushMode": push_mode,
+                "pinned": pinned_ids.contains(conv_id),
+                "lastMessage": last_messages.get(conv_id)
+            })
+        })
+        .collect();
 
-                for (id, cid, seq, role, mut content, mut status, created_at, thread_id) in missed {
-                    // Fix stuck streaming messages
-                    if status == "streaming" && !ws_state.has_active_stream(conv_id) {
-                        status = if !content.is_empty() {
-                            "completed".to_string()
-                        } else {
-                            "error".to_string()
-                        };
-                        let _ = sqlx::query(
-                            r#"UPDATE messages SET status = $1::message_status, updated_at = NOW() WHERE id = $2::uuid"#,
-                        )
-                        .bind(&status)
-                        .bind(&id)
-                        .execute(db)
-                        .await;
-                    }
+    // Missed messages for conversations the client knows about, first
+    // SYNC_MISSED_PER_CONVERSATION of each, in conversation-list order
+    let (missed_conv_ids, client_seqs) = sync_missed_targets(&conv_ids, client_conversations, &max_seqs);
+    let missed = if missed_conv_ids.is_empty() {
+        Vec::new()
+    } else {
+        sqlx::query_as::<_, (String, String, i32, String, String, String, chrono::NaiveDateTime, Option<String>)>(
+            r#"SELECT m.id::text, m.conversation_id::text, m.seq, m.role::text, m.content,
+                      m.status::text, m.created_at, m.thread_id::text
+               FROM unnest($1::text[]::uuid[], $2::int[]) WITH ORDINALITY AS k(conversation_id, last_seq, ord)
+               CROSS JOIN LATERAL (
+                   SELECT id, conversation_id, seq, role, content, status, created_at, thread_id
+                   FROM messages
+                   WHERE conversation_id = k.conversation_id AND seq > k.last_seq
+                   ORDER BY seq
+                   LIMIT $3
+               ) m
+               ORDER BY k.ord, m.seq"#,
+        )
+        .bind(&missed_conv_ids)
+        .bind(&client_seqs)
+        .bind(SYNC_MISSED_PER_CONVERSATION)
+        .fetch_all(db)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Sync: missed messages query failed: {}", e);
+            Vec::new()
+        })
+    };
 
-                    // For active streaming, fetch content from Redis
-                    if status == "streaming" {
-                        if let Ok(mut conn) = redis.get().await {
-                            if let Ok(cached) = conn.get::<_, Option<String>>(&format!("stream:{}", id)).await {
-                                if let Some(cached) = cached {
-                                    content = cached;
-                                }
-                            }
-                        }
-                    }
+    let mut missed_messages = Vec::with_capacity(missed.len());
+    for (id, cid, seq, role, mut content, mut status, created_at, thread_id) in missed {
+        // Fix stuck streaming messages
+        if status == "streaming" && !ws_state.has_active_stream(&cid) {
+            status = if !content.is_empty() {
+                "completed".to_string()
+            } else {
+                "error".to_string()
+            };
+            let _ = sqlx::query(
+                r#"UPDATE messages SET status = $1::message_status, updated_at = NOW() WHERE id = $2::uuid"#,
+            )
+            .bind(&status)
+            .bind(&id)
+            .execute(db)
+            .await;
+        }
 
-                    missed_messages.push(json!({
-                        "id": id,
-                        "conversationId": cid,
-                        "seq": seq,
-                        "role": role,
-                        "content": content,
-                        "status": status,
-                        "createdAt": created_at.and_utc().to_rfc3339(),
-                        "threadId": thread_id
-                    }));
+        // For active streaming, fetch content from Redis
+        if status == "streaming" {
+            if let Ok(mut conn) = redis.get().await {
+                if let Ok(Some(cached)) = conn.get::<_, Option<String>>(&format!("stream:{}", id)).await {
+                    content = cached;
                 }
             }
         }
+
+        missed_messages.push(json!({
+            "id": id,
+            "conversationId": cid,
+            "seq": seq,
+            "role": role,
+            "content": content,
+            "status": status,
+            "createdAt": created_at.and_utc().to_rfc3339(),
+            "threadId": thread_id
+        }));
     }
 
     send_event(tx, &json!({
@@ -964,8 +1317,8 @@ async fn handle_sync(
             continue;
         }
 
-        let streaming_msg = sqlx::query_as::<_, (String, i32)>(
-            r#"SELECT id::text, seq FROM messages
+        let streaming_msg = sqlx::query_as::<_, (String, i32, Option<String>)>(
+            r#"SELECT id::text, seq, sender_agent_id::text FROM messages
                WHERE conversation_id = $1::uuid AND status = 'streaming'
                ORDER BY created_at DESC LIMIT 1"#,
         )
@@ -973,7 +1326,7 @@ async fn handle_sync(
         .fetch_optional(db)
         .await;
 
-        if let Ok(Some((msg_id, seq))) = streaming_msg {
+        if let Ok(Some((msg_id, seq, agent_id))) = streaming_msg {
             let mut content = String::new();
             if let Ok(mut conn) = redis.get().await {
                 if let Ok(Some(cached)) = conn.get::<_, Option<String>>(&format!("stream:{}", msg_id)).await {
@@ -981,18 +1334,6 @@ async fn handle_sync(
                 }
             }
 
-            // Also try to get the agent info from the message
-            let agent_info = sqlx::query_as::<_, (Option<String>,)>(
-                r#"SELECT sender_agent_id::text FROM messages WHERE id = $1::uuid"#,
-            )
-            .bind(&msg_id)
-            .fetch_optional(db)
-            .await
-            .ok()
-            .flatten();
-
-            let agent_id = agent_info.and_then(|a| a.0);
-
             send_event(tx, &json!({
                 "type": "stream_resume",
                 "conversationId": conv_id,
@@ -1041,6 +1382,30 @@ async fn handle_mark_read(
     .execute(db)
     .await;
 
+    let is_group = sqlx::query_scalar::<_, String>(
+        "SELECT type::text FROM conversations WHERE id = $1::uuid",
+    )
+    .bind(conversation_id)
+    .fetch_optional(db)
+    .await
+    .ok()
+    .flatten()
+    .is_some_and(|t| t == "group");
+
+    // Groups: coalesce rapid reads from many members into one read_receipts event per window
+    if is_group {
+        if ws_state.queue_read_receipt(conversation_id, user_id, seq) {
+            let ws_state = ws_state.clone();
+            let db = db.clone();
+            let conversation_id = conversation_id.to_string();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(READ_RECEIPT_BATCH_MS)).await;
+                flush_read_receipts(&conversation_id, &db, &ws_state).await;
+            });
+        }
+        return;
+    }
+
     // Broadcast read_receipt to conversation members so senders see checkmarks update
     let member_ids = get_conv_member_ids(ws_state, db, conversation_id, user_id).await;
     ws_state.broadcast_to_members(
@@ -1055,6 +1420,179 @@ async fn handle_mark_read(
     );
 }
 
+/// Window over which group read receipts are batched into a single broadcast.
+const READ_RECEIPT_BATCH_MS: u64 = 1000;
+
+/// Broadcast a conversation's queued read receipts. Each member receives one
+/// `read_receipts` event listing the readers they are not blocked with.
+async fn flush_read_receipts(conversation_id: &str, db: &PgPool, ws_state: &WsState) {
+    let receipts = ws_state.take_read_receipts(conversation_id);
+    if receipts.is_empty() {
+        return;
+    }
+    let members = get_conv_member_ids(ws_state, db, conversation_id, "").await;
+
+    // Block pairs involving any reader, in one query: (reader, other user)
+    let readers: Vec<String> = receipts.keys().cloned().collect();
+    let blocked: std::collections::HashSet<(String, String)> = sqlx::query_as::<_, (String, String)>(
+        r#"SELECT r.id, CASE WHEN f.requester_id = r.id THEN f.addressee_id ELSE f.requester_id END
+           FROM UNNEST($1::text[]) AS r(id)
+           JOIN friendships f ON f.status = 'blocked'
+             AND (f.requester_id = r.id OR f.addressee_id = r.id)"#,
+    )
+    .bind(&readers)
+    .fetch_all(db)
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .collect();
+
+    for (recipient, receipts) in read_receipts_by_recipient(&receipts, &members, &blocked) {
+        ws_state.send_to_user(
+            &recipient,
+            &json!({
+                "type": "read_receipts",
+                "conversationId": conversation_id,
+                "receipts": receipts,
+            }),
+        );
+    }
+}
+
+/// Group queued read receipts (reader -> seq) by the member who should see
+/// them, skipping the reader themselves and any (reader, member) pair in `blocked`.
+pub fn read_receipts_by_recipient(
+    receipts: &std::collections::HashMap<String, i32>,
+    members: &[String],
+    blocked: &std::collections::HashSet<(String, String)>,
+) -> std::collections::HashMap<String, Vec<Value>> {
+    let mut by_recipient: std::collections::HashMap<String, Vec<Value>> =
+        std::collections::HashMap::new();
+    for (reader, seq) in receipts {
+        for member in members {
+            if member != reader && !blocked.contains(&(reader.clone(), member.clone())) {
+                by_recipient
+                    .entry(member.clone())
+                    .or_default()
+                    .push(json!({"userId": reader, "seq": seq}));
+            }
+        }
+    }
+    by_recipient
+}
+
+/// User ID as shown to other members — anonymized in community conversations.
+fn presence_user_id(conv_type: &str, conversation_id: &str, user_id: &str) -> String {
+    if conv_type == "community" {
+        use sha2::{Sha256, Digest};
+        let mut hasher = Sha256::new();
+        hasher.update(conversation_id.as_bytes());
+        hasher.update(user_id.as_bytes());
+        format!("anon-{}", hex::encode(&hasher.finalize()[..8]))
+    } else {
+        user_id.to_string()
+    }
+}
+
+/// Handle presence request: reply with the members of a conversation that are currently online
+async fn handle_presence(
+    user_id: &str,
+    conversation_id: &str,
+    tx: &mpsc::UnboundedSender<String>,
+    ws_state: &WsState,
+    db: &PgPool,
+) {
+    let conv_type = sqlx::query_scalar::<_, String>(
+        r#"SELECT c.type::text FROM conversations c
+           WHERE c.id = $1::uuid
+             AND (c.user_id = $2 OR EXISTS (
+                SELECT 1 FROM conversation_user_members cum
+                WHERE cum.conversation_id = c.id AND cum.user_id = $2
+             ))"#,
+    )
+    .bind(conversation_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await;
+
+    let conv_type = match conv_type {
+        Ok(Some(t)) => t,
+        _ => return,
+    };
+
+    let member_ids = get_conv_member_ids(ws_state, db, conversation_id, user_id).await;
+    let online: Vec<Value> = member_ids
+        .iter()
+        .filter(|uid| ws_state.is_user_online(uid))
+        .map(|uid| json!({
+            "userId": presence_user_id(&conv_type, conversation_id, uid),
+            "foreground": ws_state.is_user_foreground(uid),
+        }))
+        .collect();
+
+    send_event(tx, &json!({
+        "type": "presence_response",
+        "conversationId": conversation_id,
+        "online": online,
+    }));
+}
+
+/// Notify online members of the user's shared conversations that the user
+/// connected or disconnected. Presence is ephemeral, so offline members are not queued.
+async fn broadcast_presence_update(ws_state: &WsState, db: &PgPool, user_id: &str, online: bool) {
+    let recipients = presence_recipients(db, user_id).await.unwrap_or_default();
+
+    let foreground = online && ws_state.is_user_foreground(user_id);
+    for (conversation_id, conv_type, member_ids) in recipients {
+        let event = json!({
+            "type": "presence_update",
+            "conversationId": conversation_id,
+            "userId": presence_user_id(&conv_type, &conversation_id, user_id),
+            "online": online,
+            "foreground": foreground,
+        });
+        for mid in &member_ids {
+            ws_state.send_to_user(mid, &event);
+        }
+    }
+}
+
+/// Who sees `user_id`'s presence, per conversation: `(conversation id, type,
+/// other members)` for every conversation they belong to, minus members in a
+/// block with them either way. One query for all conversations.
+pub async fn presence_recipients(
+    db: &PgPool,
+    user_id: &str,
+) -> Result<Vec<(String, String, Vec<String>)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, String, String)>(
+        r#"SELECT c.id::text, c.type::text, other.user_id
+           FROM conversation_user_members mine
+           JOIN conversations c ON c.id = mine.conversation_id
+           JOIN conversation_user_members other
+             ON other.conversation_id = mine.conversation_id AND other.user_id <> $1
+           WHERE mine.user_id = $1
+             AND NOT EXISTS (
+                 SELECT 1 FROM friendships f
+                 WHERE f.status = 'blocked'
+                   AND ((f.requester_id = $1 AND f.addressee_id = other.user_id)
+                     OR (f.requester_id = other.user_id AND f.addressee_id = $1))
+             )
+           ORDER BY c.id"#,
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut recipients: Vec<(String, String, Vec<String>)> = Vec::new();
+    for (conversation_id, conv_type, member_id) in rows {
+        match recipients.last_mut() {
+            Some((cid, _, members)) if *cid == conversation_id => members.push(member_id),
+            _ => recipients.push((conversation_id, conv_type, vec![member_id])),
+        }
+    }
+    Ok(recipients)
+}
+
 /// Update thread_summaries when a message is posted to a thread.
 /// Uses UPSERT: creates the summary on first reply, increments on subsequent replies.
 async fn update_thread_summary(
@@ -1204,6 +1742,17 @@ pub async fn trigger_agent_response(
             .execute(db)
             .await;
 
+            {
+                let db2 = db.clone();
+                let mid = msg_id.to_string();
+                let cid = conversation_id.to_string();
+                let uid = user_id.to_string();
+                let text = content.to_string();
+                tokio::spawn(async move {
+                    crate::services::mention::record_message_mentions(&db2, &mid, &cid, &text, Some(&uid), &[]).await;
+                });
+            }
+
             // Spawn link preview extraction in background
             {
                 let db2 = db.clone();
@@ -1320,7 +1869,11 @@ pub async fn trigger_agent_response(
                     if !always_push { continue; }
                 }
                 if let Ok(false) = is_conversation_muted(db, mid, conversation_id).await {
-                    if let Ok(true) = should_send_push(db, mid, "message").await {
+                    let push_type = message_push_type(db, mid, &conv_type, content).await;
+                    if push_type != "mention" && matches!(is_mentions_only(db, mid, conversation_id).await, Ok(true)) {
+                        continue;
+                    }
+                    if let Ok(true) = push_allowed(db, mid, push_type).await {
                         let preview = {
                             let truncated = safe_truncate(&content, 100);
                             if truncated.len() < content.len() {
@@ -1329,12 +1882,14 @@ pub async fn trigger_agent_response(
                                 content.to_string()
                             }
                         };
-                        let _ = send_push_to_user(
+                        queue_message_push(
                             db,
+                            redis,
                             config,
                             mid,
-                            &crate::services::push::PushPayload {
-                                notification_type: "message".into(),
+                            conversation_id,
+                            crate::services::push::PushPayload {
+                                notification_type: push_type.into(),
                                 title: sender_name.clone(),
                                 body: preview,
                                 url: Some(format!("/?c={}&m={}", conversation_id, msg_id)),
@@ -1473,6 +2028,18 @@ pub async fn trigger_agent_response(
             .execute(db)
             .await;
 
+        {
+            let db2 = db.clone();
+            let mid = user_msg_id.to_string();
+            let cid = conversation_id.to_string();
+            let uid = user_id.to_string();
+            let text = content.to_string();
+            let agent_mentions = mentions.to_vec();
+            tokio::spawn(async move {
+                crate::services::mention::record_message_mentions(&db2, &mid, &cid, &text, Some(&uid), &agent_mentions).await;
+            });
+        }
+
         {
             // Spawn link preview extraction in background
             {
@@ -1592,7 +2159,11 @@ pub async fn trigger_agent_response(
                     if !always_push { continue; }
                 }
                 if let Ok(false) = is_conversation_muted(db, mid, conversation_id).await {
-                    if let Ok(true) = should_send_push(db, mid, "message").await {
+                    let push_type = message_push_type(db, mid, &conv_type, content).await;
+                    if push_type != "mention" && matches!(is_mentions_only(db, mid, conversation_id).await, Ok(true)) {
+                        continue;
+                    }
+                    if let Ok(true) = push_allowed(db, mid, push_type).await {
                         let preview = {
                             let truncated = safe_truncate(&content, 100);
                             if truncated.len() < content.len() {
@@ -1601,12 +2172,14 @@ pub async fn trigger_agent_response(
                                 content.to_string()
                             }
                         };
-                        let _ = send_push_to_user(
+                        queue_message_push(
                             db,
+                            redis,
                             config,
                             mid,
-                            &crate::services::push::PushPayload {
-                                notification_type: "message".into(),
+                            conversation_id,
+                            crate::services::push::PushPayload {
+                                notification_type: push_type.into(),
                                 title: sender_name.clone(),
                                 body: preview,
                                 url: Some(format!("/?c={}&m={}", conversation_id, user_msg_id)),
@@ -1642,13 +2215,62 @@ pub async fn trigger_agent_response(
     if is_non_ai_sticker {
         tracing::info!("Skipping agent dispatch for non-AI sticker in conv={}", conversation_id);
     }
-    for agent_id in &dispatch_ids {
+    let response_group_id = saved_user_msg_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let response_groups = assign_response_groups(&response_group_id, &dispatch_ids);
+    for (agent_id, response_group) in dispatch_ids.iter().zip(response_groups) {
         if is_non_ai_sticker {
             continue;
         }
         // Per-agent queue: if this specific agent has an active stream, queue it
-        if ws_state.has_active_stream_for_agent(conversation_id, agent_id) {
-            let queue_key = format!("{}:{}", conversation_id, agent_id);
+        let queue_key = format!("{}:{}", conversation_id, agent_id);
+        let stream_age = if ws_state.has_active_stream_for_agent(conversation_id, agent_id) {
+            ws_state.active_streams.get(&queue_key).map(|started| started.elapsed())
+        } else {
+            None
+        };
+        let tail_coalescable = ws_state.agent_response_queues.get(&queue_key)
+            .and_then(|q| q.back().map(|tail| tail.user_id == user_id && tail.thread_id == thread_id))
+            .unwrap_or(false);
+        let decision = decide_dispatch(stream_age, tail_coalescable);
+
+        if decision == DispatchDecision::Coalesce {
+            let mut merged = false;
+            if let Some(mut q) = ws_state.agent_response_queues.get_mut(&queue_key) {
+                if let Some(tail) = q.back_mut() {
+                    tail.content.push_str("\n\n");
+                    tail.content.push_str(content);
+                    if saved_user_msg_id.is_some() {
+                        tail.user_message_id = saved_user_msg_id.clone();
+                    }
+                    merged = true;
+                }
+            }
+            if merged {
+                tracing::info!("Agent dispatch coalesced: conv={} agent={}", conversation_id, agent_id);
+                let agent_name = sqlx::query_scalar::<_, String>(
+                    r#"SELECT name FROM agents WHERE id = $1::uuid"#,
+                )
+                .bind(agent_id)
+                .fetch_optional(db)
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| "Agent".to_string());
+                ws_state.send_to_user(user_id, &json!({
+                    "type": "stream_queued",
+                    "conversationId": conversation_id,
+                    "agentId": agent_id,
+                    "agentName": agent_name,
+                    "messageId": saved_user_msg_id,
+                    "coalesced": true,
+                }));
+                continue;
+            }
+        }
+
+        if decision != DispatchDecision::Start {
 
             // Dedup: skip if this message is already queued for this agent
             let already_queued = ws_state.agent_response_queues.get(&queue_key)
@@ -1673,6 +2295,7 @@ pub async fn trigger_agent_response(
                     thread_id: thread_id.clone(),
                     user_message_id: saved_user_msg_id.clone(),
                     metadata: client_metadata.clone(),
+                    response_group: Some(response_group),
                 });
 
             // Notify the user that this agent's response is queued
@@ -1725,14 +2348,18 @@ pub async fn trigger_agent_response(
             // Inject relevant agent memories into message context
             let enriched_content = inject_agent_memories(db, config, agent_id, content).await;
             do_trigger_agent_response(
-                user_id,
                 agent_id,
                 conversation_id,
-                &enriched_content,
-                reply_to_id.as_deref(),
-                thread_id.as_deref(),
-                &conv_type,
-                client_metadata.as_ref(),
+                TriggerContext {
+                    user_id,
+                    content: &enriched_content,
+                    reply_to_id: reply_to_id.as_deref(),
+                    thread_id: thread_id.as_deref(),
+                    conv_type: &conv_type,
+                    client_metadata: client_metadata.as_ref(),
+                    response_group: Some(&response_group),
+                    mention_depth: 0,
+                },
                 ws_state,
                 db,
                 redis,
@@ -1743,21 +2370,47 @@ pub async fn trigger_agent_response(
     }
 }
 
+/// What a dispatch responds to: who asked, what they said, and where it belongs.
+#[derive(Clone, Copy)]
+pub struct TriggerContext<'a> {
+    pub user_id: &'a str,
+    pub content: &'a str,
+    pub reply_to_id: Option<&'a str>,
+    pub thread_id: Option<&'a str>,
+    pub conv_type: &'a str,
+    pub client_metadata: Option<&'a serde_json::Value>,
+    pub response_group: Option<&'a ResponseGroup>,
+    /// Agent-to-agent mention hops that led here; 0 for a human's message.
+    pub mention_depth: u32,
+}
+
 /// Actually send the task to the agent and set up streaming callbacks.
 pub(crate) async fn do_trigger_agent_response(
-    user_id: &str,
     agent_id: &str,
     conversation_id: &str,
-    content: &str,
-    reply_to_id: Option<&str>,
-    thread_id: Option<&str>,
-    conv_type: &str,
-    client_metadata: Option<&serde_json::Value>,
+    ctx: TriggerContext<'_>,
     ws_state: &WsState,
     db: &PgPool,
     redis: &deadpool_redis::Pool,
     config: &crate::config::Config,
 ) {
+    let TriggerContext {
+        user_id,
+        content,
+        reply_to_id,
+        thread_id,
+        conv_type,
+        client_metadata,
+        response_group,
+        mention_depth,
+    } = ctx;
+
+    // No new streams once graceful shutdown has started
+    if ws_state.is_shutting_down() {
+        tracing::info!("Shutting down: not dispatching conv={} agent={}", conversation_id, agent_id);
+        return;
+    }
+
     // Dedup: prevent same content dispatched to same agent within 5 seconds
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
@@ -1832,28 +2485,35 @@ pub(crate) async fn do_trigger_agent_response(
     if !ws_state.is_agent_connected(agent_id) {
         let hint = "Copy the **Bot Token** from bot settings, then run:\n```\nopenclaw arinova-setup --token <bot-token>\n```";
 
-        let err_seq = match get_next_seq(db, conversation_id).await {
-            Ok(s) => s,
-            Err(_) => return,
-        };
-
-        let err_content = format!(
-            "**{}** is not connected yet. An AI agent needs to connect to this bot before it can respond.\n\n{}",
-            agent_name, hint
-        );
-
-        let err_msg_id = uuid::Uuid::new_v4().to_string();
-        let _ = sqlx::query(
-            r#"INSERT INTO messages (id, conversation_id, seq, role, content, status, sender_agent_id, created_at, updated_at)
-               VALUES ($1::uuid, $2::uuid, $3, 'agent', $4, 'error', $5::uuid, NOW(), NOW())"#,
+        let (last_seen_at, client_version) = sqlx::query_as::<_, (Option<chrono::NaiveDateTime>, Option<String>)>(
+            "SELECT last_seen_at, client_version FROM agents WHERE id = $1::uuid",
         )
-        .bind(&err_msg_id)
-        .bind(conversation_id)
-        .bind(err_seq)
-        .bind(&err_content)
         .bind(agent_id)
-        .execute(db)
-        .await;
+        .fetch_optional(db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or((None, None));
+        let last_seen_at = last_seen_at.map(|t| t.and_utc());
+        let presence = agent_presence_phrase(last_seen_at, client_version.as_deref(), Utc::now());
+
+        let err_content = if last_seen_at.is_some() {
+            format!(
+                "**{}** is not connected — it {}. It will respond once its agent reconnects.\n\n{}",
+                agent_name, presence, hint
+            )
+        } else {
+            format!(
+                "**{}** is not connected yet. An AI agent needs to connect to this bot before it can respond.\n\n{}",
+                agent_name, hint
+            )
+        };
+
+        let Some((err_msg_id, err_seq)) =
+            insert_agent_error_message(db, conversation_id, agent_id, thread_id.as_deref(), &err_content).await
+        else {
+            return;
+        };
 
         ws_state.broadcast_to_members(&member_ids, &json!({
             "type": "stream_start",
@@ -1871,12 +2531,55 @@ pub(crate) async fn do_trigger_agent_response(
             "messageId": err_msg_id,
             "seq": err_seq,
             "threadId": thread_id,
-            "error": format!("{} is not connected. Copy the Bot Token from bot settings and run: openclaw arinova-setup --token <bot-token>", agent_name)
+            "error": format!("{} is not connected ({}). Copy the Bot Token from bot settings and run: openclaw arinova-setup --token <bot-token>", agent_name, presence),
+            "lastSeenAt": last_seen_at.map(|t| t.to_rfc3339()),
+            "clientVersion": client_version,
         }), redis);
 
         return;
     }
 
+    // Cap how many agent streams one user can have running at once
+    let limit = config.max_concurrent_streams_per_user;
+    let Some(stream_slot) = ws_state.try_acquire_stream_slot(user_id, limit) else {
+        let err_content = format!(
+            "**{}** did not respond: {} agent responses were already in progress. Try again once one finishes.",
+            agent_name, limit
+        );
+        if let Some((err_msg_id, err_seq)) =
+            insert_agent_error_message(db, conversation_id, agent_id, thread_id.as_deref(), &err_content).await
+        {
+            ws_state.broadcast_to_members(&member_ids, &json!({
+                "type": "stream_start",
+                "conversationId": conversation_id,
+                "messageId": err_msg_id,
+                "seq": err_seq,
+                "senderAgentId": agent_id,
+                "senderAgentName": agent_name,
+                "threadId": thread_id
+            }), redis);
+
+            ws_state.broadcast_to_members(&member_ids, &json!({
+                "type": "stream_error",
+                "conversationId": conversation_id,
+                "messageId": err_msg_id,
+                "seq": err_seq,
+                "threadId": thread_id,
+                "senderAgentId": agent_id,
+                "code": "too_many_streams",
+                "limit": limit,
+                "error": err_content,
+            }), redis);
+        }
+
+        // This may have been dequeued; with no stream running for the agent here,
+        // nothing else would move its queue on
+        if !ws_state.has_active_stream_for_agent(conversation_id, agent_id) {
+            process_next_in_queue(&format!("{}:{}", conversation_id, agent_id), ws_state, db, redis, config);
+        }
+        return;
+    };
+
     // Create pending agent message with sender_agent_id
     let agent_seq = match get_next_seq(db, conversation_id).await {
         Ok(s) => s,
@@ -1885,15 +2588,36 @@ pub(crate) async fn do_trigger_agent_response(
 
     let agent_msg_id = uuid::Uuid::new_v4().to_string();
 
+    // Responses outside a multi-agent turn form a group of one
+    let (response_group_id, response_ordinal) = match response_group {
+        Some(g) => (g.id.clone(), g.ordinal),
+        None => (agent_msg_id.clone(), 0),
+    };
+
+    // A reply to a saved user message is a version under it: the first reply
+    // is version 1, each regenerate by the same agent adds the next one
     let _ = sqlx::query(
-            r#"INSERT INTO messages (id, conversation_id, seq, role, content, status, sender_agent_id, thread_id, created_at, updated_at)
-               VALUES ($1::uuid, $2::uuid, $3, 'agent', '', 'streaming', $4::uuid, $5::uuid, NOW(), NOW())"#,
+            r#"WITH parent AS (
+                   SELECT id FROM messages WHERE id = $7::uuid AND conversation_id = $2::uuid
+               )
+               INSERT INTO messages (id, conversation_id, seq, role, content, status, sender_agent_id, thread_id, metadata,
+                                     parent_message_id, version, created_at, updated_at)
+               VALUES ($1::uuid, $2::uuid, $3, 'agent', '', 'streaming', $4::uuid, $5::uuid, $6,
+                       (SELECT id FROM parent),
+                       COALESCE((SELECT MAX(m.version) + 1 FROM messages m, parent
+                                 WHERE m.parent_message_id = parent.id AND m.sender_agent_id = $4::uuid), 1),
+                       NOW(), NOW())"#,
         )
         .bind(&agent_msg_id)
         .bind(conversation_id)
         .bind(agent_seq)
         .bind(agent_id)
         .bind(thread_id.as_deref())
+        .bind(json!({
+            "responseGroupId": response_group_id,
+            "responseOrdinal": response_ordinal,
+        }))
+        .bind(response_group.map(|g| g.id.as_str()))
         .execute(db)
         .await;
 
@@ -1913,7 +2637,9 @@ pub(crate) async fn do_trigger_agent_response(
             "seq": agent_seq,
             "senderAgentId": agent_id,
             "senderAgentName": agent_name,
-            "threadId": thread_id
+            "threadId": thread_id,
+            "responseGroupId": response_group_id,
+            "responseOrdinal": response_ordinal
         }), redis);
 
     // Detect sticker messages and look up agent_prompt
@@ -2061,6 +2787,32 @@ pub(crate) async fn do_trigger_agent_response(
         system_prompt
     };
 
+    // Add retrieved context from the agent's linked knowledge base
+    let kb_listing_id = sqlx::query_scalar::<_, Option<uuid::Uuid>>(
+        "SELECT kb_listing_id FROM agents WHERE id = $1::uuid",
+    )
+    .bind(agent_id)
+    .fetch_optional(db)
+    .await
+    .ok()
+    .flatten()
+    .flatten();
+    let system_prompt = match kb_listing_id {
+        Some(listing_id) => {
+            let chunks = crate::services::embedding::kb_context_chunks(db, config, listing_id, content).await;
+            if chunks.is_empty() {
+                system_prompt
+            } else {
+                Some(crate::services::embedding::compose_system_prompt(
+                    system_prompt.as_deref().unwrap_or(""),
+                    None,
+                    &chunks,
+                ))
+            }
+        }
+        None => system_prompt,
+    };
+
     // Prepend system prompt if configured
     let task_content = match &system_prompt {
         Some(prompt) if !prompt.is_empty() => {
@@ -2154,18 +2906,20 @@ pub(crate) async fn do_trigger_agent_response(
         }
     }
 
-    // Fetch conversation history_limit (default 5)
-    let history_limit = sqlx::query_scalar::<_, i32>(
-        "SELECT COALESCE(history_limit, 5) FROM conversations WHERE id = $1::uuid",
+    // Fetch conversation history_limit (NULL = server default), clamped to the global cap
+    let history_limit = sqlx::query_scalar::<_, Option<i32>>(
+        "SELECT history_limit FROM conversations WHERE id = $1::uuid",
     )
     .bind(conversation_id)
     .fetch_optional(db)
     .await
     .ok()
     .flatten()
-    .unwrap_or(5);
+    .flatten()
+    .unwrap_or(config.default_history_limit)
+    .clamp(0, crate::config::MAX_HISTORY_LIMIT);
 
-    // Fetch recent conversation history
+    // Fetch recent conversation history (content only — reasoning traces stay out of agent context)
     let history_rows = sqlx::query_as::<_, (String, String, String, Option<String>, chrono::NaiveDateTime)>(
         r#"SELECT role::text, content,
                   COALESCE(status::text, 'completed') as status,
@@ -2175,6 +2929,7 @@ pub(crate) async fn do_trigger_agent_response(
            WHERE m.conversation_id = $1::uuid
              AND m.status IN ('completed', 'error', 'cancelled')
              AND m.id != $2::uuid
+             AND NOT COALESCE((m.metadata->>'superseded')::boolean, false)
            ORDER BY m.seq DESC
            LIMIT $3"#,
     )
@@ -2352,9 +3107,14 @@ pub(crate) async fn do_trigger_agent_response(
 
     // Send full task payload to agent
     let (cancel_tx, mut cancel_rx) = tokio::sync::watch::channel(false);
-    ws_state
-        .stream_cancellers
-        .insert(agent_msg_id.clone(), cancel_tx);
+    ws_state.stream_cancellers.insert(
+        agent_msg_id.clone(),
+        StreamCanceller {
+            tx: cancel_tx,
+            triggered_by: user_id.to_string(),
+            conversation_id: conversation_id.to_string(),
+        },
+    );
 
     tracing::info!(
         "Stream dispatch: conv={} agent={} msgId={} active_streams={:?}",
@@ -2365,6 +3125,7 @@ pub(crate) async fn do_trigger_agent_response(
     let agent_event_rx = send_task_to_agent(
         ws_state,
         agent_id,
+        conversation_id,
         &agent_msg_id,
         &task_payload,
     );
@@ -2382,9 +3143,13 @@ pub(crate) async fn do_trigger_agent_response(
     let conv_type = conv_type.to_string();
     let member_ids = member_ids;
     let thread_id = thread_id;
+    // Counted until the task exits, so shutdown waits for the final save
+    let stream_task = ws_state.track_stream_task();
 
     tokio::spawn(async move {
+        let _stream_task = stream_task;
         let mut stream_accumulated = String::new();
+        let mut reasoning_accumulated = String::new();
         let stream_key = format!("{}:{}", conversation_id, agent_id);
         let mut pending_mentions: Option<(Vec<String>, String)> = None;
         let mut event_rx = match agent_event_rx {
@@ -2440,6 +3205,17 @@ pub(crate) async fn do_trigger_agent_response(
                                     ).await;
                             }
                         }
+                        Some(crate::ws::state::AgentEvent::Reasoning(delta)) => {
+                            push_capped(&mut reasoning_accumulated, &delta, REASONING_MAX_CHARS);
+                            ws_state.broadcast_to_members(&member_ids, &json!({
+                                "type": "stream_reasoning",
+                                "conversationId": &conversation_id,
+                                "messageId": &agent_msg_id_clone,
+                                "seq": agent_seq,
+                                "threadId": &thread_id,
+                                "chunk": delta
+                            }), &redis);
+                        }
                         Some(crate::ws::state::AgentEvent::Complete(full_content, mentions)) => {
                             // Always prefer stream_accumulated (clean push_str concat) over
                             // full_content which may contain spurious \n\n at chunk boundaries.
@@ -2486,10 +3262,14 @@ pub(crate) async fn do_trigger_agent_response(
                                 .bind(&agent_msg_id_clone)
                                 .execute(&db)
                                 .await;
+                                save_reasoning(&db, &agent_msg_id_clone, &reasoning_accumulated).await;
                                 tracing::info!(
                                     "stream_end reason=completed conv={} agent={} msgId={} len={}",
                                     conversation_id, agent_id, agent_msg_id_clone, full_content.len()
                                 );
+                                crate::services::mention::record_message_mentions(
+                                    &db, &agent_msg_id_clone, &conversation_id, &full_content, None, &mentions,
+                                ).await;
 
                                 // Spawn link preview extraction in background
                                 {
@@ -2514,6 +3294,21 @@ pub(crate) async fn do_trigger_agent_response(
                                 }
 
 
+                                // Title untitled direct chats from their first exchange
+                                if config.auto_title_conversations && crate::services::conversation_title::is_auto_title_type(&conv_type) {
+                                    let db5 = db.clone();
+                                    let ws5 = ws_state.clone();
+                                    let redis5 = redis.clone();
+                                    let config5 = config.clone();
+                                    let cid5 = conversation_id.clone();
+                                    let mids5 = member_ids.clone();
+                                    tokio::spawn(async move {
+                                        crate::services::conversation_title::maybe_generate_title(
+                                            &db5, &ws5, &redis5, &config5, &cid5, &mids5,
+                                        ).await;
+                                    });
+                                }
+
                                 // Spawn auto memory extraction in background (throttled)
                                 {
                                     let db4 = db.clone();
@@ -2555,7 +3350,12 @@ pub(crate) async fn do_trigger_agent_response(
                                 // Skip push if user has the app in foreground
                                 if ws_state.is_user_foreground(mid) { continue; }
                                 if let Ok(false) = is_conversation_muted(&db, mid, &conversation_id).await {
-                                    if let Ok(true) = should_send_push(&db, mid, "message").await {
+                                    let push_type = message_push_type(&db, mid, &conv_type, &full_content).await;
+                                    // Mentions-only members are pushed only when the reply @-mentions them
+                                    if push_type != "mention" && matches!(is_mentions_only(&db, mid, &conversation_id).await, Ok(true)) {
+                                        continue;
+                                    }
+                                    if let Ok(true) = push_allowed(&db, mid, push_type).await {
                                         let preview = {
                                             let truncated = safe_truncate(&full_content, 100);
                                             if truncated.len() < full_content.len() {
@@ -2564,12 +3364,14 @@ pub(crate) async fn do_trigger_agent_response(
                                                 full_content.clone()
                                             }
                                         };
-                                        let _ = send_push_to_user(
+                                        queue_message_push(
                                             &db,
+                                            &redis,
                                             &config,
                                             mid,
-                                            &crate::services::push::PushPayload {
-                                                notification_type: "message".into(),
+                                            &conversation_id,
+                                            crate::services::push::PushPayload {
+                                                notification_type: push_type.into(),
                                                 title: agent_name.clone(),
                                                 body: preview,
                                                 url: Some(format!("/?c={}&m={}", conversation_id, agent_msg_id_clone)),
@@ -2657,6 +3459,7 @@ pub(crate) async fn do_trigger_agent_response(
                                 .bind(&agent_msg_id_clone)
                                 .execute(&db)
                                 .await;
+                                save_reasoning(&db, &agent_msg_id_clone, &reasoning_accumulated).await;
 
                                 tracing::info!(
                                     "stream_end reason=agent_disconnect conv={} agent={} msgId={} len={}",
@@ -2683,7 +3486,14 @@ pub(crate) async fn do_trigger_agent_response(
                 }
                 _ = cancel_rx.changed() => {
                     if *cancel_rx.borrow() {
-                        // User cancelled the stream — clean up everything
+                        // User cancelled the stream (or the server is shutting
+                        // down) — clean up everything
+                        let shutdown = ws_state.is_shutting_down();
+                        let (end_status, end_reason) = if shutdown {
+                            ("completed", "shutdown")
+                        } else {
+                            ("cancelled", "cancelled")
+                        };
 
                         // 1. Remove stream canceller
                         ws_state.stream_cancellers.remove(&agent_msg_id_clone);
@@ -2693,19 +3503,21 @@ pub(crate) async fn do_trigger_agent_response(
                             let _: Result<(), _> = conn.del(&format!("stream:{}", agent_msg_id_clone)).await;
                         }
 
-                        // 3. Update message status to 'cancelled' in DB with accumulated content
+                        // 3. Persist accumulated content ('completed' on shutdown, else 'cancelled')
                         let _ = sqlx::query(
-                            r#"UPDATE messages SET content = $1, status = 'cancelled', updated_at = NOW() WHERE id = $2::uuid"#,
+                            r#"UPDATE messages SET content = $1, status = $3::message_status, updated_at = NOW() WHERE id = $2::uuid"#,
                         )
                         .bind(&stream_accumulated)
                         .bind(&agent_msg_id_clone)
+                        .bind(end_status)
                         .execute(&db)
                         .await;
+                        save_reasoning(&db, &agent_msg_id_clone, &reasoning_accumulated).await;
 
                         // 4. Notify all members that stream was cancelled
                         tracing::info!(
-                            "stream_end reason=cancelled conv={} agent={} msgId={}",
-                            conversation_id, agent_id, agent_msg_id_clone
+                            "stream_end reason={} conv={} agent={} msgId={}",
+                            end_reason, conversation_id, agent_id, agent_msg_id_clone
                         );
                         ws_state.broadcast_to_members(&member_ids, &json!({
                             "type": "stream_end",
@@ -2715,7 +3527,7 @@ pub(crate) async fn do_trigger_agent_response(
                             "threadId": &thread_id,
                             "senderAgentId": &agent_id,
                             "senderAgentName": &agent_name,
-                            "reason": "cancelled"
+                            "reason": end_reason
                         }), &redis);
 
                         // 5. Send cancel_task to agent so it can stop generating
@@ -2731,7 +3543,9 @@ pub(crate) async fn do_trigger_agent_response(
 
                         // 7. Clean up active stream and process queue
                         ws_state.active_streams.remove(&stream_key);
-                        process_next_in_queue(&stream_key, &ws_state, &db, &redis, &config);
+                        if !shutdown {
+                            process_next_in_queue(&stream_key, &ws_state, &db, &redis, &config);
+                        }
 
                         break;
                     }
@@ -2739,6 +3553,9 @@ pub(crate) async fn do_trigger_agent_response(
             }
         }
 
+        // The stream is over on every path out of the loop; free the user's slot
+        drop(stream_slot);
+
         // Dispatch to agents via listen_mode filter (mirrors user message path)
         let mut already_dispatched = std::collections::HashSet::new();
         if conv_type == "group" || conv_type == "community" {
@@ -2799,51 +3616,126 @@ pub(crate) async fn do_trigger_agent_response(
                 .map(|(_, c)| c.clone())
                 .unwrap_or_default();
 
+            let ctx = TriggerContext {
+                user_id: &user_id,
+                content: &content_for_dispatch,
+                reply_to_id: Some(&agent_msg_id_clone),
+                thread_id: None,
+                conv_type: &conv_type,
+                client_metadata: None,
+                response_group: None,
+                mention_depth: mention_depth + 1,
+            };
             for target_id in &dispatch_ids {
                 already_dispatched.insert(target_id.clone());
-                spawn_mention_dispatch(
-                    &user_id, target_id, &conversation_id, &content_for_dispatch,
-                    &agent_msg_id_clone, &conv_type,
-                    &ws_state, &db, &redis, &config,
-                );
+                if claim_mention_dispatch(&ws_state, &config, &conversation_id, &agent_id, target_id, ctx.mention_depth) {
+                    spawn_mention_dispatch(target_id, &conversation_id, ctx, &ws_state, &db, &redis, &config);
+                }
             }
         }
 
         // Dispatch to @mentioned agents not already dispatched by listen_mode filter
         if let Some((mentions, content)) = pending_mentions {
+            let ctx = TriggerContext {
+                user_id: &user_id,
+                content: &content,
+                reply_to_id: Some(&agent_msg_id_clone),
+                thread_id: None,
+                conv_type: &conv_type,
+                client_metadata: None,
+                response_group: None,
+                mention_depth: mention_depth + 1,
+            };
             for mentioned_id in mentions {
                 if mentioned_id == agent_id || already_dispatched.contains(&mentioned_id) {
                     continue;
                 }
-                spawn_mention_dispatch(
-                    &user_id, &mentioned_id, &conversation_id, &content,
-                    &agent_msg_id_clone, &conv_type,
-                    &ws_state, &db, &redis, &config,
-                );
+                if claim_mention_dispatch(&ws_state, &config, &conversation_id, &agent_id, &mentioned_id, ctx.mention_depth) {
+                    spawn_mention_dispatch(&mentioned_id, &conversation_id, ctx, &ws_state, &db, &redis, &config);
+                }
             }
         }
     });
 }
 
-/// Spawn a task to dispatch a single agent mention.
+/// Persist the (capped) reasoning trace of an agent message so a refresh can show it.
+async fn save_reasoning(db: &PgPool, message_id: &str, reasoning: &str) {
+    if reasoning.is_empty() {
+        return;
+    }
+    let _ = sqlx::query(r#"UPDATE messages SET reasoning = $1 WHERE id = $2::uuid"#)
+        .bind(safe_truncate(reasoning, REASONING_MAX_CHARS))
+        .bind(message_id)
+        .execute(db)
+        .await;
+}
+
+/// Why an agent-to-agent mention must not be dispatched, if it must not:
+/// the chain would exceed `max_depth`, or the same pair fired within `cooldown`.
+pub fn mention_dispatch_blocked(
+    depth: u32,
+    max_depth: u32,
+    since_last: Option<Duration>,
+    cooldown: Duration,
+) -> Option<&'static str> {
+    if depth > max_depth {
+        return Some("depth_cap");
+    }
+    if since_last.is_some_and(|elapsed| elapsed < cooldown) {
+        return Some("cooldown");
+    }
+    None
+}
+
+/// Whether `from_agent_id` may mention `mentioned_id` at `depth` (the length of the
+/// agent mention chain the dispatch would extend to). Records the pair's cooldown when allowed.
+fn claim_mention_dispatch(
+    ws_state: &WsState,
+    config: &crate::config::Config,
+    conversation_id: &str,
+    from_agent_id: &str,
+    mentioned_id: &str,
+    depth: u32,
+) -> bool {
+    let pair_key = format!("{}:{}:{}", conversation_id, from_agent_id, mentioned_id);
+    let now = std::time::Instant::now();
+    let since_last = ws_state
+        .agent_mention_cooldowns
+        .get(&pair_key)
+        .map(|prev| now.duration_since(*prev));
+    if let Some(reason) = mention_dispatch_blocked(
+        depth,
+        config.agent_mention_max_depth,
+        since_last,
+        Duration::from_secs(config.agent_mention_cooldown_secs),
+    ) {
+        tracing::warn!(
+            "Mention dispatch stopped ({}): conv={} from={} to={} depth={}",
+            reason, conversation_id, from_agent_id, mentioned_id, depth
+        );
+        return false;
+    }
+    ws_state.agent_mention_cooldowns.insert(pair_key, now);
+    true
+}
+
+/// Spawn a task to dispatch a single agent mention claimed with [`claim_mention_dispatch`].
 fn spawn_mention_dispatch(
-    user_id: &str,
     mentioned_id: &str,
     conversation_id: &str,
-    content: &str,
-    reply_to_id: &str,
-    conv_type: &str,
+    ctx: TriggerContext<'_>,
     ws_state: &WsState,
     db: &PgPool,
     redis: &deadpool_redis::Pool,
     config: &crate::config::Config,
 ) {
-    let user_id = user_id.to_string();
+    let user_id = ctx.user_id.to_string();
     let mentioned_id = mentioned_id.to_string();
     let conversation_id = conversation_id.to_string();
-    let content = content.to_string();
-    let reply_to_id = reply_to_id.to_string();
-    let conv_type = conv_type.to_string();
+    let content = ctx.content.to_string();
+    let reply_to_id = ctx.reply_to_id.map(str::to_string);
+    let conv_type = ctx.conv_type.to_string();
+    let depth = ctx.mention_depth;
     let ws_state = ws_state.clone();
     let db = db.clone();
     let redis = redis.clone();
@@ -2851,14 +3743,18 @@ fn spawn_mention_dispatch(
 
     tokio::spawn(async move {
         do_trigger_agent_response(
-            &user_id,
             &mentioned_id,
             &conversation_id,
-            &content,
-            Some(&reply_to_id),
-            None,
-            &conv_type,
-            None,
+            TriggerContext {
+                user_id: &user_id,
+                content: &content,
+                reply_to_id: reply_to_id.as_deref(),
+                thread_id: None,
+                conv_type: &conv_type,
+                client_metadata: None,
+                response_group: None,
+                mention_depth: depth,
+            },
             &ws_state,
             &db,
             &redis,
@@ -2868,9 +3764,36 @@ fn spawn_mention_dispatch(
     });
 }
 
+/// Save an agent reply that failed before streaming as an `error` message.
+/// Returns its id and seq, or `None` if it could not be saved.
+async fn insert_agent_error_message(
+    db: &PgPool,
+    conversation_id: &str,
+    agent_id: &str,
+    thread_id: Option<&str>,
+    content: &str,
+) -> Option<(String, i32)> {
+    let seq = get_next_seq(db, conversation_id).await.ok()?;
+    let msg_id = uuid::Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"INSERT INTO messages (id, conversation_id, seq, role, content, status, sender_agent_id, thread_id, created_at, updated_at)
+           VALUES ($1::uuid, $2::uuid, $3, 'agent', $4, 'error', $5::uuid, $6::uuid, NOW(), NOW())"#,
+    )
+    .bind(&msg_id)
+    .bind(conversation_id)
+    .bind(seq)
+    .bind(content)
+    .bind(agent_id)
+    .bind(thread_id)
+    .execute(db)
+    .await
+    .ok()?;
+    Some((msg_id, seq))
+}
+
 /// Process the next queued agent response.
 /// queue_key is "{conversation_id}:{agent_id}".
-fn process_next_in_queue(
+pub fn process_next_in_queue(
     queue_key: &str,
     ws_state: &WsState,
     db: &PgPool,
@@ -2914,14 +3837,18 @@ fn process_next_in_queue(
         .unwrap_or_else(|| "h2a".to_string());
 
         do_trigger_agent_response(
-            &next.user_id,
             &next.agent_id,
             &next.conversation_id,
-            &next.content,
-            next.reply_to_id.as_deref(),
-            next.thread_id.as_deref(),
-            &conv_type,
-            next.metadata.as_ref(),
+            TriggerContext {
+                user_id: &next.user_id,
+                content: &next.content,
+                reply_to_id: next.reply_to_id.as_deref(),
+                thread_id: next.thread_id.as_deref(),
+                conv_type: &conv_type,
+                client_metadata: next.metadata.as_ref(),
+                response_group: next.response_group.as_ref(),
+                mention_depth: 0,
+            },
             &ws_state,
             &db,
             &redis,
@@ -2932,7 +3859,7 @@ fn process_next_in_queue(
 }
 
 /// Strip potentially dangerous HTML tags from user-submitted content
-fn sanitize_content(content: &str) -> String {
+pub(crate) fn sanitize_content(content: &str) -> String {
     let re_script = regex_lite::Regex::new(r"(?i)<script\b[^>]*>.*?</script>").unwrap();
     let re_script_open = regex_lite::Regex::new(r"(?i)<script\b[^>]*>").unwrap();
     let re_script_close = regex_lite::Regex::new(r"(?i)</script>").unwrap();
@@ -1,6 +1,7 @@
 use dashmap::DashMap;
 use serde_json::Value;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::mpsc;
@@ -21,6 +22,7 @@ pub struct UserConnection {
 /// Pending task handler callbacks
 pub struct PendingTask {
     pub agent_id: String,
+    pub conversation_id: String,
     pub accumulated: String,
     pub chunk_tx: mpsc::UnboundedSender<AgentEvent>,
     pub timeout_handle: tokio::task::JoinHandle<()>,
@@ -29,6 +31,8 @@ pub struct PendingTask {
 #[derive(Debug)]
 pub enum AgentEvent {
     Chunk(String),
+    /// Reasoning/thinking delta — streamed to members but never fed back into agent history
+    Reasoning(String),
     Complete(String, Vec<String>),
     Error(String),
 }
@@ -43,6 +47,31 @@ pub struct QueuedResponse {
     pub thread_id: Option<String>,
     pub user_message_id: Option<String>,
     pub metadata: Option<serde_json::Value>,
+    pub response_group: Option<ResponseGroup>,
+}
+
+/// Groups the agent responses triggered by one user message so clients can
+/// order concurrent streams deterministically instead of by arrival.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseGroup {
+    /// Shared by every response to the same turn (the triggering message id).
+    pub id: String,
+    /// Position of this agent's response within the turn, starting at 0.
+    pub ordinal: usize,
+}
+
+/// Cancel handle for an in-flight agent stream, with who started it.
+pub struct StreamCanceller {
+    pub tx: tokio::sync::watch::Sender<bool>,
+    /// User whose message triggered the dispatch.
+    pub triggered_by: String,
+    pub conversation_id: String,
+}
+
+/// Whether `requester` may cancel a stream started by `triggered_by`:
+/// the triggering user always can, otherwise only conversation admins.
+pub fn can_cancel_stream(requester: &str, triggered_by: &str, requester_is_admin: bool) -> bool {
+    requester == triggered_by || requester_is_admin
 }
 
 /// Shared WebSocket state across all connections
@@ -54,11 +83,14 @@ pub struct WsState {
     /// Per-socket visibility: connectionId -> visible
     pub socket_visible: Arc<DashMap<String, bool>>,
 
+    /// Per-socket heartbeat timeout declared via `hello`: connectionId -> timeout
+    pub heartbeat_timeouts: Arc<DashMap<String, std::time::Duration>>,
+
     /// Foreground counts: userId -> count of visible tabs
     pub foreground_counts: Arc<DashMap<String, i32>>,
 
-    /// Active stream cancellers: messageId -> cancel sender
-    pub stream_cancellers: Arc<DashMap<String, tokio::sync::watch::Sender<bool>>>,
+    /// Active stream cancellers: messageId -> cancel handle
+    pub stream_cancellers: Arc<DashMap<String, StreamCanceller>>,
 
     /// Conversation IDs with active streams (key -> start time for staleness detection)
     pub active_streams: Arc<DashMap<String, Instant>>,
@@ -69,6 +101,9 @@ pub struct WsState {
     /// Recent dispatch dedup: "conv:agent:content_hash" -> timestamp
     pub recent_dispatches: Arc<DashMap<String, Instant>>,
 
+    /// Agent-to-agent mention cooldown: "conv:from_agent:to_agent" -> timestamp
+    pub agent_mention_cooldowns: Arc<DashMap<String, Instant>>,
+
     /// Agent connections: agentId -> (connectionId, sender)
     pub agent_connections: Arc<DashMap<String, (String, WsSender)>>,
 
@@ -89,6 +124,49 @@ pub struct WsState {
 
     /// Voice WS connections: userId -> sender (for routing signaling between voice WS peers)
     pub voice_connections: Arc<DashMap<String, WsSender>>,
+
+    /// Per-user agent stream opt-outs: conversationId -> user IDs that don't receive stream_* events
+    pub agent_stream_optouts: Arc<DashMap<String, HashSet<String>>>,
+
+    /// Group read receipts awaiting a batched broadcast: conversationId -> (userId -> seq)
+    pub pending_read_receipts: Arc<DashMap<String, HashMap<String, i32>>>,
+
+    /// Agent streams currently running on behalf of each user: userId -> count
+    pub user_stream_counts: Arc<DashMap<String, usize>>,
+
+    /// Set once the server begins graceful shutdown.
+    pub shutting_down: Arc<AtomicBool>,
+
+    /// Agent stream tasks that haven't finished persisting their message yet.
+    pub stream_tasks: Arc<AtomicUsize>,
+}
+
+/// A reserved agent-stream slot for a user. Releases the slot when dropped,
+/// so every exit path of a stream gives it back.
+pub struct StreamSlot {
+    counts: Arc<DashMap<String, usize>>,
+    user_id: String,
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.counts.remove_if_mut(&self.user_id, |_, count| {
+            *count = count.saturating_sub(1);
+            *count == 0
+        });
+    }
+}
+
+/// Keeps an agent stream task counted in `stream_tasks` until it is dropped,
+/// i.e. until the task has saved its final content and exited.
+pub struct StreamTaskGuard {
+    count: Arc<AtomicUsize>,
+}
+
+impl Drop for StreamTaskGuard {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -103,11 +181,13 @@ impl WsState {
         Self {
             user_connections: Arc::new(DashMap::new()),
             socket_visible: Arc::new(DashMap::new()),
+            heartbeat_timeouts: Arc::new(DashMap::new()),
             foreground_counts: Arc::new(DashMap::new()),
             stream_cancellers: Arc::new(DashMap::new()),
             active_streams: Arc::new(DashMap::new()),
             agent_response_queues: Arc::new(DashMap::new()),
             recent_dispatches: Arc::new(DashMap::new()),
+            agent_mention_cooldowns: Arc::new(DashMap::new()),
             agent_connections: Arc::new(DashMap::new()),
             agent_connection_ips: Arc::new(DashMap::new()),
             agent_skills: Arc::new(DashMap::new()),
@@ -115,7 +195,55 @@ impl WsState {
             ws_rate_limits: Arc::new(DashMap::new()),
             conv_member_cache: Arc::new(DashMap::new()),
             voice_connections: Arc::new(DashMap::new()),
+            agent_stream_optouts: Arc::new(DashMap::new()),
+            pending_read_receipts: Arc::new(DashMap::new()),
+            user_stream_counts: Arc::new(DashMap::new()),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            stream_tasks: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Signal a stream's task to finalize, then drop its canceller and pending
+    /// task so late agent chunks are ignored. Returns whether a live stream
+    /// task was signalled.
+    pub fn cancel_stream(&self, message_id: &str) -> bool {
+        let signalled = self
+            .stream_cancellers
+            .get(message_id)
+            .is_some_and(|c| c.tx.send(true).is_ok());
+        self.stream_cancellers.remove(message_id);
+        if let Some((_, task)) = self.pending_tasks.remove(message_id) {
+            task.timeout_handle.abort();
+        }
+        signalled
+    }
+
+    /// Enter shutdown and signal every active stream to finalize with the
+    /// content accumulated so far. Returns the number of streams signalled.
+    pub fn drain_streams(&self) -> usize {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        let mut signalled = 0;
+        for entry in self.stream_cancellers.iter() {
+            if entry.tx.send(true).is_ok() {
+                signalled += 1;
+            }
         }
+        signalled
+    }
+
+    /// Count an agent stream task until the returned guard is dropped.
+    pub fn track_stream_task(&self) -> StreamTaskGuard {
+        self.stream_tasks.fetch_add(1, Ordering::SeqCst);
+        StreamTaskGuard { count: self.stream_tasks.clone() }
+    }
+
+    /// Number of agent stream tasks still running or finalizing.
+    pub fn active_stream_tasks(&self) -> usize {
+        self.stream_tasks.load(Ordering::SeqCst)
     }
 
     /// Check if a user is online (has any connections)
@@ -238,14 +366,94 @@ impl WsState {
         self.conv_member_cache.remove(conversation_id);
     }
 
-    /// Broadcast event to a list of user IDs (with offline queue fallback)
+    /// Record whether a user receives agent stream events in a conversation
+    pub fn set_agent_stream_optout(&self, conversation_id: &str, user_id: &str, opted_out: bool) {
+        if opted_out {
+            self.agent_stream_optouts
+                .entry(conversation_id.to_string())
+                .or_default()
+                .insert(user_id.to_string());
+        } else {
+            self.agent_stream_optouts
+                .remove_if_mut(conversation_id, |_, users| {
+                    users.remove(user_id);
+                    users.is_empty()
+                });
+        }
+    }
+
+    /// Reserve one of a user's concurrent agent-stream slots, or `None` if
+    /// `limit` streams are already running for them.
+    pub fn try_acquire_stream_slot(&self, user_id: &str, limit: usize) -> Option<StreamSlot> {
+        let mut count = self.user_stream_counts.entry(user_id.to_string()).or_insert(0);
+        if *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(StreamSlot {
+            counts: self.user_stream_counts.clone(),
+            user_id: user_id.to_string(),
+        })
+    }
+
+    /// Number of agent streams currently running on behalf of a user.
+    pub fn active_stream_count(&self, user_id: &str) -> usize {
+        self.user_stream_counts.get(user_id).map(|c| *c).unwrap_or(0)
+    }
+
+    /// Queue a read receipt for the next batched broadcast, keeping the highest seq per user.
+    /// Returns true if this starts a new batch for the conversation (caller schedules the flush).
+    pub fn queue_read_receipt(&self, conversation_id: &str, user_id: &str, seq: i32) -> bool {
+        let mut starts_batch = false;
+        let mut batch = self
+            .pending_read_receipts
+            .entry(conversation_id.to_string())
+            .or_insert_with(|| {
+                starts_batch = true;
+                HashMap::new()
+            });
+        let entry = batch.entry(user_id.to_string()).or_insert(seq);
+        *entry = (*entry).max(seq);
+        starts_batch
+    }
+
+    /// Take all queued read receipts for a conversation, ending its batch.
+    pub fn take_read_receipts(&self, conversation_id: &str) -> HashMap<String, i32> {
+        self.pending_read_receipts
+            .remove(conversation_id)
+            .map(|(_, batch)| batch)
+            .unwrap_or_default()
+    }
+
+    /// Check if a user opted out of agent stream events in a conversation
+    pub fn is_agent_stream_opted_out(&self, conversation_id: &str, user_id: &str) -> bool {
+        self.agent_stream_optouts
+            .get(conversation_id)
+            .map(|users| users.contains(user_id))
+            .unwrap_or(false)
+    }
+
+    /// Broadcast event to a list of user IDs (with offline queue fallback).
+    /// Agent stream events (`stream_*`) skip users who opted out for that conversation.
     pub fn broadcast_to_members(
         &self,
         member_ids: &[String],
         event: &Value,
         redis: &deadpool_redis::Pool,
     ) {
+        let stream_conv = event
+            .get("type")
+            .and_then(|t| t.as_str())
+            .filter(|t| t.starts_with("stream_"))
+            .and_then(|_| event.get("conversationId"))
+            .and_then(|c| c.as_str())
+            .filter(|cid| self.agent_stream_optouts.contains_key(*cid));
         for uid in member_ids {
+            if let Some(cid) = stream_conv {
+                if self.is_agent_stream_opted_out(cid, uid) {
+                    continue;
+                }
+            }
             self.send_to_user_or_queue(uid, event, redis);
         }
     }
@@ -65,14 +65,15 @@ mod pairing_code_tests {
 
 #[cfg(test)]
 mod config_tests {
-    #[test]
-    fn test_cors_origins_parsing() {
-        // Test with multiple origins
-        let config = arinova_server::config::Config {
+    use arinova_server::config::{Config, SandboxLimits};
+
+    /// Baseline config for tests; override fields with `..test_config()`.
+    pub fn test_config() -> Config {
+        Config {
             port: 21001,
             database_url: "postgres://localhost/test".into(),
             redis_url: "redis://localhost".into(),
-            cors_origin: "http://localhost:21000,https://app.arinova.ai".into(),
+            cors_origin: "http://localhost:21000".into(),
             better_auth_secret: "test".into(),
             better_auth_url: "http://localhost:21001".into(),
             google_client_id: String::new(),
@@ -91,6 +92,59 @@ mod config_tests {
             vapid_private_key: String::new(),
             vapid_subject: "mailto:test@test.com".into(),
             sentry_dsn: String::new(),
+            openai_api_key: None,
+            openrouter_api_key: None,
+            anthropic_api_key: None,
+            gemini_api_key: None,
+            settings_encryption_key: None,
+            settings_encryption_key_version: 1,
+            settings_encryption_key_previous: None,
+            turn_secret: None,
+            turn_host: "turn.arinova.ai".into(),
+            frontend_url: None,
+            default_history_limit: 5,
+            extra_llm_models: vec![],
+            coin_currency: "USD".into(),
+            coin_to_currency_rate: 0.01,
+            min_paid_action_coins: 1,
+            content_blocklist: vec![],
+            review_flag_hide_threshold: 5,
+            min_payout_coins: 100,
+            topup_webhook_secret: None,
+            openrouter_max_retries: 3,
+            openrouter_retry_base_ms: 500,
+            max_community_agents: 20,
+            group_max_users_ceiling: 500,
+            group_max_agents_ceiling: 50,
+            max_concurrent_streams_per_user: 5,
+            max_attachments_per_message: 10,
+            max_message_attachments_size: 10 * 1024 * 1024,
+            strip_image_metadata: true,
+            max_chunked_upload_size: 2 * 1024 * 1024 * 1024,
+            friend_request_daily_limit: 50,
+            friend_request_reject_cooldown_hours: 72,
+            agent_mention_max_depth: 3,
+            agent_mention_cooldown_secs: 30,
+            db_max_connections: 50,
+            db_idle_timeout_secs: 60,
+            db_acquire_timeout_secs: 30,
+            max_active_listings_per_user: 25,
+            max_listings_created_per_day: 10,
+            auto_title_conversations: true,
+            marketplace_free_preview: true,
+            metrics_token: None,
+            sandbox_python_limits: SandboxLimits { timeout_secs: 30, memory_mb: 512 },
+            sandbox_node_limits: SandboxLimits { timeout_secs: 30, memory_mb: 512 },
+            sandbox_bash_limits: SandboxLimits { timeout_secs: 10, memory_mb: 256 },
+        }
+    }
+
+    #[test]
+    fn test_cors_origins_parsing() {
+        // Test with multiple origins
+        let config = Config {
+            cors_origin: "http://localhost:21000,https://app.arinova.ai".into(),
+            ..test_config()
         };
 
         let origins = config.cors_origins();
@@ -101,30 +155,7 @@ mod config_tests {
 
     #[test]
     fn test_r2_not_configured() {
-        let config = arinova_server::config::Config {
-            port: 21001,
-            database_url: "postgres://localhost/test".into(),
-            redis_url: "redis://localhost".into(),
-            cors_origin: "http://localhost:21000".into(),
-            better_auth_secret: "test".into(),
-            better_auth_url: "http://localhost:21001".into(),
-            google_client_id: String::new(),
-            google_client_secret: String::new(),
-            github_client_id: String::new(),
-            github_client_secret: String::new(),
-            upload_dir: "./uploads".into(),
-            max_file_size: 10485760,
-            r2_endpoint: String::new(),
-            r2_access_key_id: String::new(),
-            r2_secret_access_key: String::new(),
-            r2_bucket: "test".into(),
-            r2_public_url: String::new(),
-            admin_emails: vec![],
-            vapid_public_key: String::new(),
-            vapid_private_key: String::new(),
-            vapid_subject: "mailto:test@test.com".into(),
-            sentry_dsn: String::new(),
-        };
+        let config = test_config();
 
         assert!(!config.is_r2_configured());
         assert!(!config.is_push_enabled());
@@ -132,33 +163,66 @@ mod config_tests {
 
     #[test]
     fn test_r2_configured() {
-        let config = arinova_server::config::Config {
-            port: 21001,
-            database_url: "postgres://localhost/test".into(),
-            redis_url: "redis://localhost".into(),
-            cors_origin: "http://localhost:21000".into(),
-            better_auth_secret: "test".into(),
-            better_auth_url: "http://localhost:21001".into(),
-            google_client_id: String::new(),
-            google_client_secret: String::new(),
-            github_client_id: String::new(),
-            github_client_secret: String::new(),
-            upload_dir: "./uploads".into(),
-            max_file_size: 10485760,
+        let config = Config {
             r2_endpoint: "https://example.r2.cloudflarestorage.com".into(),
             r2_access_key_id: "key123".into(),
             r2_secret_access_key: "secret123".into(),
-            r2_bucket: "test".into(),
             r2_public_url: "https://cdn.example.com".into(),
-            admin_emails: vec![],
-            vapid_public_key: String::new(),
-            vapid_private_key: String::new(),
-            vapid_subject: "mailto:test@test.com".into(),
-            sentry_dsn: String::new(),
+            ..test_config()
         };
 
         assert!(config.is_r2_configured());
     }
+
+    #[test]
+    fn test_fee_minimum_and_currency_rate() {
+        let config = Config {
+            coin_currency: "EUR".into(),
+            coin_to_currency_rate: 0.05,
+            min_paid_action_coins: 10,
+            ..test_config()
+        };
+
+        assert!((config.coins_to_currency(200) - 10.0).abs() < f64::EPSILON);
+        assert!(config.validate_fee("Join fee", 0).is_ok());
+        assert!(config.validate_fee("Join fee", 10).is_ok());
+        let err = config.validate_fee("Join fee", 5).unwrap_err();
+        assert!(err.contains("at least 10 coins"));
+        assert!(config.validate_fee("Join fee", -1).is_err());
+    }
+}
+
+#[cfg(test)]
+mod api_key_encryption_tests {
+    use super::config_tests::test_config;
+    use arinova_server::config::Config;
+    use arinova_server::routes::user_settings::{decrypt_api_key, encrypt_api_key};
+
+    #[test]
+    fn refuses_to_store_keys_without_an_encryption_key() {
+        assert!(encrypt_api_key(&test_config(), "sk-or-secret").is_err());
+    }
+
+    #[test]
+    fn stores_keys_encrypted() {
+        let config = Config {
+            settings_encryption_key: Some("11".repeat(32)),
+            ..test_config()
+        };
+        let stored = encrypt_api_key(&config, "sk-or-secret").unwrap();
+        assert!(!stored.contains("sk-or-secret"));
+        assert!(stored.starts_with("enc:v1:"));
+        assert_eq!(decrypt_api_key(&config, &stored), "sk-or-secret");
+    }
+
+    #[test]
+    fn bad_encryption_key_fails_instead_of_falling_back() {
+        let config = Config {
+            settings_encryption_key: Some("not-hex".into()),
+            ..test_config()
+        };
+        assert!(encrypt_api_key(&config, "sk-or-secret").is_err());
+    }
 }
 
 #[cfg(test)]
@@ -180,6 +244,111 @@ mod ws_state_tests {
         let skills = ws.get_agent_skills("nonexistent");
         assert!(skills.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_agent_stream_optout_filters_stream_events() {
+        let ws = WsState::new();
+        let redis = arinova_server::db::redis::create_redis_pool("redis://localhost");
+        let (tx_a, mut rx_a) = tokio::sync::mpsc::unbounded_channel();
+        let (tx_b, mut rx_b) = tokio::sync::mpsc::unbounded_channel();
+        ws.user_connections.insert("user-a".into(), vec![("conn-a".into(), tx_a)]);
+        ws.user_connections.insert("user-b".into(), vec![("conn-b".into(), tx_b)]);
+        ws.set_agent_stream_optout("conv-1", "user-b", true);
+
+        let members = vec!["user-a".to_string(), "user-b".to_string()];
+        let chunk = serde_json::json!({"type": "stream_chunk", "conversationId": "conv-1", "chunk": "hi"});
+        ws.broadcast_to_members(&members, &chunk, &redis);
+
+        assert!(rx_a.try_recv().is_ok(), "other members still receive stream events");
+        assert!(rx_b.try_recv().is_err(), "opted-out member receives no stream events");
+
+        // Non-stream events and other conversations are unaffected
+        let msg = serde_json::json!({"type": "new_message", "conversationId": "conv-1"});
+        ws.broadcast_to_members(&members, &msg, &redis);
+        assert!(rx_b.try_recv().is_ok());
+        let other = serde_json::json!({"type": "stream_chunk", "conversationId": "conv-2"});
+        ws.broadcast_to_members(&members, &other, &redis);
+        assert!(rx_b.try_recv().is_ok());
+
+        ws.set_agent_stream_optout("conv-1", "user-b", false);
+        assert!(!ws.is_agent_stream_opted_out("conv-1", "user-b"));
+        ws.broadcast_to_members(&members, &chunk, &redis);
+        assert!(rx_b.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_read_receipts_batch_keeps_highest_seq() {
+        let ws = WsState::new();
+        assert!(ws.queue_read_receipt("conv-1", "user-a", 5), "first receipt starts a batch");
+        assert!(!ws.queue_read_receipt("conv-1", "user-a", 3));
+        assert!(!ws.queue_read_receipt("conv-1", "user-b", 4));
+
+        let batch = ws.take_read_receipts("conv-1");
+        assert_eq!(batch.get("user-a"), Some(&5));
+        assert_eq!(batch.get("user-b"), Some(&4));
+        assert!(ws.take_read_receipts("conv-1").is_empty());
+        assert!(ws.queue_read_receipt("conv-1", "user-a", 6), "taking ends the batch");
+    }
+
+    #[test]
+    fn test_stream_slots_limit_and_release() {
+        let ws = WsState::new();
+        let first = ws.try_acquire_stream_slot("user-a", 2).expect("first slot");
+        let _second = ws.try_acquire_stream_slot("user-a", 2).expect("second slot");
+        assert!(ws.try_acquire_stream_slot("user-a", 2).is_none(), "limit reached");
+        assert!(ws.try_acquire_stream_slot("user-b", 2).is_some(), "limits are per user");
+
+        drop(first);
+        assert_eq!(ws.active_stream_count("user-a"), 1);
+        assert!(ws.try_acquire_stream_slot("user-a", 2).is_some());
+    }
+}
+
+#[cfg(test)]
+mod agent_disconnect_tests {
+    use arinova_server::ws::agent_handler::cleanup_agent_tasks;
+    use arinova_server::ws::state::{PendingTask, WsState};
+    use std::time::Instant;
+
+    fn detach_task(ws: &WsState, task_id: &str, conversation_id: &str, agent_id: &str) {
+        let (chunk_tx, _) = tokio::sync::mpsc::unbounded_channel();
+        ws.pending_tasks.insert(
+            task_id.into(),
+            PendingTask {
+                agent_id: agent_id.into(),
+                conversation_id: conversation_id.into(),
+                accumulated: String::new(),
+                chunk_tx,
+                timeout_handle: tokio::spawn(async {}),
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn resumable_streams_are_kept_and_stale_ones_removed() {
+        let ws = WsState::new();
+        detach_task(&ws, "task-1", "conv-a", "agent-1");
+        for key in ["conv-a:agent-1", "conv-b:agent-1", "conv-a:agent-2"] {
+            ws.active_streams.insert(key.into(), Instant::now());
+        }
+
+        cleanup_agent_tasks(&ws, "agent-1");
+
+        assert!(ws.pending_tasks.contains_key("task-1"), "task waits for the agent to resume");
+        assert!(ws.has_active_stream_for_agent("conv-a", "agent-1"));
+        assert!(!ws.has_active_stream_for_agent("conv-b", "agent-1"), "stale stream cleaned up");
+        assert!(ws.has_active_stream_for_agent("conv-a", "agent-2"), "other agents untouched");
+    }
+
+    #[tokio::test]
+    async fn all_streams_cleaned_without_in_flight_tasks() {
+        let ws = WsState::new();
+        ws.active_streams.insert("conv-a:agent-1".into(), Instant::now());
+
+        cleanup_agent_tasks(&ws, "agent-1");
+
+        assert!(!ws.has_active_stream_for_agent("conv-a", "agent-1"));
+    }
 }
 
 #[cfg(test)]
@@ -547,3 +716,2014 @@ mod agent_filter_tests {
         assert!(result.is_empty(), "Unknown listen_mode should reject dispatch");
     }
 }
+
+#[cfg(test)]
+mod dispatch_coalesce_tests {
+    use arinova_server::ws::handler::{decide_dispatch, DispatchDecision, DISPATCH_COALESCE_WINDOW};
+    use std::time::Duration;
+
+    #[test]
+    fn no_active_stream_starts_immediately() {
+        assert_eq!(decide_dispatch(None, false), DispatchDecision::Start);
+        assert_eq!(decide_dispatch(None, true), DispatchDecision::Start);
+    }
+
+    #[test]
+    fn burst_within_window_coalesces_into_queued_tail() {
+        let age = Duration::from_millis(500);
+        assert_eq!(decide_dispatch(Some(age), true), DispatchDecision::Coalesce);
+    }
+
+    #[test]
+    fn burst_without_compatible_tail_is_queued() {
+        let age = Duration::from_millis(500);
+        assert_eq!(decide_dispatch(Some(age), false), DispatchDecision::Queue);
+    }
+
+    #[test]
+    fn stream_older_than_window_is_queued() {
+        assert_eq!(
+            decide_dispatch(Some(DISPATCH_COALESCE_WINDOW), true),
+            DispatchDecision::Queue
+        );
+        assert_eq!(
+            decide_dispatch(Some(Duration::from_secs(30)), true),
+            DispatchDecision::Queue
+        );
+    }
+}
+
+#[cfg(test)]
+mod reaction_grouping_tests {
+    use arinova_server::routes::messages::{group_reactions, ReactionCountRow};
+    use uuid::Uuid;
+
+    #[test]
+    fn groups_a_full_page_from_one_result_set() {
+        // A 100-message page is served by a single grouped reactions query;
+        // its rows are split per message in Rust.
+        let ids: Vec<Uuid> = (0..100).map(|_| Uuid::new_v4()).collect();
+        let rows: Vec<ReactionCountRow> = ids
+            .iter()
+            .flat_map(|id| {
+                vec![
+                    (*id, "👍".to_string(), 3, true),
+                    (*id, "🎉".to_string(), 1, false),
+                ]
+            })
+            .collect();
+
+        let grouped = group_reactions(rows);
+        assert_eq!(grouped.len(), 100);
+        for id in &ids {
+            let reactions = &grouped[id];
+            assert_eq!(reactions.len(), 2);
+            assert_eq!(reactions[0]["emoji"], "👍");
+            assert_eq!(reactions[0]["count"], 3);
+            assert_eq!(reactions[0]["userReacted"], true);
+            assert_eq!(reactions[1]["emoji"], "🎉");
+        }
+    }
+
+    #[test]
+    fn messages_without_reactions_are_absent() {
+        let grouped = group_reactions(vec![]);
+        assert!(grouped.get(&Uuid::new_v4()).is_none());
+    }
+}
+
+#[cfg(test)]
+mod listing_context_tests {
+    use arinova_server::routes::agent_hub::validate_allowed_contexts;
+
+    fn ctx(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn accepts_known_contexts() {
+        assert!(validate_allowed_contexts(&ctx(&["direct"])).is_ok());
+        assert!(validate_allowed_contexts(&ctx(&["direct", "group", "community"])).is_ok());
+    }
+
+    #[test]
+    fn rejects_empty_and_unknown_contexts() {
+        assert!(validate_allowed_contexts(&[]).is_err());
+        let err = validate_allowed_contexts(&ctx(&["direct", "broadcast"])).unwrap_err();
+        assert!(err.contains("broadcast"));
+    }
+}
+
+#[cfg(test)]
+mod idempotency_tests {
+    use arinova_server::services::idempotency::key_from_headers;
+    use axum::http::{HeaderMap, HeaderValue};
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert("Idempotency-Key", HeaderValue::from_str(value).unwrap());
+        h
+    }
+
+    #[test]
+    fn reads_trimmed_key() {
+        assert_eq!(key_from_headers(&headers("  abc-123 ")).as_deref(), Some("abc-123"));
+    }
+
+    #[test]
+    fn ignores_missing_empty_and_oversized_keys() {
+        assert!(key_from_headers(&HeaderMap::new()).is_none());
+        assert!(key_from_headers(&headers("   ")).is_none());
+        assert!(key_from_headers(&headers(&"k".repeat(129))).is_none());
+    }
+}
+
+#[cfg(test)]
+mod kb_overview_tests {
+    use arinova_server::services::embedding::{build_kb_overview, compose_system_prompt};
+
+    #[test]
+    fn first_turn_prompt_includes_overview() {
+        let files = vec![
+            ("pricing.md".to_string(), "Plans start at $10/month.\n\nEnterprise on request.".to_string()),
+            ("faq.txt".to_string(), "How do I reset my password?".to_string()),
+        ];
+        let overview = build_kb_overview(&files);
+        assert!(overview.contains("- pricing.md: Plans start at $10/month. Enterprise on request."));
+        assert!(overview.contains("- faq.txt: How do I reset my password?"));
+
+        let prompt = compose_system_prompt("You are a helpful agent.", Some(&overview), &[]);
+        assert!(prompt.starts_with("You are a helpful agent."));
+        assert!(prompt.contains(&overview));
+    }
+
+    #[test]
+    fn later_turns_only_carry_retrieved_chunks() {
+        let chunks = vec!["chunk one".to_string()];
+        let prompt = compose_system_prompt("Base", None, &chunks);
+        assert!(!prompt.contains("Knowledge base overview"));
+        assert!(prompt.ends_with("chunk one"));
+    }
+
+    #[test]
+    fn overview_is_bounded() {
+        let files: Vec<(String, String)> = (0..50)
+            .map(|i| (format!("file{i}.txt"), "x".repeat(1000)))
+            .collect();
+        assert!(build_kb_overview(&files).chars().count() <= 4000);
+    }
+}
+
+#[cfg(test)]
+mod example_conversation_tests {
+    use arinova_server::routes::agent_hub::{parse_example_conversations, ExampleConversation};
+    use serde_json::json;
+
+    #[test]
+    fn normalizes_valid_pairs() {
+        let parsed = parse_example_conversations(&json!([
+            {"user": "  Hi ", "assistant": "Hello!  "}
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed,
+            vec![ExampleConversation { user: "Hi".into(), assistant: "Hello!".into() }]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert!(parse_example_conversations(&json!({"user": "Hi"})).is_err());
+        assert!(parse_example_conversations(&json!([{"user": "Hi"}])).is_err());
+        assert!(parse_example_conversations(&json!([{"user": "Hi", "assistant": "  "}])).is_err());
+    }
+
+    #[test]
+    fn enforces_pair_and_length_caps() {
+        let pair = json!({"user": "q", "assistant": "a"});
+        assert!(parse_example_conversations(&json!(vec![pair.clone(); 5])).is_ok());
+        assert!(parse_example_conversations(&json!(vec![pair; 6])).is_err());
+        let long = "x".repeat(1001);
+        assert!(parse_example_conversations(&json!([{"user": long, "assistant": "a"}])).is_err());
+    }
+}
+
+#[cfg(test)]
+mod model_allowlist_tests {
+    use arinova_server::services::llm::{supported_models, validate_fallback_models, validate_model};
+
+    #[test]
+    fn accepts_curated_models() {
+        assert!(validate_model("openai/gpt-4o-mini", &[]).is_ok());
+        assert!(validate_model("anthropic/claude-3.5-sonnet", &[]).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_model_listing_options() {
+        let err = validate_model("openai/gpt-4o-minii", &[]).unwrap_err();
+        assert!(err.contains("openai/gpt-4o-mini"));
+    }
+
+    #[test]
+    fn extra_models_extend_allowlist() {
+        let extra = vec!["x-ai/grok-3".to_string(), "openai/gpt-4o".to_string()];
+        assert!(validate_model("x-ai/grok-3", &extra).is_ok());
+        let models = supported_models(&extra);
+        assert_eq!(models.iter().filter(|m| *m == "openai/gpt-4o").count(), 1);
+    }
+
+    #[test]
+    fn fallback_chain_drops_primary_and_duplicates() {
+        let chain = vec![
+            "openai/gpt-4o-mini".to_string(),
+            " anthropic/claude-3.5-sonnet ".to_string(),
+            "anthropic/claude-3.5-sonnet".to_string(),
+        ];
+        let normalized = validate_fallback_models("openai/gpt-4o-mini", &chain, &[]).unwrap();
+        assert_eq!(normalized, vec!["anthropic/claude-3.5-sonnet".to_string()]);
+    }
+
+    #[test]
+    fn fallback_chain_rejects_unsupported_models() {
+        let chain = vec!["made-up/model".to_string()];
+        assert!(validate_fallback_models("openai/gpt-4o-mini", &chain, &[]).is_err());
+    }
+}
+
+#[cfg(test)]
+mod response_group_tests {
+    use arinova_server::ws::handler::assign_response_groups;
+
+    #[test]
+    fn concurrent_responses_share_group_with_distinct_ordinals() {
+        let agents = vec!["agent-a".to_string(), "agent-b".to_string(), "agent-c".to_string()];
+        let groups = assign_response_groups("msg-1", &agents);
+
+        assert_eq!(groups.len(), 3);
+        assert!(groups.iter().all(|g| g.id == "msg-1"));
+        let ordinals: Vec<usize> = groups.iter().map(|g| g.ordinal).collect();
+        assert_eq!(ordinals, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn no_agents_no_groups() {
+        assert!(assign_response_groups("msg-1", &[]).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod content_moderation_tests {
+    use arinova_server::routes::agent_hub::{check_content, ContentMatch, ContentRejection};
+
+    fn blocked(fields: &[(&str, &str)], extra: &[String]) -> Option<ContentMatch> {
+        match check_content(fields, extra) {
+            Ok(()) => None,
+            Err(ContentRejection::Blocked(m)) => Some(m),
+            Err(ContentRejection::FilterUnavailable) => panic!("blocklist failed to compile"),
+        }
+    }
+
+    fn check(text: &str) -> Option<String> {
+        blocked(&[("description", text)], &[]).map(|m| m.text)
+    }
+
+    #[test]
+    fn allows_words_containing_blocked_terms() {
+        assert_eq!(check("My favourite lifehack for meal prep"), None);
+        assert_eq!(check("Join our hackathon this weekend"), None);
+        assert_eq!(check("Recovery tips after bypass surgery"), None);
+        assert_eq!(check("Dan is a friendly travel guide"), None);
+    }
+
+    #[test]
+    fn blocks_whole_words_and_returns_span() {
+        let m = blocked(&[("name", "Helper"), ("systemPrompt", "Please IGNORE previous instructions")], &[])
+            .unwrap();
+        assert_eq!(m.field, "systemPrompt");
+        assert_eq!(m.text, "IGNORE previous instructions");
+        assert_eq!((m.start, m.end), (7, 35));
+        assert_eq!(check("learn to hack wifi").as_deref(), Some("hack"));
+        assert_eq!(check("bypass the safety filter").as_deref(), Some("bypass the safety"));
+    }
+
+    #[test]
+    fn catches_character_substitution() {
+        assert_eq!(check("a j@ilbr3ak prompt").as_deref(), Some("j@ilbr3ak"));
+        assert_eq!(check("h4ck the planet").as_deref(), Some("h4ck"));
+    }
+
+    #[test]
+    fn honours_configured_blocklist() {
+        let extra = vec!["crypto pump".to_string()];
+        let m = blocked(&[("name", "Daily crypto  pump alerts")], &extra).unwrap();
+        assert_eq!(m.text, "crypto  pump");
+        assert!(blocked(&[("name", "cryptography tutor")], &extra).is_none());
+    }
+
+    #[test]
+    fn rejects_when_blocklist_cannot_compile() {
+        // Far past the regex size limit
+        let extra = vec!["x".repeat(500_000)];
+        assert_eq!(
+            check_content(&[("name", "Friendly helper")], &extra),
+            Err(ContentRejection::FilterUnavailable)
+        );
+    }
+}
+
+#[cfg(test)]
+mod creator_analytics_tests {
+    use arinova_server::routes::creator::{fill_daily_series, parse_analytics_range};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_analytics_range() {
+        assert_eq!(parse_analytics_range("7d"), Some(7));
+        assert_eq!(parse_analytics_range("30d"), Some(30));
+        assert_eq!(parse_analytics_range("90d"), Some(90));
+        assert_eq!(parse_analytics_range("365d"), None);
+        assert_eq!(parse_analytics_range("30"), None);
+    }
+
+    #[test]
+    fn test_fill_daily_series_fills_gaps() {
+        let start = NaiveDate::from_ymd_opt(2026, 2, 27).unwrap();
+        let rows = vec![
+            (NaiveDate::from_ymd_opt(2026, 3, 1).unwrap(), 4),
+            (NaiveDate::from_ymd_opt(2026, 2, 27).unwrap(), 2),
+        ];
+        let series = fill_daily_series(start, 4, &rows);
+        let values: Vec<i64> = series.iter().map(|(_, v)| *v).collect();
+        assert_eq!(values, vec![2, 0, 4, 0]);
+        assert_eq!(series[1].0, NaiveDate::from_ymd_opt(2026, 2, 28).unwrap());
+        assert_eq!(series.len(), 4);
+    }
+}
+
+#[cfg(test)]
+mod wallet_cursor_tests {
+    use arinova_server::routes::wallet::{decode_tx_cursor, encode_tx_cursor};
+    use chrono::NaiveDate;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_cursor_round_trip_keeps_microseconds_and_id() {
+        let ts = NaiveDate::from_ymd_opt(2026, 3, 14)
+            .unwrap()
+            .and_hms_micro_opt(9, 26, 53, 589_793)
+            .unwrap();
+        let id = Uuid::parse_str("6f1c2a4e-8b3d-4c5e-9f70-1a2b3c4d5e6f").unwrap();
+        let cursor = encode_tx_cursor(ts, id);
+        assert_eq!(cursor, "2026-03-14T09:26:53.589793Z_6f1c2a4e-8b3d-4c5e-9f70-1a2b3c4d5e6f");
+        assert_eq!(decode_tx_cursor(&cursor), Some((ts, id)));
+    }
+
+    #[test]
+    fn test_invalid_cursor_rejected() {
+        assert_eq!(decode_tx_cursor("yesterday"), None);
+        assert_eq!(decode_tx_cursor(""), None);
+        // Timestamp-only cursors from before ids were included
+        assert_eq!(decode_tx_cursor("2026-03-14T09:26:53.589793Z"), None);
+        assert_eq!(decode_tx_cursor("2026-03-14T09:26:53.589793Z_not-a-uuid"), None);
+    }
+}
+
+#[cfg(test)]
+mod crypto_tests {
+    use arinova_server::services::crypto::{hmac_sha256_hex, verify_hmac_sha256};
+
+    #[test]
+    fn test_hmac_sha256_known_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hmac_sha256_hex("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_verify_accepts_valid_and_prefixed_signatures() {
+        let sig = hmac_sha256_hex("secret", b"{\"amount\":100}");
+        assert!(verify_hmac_sha256("secret", b"{\"amount\":100}", &sig));
+        assert!(verify_hmac_sha256("secret", b"{\"amount\":100}", &format!("sha256={sig}")));
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_payload_or_bad_hex() {
+        let sig = hmac_sha256_hex("secret", b"{\"amount\":100}");
+        assert!(!verify_hmac_sha256("secret", b"{\"amount\":1000}", &sig));
+        assert!(!verify_hmac_sha256("other", b"{\"amount\":100}", &sig));
+        assert!(!verify_hmac_sha256("secret", b"{\"amount\":100}", "not-hex"));
+    }
+}
+
+#[cfg(test)]
+mod wallet_hold_tests {
+    use arinova_server::services::wallet::creator_share;
+
+    #[test]
+    fn test_creator_share_rounds_down() {
+        assert_eq!(creator_share(10), 7);
+        assert_eq!(creator_share(3), 2);
+        assert_eq!(creator_share(1), 0);
+    }
+}
+
+#[cfg(test)]
+mod openrouter_retry_tests {
+    use arinova_server::services::openrouter::{
+        backoff_delay, is_retryable_status, parse_retry_after, RetryPolicy,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_retryable_statuses() {
+        assert!(is_retryable_status(429));
+        assert!(is_retryable_status(502));
+        assert!(is_retryable_status(503));
+        assert!(!is_retryable_status(400));
+        assert!(!is_retryable_status(401));
+        assert!(!is_retryable_status(404));
+    }
+
+    #[test]
+    fn test_parse_retry_after_seconds_only() {
+        assert_eq!(parse_retry_after("5"), Some(5));
+        assert_eq!(parse_retry_after(" 12 "), Some(12));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2026 07:28:00 GMT"), None);
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_respects_retry_after() {
+        let policy = RetryPolicy { max_retries: 3, base_delay_ms: 500 };
+        assert_eq!(backoff_delay(&policy, 0, None), Duration::from_millis(500));
+        assert_eq!(backoff_delay(&policy, 2, None), Duration::from_millis(2000));
+        assert_eq!(backoff_delay(&policy, 0, Some(3)), Duration::from_secs(3));
+        assert_eq!(backoff_delay(&policy, 3, Some(1)), Duration::from_millis(4000));
+    }
+}
+
+#[cfg(test)]
+mod llm_provider_tests {
+    use arinova_server::services::llm::{anthropic_model_id, parse_chunk, validate_model_provider, LlmProvider};
+
+    #[test]
+    fn test_anthropic_model_id_mapping() {
+        assert_eq!(anthropic_model_id("anthropic/claude-3.5-sonnet"), "claude-3-5-sonnet-latest");
+        assert_eq!(anthropic_model_id("anthropic/claude-3.7-sonnet"), "claude-3-7-sonnet-latest");
+        assert_eq!(anthropic_model_id("claude-3-5-haiku-20241022"), "claude-3-5-haiku-20241022");
+    }
+
+    #[test]
+    fn test_validate_model_provider() {
+        assert!(validate_model_provider("openrouter", "openai/gpt-4o").is_ok());
+        assert!(validate_model_provider("anthropic", "anthropic/claude-3.5-sonnet").is_ok());
+        assert!(validate_model_provider("anthropic", "openai/gpt-4o").is_err());
+        assert!(validate_model_provider("azure", "openai/gpt-4o").is_err());
+    }
+
+    #[test]
+    fn test_parse_chunk_normalizes_providers() {
+        let openai = r#"{"choices":[{"delta":{"content":"Hi"}}]}"#;
+        let anthropic = r#"{"type":"content_block_delta","delta":{"type":"text_delta","text":"Hi"}}"#;
+        assert_eq!(parse_chunk(&LlmProvider::OpenAI, openai), Some("Hi".into()));
+        assert_eq!(parse_chunk(&LlmProvider::Anthropic, anthropic), Some("Hi".into()));
+        assert_eq!(parse_chunk(&LlmProvider::Anthropic, r#"{"type":"message_stop"}"#), None);
+    }
+}
+
+#[cfg(test)]
+mod group_bulk_add_tests {
+    use arinova_server::routes::groups::added_members_message;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn single_name() {
+        assert_eq!(added_members_message(&names(&["Ann"])), "Ann was added to the group");
+    }
+
+    #[test]
+    fn two_names() {
+        assert_eq!(
+            added_members_message(&names(&["Ann", "Bo"])),
+            "Ann and Bo were added to the group"
+        );
+    }
+
+    #[test]
+    fn many_names() {
+        assert_eq!(
+            added_members_message(&names(&["Ann", "Bo", "Cy"])),
+            "Ann, Bo and Cy were added to the group"
+        );
+    }
+}
+
+#[cfg(test)]
+mod ws_rate_limit_tests {
+    use arinova_server::ws::handler::{rate_limit_reset_in, RateLimitStatus};
+
+    #[test]
+    fn reset_counts_down_within_the_minute() {
+        assert_eq!(rate_limit_reset_in(120), 60);
+        assert_eq!(rate_limit_reset_in(121), 59);
+        assert_eq!(rate_limit_reset_in(179), 1);
+    }
+
+    #[test]
+    fn status_allows_up_to_the_limit() {
+        let at_limit = RateLimitStatus { count: 10, limit: 10, reset_in_secs: 5 };
+        assert!(at_limit.allowed());
+        assert_eq!(at_limit.remaining(), 0);
+
+        let over = RateLimitStatus { count: 11, ..at_limit };
+        assert!(!over.allowed());
+        assert_eq!(over.remaining(), 0);
+    }
+}
+
+#[cfg(test)]
+mod ws_heartbeat_tests {
+    use arinova_server::ws::handler::heartbeat_timeout_for;
+    use std::time::Duration;
+
+    #[test]
+    fn default_without_hello() {
+        assert_eq!(heartbeat_timeout_for(None), Duration::from_secs(45));
+        assert_eq!(heartbeat_timeout_for(Some(0)), Duration::from_secs(45));
+    }
+
+    #[test]
+    fn doubles_declared_interval() {
+        assert_eq!(heartbeat_timeout_for(Some(30_000)), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn clamps_to_bounds() {
+        assert_eq!(heartbeat_timeout_for(Some(1_000)), Duration::from_secs(15));
+        assert_eq!(heartbeat_timeout_for(Some(3_600_000)), Duration::from_secs(300));
+    }
+}
+
+#[cfg(test)]
+mod attachment_type_tests {
+    use arinova_server::routes::uploads::is_allowed_attachment_type;
+
+    #[test]
+    fn allows_common_media_and_documents() {
+        for t in ["image/png", "video/mp4", "audio/webm", "text/plain", "application/pdf"] {
+            assert!(is_allowed_attachment_type(t), "{} should be allowed", t);
+        }
+        assert!(is_allowed_attachment_type(
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        ));
+        assert!(is_allowed_attachment_type("Image/JPEG; charset=binary"));
+    }
+
+    #[test]
+    fn rejects_executables_markup_and_unknown() {
+        for t in ["application/x-msdownload", "application/x-sh", "text/html", "image/svg+xml", ""] {
+            assert!(!is_allowed_attachment_type(t), "{} should be rejected", t);
+        }
+    }
+}
+
+#[cfg(test)]
+mod attachment_sniff_tests {
+    use arinova_server::routes::uploads::sniff_attachment_type;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    const PE: &[u8] = b"MZ\x90\0\x03\0\0\0\x04\0\0\0\xff\xff";
+    const ZIP: &[u8] = b"PK\x03\x04\x14\0\0\0\x08\0";
+
+    #[test]
+    fn sniffed_type_is_authoritative() {
+        assert_eq!(sniff_attachment_type("image/jpeg", PNG).unwrap(), "image/png");
+    }
+
+    #[test]
+    fn executable_labelled_as_image_is_rejected() {
+        assert!(sniff_attachment_type("image/png", PE).is_err());
+    }
+
+    #[test]
+    fn contradicting_family_is_rejected() {
+        assert!(sniff_attachment_type("application/pdf", PNG).is_err());
+        assert!(sniff_attachment_type("image/png", b"just some text").is_err());
+    }
+
+    #[test]
+    fn container_keeps_declared_office_type() {
+        let docx = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+        assert_eq!(sniff_attachment_type(docx, ZIP).unwrap(), docx);
+        assert!(sniff_attachment_type("image/png", ZIP).is_err());
+    }
+
+    #[test]
+    fn plain_text_without_signature_is_accepted() {
+        assert_eq!(sniff_attachment_type("text/plain; charset=utf-8", b"hello").unwrap(), "text/plain");
+        assert!(sniff_attachment_type("text/plain", b"#!/bin/sh\nrm -rf /").is_err());
+    }
+}
+
+#[cfg(test)]
+mod thumbnail_tests {
+    use arinova_server::services::thumbnail::{make_thumbnail, THUMBNAIL_MAX_EDGE};
+    use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+    use std::io::Cursor;
+
+    fn encode_png(img: DynamicImage) -> Vec<u8> {
+        let mut out = Vec::new();
+        img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png).unwrap();
+        out
+    }
+
+    #[test]
+    fn downscales_large_images_keeping_aspect_ratio() {
+        let png = encode_png(DynamicImage::ImageRgb8(RgbImage::new(2048, 1024)));
+        let thumb = make_thumbnail(&png).expect("thumbnail");
+        assert_eq!((thumb.width, thumb.height), (THUMBNAIL_MAX_EDGE, THUMBNAIL_MAX_EDGE / 2));
+        assert_eq!(thumb.content_type, "image/jpeg");
+        assert!(image::load_from_memory(&thumb.data).is_ok());
+    }
+
+    #[test]
+    fn keeps_transparency_as_png() {
+        let png = encode_png(DynamicImage::ImageRgba8(RgbaImage::new(1024, 1024)));
+        let thumb = make_thumbnail(&png).expect("thumbnail");
+        assert_eq!(thumb.extension, "png");
+    }
+
+    #[test]
+    fn skips_small_and_undecodable_images() {
+        let small = encode_png(DynamicImage::ImageRgb8(RgbImage::new(300, 200)));
+        assert!(make_thumbnail(&small).is_none());
+        assert!(make_thumbnail(b"not an image").is_none());
+    }
+}
+
+#[cfg(test)]
+mod exif_strip_tests {
+    use arinova_server::services::exif::strip_exif;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::codecs::png::PngEncoder;
+    use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageReader, RgbImage};
+    use std::io::Cursor;
+
+    /// Minimal little-endian TIFF block with Orientation = 6 (rotate 90° clockwise).
+    fn exif_rotate_90() -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend_from_slice(&8u32.to_le_bytes()); // IFD offset
+        exif.extend_from_slice(&1u16.to_le_bytes()); // one entry
+        exif.extend_from_slice(&0x0112u16.to_le_bytes()); // Orientation
+        exif.extend_from_slice(&3u16.to_le_bytes()); // SHORT
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&6u16.to_le_bytes());
+        exif.extend_from_slice(&[0, 0]);
+        exif.extend_from_slice(&0u32.to_le_bytes()); // no next IFD
+        exif
+    }
+
+    fn landscape() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::new(40, 20))
+    }
+
+    fn jpeg_with_exif() -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut out);
+        encoder.set_exif_metadata(exif_rotate_90()).unwrap();
+        landscape().write_with_encoder(encoder).unwrap();
+        out
+    }
+
+    fn exif_of(data: &[u8]) -> Option<Vec<u8>> {
+        ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap()
+            .exif_metadata()
+            .unwrap()
+    }
+
+    #[test]
+    fn jpeg_exif_removed_and_orientation_applied() {
+        let original = jpeg_with_exif();
+        assert!(exif_of(&original).is_some());
+
+        let stripped = strip_exif(&original).expect("stripped");
+        assert!(exif_of(&stripped).is_none());
+        let img = image::load_from_memory(&stripped).unwrap();
+        assert_eq!((img.width(), img.height()), (20, 40), "rotated upright");
+    }
+
+    #[test]
+    fn png_exif_removed() {
+        let mut original = Vec::new();
+        let mut encoder = PngEncoder::new(&mut original);
+        encoder.set_exif_metadata(exif_rotate_90()).unwrap();
+        landscape().write_with_encoder(encoder).unwrap();
+        assert!(exif_of(&original).is_some());
+
+        let stripped = strip_exif(&original).expect("stripped");
+        assert!(exif_of(&stripped).is_none());
+    }
+
+    #[test]
+    fn files_without_exif_are_untouched() {
+        let mut plain = Vec::new();
+        landscape()
+            .write_to(&mut Cursor::new(&mut plain), image::ImageFormat::Jpeg)
+            .unwrap();
+        assert!(strip_exif(&plain).is_none());
+        assert!(strip_exif(b"%PDF-1.7 not an image").is_none());
+    }
+}
+
+#[cfg(test)]
+mod upload_pipeline_tests {
+    use arinova_server::routes::uploads::process_upload;
+    use arinova_server::services::thumbnail::THUMBNAIL_MAX_EDGE;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageReader, RgbImage};
+    use std::io::Cursor;
+
+    /// JPEG carrying a one-entry EXIF block (Orientation = 1).
+    fn jpeg_with_exif(width: u32, height: u32) -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend_from_slice(&8u32.to_le_bytes());
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        exif.extend_from_slice(&0x0112u16.to_le_bytes());
+        exif.extend_from_slice(&3u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        exif.extend_from_slice(&[0, 0]);
+        exif.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut out = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut out);
+        encoder.set_exif_metadata(exif).unwrap();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_with_encoder(encoder)
+            .unwrap();
+        out
+    }
+
+    fn has_exif(data: &[u8]) -> bool {
+        ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap()
+            .exif_metadata()
+            .unwrap()
+            .is_some()
+    }
+
+    #[tokio::test]
+    async fn images_are_stripped_measured_and_thumbnailed() {
+        let original = jpeg_with_exif(1024, 768);
+        assert!(has_exif(&original));
+
+        let processed = process_upload(true, "image/jpeg", original.into()).await;
+        assert!(processed.stripped);
+        assert!(!has_exif(&processed.data));
+        assert_eq!((processed.width, processed.height), (Some(1024), Some(768)));
+        let thumb = processed.thumbnail.expect("thumbnail");
+        assert_eq!(thumb.width, THUMBNAIL_MAX_EDGE);
+    }
+
+    #[tokio::test]
+    async fn metadata_kept_when_stripping_disabled() {
+        let original = jpeg_with_exif(64, 64);
+        let processed = process_upload(false, "image/jpeg", original.clone().into()).await;
+        assert!(!processed.stripped);
+        assert_eq!(processed.data.as_ref(), original.as_slice());
+        assert_eq!((processed.width, processed.height), (Some(64), Some(64)));
+        assert!(processed.thumbnail.is_none());
+    }
+
+    #[tokio::test]
+    async fn non_images_pass_through() {
+        let pdf = b"%PDF-1.7 not an image".to_vec();
+        let processed = process_upload(true, "application/pdf", pdf.clone().into()).await;
+        assert!(!processed.stripped);
+        assert_eq!(processed.data.as_ref(), pdf.as_slice());
+        assert!(processed.width.is_none() && processed.thumbnail.is_none());
+    }
+}
+
+#[cfg(test)]
+mod chunked_upload_tests {
+    use arinova_server::services::chunked_upload::{chunk_count, expected_chunk_len};
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn chunk_count_rounds_up() {
+        assert_eq!(chunk_count(10 * MB, 5 * MB), 2);
+        assert_eq!(chunk_count(10 * MB + 1, 5 * MB), 3);
+        assert_eq!(chunk_count(1, 5 * MB), 1);
+    }
+
+    #[test]
+    fn last_chunk_holds_the_remainder() {
+        let total = 12 * MB + 100;
+        assert_eq!(expected_chunk_len(total, 5 * MB, 0), Some(5 * MB));
+        assert_eq!(expected_chunk_len(total, 5 * MB, 1), Some(5 * MB));
+        assert_eq!(expected_chunk_len(total, 5 * MB, 2), Some(2 * MB + 100));
+        assert_eq!(expected_chunk_len(total, 5 * MB, 3), None);
+    }
+
+    #[test]
+    fn exact_multiple_has_full_last_chunk() {
+        assert_eq!(expected_chunk_len(10 * MB, 5 * MB, 1), Some(5 * MB));
+        assert_eq!(expected_chunk_len(10 * MB, 5 * MB, 2), None);
+    }
+}
+
+#[cfg(test)]
+mod push_coalesce_tests {
+    use arinova_server::services::push::{coalesce_payloads, PushPayload};
+
+    fn push(sender: &str, msg_id: &str) -> PushPayload {
+        PushPayload {
+            notification_type: "message".into(),
+            title: sender.into(),
+            body: format!("hello from {}", sender),
+            url: Some(format!("/?c=conv&m={}", msg_id)),
+            message_id: Some(msg_id.into()),
+        }
+    }
+
+    #[test]
+    fn single_push_is_unchanged() {
+        let merged = coalesce_payloads(vec![push("Alice", "m1")]).unwrap();
+        assert_eq!(merged.body, "hello from Alice");
+        assert_eq!(merged.message_id.as_deref(), Some("m1"));
+    }
+
+    #[test]
+    fn burst_becomes_count_linking_latest() {
+        let merged = coalesce_payloads(vec![
+            push("Alice", "m1"),
+            push("Bot", "m2"),
+            push("Alice", "m3"),
+        ])
+        .unwrap();
+        assert_eq!(merged.title, "Alice, Bot");
+        assert_eq!(merged.body, "3 new messages");
+        assert_eq!(merged.message_id.as_deref(), Some("m3"));
+        assert_eq!(merged.url.as_deref(), Some("/?c=conv&m=m3"));
+    }
+
+    #[test]
+    fn empty_queue_sends_nothing() {
+        assert!(coalesce_payloads(Vec::new()).is_none());
+    }
+}
+
+#[cfg(test)]
+mod notification_preference_tests {
+    use arinova_server::db::models::NotificationPreference;
+    use arinova_server::routes::notifications::is_valid_quiet_hours_time;
+    use arinova_server::services::push_trigger::{is_in_quiet_hours, mentions_username, preferences_allow};
+    use chrono::{TimeZone, Utc};
+
+    fn prefs() -> NotificationPreference {
+        NotificationPreference {
+            id: uuid::Uuid::new_v4(),
+            user_id: "u1".into(),
+            global_enabled: true,
+            message_enabled: true,
+            playground_invite_enabled: true,
+            playground_turn_enabled: true,
+            playground_result_enabled: true,
+            quiet_hours_start: None,
+            quiet_hours_end: None,
+            always_push_mobile: false,
+            mention_enabled: true,
+            community_enabled: true,
+            friend_request_enabled: true,
+            voice_call_enabled: true,
+            quiet_hours_utc_offset: Some(0),
+        }
+    }
+
+    #[test]
+    fn quiet_hours_respect_utc_offset() {
+        // 15:30 UTC is 23:30 in UTC+8
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 15, 30, 0).unwrap();
+        assert!(is_in_quiet_hours("23:00", "07:00", 480, now));
+        assert!(!is_in_quiet_hours("23:00", "07:00", 0, now));
+        assert!(is_in_quiet_hours("15:00", "16:00", 0, now));
+    }
+
+    #[test]
+    fn per_type_toggles() {
+        let now = Utc::now();
+        let mut p = prefs();
+        p.message_enabled = false;
+        assert!(!preferences_allow(&p, "message", now));
+        assert!(preferences_allow(&p, "mention", now), "mentions are configured separately");
+        p.friend_request_enabled = false;
+        assert!(!preferences_allow(&p, "friend_request", now));
+        assert!(preferences_allow(&p, "memory_capsule", now));
+        p.global_enabled = false;
+        assert!(!preferences_allow(&p, "memory_capsule", now));
+    }
+
+    #[test]
+    fn quiet_hours_suppress_all_types() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 2, 0, 0).unwrap();
+        let mut p = prefs();
+        p.quiet_hours_start = Some("22:00".into());
+        p.quiet_hours_end = Some("07:00".into());
+        assert!(!preferences_allow(&p, "mention", now));
+        p.quiet_hours_utc_offset = Some(-600); // 16:00 in UTC-10
+        assert!(preferences_allow(&p, "mention", now));
+    }
+
+    #[test]
+    fn mention_matches_whole_username() {
+        assert!(mentions_username("hey @Alice can you look", "alice"));
+        assert!(mentions_username("@alice", "alice"));
+        assert!(!mentions_username("hey @alice_bot", "alice"));
+        assert!(!mentions_username("ping @alicia", "alice"));
+    }
+
+    #[test]
+    fn quiet_hours_time_format() {
+        assert!(is_valid_quiet_hours_time("07:05"));
+        assert!(!is_valid_quiet_hours_time("7:05"));
+        assert!(!is_valid_quiet_hours_time("24:00"));
+        assert!(!is_valid_quiet_hours_time("12:60"));
+    }
+}
+
+#[cfg(test)]
+mod sandbox_language_tests {
+    use arinova_server::routes::sandbox::resolve_language;
+
+    #[test]
+    fn aliases_resolve_to_runtimes() {
+        assert_eq!(resolve_language("javascript"), Some("node"));
+        assert_eq!(resolve_language("Python3"), Some("python"));
+        assert_eq!(resolve_language("sh"), Some("bash"));
+    }
+
+    #[test]
+    fn unknown_languages_are_rejected() {
+        assert_eq!(resolve_language("ruby"), None);
+        assert_eq!(resolve_language(""), None);
+    }
+
+    async fn response_json(res: axum::response::Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn missing_language_defaults_to_node_with_its_limits() {
+        use arinova_server::config::SandboxLimits;
+        use arinova_server::routes::sandbox::sandbox_response;
+
+        let config = arinova_server::config::Config {
+            sandbox_node_limits: SandboxLimits { timeout_secs: 7, memory_mb: 128 },
+            ..super::config_tests::test_config()
+        };
+        let body = response_json(sandbox_response(&config, None, &Default::default())).await;
+        assert_eq!(body["language"], "node");
+        assert_eq!(body["limits"]["timeoutSecs"], 7);
+        assert_eq!(body["limits"]["memoryMb"], 128);
+    }
+
+    #[tokio::test]
+    async fn unsupported_language_is_a_bad_request() {
+        use arinova_server::routes::sandbox::sandbox_response;
+
+        let config = super::config_tests::test_config();
+        let res = sandbox_response(&config, Some("ruby"), &Default::default());
+        assert_eq!(res.status(), axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(response_json(res).await["code"], "unsupported_language");
+    }
+}
+
+#[cfg(test)]
+mod agent_health_history_tests {
+    use arinova_server::routes::agent_health::parse_history_range;
+    use chrono::Duration;
+
+    #[test]
+    fn parses_hour_and_day_ranges() {
+        assert_eq!(parse_history_range("24h"), Some(Duration::hours(24)));
+        assert_eq!(parse_history_range("7d"), Some(Duration::days(7)));
+        assert_eq!(parse_history_range("31d"), None);
+        assert_eq!(parse_history_range("0h"), None);
+        assert_eq!(parse_history_range("1w"), None);
+        assert_eq!(parse_history_range(""), None);
+        assert_eq!(parse_history_range("1é"), None);
+    }
+}
+
+#[cfg(test)]
+mod agent_presence_tests {
+    use arinova_server::ws::handler::agent_presence_phrase;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn never_connected() {
+        assert_eq!(agent_presence_phrase(None, None, Utc::now()), "has never connected");
+    }
+
+    #[test]
+    fn reports_elapsed_time_and_version() {
+        let now = Utc::now();
+        assert_eq!(
+            agent_presence_phrase(Some(now - Duration::minutes(2)), None, now),
+            "was last online 2 minutes ago"
+        );
+        assert_eq!(
+            agent_presence_phrase(Some(now - Duration::hours(1)), Some("1.4.0"), now),
+            "was last online 1 hour ago (client 1.4.0)"
+        );
+        assert_eq!(
+            agent_presence_phrase(Some(now - Duration::seconds(10)), None, now),
+            "was last online just now"
+        );
+    }
+}
+
+#[cfg(test)]
+mod friend_request_limit_tests {
+    use arinova_server::routes::friends::{
+        daily_limit_reached, daily_request_key, mutual_friends_key, reject_cooldown_key,
+    };
+
+    #[test]
+    fn daily_limit() {
+        assert!(!daily_limit_reached(49, 50));
+        assert!(daily_limit_reached(50, 50));
+        assert!(!daily_limit_reached(1000, 0));
+    }
+
+    #[test]
+    fn keys_are_directional() {
+        assert_eq!(daily_request_key("u1", "20260101"), "friend_req_daily:u1:20260101");
+        assert_ne!(reject_cooldown_key("a", "b"), reject_cooldown_key("b", "a"));
+    }
+
+    #[test]
+    fn mutual_friends_key_is_symmetric() {
+        assert_eq!(mutual_friends_key("a", "b"), mutual_friends_key("b", "a"));
+        assert_eq!(mutual_friends_key("b", "a"), "mutual_friends:a:b");
+    }
+}
+
+#[cfg(test)]
+mod bulk_block_tests {
+    use arinova_server::routes::blocking::normalize_block_targets;
+
+    #[test]
+    fn dedupes_and_skips_self() {
+        let ids: Vec<String> = ["u2", " u3 ", "me", "u2", "", "u3"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(normalize_block_targets(&ids, "me"), vec!["u2", "u3"]);
+    }
+}
+
+#[cfg(test)]
+mod privacy_settings_tests {
+    use arinova_server::routes::users::PrivacySettings;
+
+    fn with(who: &str) -> PrivacySettings {
+        PrivacySettings { who_can_message: who.into(), ..Default::default() }
+    }
+
+    #[test]
+    fn default_requires_friendship() {
+        let p = PrivacySettings::default();
+        assert!(p.allows_direct_message(true));
+        assert!(!p.allows_direct_message(false));
+        assert!(p.allows_friend_requests());
+    }
+
+    #[test]
+    fn everyone_and_nobody() {
+        assert!(with("everyone").allows_direct_message(false));
+        assert!(!with("nobody").allows_direct_message(true));
+        assert!(!with("nobody").allows_friend_requests());
+    }
+}
+
+#[cfg(test)]
+mod kb_search_tests {
+    use arinova_server::services::embedding::{chunk_spans, chunk_text, fit_token_budget, query_terms};
+
+    #[test]
+    fn spans_match_chunks() {
+        let text = "First paragraph about billing.\n\n".repeat(40) + "Tail about refunds 退款.";
+        let spans = chunk_spans(&text, 300, 30);
+        let chunks = chunk_text(&text, 300, 30);
+        assert_eq!(spans.len(), chunks.len());
+        for ((start, end), chunk) in spans.iter().zip(&chunks) {
+            assert_eq!(&text[*start..*end], chunk);
+        }
+    }
+
+    #[test]
+    fn query_terms_are_distinct_and_lowercase() {
+        let terms = query_terms("Refund policy, refund?");
+        assert_eq!(terms, vec!["refund", "policy"]);
+        assert!(query_terms("a ? !").is_empty());
+    }
+
+    #[test]
+    fn context_respects_token_budget() {
+        let chunks = vec!["a".repeat(400), "b".repeat(400), "c".repeat(40)];
+        // 100 + 100 + 10 tokens
+        assert_eq!(fit_token_budget(chunks.clone(), 205).len(), 2);
+        assert_eq!(fit_token_budget(chunks.clone(), 210).len(), 3);
+        assert!(fit_token_budget(chunks, 50).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod message_mentions_tests {
+    use arinova_server::services::mention::extract_at_words;
+
+    #[test]
+    fn at_words_are_lowercased_and_deduplicated() {
+        assert_eq!(
+            extract_at_words("hi @Alice and @bob, @alice again"),
+            vec!["alice".to_string(), "bob".to_string()]
+        );
+    }
+
+    #[test]
+    fn no_at_words_without_mentions() {
+        assert!(extract_at_words("email me at home").is_empty());
+    }
+}
+
+#[cfg(test)]
+mod stream_cancel_auth_tests {
+    use arinova_server::ws::state::can_cancel_stream;
+
+    #[test]
+    fn triggering_user_can_cancel() {
+        assert!(can_cancel_stream("alice", "alice", false));
+    }
+
+    #[test]
+    fn other_member_cannot_cancel() {
+        assert!(!can_cancel_stream("bob", "alice", false));
+    }
+
+    #[test]
+    fn admin_can_cancel_others_stream() {
+        assert!(can_cancel_stream("bob", "alice", true));
+    }
+}
+
+#[cfg(test)]
+mod mention_loop_tests {
+    use arinova_server::ws::handler::mention_dispatch_blocked;
+    use std::time::Duration;
+
+    const COOLDOWN: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn allows_chain_within_depth() {
+        assert_eq!(mention_dispatch_blocked(3, 3, None, COOLDOWN), None);
+    }
+
+    #[test]
+    fn stops_chain_past_depth() {
+        assert_eq!(mention_dispatch_blocked(4, 3, None, COOLDOWN), Some("depth_cap"));
+    }
+
+    #[test]
+    fn same_pair_waits_for_cooldown() {
+        assert_eq!(
+            mention_dispatch_blocked(1, 3, Some(Duration::from_secs(5)), COOLDOWN),
+            Some("cooldown")
+        );
+        assert_eq!(mention_dispatch_blocked(1, 3, Some(Duration::from_secs(31)), COOLDOWN), None);
+    }
+}
+
+#[cfg(test)]
+mod reasoning_cap_tests {
+    use arinova_server::ws::handler::push_capped;
+
+    #[test]
+    fn caps_by_characters_not_bytes() {
+        let mut buf = String::new();
+        push_capped(&mut buf, "思考中", 2);
+        push_capped(&mut buf, "再想", 2);
+        assert_eq!(buf, "思考");
+    }
+
+    #[test]
+    fn fills_remaining_budget_on_a_char_boundary() {
+        let mut buf = "ab".to_string();
+        push_capped(&mut buf, "é🙂xyz", 4);
+        assert_eq!(buf, "abé🙂");
+        assert_eq!(buf.chars().count(), 4);
+    }
+
+    #[test]
+    fn short_deltas_are_kept_whole() {
+        let mut buf = String::new();
+        push_capped(&mut buf, "hello ", 16_000);
+        push_capped(&mut buf, "world", 16_000);
+        assert_eq!(buf, "hello world");
+    }
+}
+
+#[cfg(test)]
+mod dispatch_reason_tests {
+    use arinova_server::ws::handler::{dispatch_skip_reason, AgentFilterConfig};
+
+    fn agent(mode: &str) -> AgentFilterConfig {
+        AgentFilterConfig {
+            agent_id: "a1".into(),
+            listen_mode: mode.into(),
+            owner_user_id: "owner".into(),
+            allowed_user_ids: vec!["friend".into()],
+        }
+    }
+
+    #[test]
+    fn reports_why_an_agent_is_skipped() {
+        assert_eq!(dispatch_skip_reason(&agent("muted"), "owner", &[]), Some("muted"));
+        assert_eq!(dispatch_skip_reason(&agent("all_mentions"), "owner", &[]), Some("not_mentioned"));
+        assert_eq!(dispatch_skip_reason(&agent("owner_only"), "other", &[]), Some("owner_only"));
+        assert_eq!(
+            dispatch_skip_reason(&agent("allowlist_mentions"), "other", &["a1".into()]),
+            Some("not_allowlisted")
+        );
+    }
+
+    #[test]
+    fn dispatched_agents_have_no_reason() {
+        assert_eq!(dispatch_skip_reason(&agent("all"), "other", &[]), None);
+        assert_eq!(dispatch_skip_reason(&agent("owner_and_allowlist"), "friend", &[]), None);
+        assert_eq!(dispatch_skip_reason(&agent("all_mentions"), "other", &["__all__".into()]), None);
+    }
+}
+
+#[cfg(test)]
+mod health_tests {
+    use arinova_server::routes::health::is_ready;
+
+    #[test]
+    fn ready_when_all_ok_or_disabled() {
+        assert!(is_ready(["ok", "ok", "disabled"]));
+    }
+
+    #[test]
+    fn not_ready_when_any_dependency_fails() {
+        assert!(!is_ready(["ok", "timeout", "ok"]));
+        assert!(!is_ready(["error", "ok", "ok"]));
+    }
+}
+
+#[cfg(test)]
+mod shutdown_drain_tests {
+    use arinova_server::ws::state::{StreamCanceller, WsState};
+
+    #[test]
+    fn drain_signals_active_streams_and_sets_flag() {
+        let ws = WsState::new();
+        let (tx, rx) = tokio::sync::watch::channel(false);
+        ws.stream_cancellers.insert(
+            "m1".into(),
+            StreamCanceller { tx, triggered_by: "u1".into(), conversation_id: "c1".into() },
+        );
+
+        assert!(!ws.is_shutting_down());
+        assert_eq!(ws.drain_streams(), 1);
+        assert!(ws.is_shutting_down());
+        assert!(*rx.borrow());
+    }
+
+    #[test]
+    fn stream_tasks_stay_counted_after_canceller_is_removed() {
+        let ws = WsState::new();
+        let (tx, _rx) = tokio::sync::watch::channel(false);
+        ws.stream_cancellers.insert(
+            "m1".into(),
+            StreamCanceller { tx, triggered_by: "u1".into(), conversation_id: "c1".into() },
+        );
+        let task = ws.track_stream_task();
+
+        // Cancel path: canceller goes first, the DB save happens afterwards
+        ws.stream_cancellers.remove("m1");
+        assert_eq!(ws.active_stream_tasks(), 1);
+
+        drop(task);
+        assert_eq!(ws.active_stream_tasks(), 0);
+    }
+}
+
+#[cfg(test)]
+mod prometheus_metrics_tests {
+    use arinova_server::services::metrics;
+
+    #[test]
+    fn llm_calls_show_up_in_render() {
+        metrics::install();
+        metrics::record_llm_call("openai", std::time::Instant::now(), false);
+
+        let body = metrics::render().expect("recorder installed");
+        assert!(body.contains("llm_calls_total{provider=\"openai\",outcome=\"error\"}"));
+        assert!(body.contains("llm_call_duration_seconds_bucket"));
+    }
+
+    #[test]
+    fn scrapes_need_the_configured_bearer_token() {
+        use arinova_server::routes::metrics::metrics_authorized;
+
+        assert!(metrics_authorized(Some("s3cret"), Some("Bearer s3cret")));
+        assert!(!metrics_authorized(Some("s3cret"), Some("Bearer s3cre")));
+        assert!(!metrics_authorized(Some("s3cret"), Some("s3cret")));
+        assert!(!metrics_authorized(Some("s3cret"), None));
+        assert!(!metrics_authorized(None, Some("Bearer anything")));
+    }
+}
+
+#[cfg(test)]
+mod api_error_tests {
+    use arinova_server::utils::api_error::{default_error_code, enrich_error_json};
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[test]
+    fn adds_code_and_request_id_to_error_bodies() {
+        let mut body = json!({"error": "Not found"});
+        assert!(enrich_error_json(&mut body, StatusCode::NOT_FOUND, Some("req-1")));
+        assert_eq!(body, json!({"error": "Not found", "code": "not_found", "requestId": "req-1"}));
+    }
+
+    #[test]
+    fn keeps_handler_supplied_code() {
+        let mut body = json!({"error": "Slow down", "code": "daily_limit"});
+        enrich_error_json(&mut body, StatusCode::TOO_MANY_REQUESTS, None);
+        assert_eq!(body["code"], "daily_limit");
+        assert!(body.get("requestId").is_none());
+    }
+
+    #[test]
+    fn ignores_non_error_objects() {
+        let mut body = json!({"ok": false});
+        assert!(!enrich_error_json(&mut body, StatusCode::BAD_REQUEST, Some("req-1")));
+        assert_eq!(default_error_code(StatusCode::BAD_GATEWAY), "internal_error");
+    }
+
+    #[tokio::test]
+    async fn api_error_carries_the_request_id() {
+        use arinova_server::utils::api_error::{api_error, RequestId};
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::ServiceExt;
+
+        let app = Router::new().route(
+            "/",
+            get(|request_id: RequestId| async move {
+                api_error(StatusCode::CONFLICT, "already_member", "Already a member", &request_id)
+            }),
+        );
+        let req = Request::get("/").header("x-request-id", "req-9").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(),
+            json!({"error": "Already a member", "code": "already_member", "requestId": "req-9"})
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_error_bodies_pass_through_intact() {
+        use arinova_server::utils::api_error::enrich_error_body;
+        use axum::{body::Body, http::Request, response::IntoResponse, routing::get, Json, Router};
+        use tower::ServiceExt;
+
+        let big = "x".repeat(100 * 1024);
+        let expected = json!({"error": big.clone()});
+        let app = Router::new()
+            .route(
+                "/",
+                get(move || async move {
+                    (StatusCode::BAD_REQUEST, [("x-custom", "kept")], Json(json!({"error": big}))).into_response()
+                }),
+            )
+            .layer(axum::middleware::from_fn(enrich_error_body));
+
+        let res = app.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers()["x-custom"], "kept");
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(), expected);
+    }
+}
+
+#[cfg(test)]
+mod cors_pattern_tests {
+    use arinova_server::config::OriginPattern;
+
+    #[test]
+    fn wildcard_matches_subdomains_only() {
+        let p = OriginPattern::parse("https://*.arinova.ai").unwrap();
+        assert!(p.matches("https://app.arinova.ai"));
+        assert!(p.matches("https://a.b.arinova.ai"));
+        assert!(!p.matches("https://arinova.ai"));
+        assert!(!p.matches("http://app.arinova.ai"));
+        assert!(!p.matches("https://evilarinova.ai"));
+        assert!(!p.matches("https://app.arinova.ai.evil.com"));
+    }
+
+    #[test]
+    fn schemeless_wildcard_accepts_any_scheme() {
+        let p = OriginPattern::parse("*.arinova.ai").unwrap();
+        assert!(p.matches("http://dev.arinova.ai"));
+        assert!(p.matches("https://dev.arinova.ai"));
+    }
+
+    #[test]
+    fn exact_origins_and_invalid_patterns() {
+        let p = OriginPattern::parse("http://localhost:21000").unwrap();
+        assert!(p.matches("http://localhost:21000"));
+        assert!(!p.matches("http://localhost:21001"));
+        assert!(OriginPattern::parse("https://*.com").is_err());
+        assert!(OriginPattern::parse("https://app.*.ai").is_err());
+    }
+}
+
+#[cfg(test)]
+mod key_versioning_tests {
+    use arinova_server::routes::user_settings::{decrypt_value, encrypt_value};
+
+    const OLD: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+    const NEW: &str = "0000000000000000000000000000000000000000000000000000000000000002";
+
+    #[test]
+    fn ciphertext_carries_key_version() {
+        let stored = encrypt_value(NEW, 2, "sk-test").unwrap();
+        assert!(stored.starts_with("enc:v2:"));
+        assert_eq!(decrypt_value(&[(2, NEW), (1, OLD)], &stored).unwrap(), "sk-test");
+    }
+
+    #[test]
+    fn previous_version_still_decrypts() {
+        let stored = encrypt_value(OLD, 1, "sk-old").unwrap();
+        assert_eq!(decrypt_value(&[(2, NEW), (1, OLD)], &stored).unwrap(), "sk-old");
+        assert!(decrypt_value(&[(2, NEW)], &stored).is_err());
+    }
+
+    #[test]
+    fn legacy_unversioned_values_try_every_key() {
+        let versioned = encrypt_value(OLD, 1, "sk-legacy").unwrap();
+        let legacy = versioned.replacen("enc:v1:", "enc:", 1);
+        assert_eq!(decrypt_value(&[(2, NEW), (1, OLD)], &legacy).unwrap(), "sk-legacy");
+        assert_eq!(decrypt_value(&[(2, NEW)], "plain-key").unwrap(), "plain-key");
+    }
+}
+
+#[cfg(test)]
+mod listing_trending_tests {
+    use arinova_server::routes::agent_hub::{is_trending, TRENDING_MIN_RECENT_SALES};
+
+    #[test]
+    fn below_threshold_is_not_trending() {
+        assert!(!is_trending(0));
+        assert!(!is_trending(TRENDING_MIN_RECENT_SALES - 1));
+    }
+
+    #[test]
+    fn at_or_above_threshold_is_trending() {
+        assert!(is_trending(TRENDING_MIN_RECENT_SALES));
+        assert!(is_trending(TRENDING_MIN_RECENT_SALES * 10));
+    }
+}
+
+#[cfg(test)]
+mod pagination_tests {
+    use arinova_server::utils::pagination::PageWindow;
+
+    #[test]
+    fn page_takes_precedence_over_offset() {
+        let w = PageWindow::resolve(Some(3), Some(5), Some(10), 20, 50);
+        assert_eq!(w, PageWindow { limit: 10, offset: 20 });
+        assert_eq!(w.page(), 3);
+    }
+
+    #[test]
+    fn offset_still_works_without_page() {
+        let w = PageWindow::resolve(None, Some(40), None, 20, 50);
+        assert_eq!(w, PageWindow { limit: 20, offset: 40 });
+        assert_eq!(w.page(), 3);
+    }
+
+    #[test]
+    fn limit_and_page_are_clamped() {
+        assert_eq!(PageWindow::resolve(Some(0), None, Some(500), 20, 50), PageWindow { limit: 50, offset: 0 });
+        assert_eq!(PageWindow::resolve(None, Some(-5), Some(0), 20, 50), PageWindow { limit: 1, offset: 0 });
+    }
+
+    #[test]
+    fn meta_reports_total_pages_and_has_more() {
+        let w = PageWindow::resolve(Some(2), None, Some(10), 20, 50);
+        let m = w.meta(25);
+        assert_eq!(m["page"], 2);
+        assert_eq!(m["limit"], 10);
+        assert_eq!(m["totalPages"], 3);
+        assert_eq!(m["hasMore"], true);
+
+        let last = PageWindow::resolve(Some(3), None, Some(10), 20, 50).meta(25);
+        assert_eq!(last["hasMore"], false);
+        assert_eq!(PageWindow::resolve(None, None, None, 20, 50).meta(0)["totalPages"], 0);
+    }
+}
+
+#[cfg(test)]
+mod listing_quota_tests {
+    use arinova_server::routes::agent_hub::listing_quota_error;
+    use axum::http::StatusCode;
+
+    #[test]
+    fn under_both_limits_is_allowed() {
+        assert!(listing_quota_error(24, 9, 25, 10).is_none());
+    }
+
+    #[test]
+    fn active_cap_is_bad_request() {
+        let (status, msg) = listing_quota_error(25, 0, 25, 10).unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(msg.contains("25 active listings"));
+    }
+
+    #[test]
+    fn daily_cap_is_rate_limited() {
+        let (status, msg) = listing_quota_error(3, 10, 25, 10).unwrap();
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert!(msg.contains("10 listings per day"));
+    }
+}
+
+#[cfg(test)]
+mod conversation_title_tests {
+    use arinova_server::services::conversation_title::{clean_title, is_auto_title_type, TITLE_MAX_CHARS};
+
+    #[test]
+    fn groups_and_communities_are_skipped() {
+        assert!(is_auto_title_type("direct"));
+        assert!(is_auto_title_type("h2a"));
+        assert!(!is_auto_title_type("group"));
+        assert!(!is_auto_title_type("community"));
+    }
+
+    #[test]
+    fn strips_quotes_prefix_and_punctuation() {
+        assert_eq!(clean_title("\"Trip planning for Kyoto.\"").as_deref(), Some("Trip planning for Kyoto"));
+        assert_eq!(clean_title("Title: Rust lifetimes help").as_deref(), Some("Rust lifetimes help"));
+        assert_eq!(clean_title("\n\nFirst line\nsecond line").as_deref(), Some("First line"));
+    }
+
+    #[test]
+    fn empty_reply_yields_none() {
+        assert_eq!(clean_title(""), None);
+        assert_eq!(clean_title("  \"\"  "), None);
+    }
+
+    #[test]
+    fn long_titles_are_capped() {
+        let t = clean_title(&"word ".repeat(40)).unwrap();
+        assert!(t.chars().count() <= TITLE_MAX_CHARS);
+        assert!(!t.ends_with(' '));
+    }
+}
+
+#[cfg(test)]
+mod conversation_rename_tests {
+    use arinova_server::routes::conversations::{
+        rename_requires_admin, validate_conversation_title, MAX_CONVERSATION_TITLE_CHARS,
+    };
+
+    #[test]
+    fn trims_and_accepts_normal_titles() {
+        assert_eq!(validate_conversation_title("  Weekend plans ").unwrap(), "Weekend plans");
+    }
+
+    #[test]
+    fn rejects_empty_and_whitespace() {
+        assert!(validate_conversation_title("").is_err());
+        assert!(validate_conversation_title("   ").is_err());
+    }
+
+    #[test]
+    fn length_limit_counts_characters() {
+        assert!(validate_conversation_title(&"字".repeat(MAX_CONVERSATION_TITLE_CHARS)).is_ok());
+        assert!(validate_conversation_title(&"a".repeat(MAX_CONVERSATION_TITLE_CHARS + 1)).is_err());
+    }
+
+    #[test]
+    fn shared_conversations_need_an_admin() {
+        for t in ["group", "community", "lounge", "official"] {
+            assert!(rename_requires_admin(t), "{t}");
+        }
+        for t in ["direct", "h2a", "h2h"] {
+            assert!(!rename_requires_admin(t), "{t}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod jump_to_date_tests {
+    use arinova_server::routes::messages::parse_jump_date;
+
+    #[test]
+    fn parses_start_of_day() {
+        let ts = parse_jump_date("2024-03-09", 0).unwrap();
+        assert_eq!(ts.to_string(), "2024-03-09 00:00:00");
+        assert!(parse_jump_date(" 2024-12-31 ", 0).is_some());
+    }
+
+    #[test]
+    fn applies_client_timezone_offset() {
+        // UTC+8 midnight is 16:00 UTC the previous day
+        let ts = parse_jump_date("2024-03-09", -480).unwrap();
+        assert_eq!(ts.to_string(), "2024-03-08 16:00:00");
+        // UTC-5 midnight is 05:00 UTC
+        let ts = parse_jump_date("2024-03-09", 300).unwrap();
+        assert_eq!(ts.to_string(), "2024-03-09 05:00:00");
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        assert!(parse_jump_date("2024-02-30", 0).is_none());
+        assert!(parse_jump_date("09/03/2024", 0).is_none());
+        assert!(parse_jump_date("2024-03-09T10:00:00Z", 0).is_none());
+        assert!(parse_jump_date("", 0).is_none());
+    }
+}
+
+#[cfg(test)]
+mod conversation_stats_tests {
+    use arinova_server::routes::conversations::role_breakdown;
+
+    #[test]
+    fn sums_roles_into_total() {
+        let (total, by_role) = role_breakdown(&[("user".into(), 12), ("agent".into(), 30)]);
+        assert_eq!(total, 42);
+        assert_eq!(by_role["user"], 12);
+        assert_eq!(by_role["agent"], 30);
+    }
+
+    #[test]
+    fn empty_conversation_has_no_roles() {
+        let (total, by_role) = role_breakdown(&[]);
+        assert_eq!(total, 0);
+        assert!(by_role.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod scheduled_message_tests {
+    use arinova_server::routes::scheduled_messages::{validate_send_at, MAX_SCHEDULE_AHEAD_DAYS};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn future_time_within_window_is_accepted() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        assert!(validate_send_at(now + Duration::minutes(5), now).is_ok());
+        assert!(validate_send_at(now + Duration::days(MAX_SCHEDULE_AHEAD_DAYS), now).is_ok());
+    }
+
+    #[test]
+    fn past_or_now_is_rejected() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        assert!(validate_send_at(now, now).is_err());
+        assert!(validate_send_at(now - Duration::seconds(1), now).is_err());
+    }
+
+    #[test]
+    fn too_far_ahead_is_rejected() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        let err = validate_send_at(now + Duration::days(MAX_SCHEDULE_AHEAD_DAYS) + Duration::seconds(1), now).unwrap_err();
+        assert!(err.contains("within"));
+    }
+}
+
+#[cfg(test)]
+mod regenerate_tests {
+    use arinova_server::routes::messages::{can_regenerate, regenerate_blocked};
+
+    #[test]
+    fn latest_finished_agent_reply_can_regenerate() {
+        assert_eq!(regenerate_blocked(true, "completed", false), None);
+        assert_eq!(regenerate_blocked(true, "error", false), None);
+        assert_eq!(regenerate_blocked(true, "cancelled", false), None);
+    }
+
+    #[test]
+    fn user_messages_cannot_regenerate() {
+        assert!(regenerate_blocked(false, "completed", false).is_some());
+    }
+
+    #[test]
+    fn in_progress_or_older_replies_are_blocked() {
+        assert_eq!(regenerate_blocked(true, "streaming", false), Some("Reply is still in progress"));
+        assert_eq!(regenerate_blocked(true, "completed", true), Some("Only the latest reply can be regenerated"));
+    }
+
+    #[test]
+    fn only_prompt_author_or_admin_can_regenerate() {
+        assert!(can_regenerate("alice", "alice", false));
+        assert!(can_regenerate("admin", "alice", true));
+        assert!(!can_regenerate("bob", "alice", false));
+    }
+}
+
+#[cfg(test)]
+mod message_version_tests {
+    use arinova_server::routes::messages::is_superseded;
+    use serde_json::json;
+
+    #[test]
+    fn missing_metadata_is_active() {
+        assert!(!is_superseded(None));
+        assert!(!is_superseded(Some(&json!({"responseGroupId": "m1"}))));
+    }
+
+    #[test]
+    fn superseded_flag_is_read() {
+        assert!(is_superseded(Some(&json!({"superseded": true}))));
+        assert!(!is_superseded(Some(&json!({"superseded": false}))));
+    }
+}
+
+#[cfg(test)]
+mod billing_hold_tests {
+    use arinova_server::services::billing::{
+        hold_amount, preview_applies, trial_remaining, usage_event, BillingResult,
+    };
+    use serde_json::json;
+
+    fn result(cost: i32, is_free_trial: bool) -> BillingResult {
+        BillingResult {
+            allowed: true,
+            cost,
+            is_free_trial,
+            is_preview: false,
+            trial_remaining: 0,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn paid_message_holds_its_price() {
+        assert_eq!(hold_amount(&result(15, false)), Some(15));
+    }
+
+    #[test]
+    fn free_messages_hold_nothing() {
+        assert_eq!(hold_amount(&result(0, false)), None);
+        assert_eq!(hold_amount(&result(0, true)), None);
+    }
+
+    #[test]
+    fn usage_event_shape() {
+        assert_eq!(
+            usage_event(15, false),
+            json!({"type": "usage", "coins": 15, "freeTrial": false})
+        );
+        assert_eq!(usage_event(0, true)["freeTrial"], json!(true));
+    }
+
+    #[test]
+    fn trial_remaining_counts_down_to_zero() {
+        assert_eq!(trial_remaining(3, 0), 3);
+        assert_eq!(trial_remaining(3, 2), 1);
+        assert_eq!(trial_remaining(3, 3), 0);
+        // Usage above the trial (e.g. the creator lowered it) is never negative
+        assert_eq!(trial_remaining(2, 5), 0);
+        assert_eq!(trial_remaining(0, 0), 0);
+    }
+
+    #[test]
+    fn preview_applies_once_on_paid_listings() {
+        assert!(preview_applies(true, 10, false));
+        assert!(!preview_applies(true, 10, true));
+        // Free listings don't need a preview
+        assert!(!preview_applies(true, 0, false));
+        assert!(!preview_applies(false, 10, false));
+    }
+
+    #[test]
+    fn preview_message_holds_nothing() {
+        let mut r = result(10, false);
+        r.is_preview = true;
+        assert_eq!(hold_amount(&r), None);
+    }
+}
+
+#[cfg(test)]
+mod sync_missed_targets_tests {
+    use arinova_server::ws::handler::sync_missed_targets;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn ids(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn only_conversations_the_client_is_behind_on() {
+        let conv_ids = ids(&["a", "b", "c", "d"]);
+        let max_seqs = HashMap::from([
+            ("a".to_string(), 10),
+            ("b".to_string(), 5),
+            ("c".to_string(), 7),
+        ]);
+        // b is up to date, d has no messages, c isn't known to the client
+        let client = json!({"a": 4, "b": 5, "d": 0});
+        let (targets, seqs) = sync_missed_targets(&conv_ids, &client, &max_seqs);
+        assert_eq!(targets, ids(&["a"]));
+        assert_eq!(seqs, vec![4]);
+    }
+
+    #[test]
+    fn keeps_conversation_list_order() {
+        let conv_ids = ids(&["pinned", "recent", "older"]);
+        let max_seqs = HashMap::from([
+            ("pinned".to_string(), 3),
+            ("recent".to_string(), 9),
+            ("older".to_string(), 2),
+        ]);
+        let client = json!({"older": 0, "pinned": 1, "recent": 8});
+        let (targets, seqs) = sync_missed_targets(&conv_ids, &client, &max_seqs);
+        assert_eq!(targets, ids(&["pinned", "recent", "older"]));
+        assert_eq!(seqs, vec![1, 8, 0]);
+    }
+
+    #[test]
+    fn ignores_non_numeric_client_seqs() {
+        let conv_ids = ids(&["a"]);
+        let max_seqs = HashMap::from([("a".to_string(), 3)]);
+        let (targets, _) = sync_missed_targets(&conv_ids, &json!({"a": "2"}), &max_seqs);
+        assert!(targets.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod agent_settings_event_tests {
+    use arinova_server::routes::groups::agent_settings_changed_event;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn withdraw_event_carries_ids_and_flag() {
+        let conv_id = Uuid::new_v4();
+        let agent_id = Uuid::new_v4();
+        let event = agent_settings_changed_event(conv_id, agent_id, json!({"withdrawn": true}));
+        assert_eq!(
+            event,
+            json!({
+                "type": "agent_settings_changed",
+                "conversationId": conv_id.to_string(),
+                "agentId": agent_id.to_string(),
+                "withdrawn": true,
+            })
+        );
+    }
+
+    #[test]
+    fn merges_setting_changes() {
+        let event = agent_settings_changed_event(Uuid::new_v4(), Uuid::new_v4(), json!({"listenMode": "all"}));
+        assert_eq!(event["type"], "agent_settings_changed");
+        assert_eq!(event["listenMode"], "all");
+    }
+}
+
+#[cfg(test)]
+mod stream_reset_tests {
+    use arinova_server::ws::state::{StreamCanceller, WsState};
+
+    #[test]
+    fn cancel_stream_signals_before_dropping_the_canceller() {
+        let ws = WsState::new();
+        let (tx, rx) = tokio::sync::watch::channel(false);
+        ws.stream_cancellers.insert(
+            "m1".into(),
+            StreamCanceller { tx, triggered_by: "u1".into(), conversation_id: "c1".into() },
+        );
+
+        assert!(ws.cancel_stream("m1"));
+        assert!(*rx.borrow());
+        assert!(ws.stream_cancellers.is_empty());
+    }
+
+    #[test]
+    fn cancel_stream_without_live_task() {
+        let ws = WsState::new();
+        assert!(!ws.cancel_stream("gone"));
+    }
+}
+
+#[cfg(test)]
+mod read_receipt_batch_tests {
+    use arinova_server::ws::handler::read_receipts_by_recipient;
+    use serde_json::json;
+    use std::collections::{HashMap, HashSet};
+
+    fn ids(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn each_member_gets_other_readers() {
+        let receipts = HashMap::from([("a".to_string(), 5), ("b".to_string(), 7)]);
+        let out = read_receipts_by_recipient(&receipts, &ids(&["a", "b", "c"]), &HashSet::new());
+        assert_eq!(out["a"], vec![json!({"userId": "b", "seq": 7})]);
+        assert_eq!(out["b"], vec![json!({"userId": "a", "seq": 5})]);
+        assert_eq!(out["c"].len(), 2);
+    }
+
+    #[test]
+    fn blocked_pairs_are_skipped() {
+        let receipts = HashMap::from([("a".to_string(), 5)]);
+        let blocked = HashSet::from([("a".to_string(), "c".to_string())]);
+        let out = read_receipts_by_recipient(&receipts, &ids(&["a", "b", "c"]), &blocked);
+        assert!(out.contains_key("b"));
+        assert!(!out.contains_key("c"));
+        assert!(!out.contains_key("a"));
+    }
+}
+
+#[cfg(test)]
+mod color_theme_tests {
+    use arinova_server::services::color_theme::{
+        is_hex_color, parse_theme_choice, validate_custom_theme, ThemeChoice, BUILTIN_THEMES, TOKEN_NAMES,
+    };
+    use serde_json::json;
+
+    fn tokens() -> serde_json::Value {
+        json!({
+            "background": "#FFFFFF", "surface": "#eee", "text": "#111111",
+            "mutedText": "#777777", "accent": "#ff5500", "border": "#dddddd",
+        })
+    }
+
+    #[test]
+    fn hex_colors_need_a_hash_and_three_or_six_digits() {
+        assert!(is_hex_color("#abc"));
+        assert!(is_hex_color("#A1B2C3"));
+        assert!(!is_hex_color("abc123"));
+        assert!(!is_hex_color("#abcd"));
+        assert!(!is_hex_color("#ggg"));
+        assert!(!is_hex_color("red"));
+    }
+
+    #[test]
+    fn builtin_themes_define_every_token_as_hex() {
+        for theme in BUILTIN_THEMES {
+            let json = theme.to_json();
+            for token in TOKEN_NAMES {
+                assert!(is_hex_color(json["tokens"][token].as_str().unwrap()), "{}.{}", theme.id, token);
+            }
+        }
+    }
+
+    #[test]
+    fn valid_custom_theme_is_normalized() {
+        let theme = validate_custom_theme(&json!({"name": " Sunset ", "tokens": tokens()})).unwrap();
+        assert_eq!(theme.name, "Sunset");
+        assert_eq!(theme.tokens["background"], "#ffffff");
+    }
+
+    #[test]
+    fn malformed_custom_theme_reports_each_field() {
+        let mut bad = tokens();
+        bad["accent"] = json!("orange");
+        bad.as_object_mut().unwrap().remove("border");
+        bad["glow"] = json!("#fff");
+        let errors = validate_custom_theme(&json!({"name": "", "tokens": bad})).unwrap_err();
+        let fields: Vec<&str> = errors.keys().map(String::as_str).collect();
+        assert_eq!(fields, vec!["name", "tokens.accent", "tokens.border", "tokens.glow"]);
+    }
+
+    #[test]
+    fn choice_is_a_known_id_or_a_custom_theme_but_not_both() {
+        assert_eq!(parse_theme_choice(&json!({"themeId": "dark"})), Ok(ThemeChoice::Builtin("dark")));
+        let errors = parse_theme_choice(&json!({"themeId": "neon"})).unwrap_err();
+        assert!(errors.contains_key("themeId"));
+        let errors = parse_theme_choice(&json!({"custom": {"name": "x", "tokens": {}}})).unwrap_err();
+        assert!(errors.contains_key("custom.tokens.accent"));
+        assert!(parse_theme_choice(&json!({"themeId": "dark", "custom": {}})).is_err());
+        assert!(parse_theme_choice(&json!({})).is_err());
+    }
+}
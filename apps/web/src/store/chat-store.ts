@@ -2763,6 +2763,24 @@ export const useChatStore = create<ChatState>((set, get) => ({
       return;
     }
 
+    if (event.type === "read_receipts") {
+      const { conversationId, receipts } = event;
+      const current = { ...(get().readReceipts[conversationId] ?? {}) };
+      let changed = false;
+      for (const { userId, seq } of receipts) {
+        if (seq > (current[userId] ?? 0)) {
+          current[userId] = seq;
+          changed = true;
+        }
+      }
+      if (changed) {
+        set({
+          readReceipts: { ...get().readReceipts, [conversationId]: current },
+        });
+      }
+      return;
+    }
+
     if (event.type === "read_receipt") {
       const { conversationId, userId, seq } = event;
       const current = get().readReceipts[conversationId] ?? {};
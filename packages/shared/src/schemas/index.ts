@@ -388,6 +388,10 @@ export const pushSubscriptionSchema = z.object({
 export const notificationPreferenceSchema = z.object({
   globalEnabled: z.boolean(),
   messageEnabled: z.boolean(),
+  mentionEnabled: z.boolean().optional(),
+  communityEnabled: z.boolean().optional(),
+  friendRequestEnabled: z.boolean().optional(),
+  voiceCallEnabled: z.boolean().optional(),
   playgroundInviteEnabled: z.boolean(),
   playgroundTurnEnabled: z.boolean(),
   playgroundResultEnabled: z.boolean(),
@@ -399,4 +403,5 @@ export const notificationPreferenceSchema = z.object({
     .string()
     .regex(/^([01]\d|2[0-3]):[0-5]\d$/, "Must be HH:mm format")
     .nullable(),
+  quietHoursUtcOffset: z.number().int().min(-720).max(840).nullable().optional(),
 });
@@ -39,6 +39,7 @@ export interface Conversation {
   agentId: string | null;
   mentionOnly: boolean;
   pinnedAt: Date | null;
+  archivedAt?: Date | null;
   createdAt: Date;
   updatedAt: Date;
 }
@@ -118,6 +119,7 @@ export interface Attachment {
   duration?: number;
   width?: number | null;
   height?: number | null;
+  thumbnailUrl?: string | null;
   createdAt: Date;
 }
 
@@ -399,8 +401,9 @@ export type WSClientEvent =
   | { type: "send_message"; id?: string; conversationId: string; content: string; replyToId?: string; threadId?: string; mentions?: string[] }
   | { type: "cancel_stream"; conversationId: string; messageId: string }
   | { type: "cancel_queued"; conversationId: string; messageId: string }
-  | { type: "sync"; conversations: Record<string, number> } // convId → lastSeq
+  | { type: "sync"; conversations: Record<string, number>; includeArchived?: boolean } // convId → lastSeq
   | { type: "mark_read"; conversationId: string; seq: number }
+  | { type: "mark_all_read" }
   | { type: "focus"; visible: boolean }
   | { type: "typing"; conversationId: string }
   | { type: "ping" };
@@ -408,8 +411,13 @@ export type WSClientEvent =
 export interface SyncConversationSummary {
   conversationId: string;
   unreadCount: number;
+  /** Unread messages that @-mention the user */
+  unreadMentions: number;
   maxSeq: number;
   muted: boolean;
+  /** "mentions" = push only when @-mentioned */
+  pushMode: "all" | "mentions";
+  pinned: boolean;
   lastMessage: {
     content: string;
     role: MessageRole;
@@ -529,7 +537,9 @@ export type WSServerEvent =
   | { type: "note:deleted"; conversationId: string; noteId: string }
   | { type: "link_previews_ready"; conversationId: string; messageId: string; linkPreviews: LinkPreview[] }
   | { type: "message_deleted"; conversationId: string; messageId: string }
+  | { type: "mark_all_read_result"; updated: number }
   | { type: "read_receipt"; conversationId: string; userId: string; seq: number }
+  | { type: "read_receipts"; conversationId: string; receipts: { userId: string; seq: number }[] }
   | { type: "voice_incoming_call"; sessionId: string; callerId: string; callerName: string; callerAvatarUrl: string | null; conversationId: string; sdp: string }
   | { type: "voice_call_end"; sessionId: string; reason?: string }
   | { type: "pong" };
@@ -624,9 +634,14 @@ export interface NotificationPreference {
   userId: string;
   globalEnabled: boolean;
   messageEnabled: boolean;
+  mentionEnabled: boolean;
+  communityEnabled: boolean;
+  friendRequestEnabled: boolean;
+  voiceCallEnabled: boolean;
   playgroundInviteEnabled: boolean;
   playgroundTurnEnabled: boolean;
   playgroundResultEnabled: boolean;
   quietHoursStart: string | null; // HH:mm format
   quietHoursEnd: string | null;   // HH:mm format
+  quietHoursUtcOffset: number | null; // minutes east of UTC; null = server time
 }